@@ -1,4 +1,20 @@
-use crate::memory::Memory;
+use crate::memory::{Memory, MemoryError};
+use cop0::Mode;
+use mmu::Access;
+
+mod cop0;
+mod fpu;
+mod micromips;
+mod mips16e;
+mod mips64;
+mod mmu;
+mod release6;
+
+pub use cop0::{ControlRegisters, INTERRUPT_LINES};
+pub use fpu::FloatRegisters;
+pub use micromips::disassemble;
+pub use mips16e::disassemble as disassemble_mips16e;
+pub use mmu::KSEG_PHYSICAL;
 
 #[derive(Debug, Clone)]
 pub struct Registers {
@@ -42,10 +58,15 @@ pub struct Registers {
     pub fp: u32,
     pub ra: u32,
 
-    pub pc: u32,
+    pub pc: u64,
     pub hi: u32,
     pub lo: u32,
 
+    /// Upper words of the GPRs in MIPS64 mode, indexed by register number
+    upper: [u32; 32],
+    hi_upper: u32,
+    lo_upper: u32,
+
     position: usize,
 }
 
@@ -96,51 +117,55 @@ impl Default for Registers {
             hi: 0,
             lo: 0,
 
+            upper: [0; 32],
+            hi_upper: 0,
+            lo_upper: 0,
+
             position: 0,
         }
     }
 }
 
 impl Iterator for Registers {
-    type Item = (String, u32);
+    type Item = (String, u64);
 
     fn next(&mut self) -> Option<Self::Item> {
         let result = match self.position {
-            0 => Some(("zero".to_string(), self.zero)),
-            1 => Some(("at".to_string(), self.at)),
-            2 => Some(("v0".to_string(), self.v0)),
-            3 => Some(("v1".to_string(), self.v1)),
-            4 => Some(("a0".to_string(), self.a0)),
-            5 => Some(("a1".to_string(), self.a1)),
-            6 => Some(("a2".to_string(), self.a2)),
-            7 => Some(("a3".to_string(), self.a3)),
-            8 => Some(("t0".to_string(), self.t0)),
-            9 => Some(("t1".to_string(), self.t1)),
-            10 => Some(("t2".to_string(), self.t2)),
-            11 => Some(("t3".to_string(), self.t3)),
-            12 => Some(("t4".to_string(), self.t4)),
-            13 => Some(("t5".to_string(), self.t5)),
-            14 => Some(("t6".to_string(), self.t6)),
-            15 => Some(("t7".to_string(), self.t7)),
-            16 => Some(("s0".to_string(), self.s0)),
-            17 => Some(("s1".to_string(), self.s1)),
-            18 => Some(("s2".to_string(), self.s2)),
-            19 => Some(("s3".to_string(), self.s3)),
-            20 => Some(("s4".to_string(), self.s4)),
-            21 => Some(("s5".to_string(), self.s5)),
-            22 => Some(("s6".to_string(), self.s6)),
-            23 => Some(("s7".to_string(), self.s7)),
-            24 => Some(("t8".to_string(), self.t8)),
-            25 => Some(("t9".to_string(), self.t9)),
-            26 => Some(("k0".to_string(), self.k0)),
-            27 => Some(("k1".to_string(), self.k1)),
-            28 => Some(("gp".to_string(), self.gp)),
-            29 => Some(("sp".to_string(), self.sp)),
-            30 => Some(("fp".to_string(), self.fp)),
-            31 => Some(("ra".to_string(), self.ra)),
+            0 => Some(("zero".to_string(), self.read_register64(0))),
+            1 => Some(("at".to_string(), self.read_register64(1))),
+            2 => Some(("v0".to_string(), self.read_register64(2))),
+            3 => Some(("v1".to_string(), self.read_register64(3))),
+            4 => Some(("a0".to_string(), self.read_register64(4))),
+            5 => Some(("a1".to_string(), self.read_register64(5))),
+            6 => Some(("a2".to_string(), self.read_register64(6))),
+            7 => Some(("a3".to_string(), self.read_register64(7))),
+            8 => Some(("t0".to_string(), self.read_register64(8))),
+            9 => Some(("t1".to_string(), self.read_register64(9))),
+            10 => Some(("t2".to_string(), self.read_register64(10))),
+            11 => Some(("t3".to_string(), self.read_register64(11))),
+            12 => Some(("t4".to_string(), self.read_register64(12))),
+            13 => Some(("t5".to_string(), self.read_register64(13))),
+            14 => Some(("t6".to_string(), self.read_register64(14))),
+            15 => Some(("t7".to_string(), self.read_register64(15))),
+            16 => Some(("s0".to_string(), self.read_register64(16))),
+            17 => Some(("s1".to_string(), self.read_register64(17))),
+            18 => Some(("s2".to_string(), self.read_register64(18))),
+            19 => Some(("s3".to_string(), self.read_register64(19))),
+            20 => Some(("s4".to_string(), self.read_register64(20))),
+            21 => Some(("s5".to_string(), self.read_register64(21))),
+            22 => Some(("s6".to_string(), self.read_register64(22))),
+            23 => Some(("s7".to_string(), self.read_register64(23))),
+            24 => Some(("t8".to_string(), self.read_register64(24))),
+            25 => Some(("t9".to_string(), self.read_register64(25))),
+            26 => Some(("k0".to_string(), self.read_register64(26))),
+            27 => Some(("k1".to_string(), self.read_register64(27))),
+            28 => Some(("gp".to_string(), self.read_register64(28))),
+            29 => Some(("sp".to_string(), self.read_register64(29))),
+            30 => Some(("fp".to_string(), self.read_register64(30))),
+            31 => Some(("ra".to_string(), self.read_register64(31))),
             32 => Some(("pc".to_string(), self.pc)),
-            33 => Some(("hi".to_string(), self.hi)),
-            34 => Some(("lo".to_string(), self.lo)),
+            33 => Some(("hi".to_string(), self.hi64())),
+            34 => Some(("lo".to_string(), self.lo64())),
             _ => None,
         };
         self.position += 1;
@@ -149,45 +174,51 @@ impl Iterator for Registers {
 }
 
 impl Registers {
-    fn read_register(&mut self, number: u8) -> u32 {
+    fn read_register(&self, number: u8) -> u32 {
         match number {
-            0 => self.zero as u32,
-            1 => self.at as u32,
-            2 => self.v0 as u32,
-            3 => self.v1 as u32,
-            4 => self.a0 as u32,
-            5 => self.a1 as u32,
-            6 => self.a2 as u32,
-            7 => self.a3 as u32,
-            8 => self.t0 as u32,
-            9 => self.t1 as u32,
-            10 => self.t2 as u32,
-            11 => self.t3 as u32,
-            12 => self.t4 as u32,
-            13 => self.t5 as u32,
-            14 => self.t6 as u32,
-            15 => self.t7 as u32,
-            16 => self.s0 as u32,
-            17 => self.s1 as u32,
-            18 => self.s2 as u32,
-            19 => self.s3 as u32,
-            20 => self.s4 as u32,
-            21 => self.s5 as u32,
-            22 => self.s6 as u32,
-            23 => self.s7 as u32,
-            24 => self.t8 as u32,
-            25 => self.t9 as u32,
-            26 => self.k0 as u32,
-            27 => self.k1 as u32,
-            28 => self.gp as u32,
-            29 => self.sp as u32,
-            30 => self.fp as u32,
-            31 => self.ra as u32,
+            0 => self.zero,
+            1 => self.at,
+            2 => self.v0,
+            3 => self.v1,
+            4 => self.a0,
+            5 => self.a1,
+            6 => self.a2,
+            7 => self.a3,
+            8 => self.t0,
+            9 => self.t1,
+            10 => self.t2,
+            11 => self.t3,
+            12 => self.t4,
+            13 => self.t5,
+            14 => self.t6,
+            15 => self.t7,
+            16 => self.s0,
+            17 => self.s1,
+            18 => self.s2,
+            19 => self.s3,
+            20 => self.s4,
+            21 => self.s5,
+            22 => self.s6,
+            23 => self.s7,
+            24 => self.t8,
+            25 => self.t9,
+            26 => self.k0,
+            27 => self.k1,
+            28 => self.gp,
+            29 => self.sp,
+            30 => self.fp,
+            31 => self.ra,
             _ => panic!("Invalid register number: {}", self.position),
         }
     }
 
+    /**
+     * Writes a 32-bit result, sign-extended into the upper word as MIPS64 requires
+     */
     fn write_register(&mut self, number: u8, value: u32) {
+        if number != 0 {
+            self.upper[number as usize] = sign_extension(value);
+        }
         match number {
             0 => (),
             1 => self.at = value,
@@ -225,52 +256,131 @@ impl Registers {
         }
     }
 
-    fn write_register_high(&mut self, number: u8, value: u16) {
-        let value = (value as u32) << 16;
-        match number {
-            0 => (),
-            1 => self.at = self.at & 0xffff | value,
-            2 => self.v0 = self.v0 & 0xffff | value,
-            3 => self.v1 = self.v1 & 0xffff | value,
-            4 => self.a0 = self.a0 & 0xffff | value,
-            5 => self.a1 = self.a1 & 0xffff | value,
-            6 => self.a2 = self.a2 & 0xffff | value,
-            7 => self.a3 = self.a3 & 0xffff | value,
-            8 => self.t0 = self.t0 & 0xffff | value,
-            9 => self.t1 = self.t1 & 0xffff | value,
-            10 => self.t2 = self.t2 & 0xffff | value,
-            11 => self.t3 = self.t3 & 0xffff | value,
-            12 => self.t4 = self.t4 & 0xffff | value,
-            13 => self.t5 = self.t5 & 0xffff | value,
-            14 => self.t6 = self.t6 & 0xffff | value,
-            15 => self.t7 = self.t7 & 0xffff | value,
-            16 => self.s0 = self.s0 & 0xffff | value,
-            17 => self.s1 = self.s1 & 0xffff | value,
-            18 => self.s2 = self.s2 & 0xffff | value,
-            19 => self.s3 = self.s3 & 0xffff | value,
-            20 => self.s4 = self.s4 & 0xffff | value,
-            21 => self.s5 = self.s5 & 0xffff | value,
-            22 => self.s6 = self.s6 & 0xffff | value,
-            23 => self.s7 = self.s7 & 0xffff | value,
-            24 => self.t8 = self.t8 & 0xffff | value,
-            25 => self.t9 = self.t9 & 0xffff | value,
-            26 => self.k0 = self.k0 & 0xffff | value,
-            27 => self.k1 = self.k1 & 0xffff | value,
-            28 => self.gp = self.gp & 0xffff | value,
-            29 => self.sp = self.sp & 0xffff | value,
-            30 => self.fp = self.fp & 0xffff | value,
-            31 => self.ra = self.ra & 0xffff | value,
-            _ => panic!("Invalid register number: {}", self.position),
+    fn read_register64(&self, number: u8) -> u64 {
+        let lower = self.read_register(number);
+        ((self.upper[number as usize] as u64) << 32) | lower as u64
+    }
+
+    fn write_register64(&mut self, number: u8, value: u64) {
+        self.write_register(number, value as u32);
+        if number != 0 {
+            self.upper[number as usize] = (value >> 32) as u32;
         }
     }
+
+    fn hi64(&self) -> u64 {
+        ((self.hi_upper as u64) << 32) | self.hi as u64
+    }
+
+    fn lo64(&self) -> u64 {
+        ((self.lo_upper as u64) << 32) | self.lo as u64
+    }
+
+    /**
+     * Writes 32-bit HI and LO results, sign-extended like GPR results
+     */
+    fn write_hi_lo(&mut self, hi: u32, lo: u32) {
+        self.write_hi_lo64(hi as i32 as u64, lo as i32 as u64);
+    }
+
+    fn write_hi_lo64(&mut self, hi: u64, lo: u64) {
+        self.hi = hi as u32;
+        self.hi_upper = (hi >> 32) as u32;
+        self.lo = lo as u32;
+        self.lo_upper = (lo >> 32) as u32;
+    }
 }
 
-#[derive(Debug, Default, Clone)]
+/**
+ * Upper word of a 32-bit value sign-extended to 64 bits
+ */
+fn sign_extension(value: u32) -> u32 {
+    ((value as i32) >> 31) as u32
+}
+
+#[derive(Debug, Clone)]
 pub struct CPU {
     pub registers: Registers,
+    pub float_registers: FloatRegisters,
+    pub control_registers: ControlRegisters,
     pub halted: bool,
     pub exception: Exception,
+    /// Execute the instruction after a branch before the branch takes effect.
+    /// Turn off for SPIM-style programs that assume branches are immediate.
+    pub delay_slots: bool,
+    pub isa: IsaLevel,
+    /// 64-bit GPRs and the doubleword instructions, otherwise those raise Reserved Instruction
+    pub mips64: bool,
+    /// The microMIPS ASE is implemented: jalx and jumps to odd addresses switch the ISA mode
+    pub micromips_ase: bool,
+    /// ISA mode, executing microMIPS instead of MIPS32 instructions
+    pub micromips: bool,
+    /// The MIPS16e ASE is implemented instead of microMIPS, odd jump targets switch to MIPS16e
+    pub mips16e_ase: bool,
+    /// ISA mode, executing MIPS16e instead of MIPS32 instructions
+    pub mips16e: bool,
+    /// Let stores write to executable pages that are not writable, for self-modifying code
+    pub self_modifying_code: bool,
+    /// Translate addresses through the kseg0/kseg1 windows and the TLB.
+    /// Off, virtual addresses are used as physical ones, as in SPIM.
+    pub mmu: bool,
+    /// A handler is loaded at the exception vector.
+    /// Without one, exceptions halt the CPU so they can be inspected.
+    pub exception_handler: bool,
+    /// Set by ll and cleared by exceptions, sc decides success from it
+    pub llbit: bool,
+    /// Word address of the last ll
+    pub lladdr: u64,
     jump: bool,
+    /// Target of a taken branch, applied after the delay slot has executed
+    branch_target: Option<u64>,
+    /// The instruction being executed sits in a delay slot
+    in_delay_slot: bool,
+    /// Address of the last branch, where an exception in its delay slot restarts
+    branch_address: u64,
+    /// Size in bytes of the instruction being executed, 2 for 16-bit microMIPS and MIPS16e instructions
+    instruction_size: u32,
+    /// The jump being executed has a 16-bit delay slot (microMIPS jals and jalrs, MIPS16e jumps)
+    short_delay_slot: bool,
+    /// Memory store generation of lladdr when it was linked
+    link_generation: u32,
+    /// Exit code passed to the exit syscall
+    pub exit_code: u32,
+    /// Exception raised by the current step
+    raised: Option<Exception>,
+}
+
+impl Default for CPU {
+    fn default() -> Self {
+        Self {
+            registers: Registers::default(),
+            float_registers: FloatRegisters::default(),
+            control_registers: ControlRegisters::default(),
+            halted: false,
+            exception: Exception::None,
+            delay_slots: true,
+            isa: IsaLevel::default(),
+            mips64: false,
+            micromips_ase: false,
+            micromips: false,
+            mips16e_ase: false,
+            mips16e: false,
+            self_modifying_code: false,
+            mmu: false,
+            exception_handler: false,
+            llbit: false,
+            lladdr: 0,
+            jump: false,
+            branch_target: None,
+            in_delay_slot: false,
+            branch_address: 0,
+            instruction_size: 4,
+            short_delay_slot: false,
+            link_generation: 0,
+            exit_code: 0,
+            raised: None,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -283,14 +393,23 @@ pub struct Instruction {
     funct: u8,
     imm: u16,
     address: u32,
+    /// The raw encoding
+    word: u32,
     kind: InstructionKind,
 }
 
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 enum InstructionKind {
     RType,
     IType,
     JType,
+    /// Opcode not implemented, executing it raises a Reserved Instruction exception
+    Reserved,
+    /// microMIPS instruction without a MIPS32 equivalent
+    MicroMips,
+    /// MIPS16e instruction without a MIPS32 equivalent
+    Mips16e,
 }
 
 impl std::fmt::Display for Instruction {
@@ -307,6 +426,9 @@ impl std::fmt::Display for Instruction {
                 self.opcode, self.rs, self.rt, self.imm
             ),
             InstructionKind::JType => write!(f, "opcode: 0b{:06b}, address: 0x{:08x}", self.opcode, self.address),
+            InstructionKind::Reserved => write!(f, "opcode: 0b{:06b}, word: 0x{:08x}", self.opcode, self.word),
+            InstructionKind::MicroMips => write!(f, "microMIPS opcode: 0b{:06b}, word: 0x{:08x}", self.opcode, self.word),
+            InstructionKind::Mips16e => write!(f, "MIPS16e opcode: 0b{:05b}, word: 0x{:08x}", self.opcode, self.word),
         }
     }
 }
@@ -315,25 +437,213 @@ impl std::fmt::Display for Instruction {
 pub enum Exception {
     #[default]
     None,
+    Interrupt,
+    /// syscall for the exception handler, or with a service the emulator does not provide
+    Syscall,
     Breakpoint,
     IntegerOverflow,
     Trap,
+    FloatingPoint,
+    /// Misaligned load or instruction fetch, or one from a kernel address outside kernel mode
+    AddressErrorLoad(u64),
+    /// Misaligned store, or one to a kernel address outside kernel mode
+    AddressErrorStore(u64),
+    /// Load from a page that is not readable
+    ReadInhibit(u64),
+    /// Instruction fetch from a page that is not executable
+    ExecuteInhibit(u64),
+    /// Store to a page that is not writable
+    WriteProtected(u64),
+    /// COP0 instruction outside kernel mode without CU0, with the coprocessor number
+    CoprocessorUnusable(u8),
+    /// TLB miss on a load or instruction fetch, with the faulting address
+    TlbRefillLoad(u64),
+    /// TLB miss on a store
+    TlbRefillStore(u64),
+    /// The matching TLB entry is not valid on a load or instruction fetch
+    TlbInvalidLoad(u64),
+    /// The matching TLB entry is not valid on a store
+    TlbInvalidStore(u64),
+    /// Store to a page whose TLB entry is not dirty
+    TlbModified(u64),
+    /// Unknown or unimplemented encoding
+    ReservedInstruction {
+        word: u32,
+        address: u64,
+    },
+    /// Instruction fetch from an address with no memory behind it
+    InstructionBusError(u64),
+    /// Load or store to an address with no memory behind it
+    DataBusError(u64),
+    /// Load or store just below the stack limit, a bus error to exception handlers
+    StackOverflow(u64),
+}
+
+/// How a call to step ended
+#[derive(Debug, Clone, PartialEq)]
+pub enum StepResult {
+    /// The instruction completed normally
+    Retired,
+    /// The program exited
+    Halted { exit_code: u32 },
+    /// The instruction raised an exception, pc is the faulting instruction
+    Exception { exception: Exception, pc: u64 },
+    /// A break instruction was executed
+    Breakpoint { pc: u64 },
+    /// The instruction accessed memory that does not exist
+    BusError { address: u64, pc: u64 },
+}
+
+/// Instruction set the CPU decodes, anything outside it is a Reserved Instruction
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum IsaLevel {
+    Mips1,
+    Mips2,
+    Mips32r1,
+    #[default]
+    Mips32r2,
+    Mips32r6,
+}
+
+impl IsaLevel {
+    pub const ALL: [IsaLevel; 5] = [
+        IsaLevel::Mips1,
+        IsaLevel::Mips2,
+        IsaLevel::Mips32r1,
+        IsaLevel::Mips32r2,
+        IsaLevel::Mips32r6,
+    ];
+
+    /**
+     * Traps, ll/sc, sqrt and ldc1/sdc1
+     */
+    fn has_mips2(self) -> bool {
+        self >= IsaLevel::Mips2
+    }
+
+    /**
+     * eret and the MIPS32 additions kept by Release 6
+     */
+    fn has_release1(self) -> bool {
+        self >= IsaLevel::Mips32r1
+    }
+
+    /**
+     * Rotates, SPECIAL3, di/ei and mfhc1/mthc1
+     */
+    fn has_release2(self) -> bool {
+        self >= IsaLevel::Mips32r2
+    }
+
+    /**
+     * Compact branches and the reassigned encodings
+     */
+    fn is_release6(self) -> bool {
+        self == IsaLevel::Mips32r6
+    }
+
+    /**
+     * Encodings that Release 6 removed or reassigned: hi/lo, lwl/lwr/swl/swr, ll/sc, addi
+     */
+    fn before_release6(self) -> bool {
+        self < IsaLevel::Mips32r6
+    }
+
+    /**
+     * Branch likely, from MIPS II until Release 6
+     */
+    fn has_branch_likely(self) -> bool {
+        self.has_mips2() && self.before_release6()
+    }
+
+    /**
+     * movz, movn, movf, movt and SPECIAL2, from MIPS32 Release 1 until Release 6
+     */
+    fn has_release1_only(self) -> bool {
+        self.has_release1() && self.before_release6()
+    }
+}
+
+impl std::fmt::Display for IsaLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            IsaLevel::Mips1 => write!(f, "MIPS I"),
+            IsaLevel::Mips2 => write!(f, "MIPS II"),
+            IsaLevel::Mips32r1 => write!(f, "MIPS32 Release 1"),
+            IsaLevel::Mips32r2 => write!(f, "MIPS32 Release 2"),
+            IsaLevel::Mips32r6 => write!(f, "MIPS32 Release 6"),
+        }
+    }
 }
 
 impl std::fmt::Display for Exception {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
             Exception::None => write!(f, "No exception"),
+            Exception::Interrupt => write!(f, "Interrupt"),
+            Exception::Syscall => write!(f, "System call"),
             Exception::Breakpoint => write!(f, "Breakpoint"),
             Exception::IntegerOverflow => write!(f, "Integer overflow"),
             Exception::Trap => write!(f, "Trap"),
+            Exception::FloatingPoint => write!(f, "Floating point exception"),
+            Exception::AddressErrorLoad(address) => {
+                write!(f, "Address error on load from 0x{:08x}", address)
+            }
+            Exception::AddressErrorStore(address) => {
+                write!(f, "Address error on store to 0x{:08x}", address)
+            }
+            Exception::CoprocessorUnusable(coprocessor) => {
+                write!(f, "Coprocessor {} unusable", coprocessor)
+            }
+            Exception::TlbRefillLoad(address) => {
+                write!(f, "TLB miss on load from 0x{:08x}", address)
+            }
+            Exception::TlbRefillStore(address) => {
+                write!(f, "TLB miss on store to 0x{:08x}", address)
+            }
+            Exception::TlbInvalidLoad(address) => {
+                write!(f, "Invalid TLB entry on load from 0x{:08x}", address)
+            }
+            Exception::TlbInvalidStore(address) => {
+                write!(f, "Invalid TLB entry on store to 0x{:08x}", address)
+            }
+            Exception::TlbModified(address) => {
+                write!(f, "Store to clean page at 0x{:08x}", address)
+            }
+            Exception::ReadInhibit(address) => {
+                write!(f, "Load from unreadable page at 0x{:08x}", address)
+            }
+            Exception::ExecuteInhibit(address) => {
+                write!(f, "Fetch from non-executable page at 0x{:08x}", address)
+            }
+            Exception::WriteProtected(address) => {
+                write!(f, "Store to read-only page at 0x{:08x}", address)
+            }
+            Exception::ReservedInstruction { word, address } => write!(
+                f,
+                "Reserved instruction 0x{:08x} at 0x{:08x}",
+                word, address
+            ),
+            Exception::InstructionBusError(address) => {
+                write!(f, "Bus error on fetch from 0x{:08x}", address)
+            }
+            Exception::DataBusError(address) => {
+                write!(f, "Bus error on access to 0x{:08x}", address)
+            }
+            Exception::StackOverflow(address) => {
+                write!(f, "Stack overflow on access to 0x{:08x}", address)
+            }
         }
     }
 }
 
 impl CPU {
-    pub fn fetch_instruction(&mut self, memory: &Memory) -> Instruction {
-        let instruction = memory.read_word(self.registers.pc);
+    /**
+     * Fetch and decode the MIPS32 instruction at the PC.
+     * Raises the exception and returns None when it cannot be fetched.
+     */
+    pub fn fetch_instruction(&mut self, memory: &Memory) -> Option<Instruction> {
+        let instruction = self.fetch_word(memory, self.registers.pc)?;
         let opcode = (instruction >> 26) as u8;
         let rs = ((instruction >> 21) & 0x1f) as u8;
         let rt = ((instruction >> 16) & 0x1f) as u8;
@@ -341,23 +651,65 @@ impl CPU {
         let shamt = ((instruction >> 6) & 0x1f) as u8;
         let funct = (instruction & 0x3f) as u8;
         let imm = instruction as u16;
-        let address = (instruction & 0x3ffffff) as u32;
+        let address = instruction & 0x3ffffff;
 
         let kind = match opcode {
             0b000000 => InstructionKind::RType, // Special
+            0b000001 => InstructionKind::IType, // Regimm
             0b000010 => InstructionKind::JType, // j
+            0b000011 => InstructionKind::JType, // jal
             0b000100 => InstructionKind::IType, // beq
             0b000101 => InstructionKind::IType, // bne
+            0b000110 => InstructionKind::IType, // blez
+            0b000111 => InstructionKind::IType, // bgtz
             0b001000 => InstructionKind::IType, // addi
             0b001001 => InstructionKind::IType, // addiu
+            0b001010 => InstructionKind::IType, // slti
+            0b001011 => InstructionKind::IType, // sltiu
             0b001100 => InstructionKind::IType, // andi
+            0b001101 => InstructionKind::IType, // ori
+            0b001110 => InstructionKind::IType, // xori
             0b001111 => InstructionKind::IType, // lui
+            0b010000 => InstructionKind::RType, // Cop0
+            0b010001 => InstructionKind::RType, // Cop1
             0b010100 => InstructionKind::IType, // beql
+            0b010101 => InstructionKind::IType, // bnel
+            0b010110 => InstructionKind::IType, // blezl
+            0b010111 => InstructionKind::IType, // bgtzl
+            0b011000 => InstructionKind::IType, // daddi, Release 6 pop30
+            0b011001 => InstructionKind::IType, // daddiu
+            0b011100 => InstructionKind::RType, // Special2
+            0b011101 => InstructionKind::JType, // jalx
+            0b011111 => InstructionKind::RType, // Special3
+            0b100000 => InstructionKind::IType, // lb
+            0b100001 => InstructionKind::IType, // lh
+            0b100010 => InstructionKind::IType, // lwl
             0b100011 => InstructionKind::IType, // lw
+            0b100100 => InstructionKind::IType, // lbu
+            0b100101 => InstructionKind::IType, // lhu
+            0b100110 => InstructionKind::IType, // lwr
+            0b100111 => InstructionKind::IType, // lwu
+            0b101000 => InstructionKind::IType, // sb
+            0b101001 => InstructionKind::IType, // sh
+            0b101010 => InstructionKind::IType, // swl
             0b101011 => InstructionKind::IType, // sw
-            _ => panic!("Unknown opcode: {:06b}", opcode),
+            0b101110 => InstructionKind::IType, // swr
+            0b110000 => InstructionKind::IType, // ll
+            0b110001 => InstructionKind::IType, // lwc1
+            0b110010 => InstructionKind::JType, // Release 6 bc
+            0b110101 => InstructionKind::IType, // ldc1
+            0b110110 => InstructionKind::IType, // Release 6 pop66
+            0b110111 => InstructionKind::IType, // ld
+            0b111000 => InstructionKind::IType, // sc
+            0b111001 => InstructionKind::IType, // swc1
+            0b111010 => InstructionKind::JType, // Release 6 balc
+            0b111011 => InstructionKind::IType, // Release 6 pcrel
+            0b111101 => InstructionKind::IType, // sdc1
+            0b111110 => InstructionKind::IType, // Release 6 pop76
+            0b111111 => InstructionKind::IType, // sd
+            _ => InstructionKind::Reserved,
         };
-        Instruction {
+        Some(Instruction {
             opcode,
             rs,
             rt,
@@ -366,69 +718,445 @@ impl CPU {
             funct,
             imm,
             address,
+            word: instruction,
             kind,
-        }
+        })
     }
 
     fn trigger_exception(&mut self, exception: Exception) {
-        self.exception = exception.clone();
-        self.halted = true;
+        self.llbit = false;
+        self.enter_exception(&exception);
+        self.raised = Some(exception);
+    }
+
+    /**
+     * Raise a Reserved Instruction exception for an encoding that is not implemented
+     */
+    fn reserved_instruction(&mut self, instruction: &Instruction) {
+        self.trigger_exception(Exception::ReservedInstruction {
+            word: instruction.word,
+            address: self.registers.pc,
+        });
+    }
+
+    /**
+     * Step until the CPU halts or `steps` instructions have run and return the result of the
+     * last step
+     */
+    pub fn run(&mut self, memory: &mut Memory, steps: usize) -> StepResult {
+        let mut result = StepResult::Retired;
+        for _ in 0..steps {
+            if self.halted {
+                break;
+            }
+            result = self.step(memory);
+        }
+        result
+    }
+
+    /**
+     * Transfer control to target.
+     * With delay slots the jump happens after the next instruction has executed.
+     */
+    fn branch(&mut self, target: u64) {
+        self.branch_address = self.registers.pc;
+        if self.delay_slots {
+            self.branch_target = Some(target);
+        } else {
+            self.set_pc(target);
+            self.jump = true;
+        }
     }
 
-    pub fn run(&mut self, memory: &mut Memory) {
-        while !self.halted {
-            self.step(memory);
+    /**
+     * Move the PC to a jump target.
+     * With the microMIPS or MIPS16e ASE the low bit of the target selects the ISA mode.
+     */
+    fn set_pc(&mut self, target: u64) {
+        if self.micromips_ase {
+            self.micromips = target & 1 != 0;
+            self.registers.pc = target & !1;
+        } else if self.mips16e_ase {
+            self.mips16e = target & 1 != 0;
+            self.registers.pc = target & !1;
+        } else {
+            self.registers.pc = target;
         }
     }
 
-    pub fn step(&mut self, memory: &mut Memory) {
-        let instruction = self.fetch_instruction(memory);
-        match instruction.opcode {
-            0b000000 => self.special(&instruction),
-            0b000010 => self.j(&instruction),
-            0b000100 => self.beq(&instruction),
-            0b000101 => self.bne(&instruction),
-            0b001000 => self.addi(&instruction),
-            0b001001 => self.addiu(&instruction),
-            0b001100 => self.andi(&instruction),
-            0b001111 => self.lui(&instruction),
-            0b010100 => self.beql(&instruction),
-            0b100011 => self.lw(&instruction, memory),
-            0b101011 => self.sw(&instruction, memory),
-            _ => panic!("Unknown instruction: {}", instruction),
-        }
-        if !self.jump {
-            self.registers.pc += 4;
+    /**
+     * Skip the delay slot of a branch likely that was not taken
+     */
+    fn nullify_delay_slot(&mut self) {
+        if self.delay_slots {
+            self.registers.pc = self.wrap_address(self.registers.pc.wrapping_add(8));
+            self.jump = true;
+        }
+    }
+
+    /**
+     * Return address saved by the linking jumps and branches
+     */
+    fn link_address(&self) -> u64 {
+        let delay_slot = match (self.delay_slots, self.short_delay_slot) {
+            (false, _) => 0,
+            (true, false) => 4,
+            (true, true) => 2,
+        };
+        let link = self
+            .registers
+            .pc
+            .wrapping_add((self.instruction_size + delay_slot) as u64);
+        // Returning to microMIPS or MIPS16e code needs the ISA mode bit
+        self.wrap_address(link) | self.compressed() as u64
+    }
+
+    /**
+     * Executing 16/32-bit microMIPS or MIPS16e code instead of MIPS32
+     */
+    fn compressed(&self) -> bool {
+        self.micromips || self.mips16e
+    }
+
+    /**
+     * Reads a GPR at machine width, sign-extended from the low word outside MIPS64 mode
+     */
+    fn read_gpr(&self, number: u8) -> i64 {
+        if self.mips64 {
+            self.registers.read_register64(number) as i64
+        } else {
+            self.registers.read_register(number) as i32 as i64
+        }
+    }
+
+    /**
+     * An address computed at machine width, wrapping around at 32 bits outside MIPS64 mode
+     */
+    fn wrap_address(&self, address: u64) -> u64 {
+        if self.mips64 {
+            address
+        } else {
+            address as u32 as u64
+        }
+    }
+
+    /**
+     * A 32-bit address such as an exception vector, sign-extended into the compatibility
+     * segments in MIPS64 mode
+     */
+    fn extend_address(&self, address: u32) -> u64 {
+        self.wrap_address(address as i32 as u64)
+    }
+
+    /**
+     * Reads a GPR holding an address, all 64 bits of it in MIPS64 mode
+     */
+    fn read_address(&self, number: u8) -> u64 {
+        self.wrap_address(self.read_gpr(number) as u64)
+    }
+
+    /**
+     * Writes an address such as a return address to a GPR, all 64 bits of it in MIPS64 mode
+     */
+    fn write_address(&mut self, number: u8, address: u64) {
+        if self.mips64 {
+            self.registers.write_register64(number, address);
+        } else {
+            self.registers.write_register(number, address as u32);
+        }
+    }
+
+    /**
+     * Target of a PC-relative branch, relative to the delay slot
+     */
+    fn branch_offset_target(&self, instruction: &Instruction) -> u64 {
+        if self.micromips {
+            // microMIPS offsets count halfwords
+            let offset = (instruction.imm as i16 as i64) << 1;
+            let target = self
+                .registers
+                .pc
+                .wrapping_add(self.instruction_size as u64)
+                .wrapping_add(offset as u64);
+            return self.wrap_address(target) | 1;
+        }
+        let offset = (instruction.imm as i16 as i64) << 2;
+        let target = self
+            .registers
+            .pc
+            .wrapping_add(4)
+            .wrapping_add(offset as u64);
+        self.wrap_address(target)
+    }
+
+    /**
+     * Target of j and jal, in the region of the delay slot.
+     * microMIPS jumps count halfwords and keep the upper 5 bits instead of 4.
+     * The MIPS16e jal stays in MIPS16e mode.
+     */
+    fn jump_target(&self, instruction: &Instruction) -> u64 {
+        let delay_slot = self.wrap_address(self.registers.pc.wrapping_add(4));
+        let index = instruction.address as u64;
+        if self.micromips {
+            delay_slot & !0x07ff_ffff | index << 1 | 1
+        } else {
+            delay_slot & !0x0fff_ffff | index << 2 | self.mips16e as u64
+        }
+    }
+
+    /**
+     * Address accessed by a load or store: base register plus signed offset
+     */
+    fn effective_address(&mut self, instruction: &Instruction) -> u64 {
+        let base = self.read_address(instruction.rs);
+        let offset = instruction.imm as i16 as i64;
+        self.wrap_address(base.wrapping_add(offset as u64))
+    }
+
+    /**
+     * Physical address of a load of size bytes.
+     * Raises an address error when it is not naturally aligned, or a TLB or protection exception
+     * when it cannot be accessed, and returns None.
+     */
+    fn load_address(
+        &mut self,
+        instruction: &Instruction,
+        size: u64,
+        memory: &Memory,
+    ) -> Option<u64> {
+        let address = self.effective_address(instruction);
+        if address & (size - 1) != 0 {
+            self.trigger_exception(Exception::AddressErrorLoad(address));
+            return None;
+        }
+        self.translate(address, Access::Load, memory)
+    }
+
+    /**
+     * Physical address of a store of size bytes.
+     * Raises an address error when it is not naturally aligned, or a TLB or protection exception
+     * when it cannot be accessed, and returns None.
+     */
+    fn store_address(
+        &mut self,
+        instruction: &Instruction,
+        size: u64,
+        memory: &Memory,
+    ) -> Option<u64> {
+        let address = self.effective_address(instruction);
+        if address & (size - 1) != 0 {
+            self.trigger_exception(Exception::AddressErrorStore(address));
+            return None;
+        }
+        self.translate(address, Access::Store, memory)
+    }
+
+    /**
+     * Execute one instruction and report how it ended
+     */
+    pub fn step(&mut self, memory: &mut Memory) -> StepResult {
+        let pc = self.registers.pc;
+        self.execute(memory);
+        if self.raised.is_none() {
+            self.tick_timer();
+        }
+        match self.raised.take() {
+            Some(Exception::Breakpoint) => StepResult::Breakpoint { pc },
+            Some(Exception::InstructionBusError(address) | Exception::DataBusError(address)) => {
+                StepResult::BusError { address, pc }
+            }
+            Some(exception) => StepResult::Exception { exception, pc },
+            None if self.halted => StepResult::Halted {
+                exit_code: self.exit_code,
+            },
+            None => StepResult::Retired,
+        }
+    }
+
+    fn execute(&mut self, memory: &mut Memory) {
+        let delayed_branch = self.branch_target.take();
+        self.in_delay_slot = delayed_branch.is_some();
+        if self.take_interrupt() {
+            self.jump = false;
+            return;
+        }
+        if !self.compressed() && self.registers.pc & 3 != 0 {
+            self.trigger_exception(Exception::AddressErrorLoad(self.registers.pc));
+            self.jump = false;
+            return;
+        }
+        let fetched = if self.micromips {
+            self.fetch_micromips(memory)
+        } else if self.mips16e {
+            self.fetch_mips16e(memory)
+        } else {
+            self.instruction_size = 4;
+            self.short_delay_slot = false;
+            self.fetch_instruction(memory)
+        };
+        let Some(instruction) = fetched else {
+            self.jump = false;
+            return;
+        };
+        match instruction.kind {
+            InstructionKind::MicroMips => self.micromips_native(&instruction, memory),
+            InstructionKind::Mips16e => self.mips16e_native(&instruction, memory),
+            _ => self.dispatch(&instruction, memory),
+        }
+        if self.jump {
+            // The PC was set directly, by an exception or a branch without delay slot
+        } else if let Some(target) = delayed_branch {
+            self.set_pc(target);
+        } else {
+            let next = self.registers.pc.wrapping_add(self.instruction_size as u64);
+            self.registers.pc = self.wrap_address(next);
         }
         self.jump = false;
     }
 
+    /**
+     * Execute a decoded MIPS32 instruction
+     */
+    fn dispatch(&mut self, instruction: &Instruction, memory: &mut Memory) {
+        let isa = self.isa;
+        match instruction.opcode {
+            0b000000 => self.special(instruction, memory),
+            0b000001 => self.regimm(instruction),
+            0b000010 => self.j(instruction),
+            0b000011 => self.jal(instruction),
+            0b000100 => self.beq(instruction),
+            0b000101 => self.bne(instruction),
+            0b000110 if isa.is_release6() && instruction.rt != 0 => self.pop06(instruction),
+            0b000110 => self.blez(instruction),
+            0b000111 if isa.is_release6() && instruction.rt != 0 => self.pop07(instruction),
+            0b000111 => self.bgtz(instruction),
+            0b001000 if isa.is_release6() => self.pop10(instruction),
+            0b001000 => self.addi(instruction),
+            0b001001 => self.addiu(instruction),
+            0b001010 => self.slti(instruction),
+            0b001011 => self.sltiu(instruction),
+            0b001100 => self.andi(instruction),
+            0b001101 => self.ori(instruction),
+            0b001110 => self.xori(instruction),
+            0b001111 if isa.is_release6() && instruction.rs != 0 => self.aui(instruction),
+            0b001111 => self.lui(instruction),
+            0b010000 => self.cop0(instruction),
+            0b010001 => self.cop1(instruction),
+            0b010100 if isa.has_branch_likely() => self.beql(instruction),
+            0b010101 if isa.has_branch_likely() => self.bnel(instruction),
+            0b010110 if isa.is_release6() => self.pop26(instruction),
+            0b010110 if isa.has_branch_likely() => self.blezl(instruction),
+            0b010111 if isa.is_release6() => self.pop27(instruction),
+            0b010111 if isa.has_branch_likely() => self.bgtzl(instruction),
+            0b011000 if isa.is_release6() => self.pop30(instruction),
+            0b011000 if self.mips64 => self.daddi(instruction),
+            0b011001 if self.mips64 => self.daddiu(instruction),
+            0b011100 if isa.has_release1_only() => self.special2(instruction),
+            0b011101 if (self.micromips_ase || self.mips16e_ase) && isa.before_release6() => {
+                self.jalx(instruction)
+            }
+            0b011111 if isa.has_release2() => self.special3(instruction, memory),
+            0b110010 if isa.is_release6() => self.bc(instruction),
+            0b110110 if isa.is_release6() => self.pop66(instruction),
+            0b111010 if isa.is_release6() => self.balc(instruction),
+            0b111110 if isa.is_release6() => self.pop76(instruction),
+            0b100000..=0b111111 => {
+                if let Err(error) = self.load_store(instruction, memory) {
+                    self.memory_fault(error);
+                }
+            }
+            _ => self.reserved_instruction(instruction),
+        }
+    }
+
+    /**
+     * Raise the exception for a load or store that memory could not satisfy
+     */
+    fn memory_fault(&mut self, error: MemoryError) {
+        let exception = match error {
+            MemoryError::StackOverflow(address) => Exception::StackOverflow(address),
+            MemoryError::Unmapped(address) => Exception::DataBusError(address),
+        };
+        self.trigger_exception(exception);
+    }
+
+    /**
+     * Loads and stores
+     * opcode: 0b100000 to 0b111111
+     */
+    fn load_store(
+        &mut self,
+        instruction: &Instruction,
+        memory: &mut Memory,
+    ) -> Result<(), MemoryError> {
+        let isa = self.isa;
+        match instruction.opcode {
+            0b100000 => self.lb(instruction, memory),
+            0b100001 => self.lh(instruction, memory),
+            0b100010 if isa.before_release6() => self.lwl(instruction, memory),
+            0b100011 => self.lw(instruction, memory),
+            0b100100 => self.lbu(instruction, memory),
+            0b100101 => self.lhu(instruction, memory),
+            0b100110 if isa.before_release6() => self.lwr(instruction, memory),
+            0b101000 => self.sb(instruction, memory),
+            0b101001 => self.sh(instruction, memory),
+            0b101010 if isa.before_release6() => self.swl(instruction, memory),
+            0b101011 => self.sw(instruction, memory),
+            0b100111 if self.mips64 => self.lwu(instruction, memory),
+            0b101110 if isa.before_release6() => self.swr(instruction, memory),
+            0b110000 if isa.has_mips2() && isa.before_release6() => self.ll(instruction, memory),
+            0b110001 => self.lwc1(instruction, memory),
+            0b110101 if isa.has_mips2() => self.ldc1(instruction, memory),
+            0b110111 if self.mips64 => self.ld(instruction, memory),
+            0b111000 if isa.has_mips2() && isa.before_release6() => self.sc(instruction, memory),
+            0b111001 => self.swc1(instruction, memory),
+            0b111011 if isa.is_release6() => self.pcrel(instruction, memory),
+            0b111101 if isa.has_mips2() => self.sdc1(instruction, memory),
+            0b111111 if self.mips64 => self.sd(instruction, memory),
+            _ => {
+                self.reserved_instruction(instruction);
+                Ok(())
+            }
+        }
+    }
+
     /**
      * r-type
      * opcode: 0b000000
      */
-    fn special(&mut self, instruction: &Instruction) {
+    fn special(&mut self, instruction: &Instruction, memory: &mut Memory) {
+        let isa = self.isa;
         match instruction.funct {
             0b000000 => self.sll(instruction),
+            0b000001 if isa.has_release1_only() => self.movci(instruction),
             0b000010 => self.srl(instruction),
             0b000011 => self.sra(instruction),
             0b000100 => self.sllv(instruction),
+            0b000101 if isa.is_release6() => self.lsa(instruction),
             0b000110 => self.srlv(instruction),
             0b000111 => self.srav(instruction),
-            0b001000 => self.jr(instruction),
+            // Release 6 jr is jalr with rd zero
+            0b001000 if isa.before_release6() => self.jr(instruction),
             0b001001 => self.jalr(instruction),
-            0b001010 => self.movz(instruction),
-            0b001011 => self.movn(instruction),
-            0b001100 => self.syscall(),
+            0b001010 if isa.has_release1_only() => self.movz(instruction),
+            0b001011 if isa.has_release1_only() => self.movn(instruction),
+            0b001100 => self.syscall(memory),
             0b001101 => self.breakpoint(),
-            0b010000 => self.mfhi(instruction),
-            0b010001 => self.mthi(instruction),
-            0b010010 => self.mflo(instruction),
-            0b010011 => self.mtlo(instruction),
-            0b011000 => self.mult(instruction),
-            0b011001 => self.multu(instruction),
-            0b011010 => self.div(instruction),
-            0b011011 => self.divu(instruction),
+            0b001111 if isa.has_mips2() => self.sync(),
+            0b010000 if isa.before_release6() => self.mfhi(instruction),
+            0b010000 if instruction.shamt == 1 => self.clz(instruction),
+            0b010001 if isa.before_release6() => self.mthi(instruction),
+            0b010001 if instruction.shamt == 1 => self.clo(instruction),
+            0b010010 if isa.before_release6() => self.mflo(instruction),
+            0b010011 if isa.before_release6() => self.mtlo(instruction),
+            0b011000 if isa.before_release6() => self.mult(instruction),
+            0b011000 => self.mul_muh(instruction),
+            0b011001 if isa.before_release6() => self.multu(instruction),
+            0b011001 => self.mulu_muhu(instruction),
+            0b011010 if isa.before_release6() => self.div(instruction),
+            0b011010 => self.div_mod(instruction),
+            0b011011 if isa.before_release6() => self.divu(instruction),
+            0b011011 => self.divu_modu(instruction),
             0b100000 => self.add(instruction),
             0b100001 => self.addu(instruction),
             0b100010 => self.sub(instruction),
@@ -439,13 +1167,65 @@ impl CPU {
             0b100111 => self.nor(instruction),
             0b101010 => self.slt(instruction),
             0b101011 => self.sltu(instruction),
-            0b110000 => self.tge(instruction),
-            0b110001 => self.tgeu(instruction),
-            0b110010 => self.tlt(instruction),
-            0b110011 => self.tltu(instruction),
-            0b110100 => self.teq(instruction),
-            0b110110 => self.tne(instruction),
-            _ => panic!("Unknown SPECIAL instruction: {}", instruction),
+            0b110000 if isa.has_mips2() => self.tge(instruction),
+            0b110001 if isa.has_mips2() => self.tgeu(instruction),
+            0b110010 if isa.has_mips2() => self.tlt(instruction),
+            0b110011 if isa.has_mips2() => self.tltu(instruction),
+            0b110100 if isa.has_mips2() => self.teq(instruction),
+            0b110101 if isa.is_release6() => self.seleqz(instruction),
+            0b110110 if isa.has_mips2() => self.tne(instruction),
+            0b110111 if isa.is_release6() => self.selnez(instruction),
+            _ if self.mips64 => self.special64(instruction),
+            _ => self.reserved_instruction(instruction),
+        }
+    }
+
+    /**
+     * r-type
+     * opcode: 0b011100
+     */
+    fn special2(&mut self, instruction: &Instruction) {
+        match instruction.funct {
+            0b000000 => self.madd(instruction),
+            0b000001 => self.maddu(instruction),
+            0b000010 => self.mul(instruction),
+            0b000100 => self.msub(instruction),
+            0b000101 => self.msubu(instruction),
+            0b100000 => self.clz(instruction),
+            0b100001 => self.clo(instruction),
+            _ => self.reserved_instruction(instruction),
+        }
+    }
+
+    /**
+     * r-type
+     * opcode: 0b011111
+     */
+    fn special3(&mut self, instruction: &Instruction, memory: &mut Memory) {
+        match instruction.funct {
+            0b000000 => self.ext(instruction),
+            0b000100 => self.ins(instruction),
+            0b100000 => self.bshfl(instruction),
+            0b100110 | 0b110110 if self.isa.is_release6() => {
+                if let Err(error) = self.ll_sc(instruction, memory) {
+                    self.memory_fault(error);
+                }
+            }
+            _ => self.reserved_instruction(instruction),
+        }
+    }
+
+    /**
+     * r-type, byte shuffles selected by shamt
+     * opcode: 0b011111
+     * funct: 0b100000
+     */
+    fn bshfl(&mut self, instruction: &Instruction) {
+        match instruction.shamt {
+            0b00010 => self.wsbh(instruction),
+            0b10000 => self.seb(instruction),
+            0b11000 => self.seh(instruction),
+            _ => self.reserved_instruction(instruction),
         }
     }
 
@@ -460,12 +1240,29 @@ impl CPU {
         self.registers.write_register(instruction.rd, rt << shamt);
     }
 
+    /**
+     * Move conditional on floating point false or true
+     * opcode: 0b000000
+     * funct: 0b000001
+     */
+    fn movci(&mut self, instruction: &Instruction) {
+        let cc = instruction.rt >> 2;
+        let expected = instruction.rt & 1 != 0;
+        if self.float_registers.condition(cc) == expected {
+            let rs = self.registers.read_register64(instruction.rs);
+            self.registers.write_register64(instruction.rd, rs);
+        }
+    }
+
     /**
      * Shift right logical
      * opcode: 0b000000
      * funct: 0b000010
      */
     fn srl(&mut self, instruction: &Instruction) {
+        if instruction.rs != 0 && !self.isa.has_release2() {
+            return self.reserved_instruction(instruction);
+        }
         let rt = self.registers.read_register(instruction.rt);
         let shamt = instruction.shamt;
         let result = if instruction.rs == 0 {
@@ -508,6 +1305,9 @@ impl CPU {
      * funct: 0b000110
      */
     fn srlv(&mut self, instruction: &Instruction) {
+        if instruction.shamt != 0 && !self.isa.has_release2() {
+            return self.reserved_instruction(instruction);
+        }
         let rt = self.registers.read_register(instruction.rt);
         let rs = self.registers.read_register(instruction.rs);
         let shamt = rs & 0x1f;
@@ -538,9 +1338,8 @@ impl CPU {
      * funct: 0b001000
      */
     fn jr(&mut self, instruction: &Instruction) {
-        let rs = self.registers.read_register(instruction.rs);
-        self.registers.pc = rs;
-        self.jump = true;
+        let rs = self.read_address(instruction.rs);
+        self.branch(rs);
     }
 
     /**
@@ -549,11 +1348,10 @@ impl CPU {
      * funct: 0b001001
      */
     fn jalr(&mut self, instruction: &Instruction) {
-        let rs = self.registers.read_register(instruction.rs);
-        self.registers
-            .write_register(instruction.rd, self.registers.pc + 4);
-        self.registers.pc = rs;
-        self.jump = true;
+        let rs = self.read_address(instruction.rs);
+        let link = self.link_address();
+        self.write_address(instruction.rd, link);
+        self.branch(rs);
     }
 
     /**
@@ -562,10 +1360,10 @@ impl CPU {
      * funct: 0b001010
      */
     fn movz(&mut self, instruction: &Instruction) {
-        let rs = self.registers.read_register(instruction.rs);
-        let rt = self.registers.read_register(instruction.rt);
+        let rs = self.registers.read_register64(instruction.rs);
+        let rt = self.read_gpr(instruction.rt);
         if rt == 0 {
-            self.registers.write_register(instruction.rd, rs);
+            self.registers.write_register64(instruction.rd, rs);
         }
     }
 
@@ -575,10 +1373,10 @@ impl CPU {
      * funct: 0b001011
      */
     fn movn(&mut self, instruction: &Instruction) {
-        let rs = self.registers.read_register(instruction.rs);
-        let rt = self.registers.read_register(instruction.rt);
+        let rs = self.registers.read_register64(instruction.rs);
+        let rt = self.read_gpr(instruction.rt);
         if rt != 0 {
-            self.registers.write_register(instruction.rd, rs);
+            self.registers.write_register64(instruction.rd, rs);
         }
     }
 
@@ -586,11 +1384,30 @@ impl CPU {
      * System call
      * opcode: 0b000000
      * funct: 0b001100
+     * A loaded exception handler, or code outside kernel mode, gets a System Call exception.
+     * Otherwise the emulator provides the SPIM services itself.
      */
-    fn syscall(&mut self) {
+    fn syscall(&mut self, memory: &mut Memory) {
+        if self.exception_handler || self.control_registers.mode() != Mode::Kernel {
+            return self.trigger_exception(Exception::Syscall);
+        }
         match self.registers.v0 {
-            10 => self.halted = true,
-            _ => panic!("Unknown system call: {}", self.registers.v0),
+            // sbrk, $v0 gets the old break or -1 when the heap limit is reached
+            9 => match memory.sbrk(self.read_gpr(4)) {
+                Some(address) => self.write_address(2, address),
+                None => self.registers.write_register(2, u32::MAX),
+            },
+            // exit
+            10 => {
+                self.exit_code = 0;
+                self.halted = true;
+            }
+            // exit2
+            17 => {
+                self.exit_code = self.registers.a0;
+                self.halted = true;
+            }
+            _ => self.trigger_exception(Exception::Syscall),
         }
     }
 
@@ -600,18 +1417,25 @@ impl CPU {
      * funct: 0b001101
      */
     fn breakpoint(&mut self) {
-        self.halted = true;
         self.trigger_exception(Exception::Breakpoint);
     }
 
+    /**
+     * Synchronize shared memory.
+     * Every access completes before the next instruction starts, so there is nothing to order.
+     * opcode: 0b000000
+     * funct: 0b001111
+     */
+    fn sync(&mut self) {}
+
     /**
      * Move from HI
      * opcode: 0b000000
      * funct: 0b010000
      */
     fn mfhi(&mut self, instruction: &Instruction) {
-        self.registers
-            .write_register(instruction.rd, self.registers.hi);
+        let value = self.registers.hi64();
+        self.registers.write_register64(instruction.rd, value);
     }
 
     /**
@@ -620,7 +1444,9 @@ impl CPU {
      * funct: 0b010001
      */
     fn mthi(&mut self, instruction: &Instruction) {
-        self.registers.hi = self.registers.read_register(instruction.rs);
+        let value = self.registers.read_register64(instruction.rs);
+        self.registers.hi = value as u32;
+        self.registers.hi_upper = (value >> 32) as u32;
     }
 
     /**
@@ -629,8 +1455,8 @@ impl CPU {
      * funct: 0b010010
      */
     fn mflo(&mut self, instruction: &Instruction) {
-        self.registers
-            .write_register(instruction.rd, self.registers.lo);
+        let value = self.registers.lo64();
+        self.registers.write_register64(instruction.rd, value);
     }
 
     /**
@@ -639,7 +1465,9 @@ impl CPU {
      * funct: 0b010011
      */
     fn mtlo(&mut self, instruction: &Instruction) {
-        self.registers.lo = self.registers.read_register(instruction.rs);
+        let value = self.registers.read_register64(instruction.rs);
+        self.registers.lo = value as u32;
+        self.registers.lo_upper = (value >> 32) as u32;
     }
 
     /**
@@ -651,8 +1479,8 @@ impl CPU {
         let rs = self.registers.read_register(instruction.rs) as i64;
         let rt = self.registers.read_register(instruction.rt) as i64;
         let result = rs * rt;
-        self.registers.hi = (result >> 32) as u32;
-        self.registers.lo = result as u32;
+        self.registers
+            .write_hi_lo((result >> 32) as u32, result as u32);
     }
 
     /**
@@ -664,8 +1492,8 @@ impl CPU {
         let rs = self.registers.read_register(instruction.rs) as u64;
         let rt = self.registers.read_register(instruction.rt) as u64;
         let result = rs * rt;
-        self.registers.hi = (result >> 32) as u32;
-        self.registers.lo = result as u32;
+        self.registers
+            .write_hi_lo((result >> 32) as u32, result as u32);
     }
 
     /**
@@ -677,11 +1505,10 @@ impl CPU {
         let rs = self.registers.read_register(instruction.rs) as i32;
         let rt = self.registers.read_register(instruction.rt) as i32;
         if rt == 0 {
-            self.registers.hi = 0;
-            self.registers.lo = 0;
+            self.registers.write_hi_lo(0, 0);
         } else {
-            self.registers.hi = (rs % rt) as u32;
-            self.registers.lo = (rs / rt) as u32;
+            self.registers
+                .write_hi_lo((rs % rt) as u32, (rs / rt) as u32);
         }
     }
 
@@ -694,11 +1521,9 @@ impl CPU {
         let rs = self.registers.read_register(instruction.rs);
         let rt = self.registers.read_register(instruction.rt);
         if rt == 0 {
-            self.registers.hi = 0;
-            self.registers.lo = 0;
+            self.registers.write_hi_lo(0, 0);
         } else {
-            self.registers.hi = (rs % rt) as u32;
-            self.registers.lo = (rs / rt) as u32;
+            self.registers.write_hi_lo(rs % rt, rs / rt);
         }
     }
 
@@ -710,11 +1535,10 @@ impl CPU {
     fn add(&mut self, instruction: &Instruction) {
         let rs = self.registers.read_register(instruction.rs) as i32;
         let rt = self.registers.read_register(instruction.rt) as i32;
-        if rs.checked_add(rt).is_none() {
-            self.trigger_exception(Exception::IntegerOverflow);
+        match rs.checked_add(rt) {
+            Some(result) => self.registers.write_register(instruction.rd, result as u32),
+            None => self.trigger_exception(Exception::IntegerOverflow),
         }
-        self.registers
-            .write_register(instruction.rd, (rs + rt) as u32);
     }
 
     /**
@@ -737,11 +1561,10 @@ impl CPU {
     fn sub(&mut self, instruction: &Instruction) {
         let rs = self.registers.read_register(instruction.rs) as i32;
         let rt = self.registers.read_register(instruction.rt) as i32;
-        if rs.checked_sub(rt).is_none() {
-            self.trigger_exception(Exception::IntegerOverflow);
+        match rs.checked_sub(rt) {
+            Some(result) => self.registers.write_register(instruction.rd, result as u32),
+            None => self.trigger_exception(Exception::IntegerOverflow),
         }
-        self.registers
-            .write_register(instruction.rd, (rs - rt) as u32);
     }
 
     /**
@@ -762,9 +1585,9 @@ impl CPU {
      * funct: 0b100100
      */
     fn and(&mut self, instruction: &Instruction) {
-        let rs = self.registers.read_register(instruction.rs);
-        let rt = self.registers.read_register(instruction.rt);
-        self.registers.write_register(instruction.rd, rs & rt);
+        let rs = self.registers.read_register64(instruction.rs);
+        let rt = self.registers.read_register64(instruction.rt);
+        self.registers.write_register64(instruction.rd, rs & rt);
     }
 
     /**
@@ -773,9 +1596,9 @@ impl CPU {
      * funct: 0b100101
      */
     fn or(&mut self, instruction: &Instruction) {
-        let rs = self.registers.read_register(instruction.rs);
-        let rt = self.registers.read_register(instruction.rt);
-        self.registers.write_register(instruction.rd, rs | rt);
+        let rs = self.registers.read_register64(instruction.rs);
+        let rt = self.registers.read_register64(instruction.rt);
+        self.registers.write_register64(instruction.rd, rs | rt);
     }
 
     /**
@@ -784,9 +1607,9 @@ impl CPU {
      * funct: 0b100110
      */
     fn xor(&mut self, instruction: &Instruction) {
-        let rs = self.registers.read_register(instruction.rs);
-        let rt = self.registers.read_register(instruction.rt);
-        self.registers.write_register(instruction.rd, rs ^ rt);
+        let rs = self.registers.read_register64(instruction.rs);
+        let rt = self.registers.read_register64(instruction.rt);
+        self.registers.write_register64(instruction.rd, rs ^ rt);
     }
 
     /**
@@ -795,9 +1618,9 @@ impl CPU {
      * funct: 0b100111
      */
     fn nor(&mut self, instruction: &Instruction) {
-        let rs = self.registers.read_register(instruction.rs);
-        let rt = self.registers.read_register(instruction.rt);
-        self.registers.write_register(instruction.rd, !(rs | rt));
+        let rs = self.registers.read_register64(instruction.rs);
+        let rt = self.registers.read_register64(instruction.rt);
+        self.registers.write_register64(instruction.rd, !(rs | rt));
     }
 
     /**
@@ -806,8 +1629,8 @@ impl CPU {
      * funct: 0b101010
      */
     fn slt(&mut self, instruction: &Instruction) {
-        let rs = self.registers.read_register(instruction.rs) as i32;
-        let rt = self.registers.read_register(instruction.rt) as i32;
+        let rs = self.read_gpr(instruction.rs);
+        let rt = self.read_gpr(instruction.rt);
         self.registers
             .write_register(instruction.rd, (rs < rt) as u32);
     }
@@ -818,8 +1641,8 @@ impl CPU {
      * funct: 0b101011
      */
     fn sltu(&mut self, instruction: &Instruction) {
-        let rs = self.registers.read_register(instruction.rs);
-        let rt = self.registers.read_register(instruction.rt);
+        let rs = self.read_gpr(instruction.rs) as u64;
+        let rt = self.read_gpr(instruction.rt) as u64;
         self.registers
             .write_register(instruction.rd, (rs < rt) as u32);
     }
@@ -830,8 +1653,8 @@ impl CPU {
      * funct: 0b110000
      */
     fn tge(&mut self, instruction: &Instruction) {
-        let rs = self.registers.read_register(instruction.rs) as i32;
-        let rt = self.registers.read_register(instruction.rt) as i32;
+        let rs = self.read_gpr(instruction.rs);
+        let rt = self.read_gpr(instruction.rt);
         if rs >= rt {
             self.trigger_exception(Exception::Trap);
         }
@@ -845,8 +1668,8 @@ impl CPU {
     fn tgeu(&mut self, instruction: &Instruction) {
         println!("tgeu");
         println!("{}", instruction);
-        let rs = self.registers.read_register(instruction.rs);
-        let rt = self.registers.read_register(instruction.rt);
+        let rs = self.read_gpr(instruction.rs) as u64;
+        let rt = self.read_gpr(instruction.rt) as u64;
         println!("rs: {}, rt: {}", rs, rt);
         if rs >= rt {
             self.trigger_exception(Exception::Trap);
@@ -859,8 +1682,8 @@ impl CPU {
      * funct: 0b110010
      */
     fn tlt(&mut self, instruction: &Instruction) {
-        let rs = self.registers.read_register(instruction.rs) as i32;
-        let rt = self.registers.read_register(instruction.rt) as i32;
+        let rs = self.read_gpr(instruction.rs);
+        let rt = self.read_gpr(instruction.rt);
         if rs < rt {
             self.trigger_exception(Exception::Trap);
         }
@@ -872,8 +1695,8 @@ impl CPU {
      * funct: 0b110011
      */
     fn tltu(&mut self, instruction: &Instruction) {
-        let rs = self.registers.read_register(instruction.rs);
-        let rt = self.registers.read_register(instruction.rt);
+        let rs = self.read_gpr(instruction.rs) as u64;
+        let rt = self.read_gpr(instruction.rt) as u64;
         if rs < rt {
             self.trigger_exception(Exception::Trap);
         }
@@ -885,8 +1708,8 @@ impl CPU {
      * funct: 0b110100
      */
     fn teq(&mut self, instruction: &Instruction) {
-        let rs = self.registers.read_register(instruction.rs);
-        let rt = self.registers.read_register(instruction.rt);
+        let rs = self.read_gpr(instruction.rs) as u64;
+        let rt = self.read_gpr(instruction.rt) as u64;
         if rs == rt {
             self.trigger_exception(Exception::Trap);
         }
@@ -898,22 +1721,173 @@ impl CPU {
      * funct: 0b110110
      */
     fn tne(&mut self, instruction: &Instruction) {
-        let rs = self.registers.read_register(instruction.rs);
-        let rt = self.registers.read_register(instruction.rt);
+        let rs = self.read_gpr(instruction.rs) as u64;
+        let rt = self.read_gpr(instruction.rt) as u64;
         if rs != rt {
             self.trigger_exception(Exception::Trap);
         }
     }
 
+    /**
+     * regimm, branches selected by rt
+     * opcode: 0b000001
+     */
+    fn regimm(&mut self, instruction: &Instruction) {
+        let isa = self.isa;
+        // Release 6 keeps only the unconditional forms nal and bal
+        let linking = isa.before_release6() || instruction.rs == 0;
+        match instruction.rt {
+            0b00000 => self.bltz(instruction),
+            0b00001 => self.bgez(instruction),
+            0b00010 if isa.has_branch_likely() => self.bltzl(instruction),
+            0b00011 if isa.has_branch_likely() => self.bgezl(instruction),
+            0b10000 if linking => self.bltzal(instruction),
+            0b10001 if linking => self.bgezal(instruction),
+            0b10010 if isa.has_branch_likely() => self.bltzall(instruction),
+            0b10011 if isa.has_branch_likely() => self.bgezall(instruction),
+            _ => self.reserved_instruction(instruction),
+        }
+    }
+
+    /**
+     * Branch on less than zero
+     * opcode: 0b000001
+     * rt: 0b00000
+     */
+    fn bltz(&mut self, instruction: &Instruction) {
+        let rs = self.read_gpr(instruction.rs);
+        if rs < 0 {
+            let target = self.branch_offset_target(instruction);
+            self.branch(target);
+        }
+    }
+
+    /**
+     * Branch on greater than or equal to zero
+     * opcode: 0b000001
+     * rt: 0b00001
+     */
+    fn bgez(&mut self, instruction: &Instruction) {
+        let rs = self.read_gpr(instruction.rs);
+        if rs >= 0 {
+            let target = self.branch_offset_target(instruction);
+            self.branch(target);
+        }
+    }
+
+    /**
+     * Branch on less than zero likely
+     * opcode: 0b000001
+     * rt: 0b00010
+     */
+    fn bltzl(&mut self, instruction: &Instruction) {
+        let rs = self.read_gpr(instruction.rs);
+        if rs < 0 {
+            let target = self.branch_offset_target(instruction);
+            self.branch(target);
+        } else {
+            self.nullify_delay_slot();
+        }
+    }
+
+    /**
+     * Branch on greater than or equal to zero likely
+     * opcode: 0b000001
+     * rt: 0b00011
+     */
+    fn bgezl(&mut self, instruction: &Instruction) {
+        let rs = self.read_gpr(instruction.rs);
+        if rs >= 0 {
+            let target = self.branch_offset_target(instruction);
+            self.branch(target);
+        } else {
+            self.nullify_delay_slot();
+        }
+    }
+
+    /**
+     * Branch on less than zero and link
+     * opcode: 0b000001
+     * rt: 0b10000
+     */
+    fn bltzal(&mut self, instruction: &Instruction) {
+        let rs = self.read_gpr(instruction.rs);
+        // The return address is written whether or not the branch is taken
+        let link = self.link_address();
+        self.write_address(31, link);
+        if rs < 0 {
+            let target = self.branch_offset_target(instruction);
+            self.branch(target);
+        }
+    }
+
+    /**
+     * Branch on greater than or equal to zero and link
+     * opcode: 0b000001
+     * rt: 0b10001
+     */
+    fn bgezal(&mut self, instruction: &Instruction) {
+        let rs = self.read_gpr(instruction.rs);
+        let link = self.link_address();
+        self.write_address(31, link);
+        if rs >= 0 {
+            let target = self.branch_offset_target(instruction);
+            self.branch(target);
+        }
+    }
+
+    /**
+     * Branch on less than zero and link likely
+     * opcode: 0b000001
+     * rt: 0b10010
+     */
+    fn bltzall(&mut self, instruction: &Instruction) {
+        let rs = self.read_gpr(instruction.rs);
+        let link = self.link_address();
+        self.write_address(31, link);
+        if rs < 0 {
+            let target = self.branch_offset_target(instruction);
+            self.branch(target);
+        } else {
+            self.nullify_delay_slot();
+        }
+    }
+
+    /**
+     * Branch on greater than or equal to zero and link likely
+     * opcode: 0b000001
+     * rt: 0b10011
+     */
+    fn bgezall(&mut self, instruction: &Instruction) {
+        let rs = self.read_gpr(instruction.rs);
+        let link = self.link_address();
+        self.write_address(31, link);
+        if rs >= 0 {
+            let target = self.branch_offset_target(instruction);
+            self.branch(target);
+        } else {
+            self.nullify_delay_slot();
+        }
+    }
+
     /**
      * Jump
      * opcode: 0b000010
      */
     fn j(&mut self, instruction: &Instruction) {
-        self.jump = true;
-        let target = instruction.address << 2;
-        // Keep the upper 4 bits of the PC
-        self.registers.pc = (self.registers.pc & 0xf0000000) | target;
+        let target = self.jump_target(instruction);
+        self.branch(target);
+    }
+
+    /**
+     * Jump and link
+     * opcode: 0b000011
+     */
+    fn jal(&mut self, instruction: &Instruction) {
+        let target = self.jump_target(instruction);
+        let link = self.link_address();
+        self.write_address(31, link);
+        self.branch(target);
     }
 
     /**
@@ -921,11 +1895,11 @@ impl CPU {
      * opcode: 0b000100
      */
     fn beq(&mut self, instruction: &Instruction) {
-        let rs = self.registers.read_register(instruction.rs);
-        let rt = self.registers.read_register(instruction.rt);
+        let rs = self.read_gpr(instruction.rs) as u64;
+        let rt = self.read_gpr(instruction.rt) as u64;
         if rs == rt {
-            let target = (instruction.imm as i16 as i32) * 4;
-            self.registers.pc = (self.registers.pc as i32 + target) as u32;
+            let target = self.branch_offset_target(instruction);
+            self.branch(target);
         }
     }
 
@@ -934,11 +1908,35 @@ impl CPU {
      * opcode: 0b000101
      */
     fn bne(&mut self, instruction: &Instruction) {
-        let rs = self.registers.read_register(instruction.rs);
-        let rt = self.registers.read_register(instruction.rt);
+        let rs = self.read_gpr(instruction.rs) as u64;
+        let rt = self.read_gpr(instruction.rt) as u64;
         if rs != rt {
-            let target = (instruction.imm as i16 as i32) * 4;
-            self.registers.pc = (self.registers.pc as i32 + target) as u32;
+            let target = self.branch_offset_target(instruction);
+            self.branch(target);
+        }
+    }
+
+    /**
+     * Branch on less than or equal to zero
+     * opcode: 0b000110
+     */
+    fn blez(&mut self, instruction: &Instruction) {
+        let rs = self.read_gpr(instruction.rs);
+        if rs <= 0 {
+            let target = self.branch_offset_target(instruction);
+            self.branch(target);
+        }
+    }
+
+    /**
+     * Branch on greater than zero
+     * opcode: 0b000111
+     */
+    fn bgtz(&mut self, instruction: &Instruction) {
+        let rs = self.read_gpr(instruction.rs);
+        if rs > 0 {
+            let target = self.branch_offset_target(instruction);
+            self.branch(target);
         }
     }
 
@@ -949,11 +1947,10 @@ impl CPU {
     fn addi(&mut self, instruction: &Instruction) {
         let rs = self.registers.read_register(instruction.rs) as i32;
         let imm = instruction.imm as i16 as i32;
-        if rs.checked_add(imm).is_none() {
-            self.trigger_exception(Exception::IntegerOverflow);
+        match rs.checked_add(imm) {
+            Some(result) => self.registers.write_register(instruction.rt, result as u32),
+            None => self.trigger_exception(Exception::IntegerOverflow),
         }
-        self.registers
-            .write_register(instruction.rt, rs.wrapping_add(imm) as u32);
     }
 
     /**
@@ -962,19 +1959,62 @@ impl CPU {
      */
     fn addiu(&mut self, instruction: &Instruction) {
         let rs = self.registers.read_register(instruction.rs);
-        let imm = instruction.imm as u32;
+        let imm = instruction.imm as i16 as u32;
         self.registers
             .write_register(instruction.rt, rs.wrapping_add(imm));
     }
 
+    /**
+     * Set on less than immediate
+     * opcode: 0b001010
+     */
+    fn slti(&mut self, instruction: &Instruction) {
+        let rs = self.read_gpr(instruction.rs);
+        let imm = instruction.imm as i16 as i64;
+        self.registers
+            .write_register(instruction.rt, (rs < imm) as u32);
+    }
+
+    /**
+     * Set on less than immediate unsigned
+     * opcode: 0b001011
+     */
+    fn sltiu(&mut self, instruction: &Instruction) {
+        let rs = self.read_gpr(instruction.rs) as u64;
+        // The immediate is sign extended, then compared as unsigned
+        let imm = instruction.imm as i16 as i64 as u64;
+        self.registers
+            .write_register(instruction.rt, (rs < imm) as u32);
+    }
+
     /**
      * And immediate
      * opcode: 0b001100
      */
     fn andi(&mut self, instruction: &Instruction) {
-        let rs = self.registers.read_register(instruction.rs);
-        let imm = instruction.imm as u32;
-        self.registers.write_register(instruction.rt, rs & imm);
+        let rs = self.registers.read_register64(instruction.rs);
+        let imm = instruction.imm as u64;
+        self.registers.write_register64(instruction.rt, rs & imm);
+    }
+
+    /**
+     * Or immediate
+     * opcode: 0b001101
+     */
+    fn ori(&mut self, instruction: &Instruction) {
+        let rs = self.registers.read_register64(instruction.rs);
+        let imm = instruction.imm as u64;
+        self.registers.write_register64(instruction.rt, rs | imm);
+    }
+
+    /**
+     * Xor immediate
+     * opcode: 0b001110
+     */
+    fn xori(&mut self, instruction: &Instruction) {
+        let rs = self.registers.read_register64(instruction.rs);
+        let imm = instruction.imm as u64;
+        self.registers.write_register64(instruction.rt, rs ^ imm);
     }
 
     /**
@@ -982,8 +2022,9 @@ impl CPU {
      * opcode: 0b001111
      */
     fn lui(&mut self, instruction: &Instruction) {
+        // The lower half is cleared so that lui/ori pairs build a full word
         self.registers
-            .write_register_high(instruction.rt, instruction.imm);
+            .write_register(instruction.rt, (instruction.imm as u32) << 16);
     }
 
     /**
@@ -991,849 +2032,2735 @@ impl CPU {
      * opcode: 0b010100
      */
     fn beql(&mut self, instruction: &Instruction) {
-        let rs = self.registers.read_register(instruction.rs);
-        let rt = self.registers.read_register(instruction.rt);
+        let rs = self.read_gpr(instruction.rs) as u64;
+        let rt = self.read_gpr(instruction.rt) as u64;
         if rs == rt {
-            let target = (instruction.imm as i16 as i32) * 4;
-            self.registers.pc = (self.registers.pc as i32 + target) as u32;
+            let target = self.branch_offset_target(instruction);
+            self.branch(target);
+        } else {
+            self.nullify_delay_slot();
         }
     }
 
     /**
-     * Load word
-     * opcode: 0b100011
+     * Branch not equal likely
+     * opcode: 0b010101
      */
-    fn lw(&mut self, instruction: &Instruction, memory: &Memory) {
-        let base = self.registers.read_register(instruction.rs);
-        let offset = instruction.imm as i16 as i32;
-        let address = base.wrapping_add(offset as u32);
-        let value = memory.read_word(address);
-        self.registers.write_register(instruction.rt, value);
+    fn bnel(&mut self, instruction: &Instruction) {
+        let rs = self.read_gpr(instruction.rs) as u64;
+        let rt = self.read_gpr(instruction.rt) as u64;
+        if rs != rt {
+            let target = self.branch_offset_target(instruction);
+            self.branch(target);
+        } else {
+            self.nullify_delay_slot();
+        }
     }
 
     /**
-     * Store word
-     * opcode: 0b101011
+     * Branch on less than or equal to zero likely
+     * opcode: 0b010110
      */
-    fn sw(&mut self, instruction: &Instruction, memory: &mut Memory) {
-        let base = self.registers.read_register(instruction.rs);
-        let offset = instruction.imm as i16 as i32;
-        let address = base.wrapping_add(offset as u32);
-        let value = self.registers.read_register(instruction.rt);
-        memory.write_word(address, value);
+    fn blezl(&mut self, instruction: &Instruction) {
+        let rs = self.read_gpr(instruction.rs);
+        if rs <= 0 {
+            let target = self.branch_offset_target(instruction);
+            self.branch(target);
+        } else {
+            self.nullify_delay_slot();
+        }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /**
+     * Branch on greater than zero likely
+     * opcode: 0b010111
+     */
+    fn bgtzl(&mut self, instruction: &Instruction) {
+        let rs = self.read_gpr(instruction.rs);
+        if rs > 0 {
+            let target = self.branch_offset_target(instruction);
+            self.branch(target);
+        } else {
+            self.nullify_delay_slot();
+        }
+    }
 
-    #[test]
-    fn test_fetch_instruction() {
-        let mut cpu = CPU::default();
-        let mut memory = Memory::default();
-        memory.text_address = 0x00400000;
-        memory.write_word(0x00400000, 0x16aaaaaa);
-        let instruction = cpu.fetch_instruction(&memory);
-        assert_eq!(instruction.opcode, 0b000101);
-        assert_eq!(instruction.rs, 0b10101);
-        assert_eq!(instruction.rt, 0b01010);
-        assert_eq!(instruction.rd, 0b10101);
-        assert_eq!(instruction.shamt, 0b01010);
-        assert_eq!(instruction.funct, 0b101010);
-        assert_eq!(instruction.imm, 0xaaaa);
-        assert_eq!(instruction.address, 0x2aaaaaa);
+    /**
+     * Multiply and add word to hi, lo
+     * opcode: 0b011100
+     * funct: 0b000000
+     */
+    fn madd(&mut self, instruction: &Instruction) {
+        let rs = self.registers.read_register(instruction.rs) as i32 as i64;
+        let rt = self.registers.read_register(instruction.rt) as i32 as i64;
+        let accumulator = ((self.registers.hi as u64) << 32 | self.registers.lo as u64) as i64;
+        let result = accumulator.wrapping_add(rs * rt);
+        self.registers
+            .write_hi_lo((result >> 32) as u32, result as u32);
     }
 
-    // opcode: 0b000000
-    // funct: 0b000000
-    #[test]
-    fn test_run_sll() {
-        let mut cpu = CPU::default();
-        let mut memory = Memory::default();
-        memory.text_address = 0x00400000;
-        memory.write_word(0x00400000, 0x000A4880); // sll $t1, $t2, 2
-        cpu.registers.t2 = 0b0000_0000_0000_0000_0000_0000_0000_1010;
-        cpu.step(&mut memory);
-        assert_eq!(cpu.registers.t1, 0b0000_0000_0000_0000_0000_0000_0010_1000);
+    /**
+     * Multiply and add unsigned word to hi, lo
+     * opcode: 0b011100
+     * funct: 0b000001
+     */
+    fn maddu(&mut self, instruction: &Instruction) {
+        let rs = self.registers.read_register(instruction.rs) as u64;
+        let rt = self.registers.read_register(instruction.rt) as u64;
+        let accumulator = (self.registers.hi as u64) << 32 | self.registers.lo as u64;
+        let result = accumulator.wrapping_add(rs * rt);
+        self.registers
+            .write_hi_lo((result >> 32) as u32, result as u32);
     }
 
-    // opcode: 0b000000
-    // funct: 0b000010
-    #[test]
-    fn test_run_srl() {
-        let mut cpu = CPU::default();
-        let mut memory = Memory::default();
-        memory.text_address = 0x00400000;
-        memory.write_word(0x00400000, 0x000A4882); // srl $t1, $t2, 2
-        cpu.registers.t2 = 0b0000_0000_0000_0000_0000_0000_0000_1010;
-        cpu.step(&mut memory);
-        assert_eq!(cpu.registers.t1, 0b0000_0000_0000_0000_0000_0000_0000_0010);
+    /**
+     * Multiply word to gpr
+     * opcode: 0b011100
+     * funct: 0b000010
+     */
+    fn mul(&mut self, instruction: &Instruction) {
+        let rs = self.registers.read_register(instruction.rs) as i32;
+        let rt = self.registers.read_register(instruction.rt) as i32;
+        self.registers
+            .write_register(instruction.rd, rs.wrapping_mul(rt) as u32);
     }
 
-    // opcode: 0b000000
-    // funct: 0b000011
-    #[test]
-    fn test_run_sra() {
-        let mut cpu = CPU::default();
-        let mut memory = Memory::default();
-        memory.text_address = 0x00400000;
-        memory.write_word(0x00400000, 0x000A4883); // sra $t1, $t2, 2
-        cpu.registers.t2 = 0b1000_0000_0000_0000_0000_0000_0000_1010;
-        cpu.step(&mut memory);
-        assert_eq!(cpu.registers.t1, 0b1110_0000_0000_0000_0000_0000_0000_0010);
+    /**
+     * Multiply and subtract word to hi, lo
+     * opcode: 0b011100
+     * funct: 0b000100
+     */
+    fn msub(&mut self, instruction: &Instruction) {
+        let rs = self.registers.read_register(instruction.rs) as i32 as i64;
+        let rt = self.registers.read_register(instruction.rt) as i32 as i64;
+        let accumulator = ((self.registers.hi as u64) << 32 | self.registers.lo as u64) as i64;
+        let result = accumulator.wrapping_sub(rs * rt);
+        self.registers
+            .write_hi_lo((result >> 32) as u32, result as u32);
     }
 
-    // opcode: 0b000000
-    // funct: 0b000010
-    #[test]
-    fn test_run_rotr() {
-        let mut cpu = CPU::default();
-        let mut memory = Memory::default();
-        memory.text_address = 0x00400000;
-        memory.write_word(0x00400000, 0x002A4882); // rotr $t1, $t2, 2
-        cpu.registers.t2 = 0b0000_0000_0000_0000_0000_0000_0000_1010;
-        cpu.step(&mut memory);
-        assert_eq!(cpu.registers.t1, 0b1000_0000_0000_0000_0000_0000_0000_0010);
+    /**
+     * Multiply and subtract unsigned word to hi, lo
+     * opcode: 0b011100
+     * funct: 0b000101
+     */
+    fn msubu(&mut self, instruction: &Instruction) {
+        let rs = self.registers.read_register(instruction.rs) as u64;
+        let rt = self.registers.read_register(instruction.rt) as u64;
+        let accumulator = (self.registers.hi as u64) << 32 | self.registers.lo as u64;
+        let result = accumulator.wrapping_sub(rs * rt);
+        self.registers
+            .write_hi_lo((result >> 32) as u32, result as u32);
     }
 
-    // opcode: 0b000000
-    // funct: 0b000100
-    #[test]
-    fn test_run_sllv() {
-        let mut cpu = CPU::default();
-        let mut memory = Memory::default();
-        memory.text_address = 0x00400000;
-        memory.write_word(0x00400000, 0x016A4804); // sllv $t1, $t2, $t3
-        cpu.registers.t2 = 0b0000_0000_0000_0000_0000_0000_0000_1010;
-        cpu.registers.t3 = 2;
+    /**
+     * Count leading zeros in word
+     * opcode: 0b011100
+     * funct: 0b100000
+     */
+    fn clz(&mut self, instruction: &Instruction) {
+        let rs = self.registers.read_register(instruction.rs);
+        self.registers
+            .write_register(instruction.rd, rs.leading_zeros());
+    }
+
+    /**
+     * Count leading ones in word
+     * opcode: 0b011100
+     * funct: 0b100001
+     */
+    fn clo(&mut self, instruction: &Instruction) {
+        let rs = self.registers.read_register(instruction.rs);
+        self.registers
+            .write_register(instruction.rd, rs.leading_ones());
+    }
+
+    /**
+     * Extract bit field
+     * opcode: 0b011111
+     * funct: 0b000000
+     * shamt holds the lowest bit and rd the size minus one
+     */
+    fn ext(&mut self, instruction: &Instruction) {
+        let rs = self.registers.read_register(instruction.rs);
+        let size = instruction.rd as u32 + 1;
+        let mask = u32::MAX >> (32 - size);
+        self.registers
+            .write_register(instruction.rt, (rs >> instruction.shamt) & mask);
+    }
+
+    /**
+     * Insert bit field
+     * opcode: 0b011111
+     * funct: 0b000100
+     * shamt holds the lowest bit and rd the highest bit
+     */
+    fn ins(&mut self, instruction: &Instruction) {
+        let lsb = instruction.shamt as u32;
+        let msb = instruction.rd as u32;
+        if msb < lsb {
+            // Unpredictable, leave rt as it is
+            return;
+        }
+        let rs = self.registers.read_register(instruction.rs);
+        let rt = self.registers.read_register(instruction.rt);
+        let mask = (u32::MAX >> (31 - (msb - lsb))) << lsb;
+        self.registers
+            .write_register(instruction.rt, rt & !mask | (rs << lsb) & mask);
+    }
+
+    /**
+     * Word swap bytes within halfwords
+     * opcode: 0b011111
+     * funct: 0b100000
+     * shamt: 0b00010
+     */
+    fn wsbh(&mut self, instruction: &Instruction) {
+        let rt = self.registers.read_register(instruction.rt);
+        let result = (rt & 0x00ff00ff) << 8 | (rt & 0xff00ff00) >> 8;
+        self.registers.write_register(instruction.rd, result);
+    }
+
+    /**
+     * Sign extend byte
+     * opcode: 0b011111
+     * funct: 0b100000
+     * shamt: 0b10000
+     */
+    fn seb(&mut self, instruction: &Instruction) {
+        let rt = self.registers.read_register(instruction.rt);
+        self.registers
+            .write_register(instruction.rd, rt as i8 as u32);
+    }
+
+    /**
+     * Sign extend halfword
+     * opcode: 0b011111
+     * funct: 0b100000
+     * shamt: 0b11000
+     */
+    fn seh(&mut self, instruction: &Instruction) {
+        let rt = self.registers.read_register(instruction.rt);
+        self.registers
+            .write_register(instruction.rd, rt as i16 as u32);
+    }
+
+    /**
+     * Load byte
+     * opcode: 0b100000
+     */
+    fn lb(&mut self, instruction: &Instruction, memory: &Memory) -> Result<(), MemoryError> {
+        let Some(address) = self.load_address(instruction, 1, memory) else {
+            return Ok(());
+        };
+        let value = memory.read_byte(address)? as i8 as u32;
+        self.registers.write_register(instruction.rt, value);
+        Ok(())
+    }
+
+    /**
+     * Load halfword
+     * opcode: 0b100001
+     */
+    fn lh(&mut self, instruction: &Instruction, memory: &Memory) -> Result<(), MemoryError> {
+        let Some(address) = self.load_address(instruction, 2, memory) else {
+            return Ok(());
+        };
+        let value = memory.read_halfword(address)? as i16 as u32;
+        self.registers.write_register(instruction.rt, value);
+        Ok(())
+    }
+
+    /**
+     * Load word left
+     * opcode: 0b100010
+     * Fills the most significant bytes of rt from address to the least significant end of its word
+     */
+    fn lwl(&mut self, instruction: &Instruction, memory: &Memory) -> Result<(), MemoryError> {
+        let Some(address) = self.load_address(instruction, 1, memory) else {
+            return Ok(());
+        };
+        let shift = 8 * memory.more_significant_bytes(address);
+        let loaded = memory.read_word_masked(address, u32::MAX >> shift)? << shift;
+        let rt = self.registers.read_register(instruction.rt);
+        let value = rt & !(u32::MAX << shift) | loaded;
+        self.registers.write_register(instruction.rt, value);
+        Ok(())
+    }
+
+    /**
+     * Load word
+     * opcode: 0b100011
+     */
+    fn lw(&mut self, instruction: &Instruction, memory: &Memory) -> Result<(), MemoryError> {
+        let Some(address) = self.load_address(instruction, 4, memory) else {
+            return Ok(());
+        };
+        let value = memory.read_word(address)?;
+        self.registers.write_register(instruction.rt, value);
+        Ok(())
+    }
+
+    /**
+     * Load byte unsigned
+     * opcode: 0b100100
+     */
+    fn lbu(&mut self, instruction: &Instruction, memory: &Memory) -> Result<(), MemoryError> {
+        let Some(address) = self.load_address(instruction, 1, memory) else {
+            return Ok(());
+        };
+        let value = memory.read_byte(address)? as u32;
+        self.registers.write_register(instruction.rt, value);
+        Ok(())
+    }
+
+    /**
+     * Load halfword unsigned
+     * opcode: 0b100101
+     */
+    fn lhu(&mut self, instruction: &Instruction, memory: &Memory) -> Result<(), MemoryError> {
+        let Some(address) = self.load_address(instruction, 2, memory) else {
+            return Ok(());
+        };
+        let value = memory.read_halfword(address)? as u32;
+        self.registers.write_register(instruction.rt, value);
+        Ok(())
+    }
+
+    /**
+     * Load word right
+     * opcode: 0b100110
+     * Fills the least significant bytes of rt from the most significant end of the word to address
+     */
+    fn lwr(&mut self, instruction: &Instruction, memory: &Memory) -> Result<(), MemoryError> {
+        let Some(address) = self.load_address(instruction, 1, memory) else {
+            return Ok(());
+        };
+        let shift = 8 * (3 - memory.more_significant_bytes(address));
+        let loaded = memory.read_word_masked(address, u32::MAX << shift)? >> shift;
+        let rt = self.registers.read_register(instruction.rt);
+        let value = rt & !(u32::MAX >> shift) | loaded;
+        self.registers.write_register(instruction.rt, value);
+        Ok(())
+    }
+
+    /**
+     * Store byte
+     * opcode: 0b101000
+     */
+    fn sb(&mut self, instruction: &Instruction, memory: &mut Memory) -> Result<(), MemoryError> {
+        let Some(address) = self.store_address(instruction, 1, memory) else {
+            return Ok(());
+        };
+        let value = self.registers.read_register(instruction.rt);
+        memory.write_byte(address, value as u8)?;
+        Ok(())
+    }
+
+    /**
+     * Store halfword
+     * opcode: 0b101001
+     */
+    fn sh(&mut self, instruction: &Instruction, memory: &mut Memory) -> Result<(), MemoryError> {
+        let Some(address) = self.store_address(instruction, 2, memory) else {
+            return Ok(());
+        };
+        let value = self.registers.read_register(instruction.rt);
+        memory.write_halfword(address, value as u16)?;
+        Ok(())
+    }
+
+    /**
+     * Store word left
+     * opcode: 0b101010
+     * Stores the most significant bytes of rt from address to the least significant end of its word
+     */
+    fn swl(&mut self, instruction: &Instruction, memory: &mut Memory) -> Result<(), MemoryError> {
+        let Some(address) = self.store_address(instruction, 1, memory) else {
+            return Ok(());
+        };
+        let shift = 8 * memory.more_significant_bytes(address);
+        let value = self.registers.read_register(instruction.rt);
+        memory.write_word_masked(address, value >> shift, u32::MAX >> shift)
+    }
+
+    /**
+     * Store word
+     * opcode: 0b101011
+     */
+    fn sw(&mut self, instruction: &Instruction, memory: &mut Memory) -> Result<(), MemoryError> {
+        let Some(address) = self.store_address(instruction, 4, memory) else {
+            return Ok(());
+        };
+        let value = self.registers.read_register(instruction.rt);
+        memory.write_word(address, value)?;
+        Ok(())
+    }
+
+    /**
+     * Store word right
+     * opcode: 0b101110
+     * Stores the least significant bytes of rt from the most significant end of the word to address
+     */
+    fn swr(&mut self, instruction: &Instruction, memory: &mut Memory) -> Result<(), MemoryError> {
+        let Some(address) = self.store_address(instruction, 1, memory) else {
+            return Ok(());
+        };
+        let shift = 8 * (3 - memory.more_significant_bytes(address));
+        let value = self.registers.read_register(instruction.rt);
+        memory.write_word_masked(address, value << shift, u32::MAX << shift)
+    }
+
+    /**
+     * Load linked word
+     * opcode: 0b110000
+     */
+    fn ll(&mut self, instruction: &Instruction, memory: &mut Memory) -> Result<(), MemoryError> {
+        let Some(address) = self.load_address(instruction, 4, memory) else {
+            return Ok(());
+        };
+        let value = memory.read_word(address)?;
+        self.registers.write_register(instruction.rt, value);
+        self.llbit = true;
+        self.lladdr = address & !3;
+        self.link_generation = memory.link(address);
+        Ok(())
+    }
+
+    /**
+     * Store conditional word
+     * opcode: 0b111000
+     * Stores only if nothing has written to the linked word since ll
+     */
+    fn sc(&mut self, instruction: &Instruction, memory: &mut Memory) -> Result<(), MemoryError> {
+        let Some(address) = self.store_address(instruction, 4, memory) else {
+            return Ok(());
+        };
+        let success = self.llbit
+            && address & !3 == self.lladdr
+            && memory.link_intact(address, self.link_generation);
+        if success {
+            let value = self.registers.read_register(instruction.rt);
+            memory.write_word(address, value)?;
+        }
+        self.registers
+            .write_register(instruction.rt, success as u32);
+        self.llbit = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::field_reassign_with_default)]
+mod tests {
+    use super::*;
+    use crate::memory::{Endianness, Permissions};
+
+    #[test]
+    fn test_fetch_instruction() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.write_word(0x00400000, 0x16aaaaaa).unwrap();
+        let instruction = cpu.fetch_instruction(&memory).unwrap();
+        assert_eq!(instruction.opcode, 0b000101);
+        assert_eq!(instruction.rs, 0b10101);
+        assert_eq!(instruction.rt, 0b01010);
+        assert_eq!(instruction.rd, 0b10101);
+        assert_eq!(instruction.shamt, 0b01010);
+        assert_eq!(instruction.funct, 0b101010);
+        assert_eq!(instruction.imm, 0xaaaa);
+        assert_eq!(instruction.address, 0x2aaaaaa);
+    }
+
+    #[test]
+    fn test_reserved_opcode() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.write_word(0x00400000, 0x4bffffff).unwrap(); // cop2
+        cpu.step(&mut memory);
+        assert!(cpu.halted);
+        assert_eq!(
+            cpu.exception,
+            Exception::ReservedInstruction {
+                word: 0x4bffffff,
+                address: 0x00400000
+            }
+        );
+        assert_eq!(cpu.control_registers.cause, 10 << 2);
+        assert_eq!(cpu.registers.pc, 0x00400000);
+    }
+
+    #[test]
+    fn test_reserved_special_funct() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.write_word(0x00400000, 0x00000000).unwrap(); // nop
+        memory.write_word(0x00400004, 0x00000005).unwrap(); // unused SPECIAL funct
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(
+            cpu.exception,
+            Exception::ReservedInstruction {
+                word: 0x00000005,
+                address: 0x00400004
+            }
+        );
+    }
+
+    #[test]
+    fn test_isa_level() {
+        for (isa, word, allowed) in [
+            (IsaLevel::Mips1, 0x00000036, false), // tne $zero, $zero
+            (IsaLevel::Mips2, 0x00000036, true),
+            (IsaLevel::Mips1, 0x50000000, false), // beql $zero, $zero, 0x00400004
+            (IsaLevel::Mips1, 0x0000000f, false), // sync
+            (IsaLevel::Mips2, 0x0000000f, true),
+            (IsaLevel::Mips32r6, 0x0000000f, true),
+            (IsaLevel::Mips2, 0x014b480a, false), // movz $t1, $t2, $t3
+            (IsaLevel::Mips32r1, 0x014b480a, true),
+            (IsaLevel::Mips32r1, 0x002a4882, false), // rotr $t1, $t2, 2
+            (IsaLevel::Mips32r2, 0x002a4882, true),
+            (IsaLevel::Mips32r1, 0x7d2a0000, false), // ext $t2, $t1, 0, 1
+            (IsaLevel::Mips32r2, 0x7d2a0000, true),
+            (IsaLevel::Mips32r2, 0x014b4845, false), // lsa $t1, $t2, $t3, 2
+            (IsaLevel::Mips32r6, 0x014b4845, true),
+            (IsaLevel::Mips32r2, 0xed200002, false), // addiupc $t1, 8
+            (IsaLevel::Mips32r6, 0xed200002, true),
+        ] {
+            let mut cpu = CPU::default();
+            let mut memory = Memory::default();
+            memory.text_address = 0x00400000;
+            memory.map(0x00400000, 0x1000, Permissions::ALL);
+            cpu.isa = isa;
+            memory.write_word(0x00400000, word).unwrap();
+            let result = cpu.step(&mut memory);
+            assert_eq!(
+                result == StepResult::Retired,
+                allowed,
+                "{} 0x{:08x}",
+                isa,
+                word
+            );
+        }
+    }
+
+    #[test]
+    fn test_step_results() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t2 = i32::MAX as u32;
+        memory.write_word(0x00400000, 0x00000000).unwrap(); // nop
+        memory.write_word(0x00400004, 0x21490001).unwrap(); // addi $t1, $t2, 1
+        assert_eq!(cpu.step(&mut memory), StepResult::Retired);
+        assert_eq!(
+            cpu.step(&mut memory),
+            StepResult::Exception {
+                exception: Exception::IntegerOverflow,
+                pc: 0x00400004
+            }
+        );
+    }
+
+    #[test]
+    fn test_fetch_bus_error() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        // The last word of the last page
+        cpu.registers.pc = 0x00400ffc;
+        memory.write_word(0x00400ffc, 0x00000000).unwrap(); // nop
+        assert_eq!(cpu.step(&mut memory), StepResult::Retired);
+        assert_eq!(
+            cpu.step(&mut memory),
+            StepResult::BusError {
+                address: 0x00401000,
+                pc: 0x00401000
+            }
+        );
+        assert_eq!(cpu.exception, Exception::InstructionBusError(0x00401000));
+        assert_eq!(cpu.control_registers.cause, 6 << 2);
+    }
+
+    #[test]
+    fn test_data_bus_error() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.data_address = 0x10000000;
+        memory.map(0x10000000, 0x1000, Permissions::READ_WRITE);
+        cpu.registers.t1 = 0x10000ff0;
+        memory.write_word(0x00400000, 0x8d2a0010).unwrap(); // lw $t2, 16($t1)
+        memory.write_word(0x10000ff0, 0x12345678).unwrap();
+        // One past the only page written
+        assert_eq!(
+            cpu.step(&mut memory),
+            StepResult::BusError {
+                address: 0x10001000,
+                pc: 0x00400000
+            }
+        );
+        assert_eq!(cpu.control_registers.cause, 7 << 2);
+        assert_eq!(cpu.registers.pc, 0x00400000);
+    }
+
+    #[test]
+    fn test_stack() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.ra = 0x00400100;
+        memory.write_word(0x00400000, 0x27bdffe8).unwrap(); // addiu $sp, $sp, -24
+        memory.write_word(0x00400004, 0xafbf0014).unwrap(); // sw $ra, 20($sp)
+        memory.write_word(0x00400008, 0x8fa80010).unwrap(); // lw $t0, 16($sp)
+        memory.write_word(0x0040000c, 0x8fa90014).unwrap(); // lw $t1, 20($sp)
+        for _ in 0..4 {
+            assert_eq!(cpu.step(&mut memory), StepResult::Retired);
+        }
+        assert_eq!(cpu.registers.sp, 0x7fffffe4);
+        // Never written, but still inside the stack
+        assert_eq!(cpu.registers.t0, 0);
+        assert_eq!(cpu.registers.t1, 0x00400100);
+        assert_eq!(memory.read_word(0x7ffffff8).unwrap(), 0x00400100);
+    }
+
+    #[test]
+    fn test_stack_overflow() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.stack_size = 0x1000;
+        cpu.registers.sp = 0x7ffff000;
+        memory.write_word(0x00400000, 0xafbf0000).unwrap(); // sw $ra, 0($sp)
+        memory.write_word(0x00400004, 0xafbffffc).unwrap(); // sw $ra, -4($sp)
+        assert_eq!(cpu.step(&mut memory), StepResult::Retired);
+        assert_eq!(
+            cpu.step(&mut memory),
+            StepResult::Exception {
+                exception: Exception::StackOverflow(0x7fffeffc),
+                pc: 0x00400004
+            }
+        );
+        assert_eq!(cpu.control_registers.cause, 7 << 2);
+        assert!(!memory.is_mapped(0x7fffeffc));
+    }
+
+    #[test]
+    fn test_run_until_halted() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.write_word(0x00400000, 0x24020011).unwrap(); // addiu $v0, $zero, 17
+        memory.write_word(0x00400004, 0x24040002).unwrap(); // addiu $a0, $zero, 2
+        memory.write_word(0x00400008, 0x0000000c).unwrap(); // syscall
+        assert_eq!(
+            cpu.run(&mut memory, usize::MAX),
+            StepResult::Halted { exit_code: 2 }
+        );
+    }
+
+    #[test]
+    fn test_run_step_limit() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        assert_eq!(cpu.run(&mut memory, 2), StepResult::Retired);
+        assert_eq!(cpu.registers.pc, 0x00400008);
+    }
+
+    #[test]
+    fn test_run_pc_wraps_at_top_of_address_space() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0xfffff000;
+        memory.map(0xfffff000, 0x1000, Permissions::ALL);
+        cpu.registers.pc = 0xfffffffc;
+        assert_eq!(cpu.step(&mut memory), StepResult::Retired);
+        assert_eq!(cpu.registers.pc, 0);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b000000
+    #[test]
+    fn test_run_sll() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.write_word(0x00400000, 0x000A4880).unwrap(); // sll $t1, $t2, 2
+        cpu.registers.t2 = 0b0000_0000_0000_0000_0000_0000_0000_1010;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t1, 0b0000_0000_0000_0000_0000_0000_0010_1000);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b000001
+    #[test]
+    fn test_run_movci() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t2 = 0xDEADBEEF;
+        cpu.float_registers.fcsr = 1 << 23;
+        memory.write_word(0x00400000, 0x01414801).unwrap(); // movt $t1, $t2, $fcc0
+        memory.write_word(0x00400004, 0x01405801).unwrap(); // movf $t3, $t2, $fcc0
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t1, 0xDEADBEEF);
+        assert_eq!(cpu.registers.t3, 0);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b000010
+    #[test]
+    fn test_run_srl() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.write_word(0x00400000, 0x000A4882).unwrap(); // srl $t1, $t2, 2
+        cpu.registers.t2 = 0b0000_0000_0000_0000_0000_0000_0000_1010;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t1, 0b0000_0000_0000_0000_0000_0000_0000_0010);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b000011
+    #[test]
+    fn test_run_sra() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.write_word(0x00400000, 0x000A4883).unwrap(); // sra $t1, $t2, 2
+        cpu.registers.t2 = 0b1000_0000_0000_0000_0000_0000_0000_1010;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t1, 0b1110_0000_0000_0000_0000_0000_0000_0010);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b000010
+    #[test]
+    fn test_run_rotr() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.write_word(0x00400000, 0x002A4882).unwrap(); // rotr $t1, $t2, 2
+        cpu.registers.t2 = 0b0000_0000_0000_0000_0000_0000_0000_1010;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t1, 0b1000_0000_0000_0000_0000_0000_0000_0010);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b000100
+    #[test]
+    fn test_run_sllv() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.write_word(0x00400000, 0x016A4804).unwrap(); // sllv $t1, $t2, $t3
+        cpu.registers.t2 = 0b0000_0000_0000_0000_0000_0000_0000_1010;
+        cpu.registers.t3 = 2;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t1, 0b0000_0000_0000_0000_0000_0000_0010_1000);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b000110
+    #[test]
+    fn test_run_srlv() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.write_word(0x00400000, 0x016A4806).unwrap(); // srlv $t1, $t2, $t3
+        cpu.registers.t2 = 0b0000_0000_0000_0000_0000_0000_0000_1010;
+        cpu.registers.t3 = 2;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t1, 0b0000_0000_0000_0000_0000_0000_0000_0010);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b000111
+    #[test]
+    fn test_run_srav() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.write_word(0x00400000, 0x016A4807).unwrap(); // srav $t1, $t2, $t3
+        cpu.registers.t2 = 0b1000_0000_0000_0000_0000_0000_0000_1010;
+        cpu.registers.t3 = 2;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t1, 0b1110_0000_0000_0000_0000_0000_0000_0010);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b000110
+    #[test]
+    fn test_run_rotrv() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.write_word(0x00400000, 0x014B4846).unwrap(); // rotrv $t1, $t2, $t3
+        cpu.registers.t2 = 2;
+        cpu.registers.t3 = 0b0000_0000_0000_0000_0000_0000_0000_1010;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t1, 0b1000_0000_0000_0000_0000_0000_0000_0010);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b001000
+    #[test]
+    fn test_run_jr() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t1 = 0xDEADBEEF;
+        memory.write_word(0x00400000, 0x01200008).unwrap(); // jr $t1
+        memory.write_word(0x00400004, 0x00000000).unwrap(); // nop
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400004);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0xDEADBEEF);
+    }
+
+    #[test]
+    fn test_misaligned_fetch() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.pc = 0x00400002;
+        memory.write_word(0x00400000, 0x00000000).unwrap(); // nop
+        cpu.step(&mut memory);
+        assert_eq!(cpu.exception, Exception::AddressErrorLoad(0x00400002));
+        assert_eq!(cpu.control_registers.epc, 0x00400002);
+        assert_eq!(cpu.control_registers.bad_vaddr, 0x00400002);
+        assert_eq!(cpu.registers.pc, 0x00400002);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b001001
+    #[test]
+    fn test_run_jalr() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t1 = 0xDEADBEEF;
+        memory.write_word(0x00400000, 0x01205009).unwrap(); // jalr $t1, $t2
+        memory.write_word(0x00400004, 0x00000000).unwrap(); // nop
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0xDEADBEEF);
+        assert_eq!(cpu.registers.t2, 0x00400008);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b001010
+    #[test]
+    fn test_run_movz() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t1 = 0;
+        cpu.registers.t2 = 0xDEADBEEF;
+        cpu.registers.t3 = 0;
+        memory.write_word(0x00400000, 0x014b480a).unwrap(); // movz $t1, $t2, $t3
+        memory.write_word(0x00400004, 0x014b480a).unwrap(); // movz $t1, $t2, $t3
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t1, 0xDEADBEEF);
+        assert_eq!(cpu.registers.t2, 0xDEADBEEF);
+        assert_eq!(cpu.registers.t3, 0);
+        cpu.registers.t1 = 0;
+        cpu.registers.t3 = 1;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t1, 0);
+        assert_eq!(cpu.registers.t2, 0xDEADBEEF);
+        assert_eq!(cpu.registers.t3, 1);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b001011
+    #[test]
+    fn test_run_movn() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t1 = 0;
+        cpu.registers.t2 = 0xDEADBEEF;
+        cpu.registers.t3 = 0;
+        memory.write_word(0x00400000, 0x014b480b).unwrap(); // movn $t1, $t2, $t3
+        memory.write_word(0x00400004, 0x014b480b).unwrap(); // movn $t1, $t2, $t3
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t1, 0);
+        assert_eq!(cpu.registers.t2, 0xDEADBEEF);
+        assert_eq!(cpu.registers.t3, 0);
+        cpu.registers.t3 = 1;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t1, 0xDEADBEEF);
+        assert_eq!(cpu.registers.t2, 0xDEADBEEF);
+        assert_eq!(cpu.registers.t3, 1);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b001100
+    #[test]
+    fn test_run_syscall() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.v0 = 10;
+        memory.write_word(0x00400000, 0x0000000c).unwrap(); // syscall
+        assert_eq!(cpu.step(&mut memory), StepResult::Halted { exit_code: 0 });
+        assert!(cpu.halted);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b001100
+    #[test]
+    fn test_run_syscall_exit2() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.v0 = 17;
+        cpu.registers.a0 = 3;
+        memory.write_word(0x00400000, 0x0000000c).unwrap(); // syscall
+        assert_eq!(cpu.step(&mut memory), StepResult::Halted { exit_code: 3 });
+    }
+
+    // opcode: 0b000000
+    // funct: 0b001100
+    #[test]
+    fn test_run_syscall_sbrk() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.heap_address = 0x10010000;
+        memory.program_break = 0x10010000;
+        cpu.registers.v0 = 9;
+        cpu.registers.a0 = 0x2000;
+        memory.write_word(0x00400000, 0x0000000c).unwrap(); // syscall
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.v0, 0x10010000);
+        assert_eq!(memory.program_break, 0x10012000);
+        // Heap pages are allocated when first written
+        assert!(!memory.is_mapped(0x10011ffc));
+        assert_eq!(memory.read_word(0x10011ffc), Ok(0));
+        assert_eq!(
+            memory.read_word(0x10012000),
+            Err(MemoryError::Unmapped(0x10012000))
+        );
+        assert_eq!(memory.permissions(0x10010000), Permissions::READ_WRITE);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b001100
+    #[test]
+    fn test_run_syscall_sbrk_limit() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.heap_address = 0x10010000;
+        memory.program_break = 0x10010000;
+        memory.heap_size = 0x1000;
+        cpu.registers.v0 = 9;
+        cpu.registers.a0 = 0x1004;
+        memory.write_word(0x00400000, 0x0000000c).unwrap(); // syscall
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.v0, 0xffffffff);
+        assert_eq!(memory.program_break, 0x10010000);
+        assert!(!memory.is_mapped(0x10010000));
+    }
+
+    // opcode: 0b000000
+    // funct: 0b001100
+    #[test]
+    fn test_run_syscall_sbrk_shrink() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.heap_address = 0x10010000;
+        memory.program_break = 0x10010100;
+        cpu.registers.v0 = 9;
+        cpu.registers.a0 = -0x80i32 as u32;
+        memory.write_word(0x00400000, 0x0000000c).unwrap(); // syscall
+        memory.write_word(0x00400004, 0x0000000c).unwrap(); // syscall
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.v0, 0x10010100);
+        assert_eq!(memory.program_break, 0x10010080);
+        // Shrinking below the start of the heap fails
+        cpu.registers.v0 = 9;
+        cpu.registers.a0 = -0x100i32 as u32;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.v0, 0xffffffff);
+        assert_eq!(memory.program_break, 0x10010080);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b001100
+    #[test]
+    fn test_run_syscall_unknown() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.v0 = 1234;
+        memory.write_word(0x00400000, 0x0000000c).unwrap(); // syscall
+        assert_eq!(
+            cpu.step(&mut memory),
+            StepResult::Exception {
+                exception: Exception::Syscall,
+                pc: 0x00400000
+            }
+        );
+        assert!(cpu.halted);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b001101
+    #[test]
+    fn test_run_breakpoint() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.write_word(0x00400000, 0x0000000d).unwrap(); // breakpoint
+        assert_eq!(
+            cpu.step(&mut memory),
+            StepResult::Breakpoint { pc: 0x00400000 }
+        );
+        assert!(cpu.halted);
+        assert_eq!(cpu.exception, Exception::Breakpoint);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b010000
+    #[test]
+    fn test_run_mfhi() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.hi = 0xDEADBEEF;
+        memory.write_word(0x00400000, 0x00004810).unwrap(); // mfhi $t1
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t1, 0xDEADBEEF);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b010001
+    #[test]
+    fn test_run_mthi() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t1 = 0xDEADBEEF;
+        memory.write_word(0x00400000, 0x01200011).unwrap(); // mthi $t1
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.hi, 0xDEADBEEF);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b010010
+    #[test]
+    fn test_run_mflo() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.lo = 0xDEADBEEF;
+        memory.write_word(0x00400000, 0x00004812).unwrap(); // mflo $t1
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t1, 0xDEADBEEF);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b010011
+    #[test]
+    fn test_run_mtlo() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t1 = 0xDEADBEEF;
+        memory.write_word(0x00400000, 0x01200013).unwrap(); // mthi $t1
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.lo, 0xDEADBEEF);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b011000
+    #[test]
+    fn test_run_mult() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t1 = 0xaaaaaaaa;
+        cpu.registers.t2 = 0x33333333;
+        memory.write_word(0x00400000, 0x012a0018).unwrap(); // mult $t1, $t2
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.hi, 0x22222221);
+        assert_eq!(cpu.registers.lo, 0xDDDDDDDE);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b011001
+    #[test]
+    fn test_run_multu() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t1 = 0xaaaaaaaa;
+        cpu.registers.t2 = 0x33333333;
+        memory.write_word(0x00400000, 0x012a0019).unwrap(); // multu $t1, $t2
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.hi, 0x22222221);
+        assert_eq!(cpu.registers.lo, 0xDDDDDDDE);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b011010
+    #[test]
+    fn test_run_div() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t1 = 10;
+        cpu.registers.t2 = 3;
+        memory.write_word(0x00400000, 0x012a001a).unwrap(); // div $t1, $t2
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.hi, 1);
+        assert_eq!(cpu.registers.lo, 3);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b011011
+    #[test]
+    fn test_run_divu() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t1 = 10;
+        cpu.registers.t2 = 3;
+        memory.write_word(0x00400000, 0x012a001b).unwrap(); // div $t1, $t2
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.hi, 1);
+        assert_eq!(cpu.registers.lo, 3);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b100000
+    #[test]
+    fn test_run_add() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t2 = 1;
+        cpu.registers.t3 = 2;
+        memory.write_word(0x00400000, 0x014b4820).unwrap(); // add $t1, $t2, $t3
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t1, 3);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b100000
+    #[test]
+    fn test_run_add_overflow() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t1 = 0xDEADBEEF;
+        cpu.registers.t2 = i32::MAX as u32;
+        cpu.registers.t3 = 1;
+        memory.write_word(0x00400000, 0x014b4820).unwrap(); // add $t1, $t2, $t3
+        cpu.step(&mut memory);
+        assert_eq!(cpu.exception, Exception::IntegerOverflow);
+        assert_eq!(cpu.registers.t1, 0xDEADBEEF);
+        assert_eq!(cpu.registers.pc, 0x00400000);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b100001
+    #[test]
+    fn test_run_addu() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t2 = 1;
+        cpu.registers.t3 = 2;
+        memory.write_word(0x00400000, 0x014b4821).unwrap(); // addu $t1, $t2, $t3
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t1, 3);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b100010
+    #[test]
+    fn test_run_sub() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t2 = u32::MAX;
+        cpu.registers.t3 = u32::MAX - 1;
+        memory.write_word(0x00400000, 0x014b4822).unwrap(); // sub $t1, $t2, $t3
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t1 as i32, 1);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b100011
+    #[test]
+    fn test_run_subu() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t2 = u32::MAX;
+        cpu.registers.t3 = u32::MAX - 1;
+        memory.write_word(0x00400000, 0x014b4823).unwrap(); // subu $t1, $t2, $t3
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t1, 1);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b100100
+    #[test]
+    fn test_run_and() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t2 = 0b10101010;
+        cpu.registers.t3 = 0b11100011;
+        memory.write_word(0x00400000, 0x014b4824).unwrap(); // and $t1, $t2, $t3
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t1, 0b10100010);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b100101
+    #[test]
+    fn test_run_or() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t2 = 0b10101010;
+        cpu.registers.t3 = 0b11100011;
+        memory.write_word(0x00400000, 0x014b4825).unwrap(); // or $t1, $t2, $t3
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t1, 0b11101011);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b100110
+    #[test]
+    fn test_run_xor() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t2 = 0b10101010;
+        cpu.registers.t3 = 0b11100011;
+        memory.write_word(0x00400000, 0x014b4826).unwrap(); // xor $t1, $t2, $t3
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t1, 0b01001001);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b100111
+    #[test]
+    fn test_run_nor() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t2 = 0x0000FAFB;
+        cpu.registers.t3 = 0x0000F000;
+        memory.write_word(0x00400000, 0x014b4827).unwrap(); // nor $t1, $t2, $t3
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t1, 0xFFFF0504);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b101010
+    #[test]
+    fn test_run_slt() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t2 = u32::MAX - 1;
+        cpu.registers.t3 = u32::MAX;
+        memory.write_word(0x00400000, 0x014b482a).unwrap(); // slt $t1, $t2, $t3
+        memory.write_word(0x00400004, 0x014b482a).unwrap(); // slt $t1, $t2, $t3
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t1, 1);
+        cpu.registers.t2 = u32::MAX;
+        cpu.registers.t3 = u32::MAX - 1;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t1, 0);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b101011
+    #[test]
+    fn test_run_sltu() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t2 = u32::MAX - 1;
+        cpu.registers.t3 = u32::MAX;
+        memory.write_word(0x00400000, 0x014b482b).unwrap(); // sltu $t1, $t2, $t3
+        memory.write_word(0x00400004, 0x014b482b).unwrap(); // sltu $t1, $t2, $t3
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t1, 1);
+        cpu.registers.t2 = u32::MAX;
+        cpu.registers.t3 = u32::MAX - 1;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t1, 0);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b110000
+    #[test]
+    fn test_run_tge() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.write_word(0x00400000, 0x014b4830).unwrap(); // tge $t2, $t3
+        memory.write_word(0x00400004, 0x014b4830).unwrap(); // tge $t2, $t3
+        memory.write_word(0x00400008, 0x014b4830).unwrap(); // tge $t2, $t3
+        memory.write_word(0x0040000c, 0x014b4830).unwrap(); // tge $t2, $t3
+        cpu.registers.t2 = 1;
+        cpu.registers.t3 = 2;
+        cpu.exception = Exception::None;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.exception, Exception::None);
+        cpu.registers.t2 = 1;
+        cpu.registers.t3 = 1;
+        cpu.exception = Exception::None;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.exception, Exception::Trap);
+        cpu.registers.t2 = u32::MAX;
+        cpu.registers.t3 = 1;
+        cpu.exception = Exception::None;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.exception, Exception::None);
+        cpu.registers.t2 = 2;
+        cpu.registers.t3 = 1;
+        cpu.exception = Exception::None;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.exception, Exception::Trap);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b110001
+    #[test]
+    fn test_run_tgeu() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.write_word(0x00400000, 0x014b4831).unwrap(); // tgeu $t2, $t3
+        memory.write_word(0x00400004, 0x014b4831).unwrap(); // tgeu $t2, $t3
+        memory.write_word(0x00400008, 0x014b4831).unwrap(); // tgeu $t2, $t3
+        memory.write_word(0x0040000c, 0x014b4831).unwrap(); // tgeu $t2, $t3
+        cpu.registers.t2 = 1;
+        cpu.registers.t3 = 2;
+        cpu.exception = Exception::None;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.exception, Exception::None);
+        cpu.registers.t2 = 1;
+        cpu.registers.t3 = 1;
+        cpu.exception = Exception::None;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.exception, Exception::Trap);
+        cpu.registers.t2 = u32::MAX;
+        cpu.registers.t3 = 1;
+        cpu.exception = Exception::None;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.exception, Exception::Trap);
+        cpu.registers.t2 = 2;
+        cpu.registers.t3 = 1;
+        cpu.exception = Exception::None;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.exception, Exception::Trap);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b110010
+    #[test]
+    fn test_run_tlt() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.write_word(0x00400000, 0x014b4832).unwrap(); // tge $t2, $t3
+        memory.write_word(0x00400004, 0x014b4832).unwrap(); // tge $t2, $t3
+        memory.write_word(0x00400008, 0x014b4832).unwrap(); // tge $t2, $t3
+        memory.write_word(0x0040000c, 0x014b4832).unwrap(); // tge $t2, $t3
+        cpu.registers.t2 = 1;
+        cpu.registers.t3 = 2;
+        cpu.exception = Exception::None;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.exception, Exception::Trap);
+        cpu.registers.t2 = 1;
+        cpu.registers.t3 = 1;
+        cpu.exception = Exception::None;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.exception, Exception::None);
+        cpu.registers.t2 = u32::MAX;
+        cpu.registers.t3 = 1;
+        cpu.exception = Exception::None;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.exception, Exception::Trap);
+        cpu.registers.t2 = 2;
+        cpu.registers.t3 = 1;
+        cpu.exception = Exception::None;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.exception, Exception::None);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b110011
+    #[test]
+    fn test_run_tltu() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.write_word(0x00400000, 0x014b4833).unwrap(); // tgeu $t2, $t3
+        memory.write_word(0x00400004, 0x014b4833).unwrap(); // tgeu $t2, $t3
+        memory.write_word(0x00400008, 0x014b4833).unwrap(); // tgeu $t2, $t3
+        memory.write_word(0x0040000c, 0x014b4833).unwrap(); // tgeu $t2, $t3
+        cpu.registers.t2 = 1;
+        cpu.registers.t3 = 2;
+        cpu.exception = Exception::None;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.exception, Exception::Trap);
+        cpu.registers.t2 = 1;
+        cpu.registers.t3 = 1;
+        cpu.exception = Exception::None;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.exception, Exception::None);
+        cpu.registers.t2 = u32::MAX;
+        cpu.registers.t3 = 1;
+        cpu.exception = Exception::None;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.exception, Exception::None);
+        cpu.registers.t2 = 2;
+        cpu.registers.t3 = 1;
+        cpu.exception = Exception::None;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.exception, Exception::None);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b110100
+    #[test]
+    fn test_run_teq() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.write_word(0x00400000, 0x014b4834).unwrap(); // teq $t2, $t3
+        memory.write_word(0x00400004, 0x014b4834).unwrap(); // teq $t2, $t3
+        cpu.registers.t2 = 1;
+        cpu.registers.t3 = 2;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.exception, Exception::None);
+        cpu.registers.t3 = 1;
+        cpu.exception = Exception::None;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.exception, Exception::Trap);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b110110
+    #[test]
+    fn test_run_tne() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.write_word(0x00400000, 0x014b4836).unwrap(); // tne $t2, $t3
+        memory.write_word(0x00400004, 0x014b4836).unwrap(); // tne $t2, $t3
+        cpu.registers.t2 = 1;
+        cpu.registers.t3 = 2;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.exception, Exception::Trap);
+        cpu.registers.t3 = 1;
+        cpu.exception = Exception::None;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.exception, Exception::None);
+    }
+
+    // opcode: 0b000001
+    // rt: 0b00000
+    #[test]
+    fn test_run_bltz() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.write_word(0x00400000, 0x05200003).unwrap(); // bltz $t1, 3
+        memory.write_word(0x00400004, 0x00000000).unwrap(); // nop
+        cpu.registers.t1 = 0x80000000;
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400010);
+        cpu.registers.pc = 0x00400000;
+        cpu.registers.t1 = 0;
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400008);
+    }
+
+    // opcode: 0b000001
+    // rt: 0b00001
+    #[test]
+    fn test_run_bgez() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.write_word(0x00400000, 0x05210003).unwrap(); // bgez $t1, 3
+        memory.write_word(0x00400004, 0x00000000).unwrap(); // nop
+        cpu.registers.t1 = 0;
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400010);
+        cpu.registers.pc = 0x00400000;
+        cpu.registers.t1 = 0x80000000;
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400008);
+    }
+
+    // opcode: 0b000001
+    // rt: 0b00010
+    #[test]
+    fn test_run_bltzl() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.write_word(0x00400000, 0x05220003).unwrap(); // bltzl $t1, 3
+        memory.write_word(0x00400004, 0x00000000).unwrap(); // nop
+        cpu.registers.t1 = 0x80000000;
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400010);
+        cpu.registers.pc = 0x00400000;
+        cpu.registers.t1 = 0;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400008);
+    }
+
+    // opcode: 0b000001
+    // rt: 0b00011
+    #[test]
+    fn test_run_bgezl() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.write_word(0x00400000, 0x05230003).unwrap(); // bgezl $t1, 3
+        memory.write_word(0x00400004, 0x00000000).unwrap(); // nop
+        cpu.registers.t1 = 0;
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400010);
+        cpu.registers.pc = 0x00400000;
+        cpu.registers.t1 = 0x80000000;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400008);
+    }
+
+    // opcode: 0b000001
+    // rt: 0b10000
+    #[test]
+    fn test_run_bltzal() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.write_word(0x00400000, 0x05300003).unwrap(); // bltzal $t1, 3
+        memory.write_word(0x00400004, 0x00000000).unwrap(); // nop
+        cpu.registers.t1 = 0x80000000;
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400010);
+        assert_eq!(cpu.registers.ra, 0x00400008);
+        cpu.registers.pc = 0x00400000;
+        cpu.registers.ra = 0;
+        cpu.registers.t1 = 0;
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400008);
+        assert_eq!(cpu.registers.ra, 0x00400008);
+    }
+
+    // opcode: 0b000001
+    // rt: 0b10001
+    #[test]
+    fn test_run_bgezal() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.write_word(0x00400000, 0x05310003).unwrap(); // bgezal $t1, 3
+        memory.write_word(0x00400004, 0x00000000).unwrap(); // nop
+        cpu.registers.t1 = 0;
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400010);
+        assert_eq!(cpu.registers.ra, 0x00400008);
+        cpu.registers.pc = 0x00400000;
+        cpu.registers.ra = 0;
+        cpu.registers.t1 = 0x80000000;
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400008);
+        assert_eq!(cpu.registers.ra, 0x00400008);
+    }
+
+    // opcode: 0b000001
+    // rt: 0b10010
+    #[test]
+    fn test_run_bltzall() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.write_word(0x00400000, 0x05320003).unwrap(); // bltzall $t1, 3
+        memory.write_word(0x00400004, 0x00000000).unwrap(); // nop
+        cpu.registers.t1 = 0x80000000;
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400010);
+        assert_eq!(cpu.registers.ra, 0x00400008);
+        cpu.registers.pc = 0x00400000;
+        cpu.registers.ra = 0;
+        cpu.registers.t1 = 0;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400008);
+        assert_eq!(cpu.registers.ra, 0x00400008);
+    }
+
+    // opcode: 0b000001
+    // rt: 0b10011
+    #[test]
+    fn test_run_bgezall() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.write_word(0x00400000, 0x05330003).unwrap(); // bgezall $t1, 3
+        memory.write_word(0x00400004, 0x00000000).unwrap(); // nop
+        cpu.registers.t1 = 0;
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400010);
+        assert_eq!(cpu.registers.ra, 0x00400008);
+        cpu.registers.pc = 0x00400000;
+        cpu.registers.ra = 0;
+        cpu.registers.t1 = 0x80000000;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400008);
+        assert_eq!(cpu.registers.ra, 0x00400008);
+    }
+
+    // opcode: 0b000010
+    #[test]
+    fn test_run_j() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.write_word(0x00400000, 0x08100000).unwrap(); // j 0x00400000
+        memory.write_word(0x00400004, 0x00000000).unwrap(); // nop
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400000);
+    }
+
+    // opcode: 0b000011
+    #[test]
+    fn test_run_jal() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.write_word(0x00400000, 0x0c100004).unwrap(); // jal 0x00400010
+        memory.write_word(0x00400004, 0x00000000).unwrap(); // nop
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400010);
+        assert_eq!(cpu.registers.ra, 0x00400008);
+    }
+
+    // opcode: 0b000100
+    #[test]
+    fn test_run_beq() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t1 = 1;
+        cpu.registers.t2 = 2;
+        cpu.registers.t3 = 3;
+        memory.write_word(0x00400000, 0x012a4820).unwrap(); // add $t1, $t1, $t2
+        memory.write_word(0x00400004, 0x112bfffe).unwrap(); // beq $t1, $t3, -2
+        memory.write_word(0x00400008, 0x00000000).unwrap(); // nop
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t1, 3);
+        assert_eq!(cpu.registers.t2, 2);
+        assert_eq!(cpu.registers.t3, 3);
+        assert_eq!(cpu.registers.pc, 0x00400000);
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t1, 5);
+        assert_eq!(cpu.registers.t2, 2);
+        assert_eq!(cpu.registers.t3, 3);
+        assert_eq!(cpu.registers.pc, 0x00400008);
+    }
+
+    // opcode: 0b000101
+    #[test]
+    fn test_run_bne() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t1 = 1;
+        cpu.registers.t2 = 2;
+        memory.write_word(0x00400000, 0x012a4822).unwrap(); // sub $t1, $t1, $t2
+        memory.write_word(0x00400004, 0x152afffe).unwrap(); // bne $t1, $t2, -2
+        memory.write_word(0x00400008, 0x00000000).unwrap(); // nop
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400000);
+    }
+
+    // opcode: 0b000110
+    #[test]
+    fn test_run_blez() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.write_word(0x00400000, 0x19200003).unwrap(); // blez $t1, 3
+        memory.write_word(0x00400004, 0x00000000).unwrap(); // nop
+        cpu.registers.t1 = 0;
         cpu.step(&mut memory);
-        assert_eq!(cpu.registers.t1, 0b0000_0000_0000_0000_0000_0000_0010_1000);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400010);
+        cpu.registers.pc = 0x00400000;
+        cpu.registers.t1 = 1;
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400008);
     }
 
-    // opcode: 0b000000
-    // funct: 0b000110
+    // opcode: 0b000111
     #[test]
-    fn test_run_srlv() {
+    fn test_run_bgtz() {
         let mut cpu = CPU::default();
         let mut memory = Memory::default();
         memory.text_address = 0x00400000;
-        memory.write_word(0x00400000, 0x016A4806); // srlv $t1, $t2, $t3
-        cpu.registers.t2 = 0b0000_0000_0000_0000_0000_0000_0000_1010;
-        cpu.registers.t3 = 2;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.write_word(0x00400000, 0x1d200003).unwrap(); // bgtz $t1, 3
+        memory.write_word(0x00400004, 0x00000000).unwrap(); // nop
+        cpu.registers.t1 = 1;
         cpu.step(&mut memory);
-        assert_eq!(cpu.registers.t1, 0b0000_0000_0000_0000_0000_0000_0000_0010);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400010);
+        cpu.registers.pc = 0x00400000;
+        cpu.registers.t1 = 0;
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400008);
     }
 
-    // opcode: 0b000000
-    // funct: 0b000111
+    // opcode: 0b001000
     #[test]
-    fn test_run_srav() {
+    fn test_run_addi() {
         let mut cpu = CPU::default();
         let mut memory = Memory::default();
         memory.text_address = 0x00400000;
-        memory.write_word(0x00400000, 0x016A4807); // srav $t1, $t2, $t3
-        cpu.registers.t2 = 0b1000_0000_0000_0000_0000_0000_0000_1010;
-        cpu.registers.t3 = 2;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t1 = 1;
+        memory.write_word(0x00400000, 0x21290003).unwrap(); // addi $t1, $t1, 0x4821
         cpu.step(&mut memory);
-        assert_eq!(cpu.registers.t1, 0b1110_0000_0000_0000_0000_0000_0000_0010);
+        assert_eq!(cpu.registers.t1, 4);
     }
 
-    // opcode: 0b000000
-    // funct: 0b000110
+    // opcode: 0b001000
     #[test]
-    fn test_run_rotrv() {
+    fn test_run_addi_overflow() {
         let mut cpu = CPU::default();
         let mut memory = Memory::default();
         memory.text_address = 0x00400000;
-        memory.write_word(0x00400000, 0x014B4846); // rotrv $t1, $t2, $t3
-        cpu.registers.t2 = 2;
-        cpu.registers.t3 = 0b0000_0000_0000_0000_0000_0000_0000_1010;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t1 = i32::MIN as u32;
+        memory.write_word(0x00400000, 0x212affff).unwrap(); // addi $t2, $t1, -1
         cpu.step(&mut memory);
-        assert_eq!(cpu.registers.t1, 0b1000_0000_0000_0000_0000_0000_0000_0010);
+        assert_eq!(cpu.exception, Exception::IntegerOverflow);
+        assert_eq!(cpu.registers.t2, 0);
+        assert_eq!(cpu.registers.pc, 0x00400000);
     }
 
-    // opcode: 0b000000
-    // funct: 0b001000
+    // opcode: 0b001001
     #[test]
-    fn test_run_jr() {
+    fn test_run_addiu() {
         let mut cpu = CPU::default();
         let mut memory = Memory::default();
         memory.text_address = 0x00400000;
-        cpu.registers.t1 = 0xDEADBEEF;
-        memory.write_word(0x00400000, 0x01200008); // jr $t1
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t1 = 1;
+        memory.write_word(0x00400000, 0x25290003).unwrap(); // addiu $t1, $t1, 3
+        memory.write_word(0x00400004, 0x2529fffe).unwrap(); // addiu $t1, $t1, -2
         cpu.step(&mut memory);
-        assert_eq!(cpu.registers.pc, 0xDEADBEEF);
+        assert_eq!(cpu.registers.t1, 4);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t1, 2);
     }
 
-    // opcode: 0b000000
-    // funct: 0b001001
+    // opcode: 0b001010
     #[test]
-    fn test_run_jalr() {
+    fn test_run_slti() {
         let mut cpu = CPU::default();
         let mut memory = Memory::default();
         memory.text_address = 0x00400000;
-        cpu.registers.t1 = 0xDEADBEEF;
-        memory.write_word(0x00400000, 0x01205009); // jalr $t1, $t2
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t2 = u32::MAX;
+        memory.write_word(0x00400000, 0x2949ffff).unwrap(); // slti $t1, $t2, -1
+        memory.write_word(0x00400004, 0x29490000).unwrap(); // slti $t1, $t2, 0
         cpu.step(&mut memory);
-        assert_eq!(cpu.registers.pc, 0xDEADBEEF);
+        assert_eq!(cpu.registers.t1, 0);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t1, 1);
     }
 
-    // opcode: 0b000000
-    // funct: 0b001010
+    // opcode: 0b001011
     #[test]
-    fn test_run_movz() {
+    fn test_run_sltiu() {
         let mut cpu = CPU::default();
         let mut memory = Memory::default();
         memory.text_address = 0x00400000;
-        cpu.registers.t1 = 0;
-        cpu.registers.t2 = 0xDEADBEEF;
-        cpu.registers.t3 = 0;
-        memory.write_word(0x00400000, 0x014b480a); // movz $t1, $t2, $t3
-        memory.write_word(0x00400004, 0x014b480a); // movz $t1, $t2, $t3
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t2 = u32::MAX - 1;
+        memory.write_word(0x00400000, 0x2d49ffff).unwrap(); // sltiu $t1, $t2, -1
+        memory.write_word(0x00400004, 0x2d490001).unwrap(); // sltiu $t1, $t2, 1
         cpu.step(&mut memory);
-        assert_eq!(cpu.registers.t1, 0xDEADBEEF);
-        assert_eq!(cpu.registers.t2, 0xDEADBEEF);
-        assert_eq!(cpu.registers.t3, 0);
-        cpu.registers.t1 = 0;
-        cpu.registers.t3 = 1;
+        assert_eq!(cpu.registers.t1, 1);
         cpu.step(&mut memory);
         assert_eq!(cpu.registers.t1, 0);
-        assert_eq!(cpu.registers.t2, 0xDEADBEEF);
-        assert_eq!(cpu.registers.t3, 1);
     }
 
-    // opcode: 0b000000
-    // funct: 0b001011
+    // opcode: 0b001100
     #[test]
-    fn test_run_movn() {
+    fn test_run_andi() {
         let mut cpu = CPU::default();
         let mut memory = Memory::default();
         memory.text_address = 0x00400000;
-        cpu.registers.t1 = 0;
-        cpu.registers.t2 = 0xDEADBEEF;
-        cpu.registers.t3 = 0;
-        memory.write_word(0x00400000, 0x014b480b); // movn $t1, $t2, $t3
-        memory.write_word(0x00400004, 0x014b480b); // movn $t1, $t2, $t3
-        cpu.step(&mut memory);
-        assert_eq!(cpu.registers.t1, 0);
-        assert_eq!(cpu.registers.t2, 0xDEADBEEF);
-        assert_eq!(cpu.registers.t3, 0);
-        cpu.registers.t3 = 1;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t1 = 0xDEADBEEF;
+        memory.write_word(0x00400000, 0x3129BABE).unwrap(); // andi $t1, $t1, 3
         cpu.step(&mut memory);
-        assert_eq!(cpu.registers.t1, 0xDEADBEEF);
-        assert_eq!(cpu.registers.t2, 0xDEADBEEF);
-        assert_eq!(cpu.registers.t3, 1);
+        assert_eq!(cpu.registers.t1, 0x0000BAAE);
     }
 
-    // opcode: 0b000000
-    // funct: 0b001100
+    // opcode: 0b001101
     #[test]
-    fn test_run_syscall() {
+    fn test_run_ori() {
         let mut cpu = CPU::default();
         let mut memory = Memory::default();
         memory.text_address = 0x00400000;
-        cpu.registers.v0 = 10;
-        memory.write_word(0x00400000, 0x0000000c); // syscall
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t1 = 0xDEAD0000;
+        memory.write_word(0x00400000, 0x3529BEEF).unwrap(); // ori $t1, $t1, 0xbeef
         cpu.step(&mut memory);
-        assert_eq!(cpu.halted, true);
+        assert_eq!(cpu.registers.t1, 0xDEADBEEF);
     }
 
-    // opcode: 0b000000
-    // funct: 0b001101
+    // opcode: 0b001110
     #[test]
-    fn test_run_breakpoint() {
+    fn test_run_xori() {
         let mut cpu = CPU::default();
         let mut memory = Memory::default();
         memory.text_address = 0x00400000;
-        memory.write_word(0x00400000, 0x0000000d); // breakpoint
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t1 = 0xDEADBEEF;
+        memory.write_word(0x00400000, 0x3929FFFF).unwrap(); // xori $t1, $t1, 0xffff
         cpu.step(&mut memory);
-        assert_eq!(cpu.halted, true);
-        assert_eq!(cpu.exception, Exception::Breakpoint);
+        assert_eq!(cpu.registers.t1, 0xDEAD4110);
     }
 
-    // opcode: 0b000000
-    // funct: 0b010000
+    // opcode: 0b001111
     #[test]
-    fn test_run_mfhi() {
+    fn test_run_lui() {
         let mut cpu = CPU::default();
         let mut memory = Memory::default();
         memory.text_address = 0x00400000;
-        cpu.registers.hi = 0xDEADBEEF;
-        memory.write_word(0x00400000, 0x00004810); // mfhi $t1
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t1 = 0xFFFFFFFF;
+        memory.write_word(0x00400000, 0x3C090001).unwrap(); // lui $t1, 0x0001
         cpu.step(&mut memory);
-        assert_eq!(cpu.registers.t1, 0xDEADBEEF);
+        assert_eq!(cpu.registers.t1, 0x00010000);
     }
 
-    // opcode: 0b000000
-    // funct: 0b010001
+    // opcode: 0b010100
     #[test]
-    fn test_run_mthi() {
+    fn test_run_beql() {
         let mut cpu = CPU::default();
         let mut memory = Memory::default();
         memory.text_address = 0x00400000;
-        cpu.registers.t1 = 0xDEADBEEF;
-        memory.write_word(0x00400000, 0x01200011); // mthi $t1
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t1 = 1;
+        cpu.registers.t2 = 2;
+        cpu.registers.t3 = 3;
+        memory.write_word(0x00400000, 0x012a4820).unwrap(); // add $t1, $t1, $t2
+        memory.write_word(0x00400004, 0x512BFFFE).unwrap(); // beql $t1, $t3, -2
+        memory.write_word(0x00400008, 0x016a5820).unwrap(); // add $t3, $t3, $t2
         cpu.step(&mut memory);
-        assert_eq!(cpu.registers.hi, 0xDEADBEEF);
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t1, 3);
+        assert_eq!(cpu.registers.t2, 2);
+        assert_eq!(cpu.registers.t3, 5);
+        assert_eq!(cpu.registers.pc, 0x00400000);
+        cpu.registers.t3 = 3;
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        // Not taken, so the delay slot is annulled
+        assert_eq!(cpu.registers.t1, 5);
+        assert_eq!(cpu.registers.t2, 2);
+        assert_eq!(cpu.registers.t3, 3);
+        assert_eq!(cpu.registers.pc, 0x0040000c);
     }
 
-    // opcode: 0b000000
-    // funct: 0b010010
+    // opcode: 0b010101
     #[test]
-    fn test_run_mflo() {
+    fn test_run_bnel() {
         let mut cpu = CPU::default();
         let mut memory = Memory::default();
         memory.text_address = 0x00400000;
-        cpu.registers.lo = 0xDEADBEEF;
-        memory.write_word(0x00400000, 0x00004812); // mflo $t1
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.write_word(0x00400000, 0x552a0003).unwrap(); // bnel $t1, $t2, 3
+        memory.write_word(0x00400004, 0x00000000).unwrap(); // nop
+        cpu.registers.t1 = 1;
         cpu.step(&mut memory);
-        assert_eq!(cpu.registers.t1, 0xDEADBEEF);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400010);
+        cpu.registers.pc = 0x00400000;
+        cpu.registers.t2 = 1;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400008);
     }
 
-    // opcode: 0b000000
-    // funct: 0b010011
+    // opcode: 0b010110
     #[test]
-    fn test_run_mtlo() {
+    fn test_run_blezl() {
         let mut cpu = CPU::default();
         let mut memory = Memory::default();
         memory.text_address = 0x00400000;
-        cpu.registers.t1 = 0xDEADBEEF;
-        memory.write_word(0x00400000, 0x01200013); // mthi $t1
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.write_word(0x00400000, 0x59200003).unwrap(); // blezl $t1, 3
+        memory.write_word(0x00400004, 0x00000000).unwrap(); // nop
+        cpu.registers.t1 = 0x80000000;
         cpu.step(&mut memory);
-        assert_eq!(cpu.registers.lo, 0xDEADBEEF);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400010);
+        cpu.registers.pc = 0x00400000;
+        cpu.registers.t1 = 1;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400008);
     }
 
-    // opcode: 0b000000
-    // funct: 0b011000
+    // opcode: 0b010111
     #[test]
-    fn test_run_mult() {
+    fn test_run_bgtzl() {
         let mut cpu = CPU::default();
         let mut memory = Memory::default();
         memory.text_address = 0x00400000;
-        cpu.registers.t1 = 0xaaaaaaaa;
-        cpu.registers.t2 = 0x33333333;
-        memory.write_word(0x00400000, 0x012a0018); // mult $t1, $t2
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.write_word(0x00400000, 0x5d200003).unwrap(); // bgtzl $t1, 3
+        memory.write_word(0x00400004, 0x00000000).unwrap(); // nop
+        cpu.registers.t1 = 1;
         cpu.step(&mut memory);
-        assert_eq!(cpu.registers.hi, 0x22222221);
-        assert_eq!(cpu.registers.lo, 0xDDDDDDDE);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400010);
+        cpu.registers.pc = 0x00400000;
+        cpu.registers.t1 = 0x80000000;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400008);
     }
 
-    // opcode: 0b000000
-    // funct: 0b011001
     #[test]
-    fn test_run_multu() {
+    fn test_delay_slot_executes_before_branch() {
         let mut cpu = CPU::default();
         let mut memory = Memory::default();
         memory.text_address = 0x00400000;
-        cpu.registers.t1 = 0xaaaaaaaa;
-        cpu.registers.t2 = 0x33333333;
-        memory.write_word(0x00400000, 0x012a0019); // multu $t1, $t2
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t1 = 1;
+        memory.write_word(0x00400000, 0x08100004).unwrap(); // j 0x00400010
+        memory.write_word(0x00400004, 0x21290001).unwrap(); // addi $t1, $t1, 1
+        memory.write_word(0x00400008, 0x21290010).unwrap(); // addi $t1, $t1, 16
+        memory.write_word(0x0040000c, 0x00000000).unwrap(); // nop
+        memory.write_word(0x00400010, 0x00000000).unwrap(); // nop
         cpu.step(&mut memory);
-        assert_eq!(cpu.registers.hi, 0x22222221);
-        assert_eq!(cpu.registers.lo, 0xDDDDDDDE);
+        assert_eq!(cpu.registers.pc, 0x00400004);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t1, 2);
+        assert_eq!(cpu.registers.pc, 0x00400010);
     }
 
-    // opcode: 0b000000
-    // funct: 0b011010
     #[test]
-    fn test_run_div() {
+    fn test_without_delay_slots() {
         let mut cpu = CPU::default();
         let mut memory = Memory::default();
         memory.text_address = 0x00400000;
-        cpu.registers.t1 = 10;
-        cpu.registers.t2 = 3;
-        memory.write_word(0x00400000, 0x012a001a); // div $t1, $t2
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.delay_slots = false;
+        cpu.registers.t1 = 0x00400010;
+        memory.write_word(0x00400000, 0x01205009).unwrap(); // jalr $t1, $t2
+        memory.write_word(0x00400004, 0x00000000).unwrap(); // nop
+        memory.write_word(0x00400010, 0x00000000).unwrap(); // nop
         cpu.step(&mut memory);
-        assert_eq!(cpu.registers.hi, 1);
-        assert_eq!(cpu.registers.lo, 3);
+        assert_eq!(cpu.registers.pc, 0x00400010);
+        assert_eq!(cpu.registers.t2, 0x00400004);
     }
 
-    // opcode: 0b000000
-    // funct: 0b011011
+    // opcode: 0b011100
+    // funct: 0b000000
     #[test]
-    fn test_run_divu() {
+    fn test_run_madd() {
         let mut cpu = CPU::default();
         let mut memory = Memory::default();
         memory.text_address = 0x00400000;
-        cpu.registers.t1 = 10;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t1 = (-2i32) as u32;
         cpu.registers.t2 = 3;
-        memory.write_word(0x00400000, 0x012a001b); // div $t1, $t2
+        cpu.registers.hi = 1;
+        cpu.registers.lo = 0xffffffff;
+        memory.write_word(0x00400000, 0x712a0000).unwrap(); // madd $t1, $t2
         cpu.step(&mut memory);
         assert_eq!(cpu.registers.hi, 1);
-        assert_eq!(cpu.registers.lo, 3);
+        assert_eq!(cpu.registers.lo, 0xfffffff9);
     }
 
-    // opcode: 0b000000
-    // funct: 0b100000
+    // opcode: 0b011100
+    // funct: 0b000001
     #[test]
-    fn test_run_add() {
+    fn test_run_maddu() {
         let mut cpu = CPU::default();
         let mut memory = Memory::default();
         memory.text_address = 0x00400000;
-        cpu.registers.t2 = 1;
-        cpu.registers.t3 = 2;
-        memory.write_word(0x00400000, 0x014b4820); // add $t1, $t2, $t3
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t1 = 0xffffffff;
+        cpu.registers.t2 = 2;
+        cpu.registers.hi = 0;
+        cpu.registers.lo = 0xffffffff;
+        memory.write_word(0x00400000, 0x712a0001).unwrap(); // maddu $t1, $t2
         cpu.step(&mut memory);
-        assert_eq!(cpu.registers.t1, 3);
+        assert_eq!(cpu.registers.hi, 2);
+        assert_eq!(cpu.registers.lo, 0xfffffffd);
     }
 
-    // opcode: 0b000000
-    // funct: 0b100001
+    // opcode: 0b011100
+    // funct: 0b000010
     #[test]
-    fn test_run_addu() {
+    fn test_run_mul() {
         let mut cpu = CPU::default();
         let mut memory = Memory::default();
         memory.text_address = 0x00400000;
-        cpu.registers.t2 = 1;
-        cpu.registers.t3 = 2;
-        memory.write_word(0x00400000, 0x014b4821); // addu $t1, $t2, $t3
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t2 = (-6i32) as u32;
+        cpu.registers.t3 = 7;
+        cpu.registers.hi = 0xDEADBEEF;
+        memory.write_word(0x00400000, 0x714b4802).unwrap(); // mul $t1, $t2, $t3
         cpu.step(&mut memory);
-        assert_eq!(cpu.registers.t1, 3);
+        assert_eq!(cpu.registers.t1, (-42i32) as u32);
+        assert_eq!(cpu.registers.hi, 0xDEADBEEF);
     }
 
-    // opcode: 0b000000
-    // funct: 0b100010
+    // opcode: 0b011100
+    // funct: 0b000100
     #[test]
-    fn test_run_sub() {
+    fn test_run_msub() {
         let mut cpu = CPU::default();
         let mut memory = Memory::default();
         memory.text_address = 0x00400000;
-        cpu.registers.t2 = u32::MAX;
-        cpu.registers.t3 = u32::MAX - 1;
-        memory.write_word(0x00400000, 0x014b4822); // sub $t1, $t2, $t3
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t1 = (-2i32) as u32;
+        cpu.registers.t2 = 3;
+        cpu.registers.hi = 0;
+        cpu.registers.lo = 0;
+        memory.write_word(0x00400000, 0x712a0004).unwrap(); // msub $t1, $t2
         cpu.step(&mut memory);
-        assert_eq!(cpu.registers.t1 as i32, 1);
+        assert_eq!(cpu.registers.hi, 0);
+        assert_eq!(cpu.registers.lo, 6);
     }
 
-    // opcode: 0b000000
-    // funct: 0b100011
+    // opcode: 0b011100
+    // funct: 0b000101
     #[test]
-    fn test_run_subu() {
+    fn test_run_msubu() {
         let mut cpu = CPU::default();
         let mut memory = Memory::default();
         memory.text_address = 0x00400000;
-        cpu.registers.t2 = u32::MAX;
-        cpu.registers.t3 = u32::MAX - 1;
-        memory.write_word(0x00400000, 0x014b4823); // subu $t1, $t2, $t3
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t1 = 2;
+        cpu.registers.t2 = 3;
+        cpu.registers.hi = 0;
+        cpu.registers.lo = 1;
+        memory.write_word(0x00400000, 0x712a0005).unwrap(); // msubu $t1, $t2
         cpu.step(&mut memory);
-        assert_eq!(cpu.registers.t1, 1);
+        assert_eq!(cpu.registers.hi, 0xffffffff);
+        assert_eq!(cpu.registers.lo, 0xfffffffb);
     }
 
-    // opcode: 0b000000
-    // funct: 0b100100
+    // opcode: 0b011100
+    // funct: 0b100000
     #[test]
-    fn test_run_and() {
+    fn test_run_clz() {
         let mut cpu = CPU::default();
         let mut memory = Memory::default();
         memory.text_address = 0x00400000;
-        cpu.registers.t2 = 0b10101010;
-        cpu.registers.t3 = 0b11100011;
-        memory.write_word(0x00400000, 0x014b4824); // and $t1, $t2, $t3
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t2 = 0x0000ffff;
+        memory.write_word(0x00400000, 0x71404820).unwrap(); // clz $t1, $t2
         cpu.step(&mut memory);
-        assert_eq!(cpu.registers.t1, 0b10100010);
+        assert_eq!(cpu.registers.t1, 16);
     }
 
-    // opcode: 0b000000
-    // funct: 0b100101
+    // opcode: 0b011100
+    // funct: 0b100001
     #[test]
-    fn test_run_or() {
+    fn test_run_clo() {
         let mut cpu = CPU::default();
         let mut memory = Memory::default();
         memory.text_address = 0x00400000;
-        cpu.registers.t2 = 0b10101010;
-        cpu.registers.t3 = 0b11100011;
-        memory.write_word(0x00400000, 0x014b4825); // or $t1, $t2, $t3
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t2 = 0xfff00000;
+        memory.write_word(0x00400000, 0x71404821).unwrap(); // clo $t1, $t2
         cpu.step(&mut memory);
-        assert_eq!(cpu.registers.t1, 0b11101011);
+        assert_eq!(cpu.registers.t1, 12);
     }
 
-    // opcode: 0b000000
-    // funct: 0b100110
+    // opcode: 0b011111
+    // funct: 0b000000
     #[test]
-    fn test_run_xor() {
+    fn test_run_ext() {
         let mut cpu = CPU::default();
         let mut memory = Memory::default();
         memory.text_address = 0x00400000;
-        cpu.registers.t2 = 0b10101010;
-        cpu.registers.t3 = 0b11100011;
-        memory.write_word(0x00400000, 0x014b4826); // xor $t1, $t2, $t3
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t2 = 0xDEADBEEF;
+        memory.write_word(0x00400000, 0x7d493900).unwrap(); // ext $t1, $t2, 4, 8
         cpu.step(&mut memory);
-        assert_eq!(cpu.registers.t1, 0b01001001);
+        assert_eq!(cpu.registers.t1, 0xEE);
     }
 
-    // opcode: 0b000000
-    // funct: 0b100111
+    // opcode: 0b011111
+    // funct: 0b000100
     #[test]
-    fn test_run_nor() {
+    fn test_run_ins() {
         let mut cpu = CPU::default();
         let mut memory = Memory::default();
         memory.text_address = 0x00400000;
-        cpu.registers.t2 = 0x0000FAFB;
-        cpu.registers.t3 = 0x0000F000;
-        memory.write_word(0x00400000, 0x014b4827); // nor $t1, $t2, $t3
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t1 = 0xDEADBEEF;
+        cpu.registers.t2 = 0x12345678;
+        memory.write_word(0x00400000, 0x7d497a04).unwrap(); // ins $t1, $t2, 8, 8
         cpu.step(&mut memory);
-        assert_eq!(cpu.registers.t1, 0xFFFF0504);
+        assert_eq!(cpu.registers.t1, 0xDEAD78EF);
     }
 
-    // opcode: 0b000000
-    // funct: 0b101010
+    // opcode: 0b011111
+    // funct: 0b100000
+    // shamt: 0b00010
     #[test]
-    fn test_run_slt() {
+    fn test_run_wsbh() {
         let mut cpu = CPU::default();
         let mut memory = Memory::default();
         memory.text_address = 0x00400000;
-        cpu.registers.t2 = u32::MAX - 1;
-        cpu.registers.t3 = u32::MAX;
-        memory.write_word(0x00400000, 0x014b482a); // slt $t1, $t2, $t3
-        memory.write_word(0x00400004, 0x014b482a); // slt $t1, $t2, $t3
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t2 = 0x11223344;
+        memory.write_word(0x00400000, 0x7c0a48a0).unwrap(); // wsbh $t1, $t2
         cpu.step(&mut memory);
-        assert_eq!(cpu.registers.t1, 1);
-        cpu.registers.t2 = u32::MAX;
-        cpu.registers.t3 = u32::MAX - 1;
-        cpu.step(&mut memory);
-        assert_eq!(cpu.registers.t1, 0);
+        assert_eq!(cpu.registers.t1, 0x22114433);
     }
-
-    // opcode: 0b000000
-    // funct: 0b101011
+
+    // opcode: 0b011111
+    // funct: 0b100000
+    // shamt: 0b10000
     #[test]
-    fn test_run_sltu() {
+    fn test_run_seb() {
         let mut cpu = CPU::default();
         let mut memory = Memory::default();
         memory.text_address = 0x00400000;
-        cpu.registers.t2 = u32::MAX - 1;
-        cpu.registers.t3 = u32::MAX;
-        memory.write_word(0x00400000, 0x014b482b); // sltu $t1, $t2, $t3
-        memory.write_word(0x00400004, 0x014b482b); // sltu $t1, $t2, $t3
-        cpu.step(&mut memory);
-        assert_eq!(cpu.registers.t1, 1);
-        cpu.registers.t2 = u32::MAX;
-        cpu.registers.t3 = u32::MAX - 1;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t2 = 0x00000080;
+        memory.write_word(0x00400000, 0x7c0a4c20).unwrap(); // seb $t1, $t2
         cpu.step(&mut memory);
-        assert_eq!(cpu.registers.t1, 0);
+        assert_eq!(cpu.registers.t1, 0xFFFFFF80);
     }
 
-    // opcode: 0b000000
-    // funct: 0b110000
+    // opcode: 0b011111
+    // funct: 0b100000
+    // shamt: 0b11000
     #[test]
-    fn test_run_tge() {
+    fn test_run_seh() {
         let mut cpu = CPU::default();
         let mut memory = Memory::default();
         memory.text_address = 0x00400000;
-        memory.write_word(0x00400000, 0x014b4830); // tge $t2, $t3
-        memory.write_word(0x00400004, 0x014b4830); // tge $t2, $t3
-        memory.write_word(0x00400008, 0x014b4830); // tge $t2, $t3
-        memory.write_word(0x0040000c, 0x014b4830); // tge $t2, $t3
-        cpu.registers.t2 = 1;
-        cpu.registers.t3 = 2;
-        cpu.exception = Exception::None;
-        cpu.step(&mut memory);
-        assert_eq!(cpu.exception, Exception::None);
-        cpu.registers.t2 = 1;
-        cpu.registers.t3 = 1;
-        cpu.exception = Exception::None;
-        cpu.step(&mut memory);
-        assert_eq!(cpu.exception, Exception::Trap);
-        cpu.registers.t2 = u32::MAX;
-        cpu.registers.t3 = 1;
-        cpu.exception = Exception::None;
-        cpu.step(&mut memory);
-        assert_eq!(cpu.exception, Exception::None);
-        cpu.registers.t2 = 2;
-        cpu.registers.t3 = 1;
-        cpu.exception = Exception::None;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t2 = 0x12348000;
+        memory.write_word(0x00400000, 0x7c0a4e20).unwrap(); // seh $t1, $t2
         cpu.step(&mut memory);
-        assert_eq!(cpu.exception, Exception::Trap);
+        assert_eq!(cpu.registers.t1, 0xFFFF8000);
     }
 
-    // opcode: 0b000000
-    // funct: 0b110001
+    // opcode: 0b100000
     #[test]
-    fn test_run_tgeu() {
+    fn test_run_lb() {
         let mut cpu = CPU::default();
         let mut memory = Memory::default();
         memory.text_address = 0x00400000;
-        memory.write_word(0x00400000, 0x014b4831); // tgeu $t2, $t3
-        memory.write_word(0x00400004, 0x014b4831); // tgeu $t2, $t3
-        memory.write_word(0x00400008, 0x014b4831); // tgeu $t2, $t3
-        memory.write_word(0x0040000c, 0x014b4831); // tgeu $t2, $t3
-        cpu.registers.t2 = 1;
-        cpu.registers.t3 = 2;
-        cpu.exception = Exception::None;
-        cpu.step(&mut memory);
-        assert_eq!(cpu.exception, Exception::None);
-        cpu.registers.t2 = 1;
-        cpu.registers.t3 = 1;
-        cpu.exception = Exception::None;
-        cpu.step(&mut memory);
-        assert_eq!(cpu.exception, Exception::Trap);
-        cpu.registers.t2 = u32::MAX;
-        cpu.registers.t3 = 1;
-        cpu.exception = Exception::None;
-        cpu.step(&mut memory);
-        assert_eq!(cpu.exception, Exception::Trap);
-        cpu.registers.t2 = 2;
-        cpu.registers.t3 = 1;
-        cpu.exception = Exception::None;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.data_address = 0x10000000;
+        memory.map(0x10000000, 0x1000, Permissions::READ_WRITE);
+        cpu.registers.t1 = 0x10000000;
+        memory.write_word(0x00400000, 0x812a0005).unwrap(); // lb $t2, 5($t1)
+        memory.write_word(0x10000004, 0x89abcdef).unwrap();
         cpu.step(&mut memory);
-        assert_eq!(cpu.exception, Exception::Trap);
+        assert_eq!(cpu.registers.t2, 0xffffffab);
     }
 
-    // opcode: 0b000000
-    // funct: 0b110010
+    // opcode: 0b100001
     #[test]
-    fn test_run_tlt() {
+    fn test_run_lh() {
         let mut cpu = CPU::default();
         let mut memory = Memory::default();
         memory.text_address = 0x00400000;
-        memory.write_word(0x00400000, 0x014b4832); // tge $t2, $t3
-        memory.write_word(0x00400004, 0x014b4832); // tge $t2, $t3
-        memory.write_word(0x00400008, 0x014b4832); // tge $t2, $t3
-        memory.write_word(0x0040000c, 0x014b4832); // tge $t2, $t3
-        cpu.registers.t2 = 1;
-        cpu.registers.t3 = 2;
-        cpu.exception = Exception::None;
-        cpu.step(&mut memory);
-        assert_eq!(cpu.exception, Exception::Trap);
-        cpu.registers.t2 = 1;
-        cpu.registers.t3 = 1;
-        cpu.exception = Exception::None;
-        cpu.step(&mut memory);
-        assert_eq!(cpu.exception, Exception::None);
-        cpu.registers.t2 = u32::MAX;
-        cpu.registers.t3 = 1;
-        cpu.exception = Exception::None;
-        cpu.step(&mut memory);
-        assert_eq!(cpu.exception, Exception::Trap);
-        cpu.registers.t2 = 2;
-        cpu.registers.t3 = 1;
-        cpu.exception = Exception::None;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.data_address = 0x10000000;
+        memory.map(0x10000000, 0x1000, Permissions::READ_WRITE);
+        cpu.registers.t1 = 0x10000000;
+        memory.write_word(0x00400000, 0x852a0004).unwrap(); // lh $t2, 4($t1)
+        memory.write_word(0x10000004, 0x89abcdef).unwrap();
         cpu.step(&mut memory);
-        assert_eq!(cpu.exception, Exception::None);
+        assert_eq!(cpu.registers.t2, 0xffff89ab);
     }
 
-    // opcode: 0b000000
-    // funct: 0b110011
+    // opcode: 0b100010
     #[test]
-    fn test_run_tltu() {
+    fn test_run_lwl() {
+        for (word, expected) in [
+            (0x892a0000, 0x11223344), // lwl $t2, 0($t1)
+            (0x892a0001, 0x223344dd), // lwl $t2, 1($t1)
+            (0x892a0002, 0x3344ccdd), // lwl $t2, 2($t1)
+            (0x892a0003, 0x44bbccdd), // lwl $t2, 3($t1)
+        ] {
+            let mut cpu = CPU::default();
+            let mut memory = Memory::default();
+            memory.text_address = 0x00400000;
+            memory.map(0x00400000, 0x1000, Permissions::ALL);
+            memory.data_address = 0x10000000;
+            memory.map(0x10000000, 0x1000, Permissions::READ_WRITE);
+            cpu.registers.t1 = 0x10000000;
+            cpu.registers.t2 = 0xaabbccdd;
+            memory.write_word(0x00400000, word).unwrap();
+            memory.write_word(0x10000000, 0x11223344).unwrap();
+            cpu.step(&mut memory);
+            assert_eq!(cpu.registers.t2, expected);
+        }
+    }
+
+    // opcode: 0b100010
+    #[test]
+    fn test_run_lwl_little_endian() {
+        for (word, expected) in [
+            (0x892a0000, 0x44bbccdd), // lwl $t2, 0($t1)
+            (0x892a0001, 0x3344ccdd), // lwl $t2, 1($t1)
+            (0x892a0002, 0x223344dd), // lwl $t2, 2($t1)
+            (0x892a0003, 0x11223344), // lwl $t2, 3($t1)
+        ] {
+            let mut cpu = CPU::default();
+            let mut memory = Memory::default();
+            memory.endianness = Endianness::Little;
+            memory.text_address = 0x00400000;
+            memory.map(0x00400000, 0x1000, Permissions::ALL);
+            memory.data_address = 0x10000000;
+            memory.map(0x10000000, 0x1000, Permissions::READ_WRITE);
+            cpu.registers.t1 = 0x10000000;
+            cpu.registers.t2 = 0xaabbccdd;
+            memory.write_word(0x00400000, word).unwrap();
+            memory.write_word(0x10000000, 0x11223344).unwrap();
+            cpu.step(&mut memory);
+            assert_eq!(cpu.registers.t2, expected);
+        }
+    }
+
+    // opcode: 0b100011
+    #[test]
+    fn test_run_lw() {
         let mut cpu = CPU::default();
         let mut memory = Memory::default();
         memory.text_address = 0x00400000;
-        memory.write_word(0x00400000, 0x014b4833); // tgeu $t2, $t3
-        memory.write_word(0x00400004, 0x014b4833); // tgeu $t2, $t3
-        memory.write_word(0x00400008, 0x014b4833); // tgeu $t2, $t3
-        memory.write_word(0x0040000c, 0x014b4833); // tgeu $t2, $t3
-        cpu.registers.t2 = 1;
-        cpu.registers.t3 = 2;
-        cpu.exception = Exception::None;
-        cpu.step(&mut memory);
-        assert_eq!(cpu.exception, Exception::Trap);
-        cpu.registers.t2 = 1;
-        cpu.registers.t3 = 1;
-        cpu.exception = Exception::None;
-        cpu.step(&mut memory);
-        assert_eq!(cpu.exception, Exception::None);
-        cpu.registers.t2 = u32::MAX;
-        cpu.registers.t3 = 1;
-        cpu.exception = Exception::None;
-        cpu.step(&mut memory);
-        assert_eq!(cpu.exception, Exception::None);
-        cpu.registers.t2 = 2;
-        cpu.registers.t3 = 1;
-        cpu.exception = Exception::None;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.data_address = 0x10000000;
+        memory.map(0x10000000, 0x1000, Permissions::READ_WRITE);
+        cpu.registers.t1 = 0x10000000;
+        memory.write_word(0x00400000, 0x8d2a0004).unwrap(); // lw $t2, 4($t1)
+        memory.write_word(0x10000004, 0x12345678).unwrap();
         cpu.step(&mut memory);
-        assert_eq!(cpu.exception, Exception::None);
+        assert_eq!(cpu.registers.t2, 0x12345678);
+        assert!(memory.is_mapped(0x10000004));
     }
 
-    // opcode: 0b000000
-    // funct: 0b110100
     #[test]
-    fn test_run_teq() {
+    fn test_little_endian_byte_order() {
         let mut cpu = CPU::default();
         let mut memory = Memory::default();
+        memory.endianness = Endianness::Little;
         memory.text_address = 0x00400000;
-        memory.write_word(0x00400000, 0x014b4834); // teq $t2, $t3
-        memory.write_word(0x00400004, 0x014b4834); // teq $t2, $t3
-        cpu.registers.t2 = 1;
-        cpu.registers.t3 = 2;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.data_address = 0x10000000;
+        memory.map(0x10000000, 0x1000, Permissions::READ_WRITE);
+        cpu.registers.t1 = 0x10000000;
+        memory.write_word(0x00400000, 0x852a0002).unwrap(); // lh $t2, 2($t1)
+        memory.write_word(0x00400004, 0x912b0000).unwrap(); // lbu $t3, 0($t1)
+        memory.write_word(0x10000000, 0x81223344).unwrap();
+        assert_eq!(memory.read_byte(0x00400000).unwrap(), 0x02);
         cpu.step(&mut memory);
-        assert_eq!(cpu.exception, Exception::None);
-        cpu.registers.t3 = 1;
-        cpu.exception = Exception::None;
         cpu.step(&mut memory);
-        assert_eq!(cpu.exception, Exception::Trap);
+        assert_eq!(cpu.registers.t2, 0xffff8122);
+        assert_eq!(cpu.registers.t3, 0x44);
     }
 
-    // opcode: 0b000000
-    // funct: 0b110110
+    // opcode: 0b100011
     #[test]
-    fn test_run_tne() {
+    fn test_run_lw_misaligned() {
         let mut cpu = CPU::default();
         let mut memory = Memory::default();
         memory.text_address = 0x00400000;
-        memory.write_word(0x00400000, 0x014b4836); // tne $t2, $t3
-        memory.write_word(0x00400004, 0x014b4836); // tne $t2, $t3
-        cpu.registers.t2 = 1;
-        cpu.registers.t3 = 2;
-        cpu.step(&mut memory);
-        assert_eq!(cpu.exception, Exception::Trap);
-        cpu.registers.t3 = 1;
-        cpu.exception = Exception::None;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.data_address = 0x10000000;
+        memory.map(0x10000000, 0x1000, Permissions::READ_WRITE);
+        cpu.registers.t1 = 0x10000000;
+        memory.write_word(0x00400000, 0x8d2a0002).unwrap(); // lw $t2, 2($t1)
+        memory.write_word(0x10000000, 0x12345678).unwrap();
         cpu.step(&mut memory);
-        assert_eq!(cpu.exception, Exception::None);
+        assert_eq!(cpu.exception, Exception::AddressErrorLoad(0x10000002));
+        assert_eq!(cpu.control_registers.bad_vaddr, 0x10000002);
+        assert_eq!(cpu.control_registers.cause, 4 << 2);
+        assert_eq!(cpu.registers.t2, 0);
+        assert_eq!(cpu.registers.pc, 0x00400000);
     }
 
-    // opcode: 0b000010
+    // opcode: 0b100100
     #[test]
-    fn test_run_j() {
+    fn test_run_lbu() {
         let mut cpu = CPU::default();
         let mut memory = Memory::default();
         memory.text_address = 0x00400000;
-        memory.write_word(0x00400000, 0x08100000); // j 0x00400000
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.data_address = 0x10000000;
+        memory.map(0x10000000, 0x1000, Permissions::READ_WRITE);
+        cpu.registers.t1 = 0x10000000;
+        memory.write_word(0x00400000, 0x912a0005).unwrap(); // lbu $t2, 5($t1)
+        memory.write_word(0x10000004, 0x89abcdef).unwrap();
         cpu.step(&mut memory);
-        assert_eq!(cpu.registers.pc, 0x00400000);
+        assert_eq!(cpu.registers.t2, 0x000000ab);
     }
 
-    // opcode: 0b000100
+    // opcode: 0b100101
     #[test]
-    fn test_run_beq() {
+    fn test_run_lhu() {
         let mut cpu = CPU::default();
         let mut memory = Memory::default();
         memory.text_address = 0x00400000;
-        cpu.registers.t1 = 1;
-        cpu.registers.t2 = 2;
-        cpu.registers.t3 = 3;
-        memory.write_word(0x00400000, 0x012a4820); // add $t1, $t1, $t2
-        memory.write_word(0x00400004, 0x112bfffe); // beq $t1, $t3, -2
-        cpu.step(&mut memory);
-        cpu.step(&mut memory);
-        assert_eq!(cpu.registers.t1, 3);
-        assert_eq!(cpu.registers.t2, 2);
-        assert_eq!(cpu.registers.t3, 3);
-        assert_eq!(cpu.registers.pc, 0x00400000);
-        cpu.step(&mut memory);
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.data_address = 0x10000000;
+        memory.map(0x10000000, 0x1000, Permissions::READ_WRITE);
+        cpu.registers.t1 = 0x10000000;
+        memory.write_word(0x00400000, 0x952a0006).unwrap(); // lhu $t2, 6($t1)
+        memory.write_word(0x10000004, 0x89abcdef).unwrap();
         cpu.step(&mut memory);
-        assert_eq!(cpu.registers.t1, 5);
-        assert_eq!(cpu.registers.t2, 2);
-        assert_eq!(cpu.registers.t3, 3);
-        assert_eq!(cpu.registers.pc, 0x00400008);
+        assert_eq!(cpu.registers.t2, 0x0000cdef);
     }
 
-    // opcode: 0b000101
+    // opcode: 0b100110
     #[test]
-    fn test_run_bne() {
+    fn test_run_lwr() {
+        for (word, expected) in [
+            (0x992a0000, 0xaabbcc11), // lwr $t2, 0($t1)
+            (0x992a0001, 0xaabb1122), // lwr $t2, 1($t1)
+            (0x992a0002, 0xaa112233), // lwr $t2, 2($t1)
+            (0x992a0003, 0x11223344), // lwr $t2, 3($t1)
+        ] {
+            let mut cpu = CPU::default();
+            let mut memory = Memory::default();
+            memory.text_address = 0x00400000;
+            memory.map(0x00400000, 0x1000, Permissions::ALL);
+            memory.data_address = 0x10000000;
+            memory.map(0x10000000, 0x1000, Permissions::READ_WRITE);
+            cpu.registers.t1 = 0x10000000;
+            cpu.registers.t2 = 0xaabbccdd;
+            memory.write_word(0x00400000, word).unwrap();
+            memory.write_word(0x10000000, 0x11223344).unwrap();
+            cpu.step(&mut memory);
+            assert_eq!(cpu.registers.t2, expected);
+        }
+    }
+
+    // opcode: 0b100110
+    #[test]
+    fn test_run_lwr_little_endian() {
+        for (word, expected) in [
+            (0x992a0000, 0x11223344), // lwr $t2, 0($t1)
+            (0x992a0001, 0xaa112233), // lwr $t2, 1($t1)
+            (0x992a0002, 0xaabb1122), // lwr $t2, 2($t1)
+            (0x992a0003, 0xaabbcc11), // lwr $t2, 3($t1)
+        ] {
+            let mut cpu = CPU::default();
+            let mut memory = Memory::default();
+            memory.endianness = Endianness::Little;
+            memory.text_address = 0x00400000;
+            memory.map(0x00400000, 0x1000, Permissions::ALL);
+            memory.data_address = 0x10000000;
+            memory.map(0x10000000, 0x1000, Permissions::READ_WRITE);
+            cpu.registers.t1 = 0x10000000;
+            cpu.registers.t2 = 0xaabbccdd;
+            memory.write_word(0x00400000, word).unwrap();
+            memory.write_word(0x10000000, 0x11223344).unwrap();
+            cpu.step(&mut memory);
+            assert_eq!(cpu.registers.t2, expected);
+        }
+    }
+
+    // opcode: 0b101000
+    #[test]
+    fn test_run_sb() {
         let mut cpu = CPU::default();
         let mut memory = Memory::default();
         memory.text_address = 0x00400000;
-        cpu.registers.t1 = 1;
-        cpu.registers.t2 = 2;
-        memory.write_word(0x00400000, 0x012a4822); // sub $t1, $t1, $t2
-        memory.write_word(0x00400004, 0x152afffe); // bne $t1, $t2, -2
-        cpu.step(&mut memory);
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.data_address = 0x10000000;
+        memory.map(0x10000000, 0x1000, Permissions::READ_WRITE);
+        cpu.registers.t1 = 0x10000000;
+        cpu.registers.t2 = 0x12345678;
+        memory.write_word(0x00400000, 0xa12a0005).unwrap(); // sb $t2, 5($t1)
+        memory.write_word(0x10000004, 0xffffffff).unwrap();
         cpu.step(&mut memory);
-        assert_eq!(cpu.registers.pc, 0x00400000);
+        assert_eq!(memory.read_word(0x10000004).unwrap(), 0xff78ffff);
     }
 
-    // opcode: 0b001000
+    // opcode: 0b101001
     #[test]
-    fn test_run_addi() {
+    fn test_run_sh() {
         let mut cpu = CPU::default();
         let mut memory = Memory::default();
         memory.text_address = 0x00400000;
-        cpu.registers.t1 = 1;
-        memory.write_word(0x00400000, 0x21290003); // addi $t1, $t1, 0x4821
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.data_address = 0x10000000;
+        memory.map(0x10000000, 0x1000, Permissions::READ_WRITE);
+        cpu.registers.t1 = 0x10000000;
+        cpu.registers.t2 = 0x12345678;
+        memory.write_word(0x00400000, 0xa52a0006).unwrap(); // sh $t2, 6($t1)
+        memory.write_word(0x10000004, 0xffffffff).unwrap();
         cpu.step(&mut memory);
-        assert_eq!(cpu.registers.t1, 4);
+        assert_eq!(memory.read_word(0x10000004).unwrap(), 0xffff5678);
     }
 
-    // opcode: 0b001001
+    // opcode: 0b101010
     #[test]
-    fn test_run_addiu() {
+    fn test_run_swl() {
+        for (word, expected) in [
+            (0xa92a0000, 0xaabbccdd), // swl $t2, 0($t1)
+            (0xa92a0001, 0x11aabbcc), // swl $t2, 1($t1)
+            (0xa92a0002, 0x1122aabb), // swl $t2, 2($t1)
+            (0xa92a0003, 0x112233aa), // swl $t2, 3($t1)
+        ] {
+            let mut cpu = CPU::default();
+            let mut memory = Memory::default();
+            memory.text_address = 0x00400000;
+            memory.map(0x00400000, 0x1000, Permissions::ALL);
+            memory.data_address = 0x10000000;
+            memory.map(0x10000000, 0x1000, Permissions::READ_WRITE);
+            cpu.registers.t1 = 0x10000000;
+            cpu.registers.t2 = 0xaabbccdd;
+            memory.write_word(0x00400000, word).unwrap();
+            memory.write_word(0x10000000, 0x11223344).unwrap();
+            cpu.step(&mut memory);
+            assert_eq!(memory.read_word(0x10000000).unwrap(), expected);
+        }
+    }
+
+    // opcode: 0b101010
+    #[test]
+    fn test_run_swl_little_endian() {
+        for (word, expected) in [
+            (0xa92a0000, 0x112233aa), // swl $t2, 0($t1)
+            (0xa92a0001, 0x1122aabb), // swl $t2, 1($t1)
+            (0xa92a0002, 0x11aabbcc), // swl $t2, 2($t1)
+            (0xa92a0003, 0xaabbccdd), // swl $t2, 3($t1)
+        ] {
+            let mut cpu = CPU::default();
+            let mut memory = Memory::default();
+            memory.endianness = Endianness::Little;
+            memory.text_address = 0x00400000;
+            memory.map(0x00400000, 0x1000, Permissions::ALL);
+            memory.data_address = 0x10000000;
+            memory.map(0x10000000, 0x1000, Permissions::READ_WRITE);
+            cpu.registers.t1 = 0x10000000;
+            cpu.registers.t2 = 0xaabbccdd;
+            memory.write_word(0x00400000, word).unwrap();
+            memory.write_word(0x10000000, 0x11223344).unwrap();
+            cpu.step(&mut memory);
+            assert_eq!(memory.read_word(0x10000000).unwrap(), expected);
+        }
+    }
+
+    // opcode: 0b101011
+    #[test]
+    fn test_run_sw() {
         let mut cpu = CPU::default();
         let mut memory = Memory::default();
         memory.text_address = 0x00400000;
-        cpu.registers.t1 = 1;
-        memory.write_word(0x00400000, 0x25290003); // addiu $t1, $t1, 3
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.data_address = 0x10000000;
+        memory.map(0x10000000, 0x1000, Permissions::READ_WRITE);
+        cpu.registers.t1 = 0x10000000;
+        cpu.registers.t2 = 0x12345678;
+        memory.write_word(0x00400000, 0xad2a0004).unwrap(); // sw $t2, 4($t1)
         cpu.step(&mut memory);
-        assert_eq!(cpu.registers.t1, 4);
+        assert_eq!(memory.read_word(0x10000004).unwrap(), 0x12345678);
+        assert!(memory.is_mapped(0x10000004));
     }
 
-    // opcode: 0b001100
+    // opcode: 0b101001
     #[test]
-    fn test_run_andi() {
+    fn test_run_sh_misaligned() {
         let mut cpu = CPU::default();
         let mut memory = Memory::default();
         memory.text_address = 0x00400000;
-        cpu.registers.t1 = 0xDEADBEEF;
-        memory.write_word(0x00400000, 0x3129BABE); // andi $t1, $t1, 3
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.data_address = 0x10000000;
+        memory.map(0x10000000, 0x1000, Permissions::READ_WRITE);
+        cpu.registers.t1 = 0x10000000;
+        cpu.registers.t2 = 0x1234;
+        memory.write_word(0x00400000, 0xa52a0001).unwrap(); // sh $t2, 1($t1)
+        memory.write_word(0x10000000, 0).unwrap();
         cpu.step(&mut memory);
-        assert_eq!(cpu.registers.t1, 0x0000BAAE);
+        assert_eq!(cpu.exception, Exception::AddressErrorStore(0x10000001));
+        assert_eq!(cpu.control_registers.cause, 5 << 2);
+        assert_eq!(memory.read_word(0x10000000).unwrap(), 0);
     }
 
-    // opcode: 0b001111
+    // opcode: 0b101110
     #[test]
-    fn test_run_lui() {
+    fn test_run_swr() {
+        for (word, expected) in [
+            (0xb92a0000, 0xdd223344), // swr $t2, 0($t1)
+            (0xb92a0001, 0xccdd3344), // swr $t2, 1($t1)
+            (0xb92a0002, 0xbbccdd44), // swr $t2, 2($t1)
+            (0xb92a0003, 0xaabbccdd), // swr $t2, 3($t1)
+        ] {
+            let mut cpu = CPU::default();
+            let mut memory = Memory::default();
+            memory.text_address = 0x00400000;
+            memory.map(0x00400000, 0x1000, Permissions::ALL);
+            memory.data_address = 0x10000000;
+            memory.map(0x10000000, 0x1000, Permissions::READ_WRITE);
+            cpu.registers.t1 = 0x10000000;
+            cpu.registers.t2 = 0xaabbccdd;
+            memory.write_word(0x00400000, word).unwrap();
+            memory.write_word(0x10000000, 0x11223344).unwrap();
+            cpu.step(&mut memory);
+            assert_eq!(memory.read_word(0x10000000).unwrap(), expected);
+        }
+    }
+
+    // opcode: 0b101110
+    #[test]
+    fn test_run_swr_little_endian() {
+        for (word, expected) in [
+            (0xb92a0000, 0xaabbccdd), // swr $t2, 0($t1)
+            (0xb92a0001, 0xbbccdd44), // swr $t2, 1($t1)
+            (0xb92a0002, 0xccdd3344), // swr $t2, 2($t1)
+            (0xb92a0003, 0xdd223344), // swr $t2, 3($t1)
+        ] {
+            let mut cpu = CPU::default();
+            let mut memory = Memory::default();
+            memory.endianness = Endianness::Little;
+            memory.text_address = 0x00400000;
+            memory.map(0x00400000, 0x1000, Permissions::ALL);
+            memory.data_address = 0x10000000;
+            memory.map(0x10000000, 0x1000, Permissions::READ_WRITE);
+            cpu.registers.t1 = 0x10000000;
+            cpu.registers.t2 = 0xaabbccdd;
+            memory.write_word(0x00400000, word).unwrap();
+            memory.write_word(0x10000000, 0x11223344).unwrap();
+            cpu.step(&mut memory);
+            assert_eq!(memory.read_word(0x10000000).unwrap(), expected);
+        }
+    }
+
+    // opcode: 0b110000
+    #[test]
+    fn test_run_ll() {
         let mut cpu = CPU::default();
         let mut memory = Memory::default();
         memory.text_address = 0x00400000;
-        memory.write_word(0x00400000, 0x3C090001); // lui $t1, 0x0001
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.data_address = 0x10000000;
+        memory.map(0x10000000, 0x1000, Permissions::READ_WRITE);
+        cpu.registers.t1 = 0x10000000;
+        memory.write_word(0x00400000, 0xc12a0004).unwrap(); // ll $t2, 4($t1)
+        memory.write_word(0x10000004, 0x12345678).unwrap();
         cpu.step(&mut memory);
-        assert_eq!(cpu.registers.t1, 0x00010000);
+        assert_eq!(cpu.registers.t2, 0x12345678);
+        assert!(cpu.llbit);
+        assert_eq!(cpu.lladdr, 0x10000004);
     }
 
-    // opcode: 0b010100
+    // opcode: 0b111000
     #[test]
-    fn test_run_beql() {
+    fn test_run_sc() {
         let mut cpu = CPU::default();
         let mut memory = Memory::default();
         memory.text_address = 0x00400000;
-        cpu.registers.t1 = 1;
-        cpu.registers.t2 = 2;
-        cpu.registers.t3 = 3;
-        memory.write_word(0x00400000, 0x012a4820); // add $t1, $t1, $t2
-        memory.write_word(0x00400004, 0x512BFFFE); // beql $t1, $t2, -2
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.data_address = 0x10000000;
+        memory.map(0x10000000, 0x1000, Permissions::READ_WRITE);
+        cpu.registers.t1 = 0x10000000;
+        memory.write_word(0x00400000, 0xc12a0004).unwrap(); // ll $t2, 4($t1)
+        memory.write_word(0x00400004, 0x254a0001).unwrap(); // addiu $t2, $t2, 1
+        memory.write_word(0x00400008, 0xe12a0004).unwrap(); // sc $t2, 4($t1)
+        memory.write_word(0x0040000c, 0xe12a0004).unwrap(); // sc $t2, 4($t1)
+        memory.write_word(0x10000004, 41).unwrap();
         cpu.step(&mut memory);
         cpu.step(&mut memory);
-        assert_eq!(cpu.registers.t1, 3);
-        assert_eq!(cpu.registers.t2, 2);
-        assert_eq!(cpu.registers.t3, 3);
-        assert_eq!(cpu.registers.pc, 0x00400000);
         cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t2, 1);
+        assert_eq!(memory.read_word(0x10000004).unwrap(), 42);
+        assert!(!cpu.llbit);
+        // The link is consumed by the first sc
+        cpu.registers.t2 = 7;
         cpu.step(&mut memory);
-        assert_eq!(cpu.registers.t1, 5);
-        assert_eq!(cpu.registers.t2, 2);
-        assert_eq!(cpu.registers.t3, 3);
-        assert_eq!(cpu.registers.pc, 0x00400008);
+        assert_eq!(cpu.registers.t2, 0);
+        assert_eq!(memory.read_word(0x10000004).unwrap(), 42);
     }
 
-    // opcode: 0b100011
     #[test]
-    fn test_run_lw() {
+    fn test_sc_fails_after_store_to_linked_word() {
         let mut cpu = CPU::default();
         let mut memory = Memory::default();
         memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
         memory.data_address = 0x10000000;
+        memory.map(0x10000000, 0x1000, Permissions::READ_WRITE);
         cpu.registers.t1 = 0x10000000;
-        memory.write_word(0x00400000, 0x8d2a0004); // lw $t2, 4($t1)
-        memory.write_word(0x10000004, 0x12345678);
+        cpu.registers.t3 = 0xff;
+        memory.write_word(0x00400000, 0xc12a0004).unwrap(); // ll $t2, 4($t1)
+        memory.write_word(0x00400004, 0xa12b0007).unwrap(); // sb $t3, 7($t1)
+        memory.write_word(0x00400008, 0xe12a0004).unwrap(); // sc $t2, 4($t1)
+        memory.write_word(0x10000004, 0).unwrap();
         cpu.step(&mut memory);
-        assert_eq!(cpu.registers.t2, 0x12345678);
-        assert_eq!(memory.data.len(), 8);
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t2, 0);
+        assert_eq!(memory.read_word(0x10000004).unwrap(), 0xff);
     }
 
-    // opcode: 0b101011
     #[test]
-    fn test_run_sw() {
+    fn test_sc_fails_after_exception() {
         let mut cpu = CPU::default();
         let mut memory = Memory::default();
         memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
         memory.data_address = 0x10000000;
+        memory.map(0x10000000, 0x1000, Permissions::READ_WRITE);
         cpu.registers.t1 = 0x10000000;
-        cpu.registers.t2 = 0x12345678;
-        memory.write_word(0x00400000, 0xad2a0004); // sw $t2, 4($t1)
+        memory.write_word(0x00400000, 0xc12a0004).unwrap(); // ll $t2, 4($t1)
+        memory.write_word(0x00400004, 0x00000034).unwrap(); // teq $zero, $zero
+        memory.write_word(0x00400008, 0xe12a0004).unwrap(); // sc $t2, 4($t1)
+        memory.write_word(0x10000004, 5).unwrap();
         cpu.step(&mut memory);
-        assert_eq!(memory.read_word(0x10000004), 0x12345678);
-        assert_eq!(memory.data.len(), 8);
+        cpu.step(&mut memory);
+        assert!(!cpu.llbit);
+        // Resume past the trap, which is left at the PC
+        cpu.registers.pc += 4;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t2, 0);
+    }
+
+    #[test]
+    fn test_sc_fails_after_store_from_other_cpu() {
+        let mut first = CPU::default();
+        let mut second = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.data_address = 0x10000000;
+        memory.map(0x10000000, 0x1000, Permissions::READ_WRITE);
+        first.registers.t1 = 0x10000000;
+        second.registers.t1 = 0x10000000;
+        memory.write_word(0x00400000, 0xc12a0004).unwrap(); // ll $t2, 4($t1)
+        memory.write_word(0x00400004, 0xe12a0004).unwrap(); // sc $t2, 4($t1)
+        memory.write_word(0x10000004, 5).unwrap();
+        first.step(&mut memory);
+        second.step(&mut memory);
+        second.step(&mut memory);
+        assert_eq!(second.registers.t2, 1);
+        first.step(&mut memory);
+        assert_eq!(first.registers.t2, 0);
     }
 }
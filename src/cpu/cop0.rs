@@ -0,0 +1,1003 @@
+use super::mmu::{
+    TlbEntry, CONTEXT_WRITABLE, ENTRY_HI_WRITABLE, ENTRY_LO_WRITABLE, KSEG_PHYSICAL,
+    PAGE_MASK_WRITABLE, TLB_ENTRIES, TLB_REFILL_OFFSET,
+};
+use super::{Exception, Instruction, CPU};
+use crate::memory::Memory;
+
+pub const STATUS_IE: u32 = 1 << 0;
+pub const STATUS_EXL: u32 = 1 << 1;
+pub const STATUS_ERL: u32 = 1 << 2;
+/// Base operating mode: 0 kernel, 1 supervisor, 2 user
+const STATUS_KSU: u32 = 0b11 << 3;
+const STATUS_KSU_SHIFT: u32 = 3;
+/// User and supervisor code may use COP0 instructions when CU0 is set
+const STATUS_CU0: u32 = 1 << 28;
+pub const STATUS_BEV: u32 = 1 << 22;
+const STATUS_WRITABLE: u32 = 0xfe58_ff1f;
+
+pub const CAUSE_BD: u32 = 1 << 31;
+pub const CAUSE_TI: u32 = 1 << 30;
+/// Interrupts use their own vector at offset 0x200 when set
+const CAUSE_IV: u32 = 1 << 23;
+const CAUSE_EXC_CODE: u32 = 0x1f << 2;
+/// Coprocessor referenced by a Coprocessor Unusable exception
+const CAUSE_CE: u32 = 0b11 << 28;
+const CAUSE_CE_SHIFT: u32 = 28;
+const CAUSE_WRITABLE: u32 = 0x08c0_0300;
+/// IP0 to IP7, masked by IM0 to IM7 at the same bits of Status
+const CAUSE_IP: u32 = 0xff << 8;
+/// Hardware interrupt lines 0 to 5 appear as IP2 to IP7
+const HARDWARE_IP_SHIFT: u32 = 10;
+pub const INTERRUPT_LINES: u8 = 6;
+/// The timer shares the highest hardware line
+const TIMER_LINE: u8 = 5;
+
+/// MIPS Technologies 4Kc
+const PRID: u32 = 0x0001_8000;
+
+const GENERAL_EXCEPTION_OFFSET: u32 = 0x180;
+const INTERRUPT_OFFSET: u32 = 0x200;
+
+/// Privilege level the CPU is running at
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    Kernel,
+    Supervisor,
+    User,
+}
+
+impl std::fmt::Display for Mode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Mode::Kernel => write!(f, "Kernel mode"),
+            Mode::Supervisor => write!(f, "Supervisor mode"),
+            Mode::User => write!(f, "User mode"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ControlRegisters {
+    pub index: u32,
+    /// Entry written by tlbwr, counts down from the top of the TLB to Wired
+    pub(super) random: u32,
+    pub entry_lo0: u32,
+    pub entry_lo1: u32,
+    pub context: u32,
+    pub page_mask: u32,
+    pub wired: u32,
+    pub entry_hi: u32,
+    pub tlb: [TlbEntry; TLB_ENTRIES],
+    /// BadVAddr, EPC and ErrorEPC are 64-bit in MIPS64 mode, mfc0 reads the low word
+    pub bad_vaddr: u64,
+    pub status: u32,
+    pub cause: u32,
+    pub epc: u64,
+    pub error_epc: u64,
+    pub count: u32,
+    pub compare: u32,
+    /// Hardware interrupt lines currently asserted by devices
+    lines: u8,
+}
+
+impl ControlRegisters {
+    /**
+     * Base of the exception vectors, in uncached ROM while BEV is set
+     */
+    fn exception_base(&self) -> u32 {
+        if self.status & STATUS_BEV != 0 {
+            0xbfc00200
+        } else {
+            0x80000000
+        }
+    }
+
+    /**
+     * Unimplemented registers read as zero
+     */
+    fn read_register(&self, number: u8, select: u8) -> u32 {
+        match (number, select) {
+            (0, 0) => self.index,
+            (1, 0) => self.random,
+            (2, 0) => self.entry_lo0,
+            (3, 0) => self.entry_lo1,
+            (4, 0) => self.context,
+            (5, 0) => self.page_mask,
+            (6, 0) => self.wired,
+            (8, 0) => self.bad_vaddr as u32,
+            (9, 0) => self.count,
+            (10, 0) => self.entry_hi,
+            (11, 0) => self.compare,
+            (12, 0) => self.status,
+            (13, 0) => self.cause,
+            (14, 0) => self.epc as u32,
+            (15, 0) => PRID,
+            (30, 0) => self.error_epc as u32,
+            _ => 0,
+        }
+    }
+
+    /**
+     * Writes to read-only bits and unimplemented registers are ignored
+     */
+    fn write_register(&mut self, number: u8, select: u8, value: u32) {
+        match (number, select) {
+            (0, 0) => self.index = value % TLB_ENTRIES as u32,
+            (2, 0) => self.entry_lo0 = value & ENTRY_LO_WRITABLE,
+            (3, 0) => self.entry_lo1 = value & ENTRY_LO_WRITABLE,
+            (4, 0) => self.context = self.context & !CONTEXT_WRITABLE | value & CONTEXT_WRITABLE,
+            (5, 0) => self.page_mask = value & PAGE_MASK_WRITABLE,
+            // Writing Wired restarts Random from the top
+            (6, 0) => {
+                self.wired = value % TLB_ENTRIES as u32;
+                self.random = TLB_ENTRIES as u32 - 1;
+            }
+            (9, 0) => self.count = value,
+            (10, 0) => self.entry_hi = value & ENTRY_HI_WRITABLE,
+            // Writing Compare acknowledges the timer interrupt
+            (11, 0) => {
+                self.compare = value;
+                self.cause &= !CAUSE_TI;
+                self.update_interrupts();
+            }
+            (12, 0) => self.status = self.status & !STATUS_WRITABLE | value & STATUS_WRITABLE,
+            (13, 0) => self.cause = self.cause & !CAUSE_WRITABLE | value & CAUSE_WRITABLE,
+            _ => (),
+        }
+    }
+
+    /**
+     * Reflect the hardware lines and the timer in IP2 to IP7
+     */
+    fn update_interrupts(&mut self) {
+        let mut pending = self.lines as u32;
+        if self.cause & CAUSE_TI != 0 {
+            pending |= 1 << TIMER_LINE;
+        }
+        self.cause = self.cause & !(0x3f << HARDWARE_IP_SHIFT) | pending << HARDWARE_IP_SHIFT;
+    }
+
+    /**
+     * Advance Count, raising the timer interrupt when it reaches Compare.
+     * Random also moves on every instruction.
+     */
+    fn tick(&mut self) {
+        self.random = if self.random <= self.wired {
+            TLB_ENTRIES as u32 - 1
+        } else {
+            self.random - 1
+        };
+        self.count = self.count.wrapping_add(1);
+        if self.count == self.compare {
+            self.cause |= CAUSE_TI;
+            self.update_interrupts();
+        }
+    }
+
+    /**
+     * Running an exception handler (EXL or ERL) is always kernel mode, otherwise KSU decides.
+     * The reserved KSU value 3 is treated as user mode.
+     */
+    pub fn mode(&self) -> Mode {
+        if self.status & (STATUS_EXL | STATUS_ERL) != 0 {
+            return Mode::Kernel;
+        }
+        match (self.status & STATUS_KSU) >> STATUS_KSU_SHIFT {
+            0 => Mode::Kernel,
+            1 => Mode::Supervisor,
+            _ => Mode::User,
+        }
+    }
+
+    /**
+     * Whether the current mode may access address.
+     * User mode only sees kuseg, supervisor mode also sees ksseg.
+     */
+    pub(super) fn address_allowed(&self, address: u32) -> bool {
+        match self.mode() {
+            Mode::Kernel => true,
+            Mode::Supervisor => address < 0x80000000 || address >> 29 == 0b110,
+            Mode::User => address < 0x80000000,
+        }
+    }
+
+    /**
+     * Whether the current mode may access a MIPS64 address outside the compatibility segments.
+     * User mode only sees xuseg, supervisor mode also sees xsseg.
+     */
+    pub(super) fn address_allowed64(&self, address: u64) -> bool {
+        match self.mode() {
+            Mode::Kernel => true,
+            Mode::Supervisor => address >> 62 <= 0b01,
+            Mode::User => address >> 62 == 0b00,
+        }
+    }
+
+    /**
+     * An unmasked interrupt is pending and interrupts are enabled
+     */
+    fn interrupt_pending(&self) -> bool {
+        self.status & (STATUS_IE | STATUS_EXL | STATUS_ERL) == STATUS_IE
+            && self.cause & self.status & CAUSE_IP != 0
+    }
+}
+
+impl Exception {
+    /**
+     * ExcCode field of Cause
+     */
+    fn code(&self) -> u32 {
+        match self {
+            Exception::None => panic!("No exception to take"),
+            Exception::Interrupt => 0,
+            Exception::TlbModified(_) | Exception::WriteProtected(_) => 1,
+            Exception::TlbRefillLoad(_) | Exception::TlbInvalidLoad(_) => 2,
+            Exception::TlbRefillStore(_) | Exception::TlbInvalidStore(_) => 3,
+            Exception::AddressErrorLoad(_) => 4,
+            Exception::AddressErrorStore(_) => 5,
+            Exception::InstructionBusError(_) => 6,
+            Exception::DataBusError(_) | Exception::StackOverflow(_) => 7,
+            Exception::Syscall => 8,
+            Exception::Breakpoint => 9,
+            Exception::ReservedInstruction { .. } => 10,
+            Exception::ReadInhibit(_) => 19,
+            Exception::ExecuteInhibit(_) => 20,
+            Exception::CoprocessorUnusable(_) => 11,
+            Exception::IntegerOverflow => 12,
+            Exception::Trap => 13,
+            Exception::FloatingPoint => 15,
+        }
+    }
+}
+
+impl CPU {
+    /**
+     * Whether a device is asserting the hardware interrupt line
+     */
+    pub fn interrupt_line(&self, line: u8) -> bool {
+        line < INTERRUPT_LINES && self.control_registers.lines & 1 << line != 0
+    }
+
+    /**
+     * Assert or deassert one of the hardware interrupt lines.
+     * Devices call this; the interrupt is taken before the next instruction if unmasked.
+     * Returns false and changes nothing if the line does not exist.
+     */
+    pub fn set_interrupt_line(&mut self, line: u8, asserted: bool) -> bool {
+        if line >= INTERRUPT_LINES {
+            return false;
+        }
+        let cop0 = &mut self.control_registers;
+        if asserted {
+            cop0.lines |= 1 << line;
+        } else {
+            cop0.lines &= !(1 << line);
+        }
+        cop0.update_interrupts();
+        true
+    }
+
+    /**
+     * Count advances once per retired instruction
+     */
+    pub(super) fn tick_timer(&mut self) {
+        self.control_registers.tick();
+    }
+
+    /**
+     * Take a pending interrupt instead of executing the instruction at PC.
+     * Returns whether one was taken.
+     */
+    pub(super) fn take_interrupt(&mut self) -> bool {
+        if !self.control_registers.interrupt_pending() {
+            return false;
+        }
+        self.trigger_exception(Exception::Interrupt);
+        true
+    }
+
+    /**
+     * Whether memory holds a handler at the general exception vector, looked up at the
+     * physical address it maps to when the MMU is on
+     */
+    pub fn handler_loaded(&self, memory: &Memory) -> bool {
+        let vector = self.control_registers.exception_base() + GENERAL_EXCEPTION_OFFSET;
+        // The vectors are in kseg0 or kseg1, which bypass the TLB
+        let physical = if self.mmu {
+            vector & KSEG_PHYSICAL
+        } else {
+            vector
+        };
+        memory.is_mapped(physical as u64)
+    }
+
+    /**
+     * Record an exception in COP0 and move to the general exception vector.
+     * EPC points at the faulting instruction, or at its branch when it sits in a delay slot.
+     * Without a handler the CPU halts with the PC left on that same instruction.
+     */
+    pub(super) fn enter_exception(&mut self, exception: &Exception) {
+        let restart = if self.in_delay_slot {
+            self.branch_address
+        } else {
+            self.registers.pc
+        };
+        let compressed = self.compressed();
+        let cop0 = &mut self.control_registers;
+        // A TLB miss outside another exception has its own vector for the fast refill handler
+        let offset = match exception {
+            Exception::TlbRefillLoad(_) | Exception::TlbRefillStore(_)
+                if cop0.status & STATUS_EXL == 0 =>
+            {
+                TLB_REFILL_OFFSET
+            }
+            Exception::Interrupt if cop0.cause & CAUSE_IV != 0 => INTERRUPT_OFFSET,
+            _ => GENERAL_EXCEPTION_OFFSET,
+        };
+        if cop0.status & STATUS_EXL == 0 {
+            // The low bit of EPC keeps the ISA mode to return to
+            cop0.epc = restart | compressed as u64;
+            if self.in_delay_slot {
+                cop0.cause |= CAUSE_BD;
+            } else {
+                cop0.cause &= !CAUSE_BD;
+            }
+        }
+        cop0.cause = cop0.cause & !CAUSE_EXC_CODE | exception.code() << 2;
+        if let Exception::CoprocessorUnusable(coprocessor) = exception {
+            cop0.cause = cop0.cause & !CAUSE_CE | (*coprocessor as u32) << CAUSE_CE_SHIFT;
+        }
+        if let Exception::AddressErrorLoad(address)
+        | Exception::AddressErrorStore(address)
+        | Exception::TlbRefillLoad(address)
+        | Exception::TlbRefillStore(address)
+        | Exception::TlbInvalidLoad(address)
+        | Exception::TlbInvalidStore(address)
+        | Exception::TlbModified(address)
+        | Exception::ReadInhibit(address)
+        | Exception::ExecuteInhibit(address)
+        | Exception::WriteProtected(address) = exception
+        {
+            cop0.bad_vaddr = *address;
+        }
+        cop0.status |= STATUS_EXL;
+
+        if self.exception_handler {
+            let vector = cop0.exception_base() + offset;
+            self.registers.pc = self.extend_address(vector);
+            // Handlers run in MIPS32 mode
+            self.micromips = false;
+            self.mips16e = false;
+        } else {
+            self.registers.pc = restart;
+            self.exception = exception.clone();
+            self.halted = true;
+        }
+        self.branch_target = None;
+        self.jump = true;
+    }
+
+    /**
+     * Coprocessor 0
+     * opcode: 0b010000
+     */
+    pub(super) fn cop0(&mut self, instruction: &Instruction) {
+        let cop0 = &self.control_registers;
+        if cop0.mode() != Mode::Kernel && cop0.status & STATUS_CU0 == 0 {
+            self.trigger_exception(Exception::CoprocessorUnusable(0));
+            return;
+        }
+        match instruction.rs {
+            0b00000 => self.mfc0(instruction),
+            0b00100 => self.mtc0(instruction),
+            0b01011 if self.isa.has_release2() => self.mfmc0(instruction),
+            0b10000..=0b11111 => match instruction.funct {
+                0b011000 if self.isa.has_release1() => self.eret(),
+                0b000001 | 0b000010 | 0b000110 | 0b001000 if self.mmu => self.tlb(instruction),
+                _ => self.reserved_instruction(instruction),
+            },
+            _ => self.reserved_instruction(instruction),
+        }
+    }
+
+    /**
+     * Move from coprocessor 0
+     * opcode: 0b010000
+     * rs: 0b00000
+     */
+    fn mfc0(&mut self, instruction: &Instruction) {
+        let select = instruction.funct & 0b111;
+        let value = self.control_registers.read_register(instruction.rd, select);
+        self.registers.write_register(instruction.rt, value);
+    }
+
+    /**
+     * Move to coprocessor 0
+     * opcode: 0b010000
+     * rs: 0b00100
+     */
+    fn mtc0(&mut self, instruction: &Instruction) {
+        let select = instruction.funct & 0b111;
+        let value = self.registers.read_register(instruction.rt);
+        // EPC and ErrorEPC hold return addresses, sign-extended like the PC in MIPS64 mode
+        match (instruction.rd, select) {
+            (14, 0) => self.control_registers.epc = self.extend_address(value),
+            (30, 0) => self.control_registers.error_epc = self.extend_address(value),
+            _ => self
+                .control_registers
+                .write_register(instruction.rd, select, value),
+        }
+    }
+
+    /**
+     * Disable or enable interrupts: di and ei
+     * opcode: 0b010000
+     * rs: 0b01011
+     */
+    fn mfmc0(&mut self, instruction: &Instruction) {
+        let status = self.control_registers.status;
+        self.registers.write_register(instruction.rt, status);
+        if instruction.funct & 0b100000 != 0 {
+            self.control_registers.status |= STATUS_IE;
+        } else {
+            self.control_registers.status &= !STATUS_IE;
+        }
+    }
+
+    /**
+     * Exception return
+     * opcode: 0b010000
+     * funct: 0b011000
+     */
+    fn eret(&mut self) {
+        let cop0 = &mut self.control_registers;
+        let target = if cop0.status & STATUS_ERL != 0 {
+            cop0.status &= !STATUS_ERL;
+            cop0.error_epc
+        } else {
+            cop0.status &= !STATUS_EXL;
+            cop0.epc
+        };
+        self.set_pc(target);
+        // eret has no delay slot
+        self.jump = true;
+        self.llbit = false;
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::field_reassign_with_default)]
+mod tests {
+    use super::*;
+    use crate::cpu::StepResult;
+    use crate::memory::{Memory, Permissions};
+
+    // opcode: 0b010000
+    // rs: 0b00000
+    #[test]
+    fn test_run_mfc0() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.control_registers.epc = 0xDEADBEEF;
+        memory.write_word(0x00400000, 0x40097000).unwrap(); // mfc0 $t1, $14
+        memory.write_word(0x00400004, 0x400a7800).unwrap(); // mfc0 $t2, $15
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t1, 0xDEADBEEF);
+        assert_eq!(cpu.registers.t2, PRID);
+    }
+
+    // opcode: 0b010000
+    // rs: 0b00100
+    #[test]
+    fn test_run_mtc0() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t1 = 0xffffffff;
+        memory.write_word(0x00400000, 0x40896000).unwrap(); // mtc0 $t1, $12
+        memory.write_word(0x00400004, 0x40894000).unwrap(); // mtc0 $t1, $8
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.control_registers.status, STATUS_WRITABLE);
+        // BadVAddr is read only
+        assert_eq!(cpu.control_registers.bad_vaddr, 0);
+    }
+
+    // opcode: 0b010000
+    // rs: 0b01011
+    #[test]
+    fn test_run_ei_di() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.write_word(0x00400000, 0x41696020).unwrap(); // ei $t1
+        memory.write_word(0x00400004, 0x416a6000).unwrap(); // di $t2
+        cpu.step(&mut memory);
+        assert_eq!(cpu.control_registers.status, STATUS_IE);
+        assert_eq!(cpu.registers.t1, 0);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.control_registers.status, 0);
+        assert_eq!(cpu.registers.t2, STATUS_IE);
+    }
+
+    // opcode: 0b010000
+    // funct: 0b011000
+    #[test]
+    fn test_run_eret() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.control_registers.status = STATUS_EXL;
+        cpu.control_registers.epc = 0x00400010;
+        cpu.llbit = true;
+        memory.write_word(0x00400000, 0x42000018).unwrap(); // eret
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400010);
+        assert_eq!(cpu.control_registers.status, 0);
+        assert!(!cpu.llbit);
+    }
+
+    #[test]
+    fn test_exception_enters_handler() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.kernel_address = 0x80000000;
+        memory.map(0x80000000, 0x1000, Permissions::ALL);
+        cpu.exception_handler = true;
+        memory.write_word(0x00400000, 0x0000000d).unwrap(); // break
+        memory.write_word(0x80000180, 0x42000018).unwrap(); // eret
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x80000180);
+        assert_eq!(cpu.control_registers.epc, 0x00400000);
+        assert_eq!(cpu.control_registers.cause, 9 << 2);
+        assert_eq!(cpu.control_registers.status, STATUS_EXL);
+        assert!(!cpu.halted);
+        assert_eq!(cpu.exception, Exception::None);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400000);
+        assert_eq!(cpu.control_registers.status, 0);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b001100
+    #[test]
+    fn test_run_syscall_enters_handler() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.kernel_address = 0x80000000;
+        memory.map(0x80000000, 0x1000, Permissions::ALL);
+        cpu.exception_handler = true;
+        cpu.registers.v0 = 10;
+        memory.write_word(0x00400000, 0x0000000c).unwrap(); // syscall
+        memory.write_word(0x80000180, 0x00000000).unwrap(); // nop
+        cpu.step(&mut memory);
+        // The handler gets exit instead of the emulator
+        assert!(!cpu.halted);
+        assert_eq!(cpu.registers.pc, 0x80000180);
+        assert_eq!(cpu.control_registers.epc, 0x00400000);
+        assert_eq!(cpu.control_registers.cause & CAUSE_EXC_CODE, 8 << 2);
+    }
+
+    #[test]
+    fn test_exception_in_delay_slot() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.kernel_address = 0x80000000;
+        memory.map(0x80000000, 0x1000, Permissions::ALL);
+        cpu.exception_handler = true;
+        memory.write_word(0x00400000, 0x08100004).unwrap(); // j 0x00400010
+        memory.write_word(0x00400004, 0x00000034).unwrap(); // teq $zero, $zero
+        memory.write_word(0x80000180, 0x00000000).unwrap(); // nop
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x80000180);
+        assert_eq!(cpu.control_registers.epc, 0x00400000);
+        assert_eq!(cpu.control_registers.cause, CAUSE_BD | 13 << 2);
+        // The pending jump is discarded
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x80000184);
+    }
+
+    #[test]
+    fn test_exception_with_boot_vectors() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.exception_handler = true;
+        cpu.control_registers.status = STATUS_BEV;
+        memory.write_word(0x00400000, 0x0000000d).unwrap(); // break
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0xbfc00380);
+    }
+
+    #[test]
+    fn test_handler_loaded() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.map(0x80000000, 0x1000, Permissions::ALL);
+        assert!(cpu.handler_loaded(&memory));
+        cpu.control_registers.status = STATUS_BEV;
+        assert!(!cpu.handler_loaded(&memory));
+        memory.map(0xbfc00000, 0x1000, Permissions::ALL);
+        assert!(cpu.handler_loaded(&memory));
+
+        // With the MMU on, the vector is read from physical memory
+        cpu.mmu = true;
+        assert!(!cpu.handler_loaded(&memory));
+        memory.map(0x1fc00000, 0x1000, Permissions::ALL);
+        assert!(cpu.handler_loaded(&memory));
+        cpu.control_registers.status = 0;
+        assert!(!cpu.handler_loaded(&memory));
+        memory.map(0x00000000, 0x1000, Permissions::ALL);
+        assert!(cpu.handler_loaded(&memory));
+    }
+
+    #[test]
+    fn test_exception_without_handler_halts() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.write_word(0x00400000, 0x0000000d).unwrap(); // break
+        cpu.step(&mut memory);
+        assert!(cpu.halted);
+        assert_eq!(cpu.exception, Exception::Breakpoint);
+        assert_eq!(cpu.control_registers.epc, 0x00400000);
+        assert_eq!(cpu.registers.pc, 0x00400000);
+    }
+
+    #[test]
+    fn test_exception_in_delay_slot_without_handler() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.write_word(0x00400000, 0x08100004).unwrap(); // j 0x00400010
+        memory.write_word(0x00400004, 0x00000034).unwrap(); // teq $zero, $zero
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert!(cpu.halted);
+        assert_eq!(cpu.registers.pc, 0x00400000);
+        assert_eq!(cpu.branch_target, None);
+    }
+
+    #[test]
+    fn test_timer_interrupt() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.kernel_address = 0x80000000;
+        memory.map(0x80000000, 0x1000, Permissions::ALL);
+        cpu.exception_handler = true;
+        cpu.control_registers.compare = 2;
+        cpu.control_registers.status = STATUS_IE | 1 << 15;
+        memory.write_word(0x00400000, 0x00000000).unwrap(); // nop
+        memory.write_word(0x00400004, 0x00000000).unwrap(); // nop
+        memory.write_word(0x00400008, 0x00000000).unwrap(); // nop
+        memory.write_word(0x80000180, 0x00000000).unwrap(); // nop
+        assert_eq!(cpu.step(&mut memory), StepResult::Retired);
+        assert_eq!(cpu.step(&mut memory), StepResult::Retired);
+        assert_eq!(cpu.control_registers.cause, CAUSE_TI | 1 << 15);
+        assert_eq!(
+            cpu.step(&mut memory),
+            StepResult::Exception {
+                exception: Exception::Interrupt,
+                pc: 0x00400008
+            }
+        );
+        assert_eq!(cpu.registers.pc, 0x80000180);
+        assert_eq!(cpu.control_registers.epc, 0x00400008);
+        // Taking the interrupt retires nothing
+        assert_eq!(cpu.control_registers.count, 2);
+    }
+
+    #[test]
+    fn test_interrupt_vector() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.exception_handler = true;
+        cpu.control_registers.status = STATUS_IE | 1 << 10;
+        cpu.control_registers.cause = CAUSE_IV;
+        cpu.set_interrupt_line(0, true);
+        memory.write_word(0x00400000, 0x00000000).unwrap(); // nop
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x80000200);
+        // Other exceptions still use the general vector
+        cpu.control_registers.status = 0;
+        cpu.registers.pc = 0x00400000;
+        memory.write_word(0x00400000, 0x0000000d).unwrap(); // break
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x80000180);
+    }
+
+    // opcode: 0b010000
+    // rs: 0b00100
+    #[test]
+    fn test_compare_write_clears_timer_interrupt() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.control_registers.compare = 1;
+        memory.write_word(0x00400000, 0x00000000).unwrap(); // nop
+        memory.write_word(0x00400004, 0x40895800).unwrap(); // mtc0 $t1, $11
+        cpu.step(&mut memory);
+        assert_eq!(cpu.control_registers.cause, CAUSE_TI | 1 << 15);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.control_registers.cause, 0);
+    }
+
+    #[test]
+    fn test_masked_interrupt() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.exception_handler = true;
+        cpu.set_interrupt_line(0, true);
+        memory.write_word(0x00400000, 0x00000000).unwrap(); // nop
+        memory.write_word(0x00400004, 0x00000000).unwrap(); // nop
+        memory.write_word(0x00400008, 0x00000000).unwrap(); // nop
+        assert_eq!(cpu.control_registers.cause, 1 << 10);
+        // Interrupts disabled
+        cpu.control_registers.status = 1 << 10;
+        assert_eq!(cpu.step(&mut memory), StepResult::Retired);
+        // Line masked
+        cpu.control_registers.status = STATUS_IE | 1 << 11;
+        assert_eq!(cpu.step(&mut memory), StepResult::Retired);
+        // Already handling an exception
+        cpu.control_registers.status = STATUS_IE | STATUS_EXL | 1 << 10;
+        assert_eq!(cpu.step(&mut memory), StepResult::Retired);
+    }
+
+    #[test]
+    fn test_hardware_interrupt() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.kernel_address = 0x80000000;
+        memory.map(0x80000000, 0x1000, Permissions::ALL);
+        cpu.exception_handler = true;
+        cpu.control_registers.status = STATUS_IE | 1 << 12;
+        memory.write_word(0x00400000, 0x08100004).unwrap(); // j 0x00400010
+        memory.write_word(0x00400004, 0x00000000).unwrap(); // nop
+        memory.write_word(0x80000180, 0x00000000).unwrap(); // nop
+        cpu.step(&mut memory);
+        cpu.set_interrupt_line(2, true);
+        cpu.step(&mut memory);
+        // Taken in the delay slot, so the jump is restarted
+        assert_eq!(cpu.registers.pc, 0x80000180);
+        assert_eq!(cpu.control_registers.epc, 0x00400000);
+        assert_eq!(cpu.control_registers.cause, CAUSE_BD | 1 << 12);
+        cpu.set_interrupt_line(2, false);
+        assert_eq!(cpu.control_registers.cause & CAUSE_IP, 0);
+    }
+
+    #[test]
+    fn test_invalid_interrupt_line() {
+        let mut cpu = CPU::default();
+        assert!(!cpu.set_interrupt_line(INTERRUPT_LINES, true));
+        assert!(!cpu.interrupt_line(INTERRUPT_LINES));
+        assert_eq!(cpu.control_registers.cause & CAUSE_IP, 0);
+        assert!(cpu.set_interrupt_line(1, true));
+        assert!(cpu.interrupt_line(1));
+    }
+
+    /// KSU of a user mode Status
+    const USER: u32 = 0b10 << STATUS_KSU_SHIFT;
+
+    // opcode: 0b010000
+    // rs: 0b00100
+    #[test]
+    fn test_run_mtc0_user_mode() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.control_registers.status = USER;
+        cpu.registers.t1 = 0;
+        memory.write_word(0x00400000, 0x40896000).unwrap(); // mtc0 $t1, $12
+        let result = cpu.step(&mut memory);
+        assert_eq!(
+            result,
+            StepResult::Exception {
+                exception: Exception::CoprocessorUnusable(0),
+                pc: 0x00400000
+            }
+        );
+        assert_eq!(cpu.control_registers.cause & CAUSE_EXC_CODE, 11 << 2);
+        assert_eq!(cpu.control_registers.cause & CAUSE_CE, 0);
+        // Status is unchanged, so the program cannot make itself kernel mode
+        assert_eq!(cpu.control_registers.status & STATUS_KSU, USER);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b001100
+    #[test]
+    fn test_run_syscall_user_mode() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.control_registers.status = USER;
+        cpu.registers.v0 = 10;
+        memory.write_word(0x00400000, 0x0000000c).unwrap(); // syscall
+        let result = cpu.step(&mut memory);
+        assert_eq!(
+            result,
+            StepResult::Exception {
+                exception: Exception::Syscall,
+                pc: 0x00400000
+            }
+        );
+        assert_eq!(cpu.control_registers.cause & CAUSE_EXC_CODE, 8 << 2);
+    }
+
+    // opcode: 0b010000
+    // funct: 0b011000
+    #[test]
+    fn test_run_eret_user_mode() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.control_registers.status = USER;
+        cpu.control_registers.epc = 0x00400010;
+        memory.write_word(0x00400000, 0x42000018).unwrap(); // eret
+        let result = cpu.step(&mut memory);
+        assert!(matches!(
+            result,
+            StepResult::Exception {
+                exception: Exception::CoprocessorUnusable(0),
+                ..
+            }
+        ));
+        assert_eq!(cpu.registers.pc, 0x00400000);
+    }
+
+    // opcode: 0b010000
+    // rs: 0b00000
+    #[test]
+    fn test_run_mfc0_user_mode_with_cu0() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.control_registers.status = USER | STATUS_CU0;
+        memory.write_word(0x00400000, 0x40097800).unwrap(); // mfc0 $t1, $15
+        assert_eq!(cpu.step(&mut memory), StepResult::Retired);
+        assert_eq!(cpu.registers.t1, PRID);
+    }
+
+    // opcode: 0b100011
+    #[test]
+    fn test_run_lw_kernel_address_user_mode() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.kernel_address = 0x80000000;
+        memory.map(0x80000000, 0x1000, Permissions::ALL);
+        cpu.control_registers.status = USER;
+        cpu.registers.a0 = 0x80000100;
+        memory.write_word(0x80000100, 0x12345678).unwrap();
+        memory.write_word(0x00400000, 0x8c880000).unwrap(); // lw $t0, 0($a0)
+        memory.write_word(0x00400004, 0xac880000).unwrap(); // sw $t0, 0($a0)
+        let result = cpu.step(&mut memory);
+        assert_eq!(
+            result,
+            StepResult::Exception {
+                exception: Exception::AddressErrorLoad(0x80000100),
+                pc: 0x00400000
+            }
+        );
+        assert_eq!(cpu.registers.t0, 0);
+        assert_eq!(cpu.control_registers.bad_vaddr, 0x80000100);
+        cpu.halted = false;
+        cpu.control_registers.status = USER;
+        cpu.registers.pc = 0x00400004;
+        let result = cpu.step(&mut memory);
+        assert!(matches!(
+            result,
+            StepResult::Exception {
+                exception: Exception::AddressErrorStore(0x80000100),
+                ..
+            }
+        ));
+        assert_eq!(memory.read_word(0x80000100).unwrap(), 0x12345678);
+    }
+
+    // opcode: 0b100011
+    #[test]
+    fn test_run_lw_supervisor_mode() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.kernel_address = 0x80000000;
+        memory.map(0x80000000, 0x1000, Permissions::ALL);
+        cpu.control_registers.status = 0b01 << STATUS_KSU_SHIFT;
+        cpu.registers.a0 = 0xc0000000;
+        cpu.registers.a1 = 0xa0000000;
+        memory.write_word(0x00400000, 0x8c880000).unwrap(); // lw $t0, 0($a0)
+        memory.write_word(0x00400004, 0x8ca80000).unwrap(); // lw $t0, 0($a1)
+
+        // ksseg is open to supervisor mode, there is just no memory behind it
+        let result = cpu.step(&mut memory);
+        assert!(matches!(result, StepResult::BusError { .. }));
+        cpu.halted = false;
+        cpu.control_registers.status = 0b01 << STATUS_KSU_SHIFT;
+        cpu.registers.pc = 0x00400004;
+        let result = cpu.step(&mut memory);
+        assert!(matches!(
+            result,
+            StepResult::Exception {
+                exception: Exception::AddressErrorLoad(0xa0000000),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_fetch_kernel_address_user_mode() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.kernel_address = 0x80000000;
+        memory.map(0x80000000, 0x1000, Permissions::ALL);
+        cpu.control_registers.status = USER;
+        cpu.registers.pc = 0x80000000;
+        memory.write_word(0x80000000, 0x24080007).unwrap(); // addiu $t0, $zero, 7
+        let result = cpu.step(&mut memory);
+        assert_eq!(
+            result,
+            StepResult::Exception {
+                exception: Exception::AddressErrorLoad(0x80000000),
+                pc: 0x80000000
+            }
+        );
+        assert_eq!(cpu.registers.t0, 0);
+    }
+
+    #[test]
+    fn test_syscall_boundary() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.kernel_address = 0x80000000;
+        memory.map(0x80000000, 0x1000, Permissions::ALL);
+        cpu.exception_handler = true;
+        cpu.control_registers.status = USER;
+        memory.write_word(0x00400000, 0x0000000d).unwrap(); // break
+        memory.write_word(0x80000180, 0x40896000).unwrap(); // mtc0 $t1, $12
+        memory.write_word(0x80000184, 0x42000018).unwrap(); // eret
+        cpu.registers.t1 = USER | STATUS_EXL;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.control_registers.mode(), Mode::Kernel);
+        // The handler may use COP0
+        assert_eq!(cpu.step(&mut memory), StepResult::Retired);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400000);
+        assert_eq!(cpu.control_registers.mode(), Mode::User);
+    }
+}
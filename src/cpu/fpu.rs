@@ -0,0 +1,1509 @@
+use std::cmp::Ordering;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use super::{Exception, Instruction, CPU};
+use crate::memory::{Memory, MemoryError};
+
+/// FIR: single, double and word formats are implemented
+const FIR: u32 = 1 << 16 | 1 << 17 | 1 << 20;
+
+const ROUND_NEAREST: u32 = 0;
+const ROUND_ZERO: u32 = 1;
+const ROUND_PLUS: u32 = 2;
+const ROUND_MINUS: u32 = 3;
+
+// Exception bits, shifted into place for the flag, enable and cause fields of FCSR
+const INEXACT: u32 = 1 << 0;
+const UNDERFLOW: u32 = 1 << 1;
+const OVERFLOW: u32 = 1 << 2;
+const DIVIDE_BY_ZERO: u32 = 1 << 3;
+const INVALID: u32 = 1 << 4;
+
+const FLAG_SHIFT: u32 = 2;
+const ENABLE_SHIFT: u32 = 7;
+const CAUSE_SHIFT: u32 = 12;
+
+const FCSR_WRITABLE: u32 = 0xff83_ffff;
+
+const FMT_S: u8 = 0b10000;
+const FMT_D: u8 = 0b10001;
+const FMT_W: u8 = 0b10100;
+const FMT_L: u8 = 0b10101;
+
+#[derive(Debug, Clone, Default)]
+pub struct FloatRegisters {
+    pub f: [u32; 32],
+    pub fcsr: u32,
+}
+
+impl FloatRegisters {
+    pub fn read_single(&self, number: u8) -> f32 {
+        f32::from_bits(self.f[number as usize])
+    }
+
+    /**
+     * Doubles live in an even/odd register pair, the odd register holds the upper word
+     */
+    pub fn read_double(&self, number: u8) -> f64 {
+        let number = (number & !1) as usize;
+        f64::from_bits((self.f[number + 1] as u64) << 32 | self.f[number] as u64)
+    }
+
+    fn write_single(&mut self, number: u8, value: f32) {
+        self.f[number as usize] = value.to_bits();
+    }
+
+    fn write_double(&mut self, number: u8, value: f64) {
+        let number = (number & !1) as usize;
+        let bits = value.to_bits();
+        self.f[number] = bits as u32;
+        self.f[number + 1] = (bits >> 32) as u32;
+    }
+
+    pub fn condition(&self, cc: u8) -> bool {
+        self.fcsr & Self::condition_bit(cc) != 0
+    }
+
+    fn set_condition(&mut self, cc: u8, value: bool) {
+        if value {
+            self.fcsr |= Self::condition_bit(cc);
+        } else {
+            self.fcsr &= !Self::condition_bit(cc);
+        }
+    }
+
+    /**
+     * FCC0 is bit 23, FCC1 to FCC7 are bits 25 to 31
+     */
+    fn condition_bit(cc: u8) -> u32 {
+        match cc {
+            0 => 1 << 23,
+            _ => 1 << (24 + cc as u32),
+        }
+    }
+
+    fn rounding_mode(&self) -> u32 {
+        self.fcsr & 0b11
+    }
+}
+
+trait Float:
+    Copy
+    + PartialOrd
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+{
+    const FORMAT: u8;
+    const ZERO: Self;
+    const ONE: Self;
+    const MAX: Self;
+    const MIN_POSITIVE: Self;
+
+    /// The quiet NaN MIPS writes for invalid operations
+    fn default_nan() -> Self;
+    /// MIPS uses the legacy NaN encoding where a set fraction MSB means signaling
+    fn is_signaling_nan(self) -> bool;
+    fn is_nan(self) -> bool;
+    fn is_infinite(self) -> bool;
+    fn is_sign_negative(self) -> bool;
+    fn abs(self) -> Self;
+    fn sqrt(self) -> Self;
+    fn mul_add(self, a: Self, b: Self) -> Self;
+    fn next_up(self) -> Self;
+    fn next_down(self) -> Self;
+    fn to_f64(self) -> f64;
+    fn load(registers: &FloatRegisters, number: u8) -> Self;
+    fn store(self, registers: &mut FloatRegisters, number: u8);
+}
+
+macro_rules! impl_float {
+    ($type:ty, $format:expr, $quiet_bit:expr, $default_nan:expr, $read:ident, $write:ident) => {
+        impl Float for $type {
+            const FORMAT: u8 = $format;
+            const ZERO: Self = 0.0;
+            const ONE: Self = 1.0;
+            const MAX: Self = <$type>::MAX;
+            const MIN_POSITIVE: Self = <$type>::MIN_POSITIVE;
+
+            fn default_nan() -> Self {
+                <$type>::from_bits($default_nan)
+            }
+
+            fn is_signaling_nan(self) -> bool {
+                self.is_nan() && self.to_bits() & $quiet_bit != 0
+            }
+
+            fn is_nan(self) -> bool {
+                <$type>::is_nan(self)
+            }
+
+            fn is_infinite(self) -> bool {
+                <$type>::is_infinite(self)
+            }
+
+            fn is_sign_negative(self) -> bool {
+                <$type>::is_sign_negative(self)
+            }
+
+            fn abs(self) -> Self {
+                <$type>::abs(self)
+            }
+
+            fn sqrt(self) -> Self {
+                <$type>::sqrt(self)
+            }
+
+            fn mul_add(self, a: Self, b: Self) -> Self {
+                <$type>::mul_add(self, a, b)
+            }
+
+            fn next_up(self) -> Self {
+                <$type>::next_up(self)
+            }
+
+            fn next_down(self) -> Self {
+                <$type>::next_down(self)
+            }
+
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+
+            fn load(registers: &FloatRegisters, number: u8) -> Self {
+                registers.$read(number)
+            }
+
+            fn store(self, registers: &mut FloatRegisters, number: u8) {
+                registers.$write(number, self)
+            }
+        }
+    };
+}
+
+impl_float!(f32, FMT_S, 1 << 22, 0x7fbf_ffff, read_single, write_single);
+impl_float!(
+    f64,
+    FMT_D,
+    1 << 51,
+    0x7ff7_ffff_ffff_ffff,
+    read_double,
+    write_double
+);
+
+/**
+ * Sign of the rounding error of a nearest-even operation
+ */
+fn error_sign<F: Float>(error: F) -> Ordering {
+    error.partial_cmp(&F::ZERO).unwrap_or(Ordering::Equal)
+}
+
+/**
+ * Evaluate a compare condition: bit 3 signals on unordered, bits 2 to 0 accept
+ * less than, equal and unordered
+ */
+fn compare<F: Float>(condition: u8, a: F, b: F, cause: &mut u32) -> bool {
+    let unordered = a.is_nan() || b.is_nan();
+    if unordered && (condition & 0b1000 != 0 || a.is_signaling_nan() || b.is_signaling_nan()) {
+        *cause |= INVALID;
+    }
+    (condition & 0b0100 != 0 && a < b)
+        || (condition & 0b0010 != 0 && a == b)
+        || (condition & 0b0001 != 0 && unordered)
+}
+
+/**
+ * Convert to a 32-bit integer with the given rounding mode
+ */
+fn to_word(value: f64, mode: u32, cause: &mut u32) -> u32 {
+    let rounded = match mode {
+        ROUND_NEAREST => value.round_ties_even(),
+        ROUND_ZERO => value.trunc(),
+        ROUND_PLUS => value.ceil(),
+        _ => value.floor(),
+    };
+    if value.is_nan() || rounded < i32::MIN as f64 || rounded > i32::MAX as f64 {
+        *cause |= INVALID;
+        return i32::MAX as u32;
+    }
+    if rounded != value {
+        *cause |= INEXACT;
+    }
+    rounded as i32 as u32
+}
+
+impl CPU {
+    /**
+     * Round a nearest-even result to the FCSR rounding mode.
+     * error says whether the exact result was above or below result.
+     */
+    fn round<F: Float>(&self, result: F, error: Ordering, cause: &mut u32) -> F {
+        let mode = self.float_registers.rounding_mode();
+        if result.is_infinite() {
+            // Only reached when finite operands overflowed
+            *cause |= OVERFLOW | INEXACT;
+            let negative = result.is_sign_negative();
+            return match (mode, negative) {
+                (ROUND_ZERO, false) | (ROUND_MINUS, false) => F::MAX,
+                (ROUND_ZERO, true) | (ROUND_PLUS, true) => -F::MAX,
+                _ => result,
+            };
+        }
+        if error == Ordering::Equal {
+            return result;
+        }
+        *cause |= INEXACT;
+        let rounded = match mode {
+            ROUND_ZERO if result > F::ZERO && error == Ordering::Less => result.next_down(),
+            ROUND_ZERO if result < F::ZERO && error == Ordering::Greater => result.next_up(),
+            ROUND_PLUS if error == Ordering::Greater => result.next_up(),
+            ROUND_MINUS if error == Ordering::Less => result.next_down(),
+            _ => result,
+        };
+        if rounded.abs() < F::MIN_POSITIVE {
+            *cause |= UNDERFLOW;
+        }
+        rounded
+    }
+
+    /**
+     * Record the causes of an FPU operation in FCSR.
+     * Returns false and raises an exception if one of them is enabled, the result must then be dropped.
+     */
+    fn commit_float_cause(&mut self, cause: u32) -> bool {
+        let fcsr = &mut self.float_registers.fcsr;
+        *fcsr = *fcsr & !(0x3f << CAUSE_SHIFT) | cause << CAUSE_SHIFT;
+        let enables = *fcsr >> ENABLE_SHIFT & 0x1f;
+        if cause & enables != 0 {
+            self.trigger_exception(Exception::FloatingPoint);
+            return false;
+        }
+        *fcsr |= cause << FLAG_SHIFT;
+        true
+    }
+
+    /**
+     * add, sub, mul, div and sqrt with IEEE-754 rounding and exceptions
+     */
+    fn float_arithmetic<F: Float>(&mut self, funct: u8, a: F, b: F) -> Option<F> {
+        let mut cause = 0;
+        if a.is_signaling_nan() || b.is_signaling_nan() {
+            cause |= INVALID;
+        }
+        let result = if a.is_nan() || b.is_nan() {
+            F::default_nan()
+        } else {
+            let (result, error) = match funct {
+                0b000000 | 0b000001 => {
+                    let b = if funct == 0b000001 { -b } else { b };
+                    // Two-sum, the rounding error of a + b is exact
+                    let sum = a + b;
+                    let b_part = sum - a;
+                    (sum, error_sign((a - (sum - b_part)) + (b - b_part)))
+                }
+                0b000010 => {
+                    let product = a * b;
+                    (product, error_sign(a.mul_add(b, -product)))
+                }
+                0b000011 => {
+                    let quotient = a / b;
+                    let remainder = (-quotient).mul_add(b, a);
+                    let error = if b.is_sign_negative() {
+                        error_sign(-remainder)
+                    } else {
+                        error_sign(remainder)
+                    };
+                    if b == F::ZERO && !a.is_infinite() && a != F::ZERO {
+                        cause |= DIVIDE_BY_ZERO;
+                    }
+                    (quotient, error)
+                }
+                _ => {
+                    let root = a.sqrt();
+                    (root, error_sign((-root).mul_add(root, a)))
+                }
+            };
+            if result.is_nan() {
+                cause |= INVALID;
+                F::default_nan()
+            } else if result.is_infinite()
+                && (a.is_infinite() || b.is_infinite() || cause & DIVIDE_BY_ZERO != 0)
+            {
+                result
+            } else {
+                self.round(result, error, &mut cause)
+            }
+        };
+        self.commit_float_cause(cause).then_some(result)
+    }
+
+    /**
+     * Round a double to single precision
+     */
+    fn narrow(&mut self, value: f64, cause: &mut u32) -> f32 {
+        if value.is_nan() {
+            if value.is_signaling_nan() {
+                *cause |= INVALID;
+            }
+            return f32::default_nan();
+        }
+        let result = value as f32;
+        if value.is_infinite() {
+            return result;
+        }
+        let error = value.partial_cmp(&(result as f64)).unwrap();
+        self.round(result, error, cause)
+    }
+
+    /**
+     * Coprocessor 1
+     * opcode: 0b010001
+     */
+    pub(super) fn cop1(&mut self, instruction: &Instruction) {
+        match instruction.rs {
+            0b00000 => self.mfc1(instruction),
+            0b00010 => self.cfc1(instruction),
+            0b00011 if self.isa.has_release2() => self.mfhc1(instruction),
+            0b00100 => self.mtc1(instruction),
+            0b00110 => self.ctc1(instruction),
+            0b00111 if self.isa.has_release2() => self.mthc1(instruction),
+            // Release 6 branches on an FPR instead of a condition code
+            0b01000 if self.isa.is_release6() => self.reserved_instruction(instruction),
+            // bc1fl and bc1tl
+            0b01000 if instruction.rt & 0b10 != 0 && !self.isa.has_branch_likely() => {
+                self.reserved_instruction(instruction)
+            }
+            0b01000 => self.bc1(instruction),
+            0b01001 | 0b01101 if self.isa.is_release6() => self.bc1eqz_bc1nez(instruction),
+            FMT_S => self.float_format::<f32>(instruction),
+            FMT_D => self.float_format::<f64>(instruction),
+            // cmp.cond.s and cmp.cond.d reuse the W and L format numbers
+            FMT_W if self.isa.is_release6() && instruction.funct & 0b100000 == 0 => {
+                self.float_compare_mask::<f32>(instruction)
+            }
+            FMT_L if self.isa.is_release6() && instruction.funct & 0b100000 == 0 => {
+                self.float_compare_mask::<f64>(instruction)
+            }
+            FMT_W => self.word_format(instruction),
+            _ => self.reserved_instruction(instruction),
+        }
+    }
+
+    /**
+     * Move word from floating point
+     * opcode: 0b010001
+     * rs: 0b00000
+     */
+    fn mfc1(&mut self, instruction: &Instruction) {
+        let value = self.float_registers.f[instruction.rd as usize];
+        self.registers.write_register(instruction.rt, value);
+    }
+
+    /**
+     * Move control word from floating point
+     * opcode: 0b010001
+     * rs: 0b00010
+     */
+    fn cfc1(&mut self, instruction: &Instruction) {
+        let fcsr = self.float_registers.fcsr;
+        let value = match instruction.rd {
+            0 => FIR,
+            // FCCR
+            25 => (fcsr >> 24) & 0xfe | (fcsr >> 23) & 1,
+            // FEXR
+            26 => fcsr & 0x0003_f07c,
+            // FENR
+            28 => fcsr & 0x0000_0f83 | (fcsr >> 22) & 0x4,
+            31 => fcsr,
+            _ => return self.reserved_instruction(instruction),
+        };
+        self.registers.write_register(instruction.rt, value);
+    }
+
+    /**
+     * Move word from high half of floating point register
+     * opcode: 0b010001
+     * rs: 0b00011
+     */
+    fn mfhc1(&mut self, instruction: &Instruction) {
+        let value = self.float_registers.f[(instruction.rd | 1) as usize];
+        self.registers.write_register(instruction.rt, value);
+    }
+
+    /**
+     * Move word to floating point
+     * opcode: 0b010001
+     * rs: 0b00100
+     */
+    fn mtc1(&mut self, instruction: &Instruction) {
+        let value = self.registers.read_register(instruction.rt);
+        self.float_registers.f[instruction.rd as usize] = value;
+    }
+
+    /**
+     * Move control word to floating point
+     * opcode: 0b010001
+     * rs: 0b00110
+     */
+    fn ctc1(&mut self, instruction: &Instruction) {
+        let value = self.registers.read_register(instruction.rt);
+        let fcsr = self.float_registers.fcsr;
+        let fcsr = match instruction.rd {
+            25 => fcsr & !0xfe80_0000 | (value & 0xfe) << 24 | (value & 1) << 23,
+            26 => fcsr & !0x0003_f07c | value & 0x0003_f07c,
+            28 => fcsr & !0x0100_0f83 | value & 0x0000_0f83 | (value & 0x4) << 22,
+            31 => value & FCSR_WRITABLE,
+            _ => return self.reserved_instruction(instruction),
+        };
+        self.float_registers.fcsr = fcsr;
+        // Writing a cause bit together with its enable traps immediately
+        let cause = fcsr >> CAUSE_SHIFT & 0x3f;
+        if cause & (fcsr >> ENABLE_SHIFT & 0x1f | 0x20) != 0 {
+            self.trigger_exception(Exception::FloatingPoint);
+        }
+    }
+
+    /**
+     * Move word to high half of floating point register
+     * opcode: 0b010001
+     * rs: 0b00111
+     */
+    fn mthc1(&mut self, instruction: &Instruction) {
+        let value = self.registers.read_register(instruction.rt);
+        self.float_registers.f[(instruction.rd | 1) as usize] = value;
+    }
+
+    /**
+     * Branch on FP condition: bc1f, bc1t, bc1fl and bc1tl
+     * opcode: 0b010001
+     * rs: 0b01000
+     * rt holds the condition code, the likely bit and the true/false bit
+     */
+    fn bc1(&mut self, instruction: &Instruction) {
+        let cc = instruction.rt >> 2;
+        let likely = instruction.rt & 0b10 != 0;
+        let expected = instruction.rt & 0b01 != 0;
+        if self.float_registers.condition(cc) == expected {
+            let target = self.branch_offset_target(instruction);
+            self.branch(target);
+        } else if likely {
+            self.nullify_delay_slot();
+        }
+    }
+
+    /**
+     * Branch on bit 0 of an FPR: bc1eqz if it is clear, bc1nez if it is set
+     * opcode: 0b010001
+     * rs: 0b01001 or 0b01101
+     */
+    fn bc1eqz_bc1nez(&mut self, instruction: &Instruction) {
+        let set = self.float_registers.f[instruction.rt as usize] & 1 != 0;
+        if set == (instruction.rs == 0b01101) {
+            let target = self.branch_offset_target(instruction);
+            self.branch(target);
+        }
+    }
+
+    /**
+     * Single and double precision operations
+     * opcode: 0b010001
+     * rs: 0b10000 or 0b10001
+     */
+    fn float_format<F: Float>(&mut self, instruction: &Instruction) {
+        let fs = instruction.rd;
+        let ft = instruction.rt;
+        let fd = instruction.shamt;
+        let a = F::load(&self.float_registers, fs);
+        let b = F::load(&self.float_registers, ft);
+        match instruction.funct {
+            // add, sub, mul, div
+            0b000000..=0b000011 => {
+                if let Some(result) = self.float_arithmetic(instruction.funct, a, b) {
+                    result.store(&mut self.float_registers, fd);
+                }
+            }
+            // sqrt
+            0b000100 if self.isa.has_mips2() => {
+                if let Some(result) = self.float_arithmetic(0b000100, a, a) {
+                    result.store(&mut self.float_registers, fd);
+                }
+            }
+            // abs
+            0b000101 => {
+                let cause = if a.is_signaling_nan() { INVALID } else { 0 };
+                if self.commit_float_cause(cause) {
+                    a.abs().store(&mut self.float_registers, fd);
+                }
+            }
+            // mov
+            0b000110 => a.store(&mut self.float_registers, fd),
+            // neg
+            0b000111 => {
+                let cause = if a.is_signaling_nan() { INVALID } else { 0 };
+                if self.commit_float_cause(cause) {
+                    (-a).store(&mut self.float_registers, fd);
+                }
+            }
+            // round.w, trunc.w, ceil.w, floor.w
+            0b001100..=0b001111 if self.isa.has_mips2() => {
+                let mode = match instruction.funct {
+                    0b001100 => ROUND_NEAREST,
+                    0b001101 => ROUND_ZERO,
+                    0b001110 => ROUND_PLUS,
+                    _ => ROUND_MINUS,
+                };
+                self.convert_to_word(a, mode, fd);
+            }
+            // Release 6 replaced the conditional moves with sel
+            0b010001..=0b010011 if self.isa.is_release6() => self.reserved_instruction(instruction),
+            // movf, movt
+            0b010001 => {
+                let cc = instruction.rt >> 2;
+                if self.float_registers.condition(cc) == (instruction.rt & 1 != 0) {
+                    a.store(&mut self.float_registers, fd);
+                }
+            }
+            // movz
+            0b010010 => {
+                if self.read_gpr(instruction.rt) == 0 {
+                    a.store(&mut self.float_registers, fd);
+                }
+            }
+            // movn
+            0b010011 => {
+                if self.read_gpr(instruction.rt) != 0 {
+                    a.store(&mut self.float_registers, fd);
+                }
+            }
+            // recip
+            0b010101 => {
+                if let Some(result) = self.float_arithmetic(0b000011, F::ONE, a) {
+                    result.store(&mut self.float_registers, fd);
+                }
+            }
+            // rsqrt
+            0b010110 => {
+                let root = self.float_arithmetic(0b000100, a, a);
+                if let Some(result) =
+                    root.and_then(|root| self.float_arithmetic(0b000011, F::ONE, root))
+                {
+                    result.store(&mut self.float_registers, fd);
+                }
+            }
+            // cvt.s.d
+            0b100000 if F::FORMAT == FMT_D => {
+                let mut cause = 0;
+                let result = self.narrow(a.to_f64(), &mut cause);
+                if self.commit_float_cause(cause) {
+                    self.float_registers.write_single(fd, result);
+                }
+            }
+            // cvt.d.s
+            0b100001 if F::FORMAT == FMT_S => {
+                let cause = if a.is_signaling_nan() { INVALID } else { 0 };
+                let result = if a.is_nan() {
+                    f64::default_nan()
+                } else {
+                    a.to_f64()
+                };
+                if self.commit_float_cause(cause) {
+                    self.float_registers.write_double(fd, result);
+                }
+            }
+            // cvt.w
+            0b100100 => {
+                let mode = self.float_registers.rounding_mode();
+                self.convert_to_word(a, mode, fd);
+            }
+            // c.cond, replaced by cmp.cond in Release 6
+            0b110000..=0b111111 if self.isa.before_release6() => {
+                self.float_compare(instruction, a, b)
+            }
+            _ => self.reserved_instruction(instruction),
+        }
+    }
+
+    fn convert_to_word<F: Float>(&mut self, value: F, mode: u32, fd: u8) {
+        let mut cause = 0;
+        let result = to_word(value.to_f64(), mode, &mut cause);
+        if self.commit_float_cause(cause) {
+            self.float_registers.f[fd as usize] = result;
+        }
+    }
+
+    /**
+     * Compare and set a condition code
+     * funct: 0b11xxxx, the low bits select unordered, equal, less than and signaling
+     */
+    fn float_compare<F: Float>(&mut self, instruction: &Instruction, a: F, b: F) {
+        let cc = instruction.shamt >> 2;
+        let mut cause = 0;
+        let result = compare(instruction.funct & 0b1111, a, b, &mut cause);
+        if self.commit_float_cause(cause) {
+            self.float_registers.set_condition(cc, result);
+        }
+    }
+
+    /**
+     * Compare and write all ones to fd when true, all zeros when false
+     * opcode: 0b010001
+     * rs: 0b10100 or 0b10101
+     * funct: 0b0xxxxx, the c.cond conditions with bit 4 negating or, une and ne
+     */
+    fn float_compare_mask<F: Float>(&mut self, instruction: &Instruction) {
+        let condition = instruction.funct;
+        let negated = condition & 0b10000 != 0;
+        if negated && !matches!(condition & 0b0111, 1..=3) {
+            return self.reserved_instruction(instruction);
+        }
+        let a = F::load(&self.float_registers, instruction.rd);
+        let b = F::load(&self.float_registers, instruction.rt);
+        let mut cause = 0;
+        let result = compare(condition & 0b1111, a, b, &mut cause) != negated;
+        if self.commit_float_cause(cause) {
+            let mask = if result { u32::MAX } else { 0 };
+            let fd = instruction.shamt as usize;
+            if F::FORMAT == FMT_D {
+                self.float_registers.f[fd & !1] = mask;
+                self.float_registers.f[fd | 1] = mask;
+            } else {
+                self.float_registers.f[fd] = mask;
+            }
+        }
+    }
+
+    /**
+     * Fixed point word operations
+     * opcode: 0b010001
+     * rs: 0b10100
+     */
+    fn word_format(&mut self, instruction: &Instruction) {
+        let value = self.float_registers.f[instruction.rd as usize] as i32;
+        let fd = instruction.shamt;
+        match instruction.funct {
+            // cvt.s.w
+            0b100000 => {
+                let result = value as f32;
+                let error = (value as i64).cmp(&(result as i64));
+                let mut cause = 0;
+                let result = self.round(result, error, &mut cause);
+                if self.commit_float_cause(cause) {
+                    self.float_registers.write_single(fd, result);
+                }
+            }
+            // cvt.d.w
+            0b100001 => {
+                if self.commit_float_cause(0) {
+                    self.float_registers.write_double(fd, value as f64);
+                }
+            }
+            _ => self.reserved_instruction(instruction),
+        }
+    }
+
+    /**
+     * Load word to floating point
+     * opcode: 0b110001
+     */
+    pub(super) fn lwc1(
+        &mut self,
+        instruction: &Instruction,
+        memory: &Memory,
+    ) -> Result<(), MemoryError> {
+        let Some(address) = self.load_address(instruction, 4, memory) else {
+            return Ok(());
+        };
+        self.float_registers.f[instruction.rt as usize] = memory.read_word(address)?;
+        Ok(())
+    }
+
+    /**
+     * Load doubleword to floating point
+     * opcode: 0b110101
+     */
+    pub(super) fn ldc1(
+        &mut self,
+        instruction: &Instruction,
+        memory: &Memory,
+    ) -> Result<(), MemoryError> {
+        let Some(address) = self.load_address(instruction, 8, memory) else {
+            return Ok(());
+        };
+        let bits = memory.read_doubleword(address)?;
+        self.float_registers
+            .write_double(instruction.rt, f64::from_bits(bits));
+        Ok(())
+    }
+
+    /**
+     * Store word from floating point
+     * opcode: 0b111001
+     */
+    pub(super) fn swc1(
+        &mut self,
+        instruction: &Instruction,
+        memory: &mut Memory,
+    ) -> Result<(), MemoryError> {
+        let Some(address) = self.store_address(instruction, 4, memory) else {
+            return Ok(());
+        };
+        memory.write_word(address, self.float_registers.f[instruction.rt as usize])?;
+        Ok(())
+    }
+
+    /**
+     * Store doubleword from floating point
+     * opcode: 0b111101
+     */
+    pub(super) fn sdc1(
+        &mut self,
+        instruction: &Instruction,
+        memory: &mut Memory,
+    ) -> Result<(), MemoryError> {
+        let Some(address) = self.store_address(instruction, 8, memory) else {
+            return Ok(());
+        };
+        let bits = self.float_registers.read_double(instruction.rt).to_bits();
+        memory.write_doubleword(address, bits)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::field_reassign_with_default)]
+mod tests {
+    use super::*;
+    use crate::cpu::IsaLevel;
+    use crate::memory::{Endianness, Permissions};
+
+    // opcode: 0b010001
+    // rs: 0b00000
+    #[test]
+    fn test_run_mfc1() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.float_registers.f[2] = 0xDEADBEEF;
+        memory.write_word(0x00400000, 0x44091000).unwrap(); // mfc1 $t1, $f2
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t1, 0xDEADBEEF);
+    }
+
+    // opcode: 0b010001
+    // rs: 0b00010
+    #[test]
+    fn test_run_cfc1() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.float_registers.fcsr = 0x00800003;
+        memory.write_word(0x00400000, 0x4449f800).unwrap(); // cfc1 $t1, $31
+        memory.write_word(0x00400004, 0x444a0000).unwrap(); // cfc1 $t2, $0
+        memory.write_word(0x00400008, 0x444bc800).unwrap(); // cfc1 $t3, $25
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t1, 0x00800003);
+        assert_eq!(cpu.registers.t2, FIR);
+        assert_eq!(cpu.registers.t3, 1);
+    }
+
+    // opcode: 0b010001
+    // rs: 0b00011
+    #[test]
+    fn test_run_mfhc1() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.float_registers.f[3] = 0xDEADBEEF;
+        memory.write_word(0x00400000, 0x44691000).unwrap(); // mfhc1 $t1, $f2
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t1, 0xDEADBEEF);
+    }
+
+    // opcode: 0b010001
+    // rs: 0b00100
+    #[test]
+    fn test_run_mtc1() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t1 = 0xDEADBEEF;
+        memory.write_word(0x00400000, 0x44891000).unwrap(); // mtc1 $t1, $f2
+        cpu.step(&mut memory);
+        assert_eq!(cpu.float_registers.f[2], 0xDEADBEEF);
+    }
+
+    // opcode: 0b010001
+    // rs: 0b00110
+    #[test]
+    fn test_run_ctc1() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t1 = 0x00000001;
+        memory.write_word(0x00400000, 0x44c9f800).unwrap(); // ctc1 $t1, $31
+        cpu.step(&mut memory);
+        assert_eq!(cpu.float_registers.fcsr, 0x00000001);
+        assert_eq!(cpu.exception, Exception::None);
+    }
+
+    // opcode: 0b010001
+    // rs: 0b00111
+    #[test]
+    fn test_run_mthc1() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.registers.t1 = 0xDEADBEEF;
+        memory.write_word(0x00400000, 0x44e91000).unwrap(); // mthc1 $t1, $f2
+        cpu.step(&mut memory);
+        assert_eq!(cpu.float_registers.f[3], 0xDEADBEEF);
+    }
+
+    // opcode: 0b010001
+    // rs: 0b01000
+    #[test]
+    fn test_run_bc1t() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.write_word(0x00400000, 0x45010003).unwrap(); // bc1t 3
+        memory.write_word(0x00400004, 0x00000000).unwrap(); // nop
+        cpu.float_registers.fcsr = 1 << 23;
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400010);
+        cpu.registers.pc = 0x00400000;
+        cpu.float_registers.fcsr = 0;
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400008);
+    }
+
+    // opcode: 0b010001
+    // rs: 0b01001
+    #[test]
+    fn test_run_bc1eqz_bc1nez() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.isa = IsaLevel::Mips32r6;
+        cpu.float_registers.f[2] = 1;
+        memory.write_word(0x00400000, 0x45220003).unwrap(); // bc1eqz $f2, 3
+        memory.write_word(0x00400004, 0x00000000).unwrap(); // nop
+        memory.write_word(0x00400008, 0x45a20003).unwrap(); // bc1nez $f2, 3
+        memory.write_word(0x0040000c, 0x00000000).unwrap(); // nop
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400008);
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400018);
+    }
+
+    // opcode: 0b010001
+    // rs: 0b01000
+    #[test]
+    fn test_run_bc1fl() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.write_word(0x00400000, 0x45060003).unwrap(); // bc1fl $fcc1, 3
+        memory.write_word(0x00400004, 0x00000000).unwrap(); // nop
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400010);
+        cpu.registers.pc = 0x00400000;
+        cpu.float_registers.fcsr = 1 << 25;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400008);
+    }
+
+    // opcode: 0b010001
+    // rs: 0b10000
+    // funct: 0b000000
+    #[test]
+    fn test_run_add_s() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.float_registers.f[2] = 1.5f32.to_bits();
+        cpu.float_registers.f[4] = 2.25f32.to_bits();
+        memory.write_word(0x00400000, 0x46041000).unwrap(); // add.s $f0, $f2, $f4
+        cpu.step(&mut memory);
+        assert_eq!(cpu.float_registers.read_single(0), 3.75);
+        assert_eq!(cpu.float_registers.fcsr, 0);
+    }
+
+    // opcode: 0b010001
+    // rs: 0b10001
+    // funct: 0b000000
+    #[test]
+    fn test_run_add_d() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.float_registers.write_double(2, 1.5);
+        cpu.float_registers.write_double(4, 2.25);
+        memory.write_word(0x00400000, 0x46241000).unwrap(); // add.d $f0, $f2, $f4
+        cpu.step(&mut memory);
+        assert_eq!(cpu.float_registers.read_double(0), 3.75);
+    }
+
+    // opcode: 0b010001
+    // rs: 0b10000
+    // funct: 0b000001
+    #[test]
+    fn test_run_sub_s() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.float_registers.f[2] = 1.5f32.to_bits();
+        cpu.float_registers.f[4] = 2.25f32.to_bits();
+        memory.write_word(0x00400000, 0x46041001).unwrap(); // sub.s $f0, $f2, $f4
+        cpu.step(&mut memory);
+        assert_eq!(cpu.float_registers.read_single(0), -0.75);
+    }
+
+    // opcode: 0b010001
+    // rs: 0b10001
+    // funct: 0b000010
+    #[test]
+    fn test_run_mul_d() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.float_registers.write_double(2, 1.5);
+        cpu.float_registers.write_double(4, -4.0);
+        memory.write_word(0x00400000, 0x46241002).unwrap(); // mul.d $f0, $f2, $f4
+        cpu.step(&mut memory);
+        assert_eq!(cpu.float_registers.read_double(0), -6.0);
+    }
+
+    // opcode: 0b010001
+    // rs: 0b10000
+    // funct: 0b000011
+    #[test]
+    fn test_run_div_s() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.float_registers.f[2] = 1.0f32.to_bits();
+        cpu.float_registers.f[4] = 3.0f32.to_bits();
+        memory.write_word(0x00400000, 0x46041003).unwrap(); // div.s $f0, $f2, $f4
+        cpu.step(&mut memory);
+        assert_eq!(cpu.float_registers.read_single(0), 1.0 / 3.0);
+        // Inexact cause and flag
+        assert_eq!(cpu.float_registers.fcsr, 1 << 12 | 1 << 2);
+    }
+
+    // opcode: 0b010001
+    // rs: 0b10001
+    // funct: 0b000100
+    #[test]
+    fn test_run_sqrt_d() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.float_registers.write_double(2, 2.25);
+        memory.write_word(0x00400000, 0x46241004).unwrap(); // sqrt.d $f0, $f2
+        cpu.step(&mut memory);
+        assert_eq!(cpu.float_registers.read_double(0), 1.5);
+    }
+
+    // opcode: 0b010001
+    // rs: 0b10000
+    // funct: 0b000101
+    #[test]
+    fn test_run_abs_s() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.float_registers.f[2] = (-2.5f32).to_bits();
+        memory.write_word(0x00400000, 0x46041005).unwrap(); // abs.s $f0, $f2
+        cpu.step(&mut memory);
+        assert_eq!(cpu.float_registers.read_single(0), 2.5);
+    }
+
+    // opcode: 0b010001
+    // rs: 0b10001
+    // funct: 0b000110
+    #[test]
+    fn test_run_mov_d() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.float_registers.write_double(2, 2.5);
+        memory.write_word(0x00400000, 0x46241006).unwrap(); // mov.d $f0, $f2
+        cpu.step(&mut memory);
+        assert_eq!(cpu.float_registers.read_double(0), 2.5);
+    }
+
+    // opcode: 0b010001
+    // rs: 0b10001
+    // funct: 0b000111
+    #[test]
+    fn test_run_neg_d() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.float_registers.write_double(2, 2.5);
+        memory.write_word(0x00400000, 0x46241007).unwrap(); // neg.d $f0, $f2
+        cpu.step(&mut memory);
+        assert_eq!(cpu.float_registers.read_double(0), -2.5);
+    }
+
+    // opcode: 0b010001
+    // rs: 0b10000
+    // funct: 0b001100
+    #[test]
+    fn test_run_round_w_s() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.float_registers.f[2] = 2.5f32.to_bits();
+        memory.write_word(0x00400000, 0x4604100c).unwrap(); // round.w.s $f0, $f2
+        cpu.step(&mut memory);
+        assert_eq!(cpu.float_registers.f[0], 2);
+    }
+
+    // opcode: 0b010001
+    // rs: 0b10001
+    // funct: 0b001101
+    #[test]
+    fn test_run_trunc_w_d() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.float_registers.write_double(2, -2.75);
+        memory.write_word(0x00400000, 0x4624100d).unwrap(); // trunc.w.d $f0, $f2
+        cpu.step(&mut memory);
+        assert_eq!(cpu.float_registers.f[0], (-2i32) as u32);
+    }
+
+    // opcode: 0b010001
+    // rs: 0b10000
+    // funct: 0b001110
+    #[test]
+    fn test_run_ceil_w_s() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.float_registers.f[2] = 2.25f32.to_bits();
+        memory.write_word(0x00400000, 0x4604100e).unwrap(); // ceil.w.s $f0, $f2
+        cpu.step(&mut memory);
+        assert_eq!(cpu.float_registers.f[0], 3);
+    }
+
+    // opcode: 0b010001
+    // rs: 0b10001
+    // funct: 0b001111
+    #[test]
+    fn test_run_floor_w_d() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.float_registers.write_double(2, -2.25);
+        memory.write_word(0x00400000, 0x4624100f).unwrap(); // floor.w.d $f0, $f2
+        cpu.step(&mut memory);
+        assert_eq!(cpu.float_registers.f[0], (-3i32) as u32);
+    }
+
+    // opcode: 0b010001
+    // rs: 0b10001
+    // funct: 0b100000
+    #[test]
+    fn test_run_cvt_s_d() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.float_registers.write_double(2, 0.1);
+        memory.write_word(0x00400000, 0x46241020).unwrap(); // cvt.s.d $f0, $f2
+        cpu.step(&mut memory);
+        assert_eq!(cpu.float_registers.read_single(0), 0.1f32);
+    }
+
+    // opcode: 0b010001
+    // rs: 0b10000
+    // funct: 0b100001
+    #[test]
+    fn test_run_cvt_d_s() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.float_registers.f[2] = 0.1f32.to_bits();
+        memory.write_word(0x00400000, 0x46041021).unwrap(); // cvt.d.s $f0, $f2
+        cpu.step(&mut memory);
+        assert_eq!(cpu.float_registers.read_double(0), 0.1f32 as f64);
+    }
+
+    // opcode: 0b010001
+    // rs: 0b10001
+    // funct: 0b100100
+    #[test]
+    fn test_run_cvt_w_d() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.float_registers.write_double(2, 1e10);
+        memory.write_word(0x00400000, 0x46241024).unwrap(); // cvt.w.d $f0, $f2
+        cpu.step(&mut memory);
+        assert_eq!(cpu.float_registers.f[0], 0x7fffffff);
+        // Invalid cause and flag
+        assert_eq!(cpu.float_registers.fcsr, 1 << 16 | 1 << 6);
+    }
+
+    // opcode: 0b010001
+    // rs: 0b10100
+    // funct: 0b100000
+    #[test]
+    fn test_run_cvt_s_w() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.float_registers.f[2] = (-7i32) as u32;
+        memory.write_word(0x00400000, 0x46841020).unwrap(); // cvt.s.w $f0, $f2
+        cpu.step(&mut memory);
+        assert_eq!(cpu.float_registers.read_single(0), -7.0);
+    }
+
+    // opcode: 0b010001
+    // rs: 0b10100
+    // funct: 0b100001
+    #[test]
+    fn test_run_cvt_d_w() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.float_registers.f[2] = 16777217;
+        memory.write_word(0x00400000, 0x46841021).unwrap(); // cvt.d.w $f0, $f2
+        cpu.step(&mut memory);
+        assert_eq!(cpu.float_registers.read_double(0), 16777217.0);
+    }
+
+    // opcode: 0b010001
+    // rs: 0b10000
+    // funct: 0b111100
+    #[test]
+    fn test_run_c_lt_s() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.float_registers.f[2] = 1.0f32.to_bits();
+        cpu.float_registers.f[4] = 2.0f32.to_bits();
+        memory.write_word(0x00400000, 0x4604123c).unwrap(); // c.lt.s $fcc2, $f2, $f4
+        memory.write_word(0x00400004, 0x4602223c).unwrap(); // c.lt.s $fcc2, $f4, $f2
+        cpu.step(&mut memory);
+        assert!(cpu.float_registers.condition(2));
+        cpu.step(&mut memory);
+        assert!(!cpu.float_registers.condition(2));
+    }
+
+    // opcode: 0b010001
+    // rs: 0b10100
+    // funct: 0b000100
+    #[test]
+    fn test_run_cmp_lt_s() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.isa = IsaLevel::Mips32r6;
+        cpu.float_registers.f[4] = 1.0f32.to_bits();
+        cpu.float_registers.f[6] = 2.0f32.to_bits();
+        memory.write_word(0x00400000, 0x46862084).unwrap(); // cmp.lt.s $f2, $f4, $f6
+        memory.write_word(0x00400004, 0x46843084).unwrap(); // cmp.lt.s $f2, $f6, $f4
+        cpu.step(&mut memory);
+        assert_eq!(cpu.float_registers.f[2], u32::MAX);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.float_registers.f[2], 0);
+    }
+
+    // opcode: 0b010001
+    // rs: 0b10101
+    // funct: 0b010010
+    #[test]
+    fn test_run_cmp_une_d() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.isa = IsaLevel::Mips32r6;
+        cpu.float_registers.write_double(2, 1.0);
+        cpu.float_registers.write_double(4, 1.0);
+        cpu.float_registers.write_double(6, f64::NAN);
+        memory.write_word(0x00400000, 0x46a41012).unwrap(); // cmp.une.d $f0, $f2, $f4
+        memory.write_word(0x00400004, 0x46a61012).unwrap(); // cmp.une.d $f0, $f2, $f6
+        cpu.float_registers.f[0] = 0xDEADBEEF;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.float_registers.f[0], 0);
+        assert_eq!(cpu.float_registers.f[1], 0);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.float_registers.f[0], u32::MAX);
+        assert_eq!(cpu.float_registers.f[1], u32::MAX);
+    }
+
+    #[test]
+    fn test_release6_removed_encodings() {
+        for word in [
+            0x4604123c, // c.lt.s $fcc2, $f2, $f4
+            0x45010003, // bc1t 3
+            0x46001091, // movf.s $f2, $f2, $fcc0
+            0x46a41010, // cmp.cond.d with reserved condition 16
+        ] {
+            let mut cpu = CPU::default();
+            let mut memory = Memory::default();
+            memory.text_address = 0x00400000;
+            memory.map(0x00400000, 0x1000, Permissions::ALL);
+            cpu.isa = IsaLevel::Mips32r6;
+            memory.write_word(0x00400000, word).unwrap();
+            cpu.step(&mut memory);
+            assert_eq!(
+                cpu.exception,
+                Exception::ReservedInstruction {
+                    word,
+                    address: 0x00400000
+                }
+            );
+        }
+    }
+
+    // opcode: 0b010001
+    // rs: 0b10001
+    // funct: 0b110010
+    #[test]
+    fn test_run_c_eq_d() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.float_registers.write_double(2, 1.0);
+        cpu.float_registers.write_double(4, 1.0);
+        memory.write_word(0x00400000, 0x46241032).unwrap(); // c.eq.d $f2, $f4
+        cpu.step(&mut memory);
+        assert!(cpu.float_registers.condition(0));
+    }
+
+    // opcode: 0b010001
+    // rs: 0b10001
+    // funct: 0b111110
+    #[test]
+    fn test_run_c_le_d_unordered() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.float_registers.write_double(2, f64::NAN);
+        cpu.float_registers.write_double(4, 1.0);
+        memory.write_word(0x00400000, 0x4624103e).unwrap(); // c.le.d $f2, $f4
+        cpu.step(&mut memory);
+        assert!(!cpu.float_registers.condition(0));
+        // Signaling comparisons are invalid on NaN
+        assert_eq!(cpu.float_registers.fcsr, 1 << 16 | 1 << 6);
+    }
+
+    // opcode: 0b010001
+    // rs: 0b10000
+    // funct: 0b010010
+    #[test]
+    fn test_run_movz_s() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.float_registers.f[2] = 0xDEADBEEF;
+        memory.write_word(0x00400000, 0x46091012).unwrap(); // movz.s $f0, $f2, $t1
+        cpu.step(&mut memory);
+        assert_eq!(cpu.float_registers.f[0], 0xDEADBEEF);
+    }
+
+    // opcode: 0b010001
+    // rs: 0b10000
+    // funct: 0b010001
+    #[test]
+    fn test_run_movt_s() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.float_registers.f[2] = 0xDEADBEEF;
+        memory.write_word(0x00400000, 0x46011011).unwrap(); // movt.s $f0, $f2, $fcc0
+        cpu.step(&mut memory);
+        assert_eq!(cpu.float_registers.f[0], 0);
+    }
+
+    #[test]
+    fn test_run_rounding_modes() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        for (mode, expected) in [
+            (ROUND_NEAREST, 0x3eaaaaab),
+            (ROUND_ZERO, 0x3eaaaaaa),
+            (ROUND_PLUS, 0x3eaaaaab),
+            (ROUND_MINUS, 0x3eaaaaaa),
+        ] {
+            cpu.registers.pc = 0x00400000;
+            cpu.float_registers.fcsr = mode;
+            cpu.float_registers.f[2] = 1.0f32.to_bits();
+            cpu.float_registers.f[4] = 3.0f32.to_bits();
+            memory.write_word(0x00400000, 0x46041003).unwrap(); // div.s $f0, $f2, $f4
+            cpu.step(&mut memory);
+            assert_eq!(cpu.float_registers.f[0], expected);
+        }
+    }
+
+    #[test]
+    fn test_run_overflow() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.float_registers.fcsr = ROUND_ZERO;
+        cpu.float_registers.write_double(2, f64::MAX);
+        memory.write_word(0x00400000, 0x46221000).unwrap(); // add.d $f0, $f2, $f2
+        cpu.step(&mut memory);
+        assert_eq!(cpu.float_registers.read_double(0), f64::MAX);
+        assert_eq!(
+            cpu.float_registers.fcsr >> CAUSE_SHIFT & 0x3f,
+            OVERFLOW | INEXACT
+        );
+    }
+
+    #[test]
+    fn test_run_divide_by_zero_trap() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        // Enable the divide by zero exception
+        cpu.float_registers.fcsr = DIVIDE_BY_ZERO << ENABLE_SHIFT;
+        cpu.float_registers.f[0] = 0xDEADBEEF;
+        cpu.float_registers.f[2] = 1.0f32.to_bits();
+        memory.write_word(0x00400000, 0x46041003).unwrap(); // div.s $f0, $f2, $f4
+        cpu.step(&mut memory);
+        assert_eq!(cpu.exception, Exception::FloatingPoint);
+        assert_eq!(cpu.float_registers.f[0], 0xDEADBEEF);
+        assert_eq!(
+            cpu.float_registers.fcsr >> CAUSE_SHIFT & 0x3f,
+            DIVIDE_BY_ZERO
+        );
+        assert_eq!(cpu.float_registers.fcsr >> FLAG_SHIFT & 0x1f, 0);
+    }
+
+    // opcode: 0b110001
+    #[test]
+    fn test_run_lwc1() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.data_address = 0x10000000;
+        memory.map(0x10000000, 0x1000, Permissions::READ_WRITE);
+        cpu.registers.t1 = 0x10000000;
+        memory.write_word(0x10000004, 0x40490fdb).unwrap();
+        memory.write_word(0x00400000, 0xc5220004).unwrap(); // lwc1 $f2, 4($t1)
+        cpu.step(&mut memory);
+        assert_eq!(cpu.float_registers.f[2], 0x40490fdb);
+    }
+
+    // opcode: 0b110101
+    #[test]
+    fn test_run_ldc1() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.data_address = 0x10000000;
+        memory.map(0x10000000, 0x1000, Permissions::READ_WRITE);
+        cpu.registers.t1 = 0x10000000;
+        memory.write_word(0x10000008, 0x400921fb).unwrap();
+        memory.write_word(0x1000000c, 0x54442d18).unwrap();
+        memory.write_word(0x00400000, 0xd5220008).unwrap(); // ldc1 $f2, 8($t1)
+        cpu.step(&mut memory);
+        assert_eq!(cpu.float_registers.read_double(2), std::f64::consts::PI);
+        assert_eq!(cpu.float_registers.f[3], 0x400921fb);
+    }
+
+    // opcode: 0b110101
+    #[test]
+    fn test_run_ldc1_little_endian() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.endianness = Endianness::Little;
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.data_address = 0x10000000;
+        memory.map(0x10000000, 0x1000, Permissions::READ_WRITE);
+        cpu.registers.t1 = 0x10000000;
+        memory.write_word(0x10000008, 0x54442d18).unwrap();
+        memory.write_word(0x1000000c, 0x400921fb).unwrap();
+        memory.write_word(0x00400000, 0xd5220008).unwrap(); // ldc1 $f2, 8($t1)
+        cpu.step(&mut memory);
+        assert_eq!(cpu.float_registers.read_double(2), std::f64::consts::PI);
+    }
+
+    // opcode: 0b111001
+    #[test]
+    fn test_run_swc1() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.data_address = 0x10000000;
+        memory.map(0x10000000, 0x1000, Permissions::READ_WRITE);
+        cpu.registers.t1 = 0x10000000;
+        cpu.float_registers.f[2] = 0x40490fdb;
+        memory.write_word(0x00400000, 0xe5220004).unwrap(); // swc1 $f2, 4($t1)
+        cpu.step(&mut memory);
+        assert_eq!(memory.read_word(0x10000004).unwrap(), 0x40490fdb);
+    }
+
+    // opcode: 0b111101
+    #[test]
+    fn test_run_sdc1() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.data_address = 0x10000000;
+        memory.map(0x10000000, 0x1000, Permissions::READ_WRITE);
+        cpu.registers.t1 = 0x10000000;
+        cpu.float_registers.write_double(2, std::f64::consts::PI);
+        memory.write_word(0x00400000, 0xf5220008).unwrap(); // sdc1 $f2, 8($t1)
+        cpu.step(&mut memory);
+        assert_eq!(memory.read_word(0x10000008).unwrap(), 0x400921fb);
+        assert_eq!(memory.read_word(0x1000000c).unwrap(), 0x54442d18);
+    }
+}
@@ -0,0 +1,1306 @@
+use super::{Instruction, InstructionKind, CPU};
+use crate::memory::{Memory, MemoryError};
+
+/// GPRs reachable from the 3-bit register fields of 16-bit instructions, in MIPS16e as well
+pub(super) const REGISTERS_16: [u8; 8] = [16, 17, 2, 3, 4, 5, 6, 7];
+/// Source registers of sb16, sh16 and sw16, which can store $zero instead of $s0
+const STORE_REGISTERS_16: [u8; 8] = [0, 17, 2, 3, 4, 5, 6, 7];
+/// Immediates selected by the 4-bit field of andi16
+const ANDI16_IMMEDIATES: [u16; 16] = [
+    128, 1, 2, 3, 4, 7, 8, 15, 16, 31, 32, 63, 64, 255, 32768, 65535,
+];
+/// Immediates selected by the 3-bit field of addiur2
+const ADDIUR2_IMMEDIATES: [i32; 8] = [1, 4, 8, 12, 16, 20, 24, -1];
+/// Destination register pairs selected by the 3-bit field of movep
+const MOVEP_DESTINATIONS: [(u8, u8); 8] = [
+    (5, 6),
+    (5, 7),
+    (6, 7),
+    (4, 21),
+    (4, 22),
+    (4, 5),
+    (4, 6),
+    (4, 7),
+];
+/// Source registers of movep
+const MOVEP_SOURCES: [u8; 8] = [0, 17, 2, 3, 16, 18, 19, 20];
+/// POOL32B minor opcodes of lwm and swm, also used for the 16-bit forms
+const LWM: u8 = 0b0101;
+const SWM: u8 = 0b1101;
+
+const REGISTER_NAMES: [&str; 32] = [
+    "zero", "at", "v0", "v1", "a0", "a1", "a2", "a3", "t0", "t1", "t2", "t3", "t4", "t5", "t6",
+    "t7", "s0", "s1", "s2", "s3", "s4", "s5", "s6", "s7", "t8", "t9", "k0", "k1", "gp", "sp", "fp",
+    "ra",
+];
+
+/**
+ * Operands of a microMIPS or MIPS16e instruction as they are written in assembly
+ */
+pub(super) enum Syntax {
+    None,
+    /// A code or immediate on its own
+    I(i32),
+    /// The two halves of a break code
+    II(i32, i32),
+    R(u8),
+    RR(u8, u8),
+    RRR(u8, u8, u8),
+    RRRR(u8, u8, u8, u8),
+    RI(u8, i32),
+    RRI(u8, u8, i32),
+    /// rt, offset(base)
+    Memory(u8, i32, u8),
+    /// ft, offset(base) for the FPU loads and stores
+    FloatMemory(u8, i32, u8),
+    /// reglist, offset(base) of lwm and swm
+    RegisterList(u8, i32, u8),
+    /// rt, rs, pos, size of ext and ins
+    Field(u8, u8, u8, u8),
+    /// PC-relative branch, the offset is in bytes from the next instruction
+    B(i32),
+    RB(u8, i32),
+    RRB(u8, u8, i32),
+    /// Jump, the instruction index and how far it is shifted
+    J(u32, u32),
+    /// rt, rs, $pc, for the MIPS16e PC-relative addiu
+    PcImmediate(u8, i32),
+    /// rt, offset($pc), for the MIPS16e PC-relative lw
+    PcMemory(u8, i32),
+    /// Operands with a layout of their own, such as the MIPS16e save and restore lists
+    Text(String),
+}
+
+/**
+ * A microMIPS or MIPS16e instruction decoded into the MIPS32 instruction it executes as
+ */
+pub(super) struct Decoded {
+    pub(super) instruction: Instruction,
+    /// 2 or 4 bytes
+    pub(super) size: u32,
+    /// jals and jalrs, and the MIPS16e jumps, are followed by a 16-bit delay slot
+    pub(super) short_delay_slot: bool,
+    mnemonic: &'static str,
+    syntax: Syntax,
+}
+
+impl Instruction {
+    pub(super) fn r(word: u32, opcode: u8, rs: u8, rt: u8, rd: u8, shamt: u8, funct: u8) -> Self {
+        Instruction {
+            opcode,
+            rs,
+            rt,
+            rd,
+            shamt,
+            funct,
+            imm: 0,
+            address: 0,
+            word,
+            kind: InstructionKind::RType,
+        }
+    }
+
+    pub(super) fn i(word: u32, opcode: u8, rs: u8, rt: u8, imm: i32) -> Self {
+        Instruction {
+            imm: imm as u16,
+            kind: InstructionKind::IType,
+            ..Instruction::r(word, opcode, rs, rt, 0, 0, 0)
+        }
+    }
+
+    pub(super) fn j(word: u32, opcode: u8, address: u32) -> Self {
+        Instruction {
+            address,
+            kind: InstructionKind::JType,
+            ..Instruction::r(word, opcode, 0, 0, 0, 0, 0)
+        }
+    }
+
+    /**
+     * Compact jumps, compact branches, addiupc, lwm, swm and movep, which have no MIPS32
+     * equivalent and are executed by micromips_native
+     */
+    fn native(word: u32, opcode: u8, rs: u8, rt: u8, imm: i32) -> Self {
+        Instruction {
+            kind: InstructionKind::MicroMips,
+            ..Instruction::i(word, opcode, rs, rt, imm)
+        }
+    }
+
+    /**
+     * lwm or swm of the registers in reglist, in the 5-bit encoding of the 32-bit forms
+     */
+    fn multiple(word: u32, minor: u8, reglist: u8, base: u8, offset: i32) -> Self {
+        Instruction {
+            funct: minor,
+            ..Instruction::native(word, 0b001000, base, reglist, offset)
+        }
+    }
+
+    fn reserved(word: u32) -> Self {
+        Instruction {
+            kind: InstructionKind::Reserved,
+            ..Instruction::r(word, (word >> 26) as u8, 0, 0, 0, 0, 0)
+        }
+    }
+}
+
+impl Decoded {
+    pub(super) fn new(
+        instruction: Instruction,
+        size: u32,
+        mnemonic: &'static str,
+        syntax: Syntax,
+    ) -> Self {
+        Decoded {
+            instruction,
+            size,
+            short_delay_slot: false,
+            mnemonic,
+            syntax,
+        }
+    }
+
+    pub(super) fn short_delay_slot(mut self) -> Self {
+        self.short_delay_slot = true;
+        self
+    }
+
+    pub(super) fn reserved(word: u32, size: u32) -> Self {
+        Decoded::new(Instruction::reserved(word), size, ".word", Syntax::None)
+    }
+
+    /**
+     * Assembly text of the instruction when it sits at address
+     */
+    pub(super) fn text(&self, address: u64) -> String {
+        let next = address.wrapping_add(self.size as u64);
+        let target = |offset: i32| next.wrapping_add(offset as u64);
+        let operands = match self.syntax {
+            Syntax::None if self.mnemonic == ".word" => {
+                return format!(
+                    ".word 0x{:0width$x}",
+                    self.instruction.word,
+                    width = 2 * self.size as usize
+                )
+            }
+            Syntax::None => String::new(),
+            Syntax::I(value) => value.to_string(),
+            Syntax::II(first, second) => format!("{}, {}", first, second),
+            Syntax::R(r) => format!("${}", name(r)),
+            Syntax::RR(a, b) => format!("${}, ${}", name(a), name(b)),
+            Syntax::RRR(a, b, c) => format!("${}, ${}, ${}", name(a), name(b), name(c)),
+            Syntax::RRRR(a, b, c, d) => {
+                format!("${}, ${}, ${}, ${}", name(a), name(b), name(c), name(d))
+            }
+            Syntax::RI(r, value) => format!("${}, {}", name(r), value),
+            Syntax::RRI(a, b, value) => format!("${}, ${}, {}", name(a), name(b), value),
+            Syntax::Memory(rt, offset, base) => {
+                format!("${}, {}(${})", name(rt), offset, name(base))
+            }
+            Syntax::FloatMemory(ft, offset, base) => {
+                format!("$f{}, {}(${})", ft, offset, name(base))
+            }
+            Syntax::RegisterList(reglist, offset, base) => {
+                format!("{}, {}(${})", register_list(reglist), offset, name(base))
+            }
+            Syntax::Field(rt, rs, pos, size) => {
+                format!("${}, ${}, {}, {}", name(rt), name(rs), pos, size)
+            }
+            Syntax::B(offset) => format!("0x{:08x}", target(offset)),
+            Syntax::RB(r, offset) => format!("${}, 0x{:08x}", name(r), target(offset)),
+            Syntax::RRB(a, b, offset) => {
+                format!("${}, ${}, 0x{:08x}", name(a), name(b), target(offset))
+            }
+            Syntax::J(index, shift) => {
+                // j, jal and jals keep the upper 5 bits of the delay slot address, jalx the upper 4
+                let region = address.wrapping_add(4) & (u64::MAX << (shift + 26));
+                format!("0x{:08x}", region | (index << shift) as u64)
+            }
+            Syntax::PcImmediate(rt, imm) => format!("${}, $pc, {}", name(rt), imm),
+            Syntax::PcMemory(rt, offset) => format!("${}, {}($pc)", name(rt), offset),
+            Syntax::Text(ref text) => text.clone(),
+        };
+        if operands.is_empty() {
+            self.mnemonic.to_string()
+        } else {
+            format!("{} {}", self.mnemonic, operands)
+        }
+    }
+}
+
+pub(super) fn name(register: u8) -> &'static str {
+    REGISTER_NAMES[register as usize]
+}
+
+/**
+ * Registers saved by lwm and swm, in the order they are laid out in memory.
+ * The low 4 bits of reglist count from $s0 with $fp after $s7, bit 4 adds $ra.
+ */
+fn saved_registers(reglist: u8) -> impl Iterator<Item = u8> {
+    (0..reglist & 0xf)
+        .map(|index| if index == 8 { 30 } else { 16 + index })
+        .chain((reglist & 0x10 != 0).then_some(31))
+}
+
+fn register_list(reglist: u8) -> String {
+    let mut parts = Vec::new();
+    match reglist & 0xf {
+        0 => {}
+        1 => parts.push("$s0".to_string()),
+        9 => parts.push("$s0-$s7, $fp".to_string()),
+        count => parts.push(format!("$s0-${}", name(15 + count))),
+    }
+    if reglist & 0x10 != 0 {
+        parts.push("$ra".to_string());
+    }
+    parts.join(", ")
+}
+
+pub(super) fn sign_extend(value: u32, bits: u32) -> i32 {
+    ((value << (32 - bits)) as i32) >> (32 - bits)
+}
+
+/**
+ * A 16-bit instruction has a major opcode ending in 001, 010 or 011
+ */
+fn is_16bit(first: u16) -> bool {
+    matches!((first >> 10) & 0b111, 0b001..=0b011)
+}
+
+/**
+ * Decodes the instruction starting with the halfword first.
+ * second is the halfword after it, ignored for 16-bit instructions.
+ */
+pub(super) fn decode(first: u16, second: u16) -> Decoded {
+    if is_16bit(first) {
+        decode16(first)
+    } else {
+        decode32((first as u32) << 16 | second as u32)
+    }
+}
+
+fn decode16(half: u16) -> Decoded {
+    let word = half as u32;
+    let field = |shift: u32, bits: u32| (word >> shift) & ((1 << bits) - 1);
+    // 3-bit register fields at bits 9..7, 6..4 and 3..1
+    let r7 = REGISTERS_16[field(7, 3) as usize];
+    let r4 = REGISTERS_16[field(4, 3) as usize];
+    let r1 = REGISTERS_16[field(1, 3) as usize];
+    // 5-bit register fields at bits 9..5 and 4..0
+    let r5 = field(5, 5) as u8;
+    let r0 = field(0, 5) as u8;
+    let d = |instruction, mnemonic, syntax| Decoded::new(instruction, 2, mnemonic, syntax);
+    match half >> 10 {
+        // POOL16A
+        0b000001 => {
+            if half & 1 == 0 {
+                let instruction = Instruction::r(word, 0, r1, r4, r7, 0, 0b100001);
+                d(instruction, "addu16", Syntax::RRR(r7, r1, r4))
+            } else {
+                let instruction = Instruction::r(word, 0, r1, r4, r7, 0, 0b100011);
+                d(instruction, "subu16", Syntax::RRR(r7, r1, r4))
+            }
+        }
+        0b000010 => {
+            // An offset field of 15 means -1
+            let offset = if field(0, 4) == 0xf {
+                -1
+            } else {
+                field(0, 4) as i32
+            };
+            let instruction = Instruction::i(word, 0b100100, r4, r7, offset);
+            d(instruction, "lbu16", Syntax::Memory(r7, offset, r4))
+        }
+        0b000011 => {
+            let instruction = Instruction::r(word, 0, r0, 0, r5, 0, 0b100001);
+            d(instruction, "move", Syntax::RR(r5, r0))
+        }
+        0b100001 if half & 1 == 0 => {
+            let (first, second) = MOVEP_DESTINATIONS[field(7, 3) as usize];
+            let rs = MOVEP_SOURCES[field(1, 3) as usize];
+            let rt = MOVEP_SOURCES[field(4, 3) as usize];
+            // The second destination is carried in shamt
+            let instruction = Instruction {
+                kind: InstructionKind::MicroMips,
+                ..Instruction::r(word, 0b100001, rs, rt, first, second, 0)
+            };
+            d(instruction, "movep", Syntax::RRRR(first, second, rs, rt))
+        }
+        // POOL16B
+        0b001001 => {
+            let shamt = match field(1, 3) {
+                0 => 8,
+                shamt => shamt as u8,
+            };
+            if half & 1 == 0 {
+                let instruction = Instruction::r(word, 0, 0, r4, r7, shamt, 0b000000);
+                d(instruction, "sll16", Syntax::RRI(r7, r4, shamt as i32))
+            } else {
+                let instruction = Instruction::r(word, 0, 0, r4, r7, shamt, 0b000010);
+                d(instruction, "srl16", Syntax::RRI(r7, r4, shamt as i32))
+            }
+        }
+        0b001010 => {
+            let offset = field(0, 4) as i32 * 2;
+            let instruction = Instruction::i(word, 0b100101, r4, r7, offset);
+            d(instruction, "lhu16", Syntax::Memory(r7, offset, r4))
+        }
+        0b001011 => {
+            let imm = ANDI16_IMMEDIATES[field(0, 4) as usize];
+            let instruction = Instruction::i(word, 0b001100, r4, r7, imm as i32);
+            d(instruction, "andi16", Syntax::RRI(r7, r4, imm as i32))
+        }
+        0b010001 => decode_pool16c(half),
+        0b010010 => {
+            let offset = field(0, 5) as i32 * 4;
+            let instruction = Instruction::i(word, 0b100011, 29, r5, offset);
+            d(instruction, "lwsp", Syntax::Memory(r5, offset, 29))
+        }
+        // POOL16D
+        0b010011 => {
+            if half & 1 == 0 {
+                let imm = sign_extend(field(1, 4), 4);
+                let instruction = Instruction::i(word, 0b001001, r5, r5, imm);
+                d(instruction, "addius5", Syntax::RI(r5, imm))
+            } else {
+                let imm = match field(1, 9) as i32 {
+                    encoded @ 0..=1 => encoded + 256,
+                    encoded @ 2..=255 => encoded,
+                    encoded @ 256..=509 => encoded - 512,
+                    encoded => encoded - 768,
+                } * 4;
+                let instruction = Instruction::i(word, 0b001001, 29, 29, imm);
+                d(instruction, "addiusp", Syntax::I(imm))
+            }
+        }
+        0b011001 => {
+            let offset = field(0, 7) as i32 * 4;
+            let instruction = Instruction::i(word, 0b100011, 28, r7, offset);
+            d(instruction, "lwgp", Syntax::Memory(r7, offset, 28))
+        }
+        0b011010 => {
+            let offset = field(0, 4) as i32 * 4;
+            let instruction = Instruction::i(word, 0b100011, r4, r7, offset);
+            d(instruction, "lw16", Syntax::Memory(r7, offset, r4))
+        }
+        // POOL16E
+        0b011011 => {
+            if half & 1 == 0 {
+                let imm = ADDIUR2_IMMEDIATES[field(1, 3) as usize];
+                let instruction = Instruction::i(word, 0b001001, r4, r7, imm);
+                d(instruction, "addiur2", Syntax::RRI(r7, r4, imm))
+            } else {
+                let imm = field(1, 6) as i32 * 4;
+                let instruction = Instruction::i(word, 0b001001, 29, r7, imm);
+                d(instruction, "addiur1sp", Syntax::RI(r7, imm))
+            }
+        }
+        0b100010 | 0b101010 | 0b111010 => {
+            let source = STORE_REGISTERS_16[field(7, 3) as usize];
+            let (opcode, scale, mnemonic) = match half >> 10 {
+                0b100010 => (0b101000, 1, "sb16"),
+                0b101010 => (0b101001, 2, "sh16"),
+                _ => (0b101011, 4, "sw16"),
+            };
+            let offset = field(0, 4) as i32 * scale;
+            let instruction = Instruction::i(word, opcode, r4, source, offset);
+            d(instruction, mnemonic, Syntax::Memory(source, offset, r4))
+        }
+        0b100011 | 0b101011 => {
+            let offset = sign_extend(field(0, 7), 7);
+            let (opcode, mnemonic) = if half >> 10 == 0b100011 {
+                (0b000100, "beqz16")
+            } else {
+                (0b000101, "bnez16")
+            };
+            let instruction = Instruction::i(word, opcode, r7, 0, offset);
+            d(instruction, mnemonic, Syntax::RB(r7, offset * 2))
+        }
+        0b110010 => {
+            let offset = field(0, 5) as i32 * 4;
+            let instruction = Instruction::i(word, 0b101011, 29, r5, offset);
+            d(instruction, "swsp", Syntax::Memory(r5, offset, 29))
+        }
+        0b110011 => {
+            let offset = sign_extend(field(0, 10), 10);
+            let instruction = Instruction::i(word, 0b000100, 0, 0, offset);
+            d(instruction, "b16", Syntax::B(offset * 2))
+        }
+        0b111011 => {
+            // An immediate field of 127 means -1
+            let imm = if field(0, 7) == 0x7f {
+                -1
+            } else {
+                field(0, 7) as i32
+            };
+            let instruction = Instruction::i(word, 0b001001, 0, r7, imm);
+            d(instruction, "li16", Syntax::RI(r7, imm))
+        }
+        _ => Decoded::reserved(word, 2),
+    }
+}
+
+/**
+ * POOL16C: two-operand logic, 16-bit jumps and HI/LO moves
+ */
+fn decode_pool16c(half: u16) -> Decoded {
+    let word = half as u32;
+    let rt = REGISTERS_16[(word >> 3 & 7) as usize];
+    let rs = REGISTERS_16[(word & 7) as usize];
+    let r0 = (word & 0x1f) as u8;
+    let d = |instruction, mnemonic, syntax| Decoded::new(instruction, 2, mnemonic, syntax);
+    let logic = |funct, mnemonic| {
+        let instruction = Instruction::r(word, 0, rs, rt, rt, 0, funct);
+        d(instruction, mnemonic, Syntax::RR(rt, rs))
+    };
+    match word >> 6 & 0xf {
+        0b0000 => {
+            let instruction = Instruction::r(word, 0, rs, 0, rt, 0, 0b100111);
+            return d(instruction, "not16", Syntax::RR(rt, rs));
+        }
+        0b0001 => return logic(0b100110, "xor16"),
+        0b0010 => return logic(0b100100, "and16"),
+        0b0011 => return logic(0b100101, "or16"),
+        0b0100 | 0b0101 => {
+            // The 2-bit list saves $s0 up to $s3, and always $ra
+            let reglist = 0x10 | ((word >> 4 & 3) as u8 + 1);
+            let offset = (word & 0xf) as i32 * 4;
+            let (minor, mnemonic) = if word >> 6 & 0xf == 0b0100 {
+                (LWM, "lwm16")
+            } else {
+                (SWM, "swm16")
+            };
+            let instruction = Instruction::multiple(word, minor, reglist, 29, offset);
+            return d(
+                instruction,
+                mnemonic,
+                Syntax::RegisterList(reglist, offset, 29),
+            );
+        }
+        _ => {}
+    }
+    match word >> 5 & 0x1f {
+        0b01100 => d(
+            Instruction::r(word, 0, r0, 0, 0, 0, 0b001000),
+            "jr16",
+            Syntax::R(r0),
+        ),
+        0b01101 => d(
+            Instruction::native(word, 0b010001, r0, 0, 0),
+            "jrc",
+            Syntax::R(r0),
+        ),
+        0b01110 => d(
+            Instruction::r(word, 0, r0, 0, 31, 0, 0b001001),
+            "jalr16",
+            Syntax::R(r0),
+        ),
+        0b01111 => d(
+            Instruction::r(word, 0, r0, 0, 31, 0, 0b001001),
+            "jalrs16",
+            Syntax::R(r0),
+        )
+        .short_delay_slot(),
+        0b10000 => d(
+            Instruction::r(word, 0, 0, 0, r0, 0, 0b010000),
+            "mfhi16",
+            Syntax::R(r0),
+        ),
+        0b10010 => d(
+            Instruction::r(word, 0, 0, 0, r0, 0, 0b010010),
+            "mflo16",
+            Syntax::R(r0),
+        ),
+        0b10100 => {
+            let code = (word & 0xf) as i32;
+            d(
+                Instruction::r(word, 0, 0, 0, 0, 0, 0b001101),
+                "break16",
+                Syntax::I(code),
+            )
+        }
+        0b11000 => {
+            let imm = (word & 0x1f) as i32 * 4;
+            d(
+                Instruction::native(word, 0b010001, 31, 0, imm),
+                "jraddiusp",
+                Syntax::I(imm),
+            )
+        }
+        _ => Decoded::reserved(word, 2),
+    }
+}
+
+fn decode32(word: u32) -> Decoded {
+    let opcode = (word >> 26) as u8;
+    // The first register field is rt and the second rs, the other way round from MIPS32
+    let rt = (word >> 21 & 0x1f) as u8;
+    let rs = (word >> 16 & 0x1f) as u8;
+    let imm = word as u16 as i16 as i32;
+    let d = |instruction, mnemonic, syntax| Decoded::new(instruction, 4, mnemonic, syntax);
+    let immediate = |mips32, mnemonic, imm: i32| {
+        let instruction = Instruction::i(word, mips32, rs, rt, imm);
+        d(instruction, mnemonic, Syntax::RRI(rt, rs, imm))
+    };
+    let memory = |mips32, mnemonic| {
+        let instruction = Instruction::i(word, mips32, rs, rt, imm);
+        d(instruction, mnemonic, Syntax::Memory(rt, imm, rs))
+    };
+    let float_memory = |mips32, mnemonic| {
+        let instruction = Instruction::i(word, mips32, rs, rt, imm);
+        d(instruction, mnemonic, Syntax::FloatMemory(rt, imm, rs))
+    };
+    match opcode {
+        0b000000 => decode_pool32a(word),
+        0b010000 => decode_pool32i(word),
+        0b000100 => immediate(0b001000, "addi", imm),
+        0b001100 => immediate(0b001001, "addiu", imm),
+        0b100100 => immediate(0b001010, "slti", imm),
+        0b101100 => immediate(0b001011, "sltiu", imm),
+        0b110100 => immediate(0b001100, "andi", word as u16 as i32),
+        0b010100 => immediate(0b001101, "ori", word as u16 as i32),
+        0b011100 => immediate(0b001110, "xori", word as u16 as i32),
+        0b000111 => memory(0b100000, "lb"),
+        0b000101 => memory(0b100100, "lbu"),
+        0b001111 => memory(0b100001, "lh"),
+        0b001101 => memory(0b100101, "lhu"),
+        0b111111 => memory(0b100011, "lw"),
+        0b000110 => memory(0b101000, "sb"),
+        0b001110 => memory(0b101001, "sh"),
+        0b111110 => memory(0b101011, "sw"),
+        0b100111 => float_memory(0b110001, "lwc1"),
+        0b100110 => float_memory(0b111001, "swc1"),
+        0b101111 => float_memory(0b110101, "ldc1"),
+        0b101110 => float_memory(0b111101, "sdc1"),
+        0b100101 | 0b101101 => {
+            let (mips32, mnemonic) = if opcode == 0b100101 {
+                (0b000100, "beq")
+            } else {
+                (0b000101, "bne")
+            };
+            let instruction = Instruction::i(word, mips32, rs, rt, imm);
+            d(instruction, mnemonic, Syntax::RRB(rs, rt, imm * 2))
+        }
+        0b110101 | 0b111101 | 0b011101 => {
+            let index = word & 0x3ffffff;
+            let (mips32, mnemonic) = match opcode {
+                0b110101 => (0b000010, "j"),
+                0b111101 => (0b000011, "jal"),
+                _ => (0b000011, "jals"),
+            };
+            let decoded = d(
+                Instruction::j(word, mips32, index),
+                mnemonic,
+                Syntax::J(index, 1),
+            );
+            if opcode == 0b011101 {
+                decoded.short_delay_slot()
+            } else {
+                decoded
+            }
+        }
+        0b111100 => {
+            let index = word & 0x3ffffff;
+            d(
+                Instruction::j(word, 0b011101, index),
+                "jalx",
+                Syntax::J(index, 2),
+            )
+        }
+        // POOL32B
+        0b001000 => {
+            let offset = sign_extend(word & 0xfff, 12);
+            let minor = (word >> 12 & 0xf) as u8;
+            let mnemonic = match minor {
+                LWM => "lwm",
+                SWM => "swm",
+                _ => return Decoded::reserved(word, 4),
+            };
+            // The first register field is the list, lists of more than 9 saved registers are reserved
+            if rt == 0 || rt & 0xf > 9 {
+                return Decoded::reserved(word, 4);
+            }
+            let instruction = Instruction::multiple(word, minor, rt, rs, offset);
+            d(instruction, mnemonic, Syntax::RegisterList(rt, offset, rs))
+        }
+        // POOL32C
+        0b011000 => {
+            let offset = sign_extend(word & 0xfff, 12);
+            let (mips32, mnemonic) = match word >> 12 & 0xf {
+                0b0000 => (0b100010, "lwl"),
+                0b0001 => (0b100110, "lwr"),
+                0b0011 => (0b110000, "ll"),
+                0b1000 => (0b101010, "swl"),
+                0b1001 => (0b101110, "swr"),
+                0b1011 => (0b111000, "sc"),
+                _ => return Decoded::reserved(word, 4),
+            };
+            let instruction = Instruction::i(word, mips32, rs, rt, offset);
+            d(instruction, mnemonic, Syntax::Memory(rt, offset, rs))
+        }
+        0b011110 => {
+            let register = REGISTERS_16[(word >> 23 & 7) as usize];
+            let offset = sign_extend(word & 0x7fffff, 23) * 4;
+            let instruction = Instruction {
+                address: offset as u32,
+                ..Instruction::native(word, opcode, 0, register, 0)
+            };
+            d(instruction, "addiupc", Syntax::RI(register, offset))
+        }
+        _ => Decoded::reserved(word, 4),
+    }
+}
+
+/**
+ * POOL32A: register to register operations
+ */
+fn decode_pool32a(word: u32) -> Decoded {
+    let rt = (word >> 21 & 0x1f) as u8;
+    let rs = (word >> 16 & 0x1f) as u8;
+    let rd = (word >> 11 & 0x1f) as u8;
+    let d = |instruction, mnemonic, syntax| Decoded::new(instruction, 4, mnemonic, syntax);
+    match word & 0x3f {
+        // Shifts by an immediate put the destination in the first field
+        0b000000 => {
+            let (funct, rotate, mnemonic) = match word & 0x3ff {
+                0x000 => (0b000000, 0, "sll"),
+                0x040 => (0b000010, 0, "srl"),
+                0x080 => (0b000011, 0, "sra"),
+                0x0c0 => (0b000010, 1, "rotr"),
+                _ => return Decoded::reserved(word, 4),
+            };
+            let instruction = Instruction::r(word, 0, rotate, rs, rt, rd, funct);
+            if word == 0 {
+                d(instruction, "nop", Syntax::None)
+            } else {
+                d(instruction, mnemonic, Syntax::RRI(rt, rs, rd as i32))
+            }
+        }
+        0b010000 => {
+            let (opcode, funct, shamt, mnemonic) = match word & 0x3ff {
+                0x010 => (0, 0b000100, 0, "sllv"),
+                0x050 => (0, 0b000110, 0, "srlv"),
+                0x090 => (0, 0b000111, 0, "srav"),
+                0x0d0 => (0, 0b000110, 1, "rotrv"),
+                0x110 => (0, 0b100000, 0, "add"),
+                0x150 => (0, 0b100001, 0, "addu"),
+                0x190 => (0, 0b100010, 0, "sub"),
+                0x1d0 => (0, 0b100011, 0, "subu"),
+                0x210 => (0b011100, 0b000010, 0, "mul"),
+                0x250 => (0, 0b100100, 0, "and"),
+                0x290 => (0, 0b100101, 0, "or"),
+                0x2d0 => (0, 0b100111, 0, "nor"),
+                0x310 => (0, 0b100110, 0, "xor"),
+                0x350 => (0, 0b101010, 0, "slt"),
+                0x390 => (0, 0b101011, 0, "sltu"),
+                _ => return Decoded::reserved(word, 4),
+            };
+            let instruction = Instruction::r(word, opcode, rs, rt, rd, shamt, funct);
+            if opcode == 0 && funct < 0b001000 {
+                // Variable shifts are written rd, rt, rs
+                d(instruction, mnemonic, Syntax::RRR(rd, rt, rs))
+            } else {
+                d(instruction, mnemonic, Syntax::RRR(rd, rs, rt))
+            }
+        }
+        0b011000 => {
+            let (funct, mnemonic) = match word & 0x3ff {
+                0x018 => (0b001011, "movn"),
+                0x058 => (0b001010, "movz"),
+                _ => return Decoded::reserved(word, 4),
+            };
+            let instruction = Instruction::r(word, 0, rs, rt, rd, 0, funct);
+            d(instruction, mnemonic, Syntax::RRR(rd, rs, rt))
+        }
+        0b000111 => {
+            let instruction = Instruction::r(word, 0, 0, 0, 0, 0, 0b001101);
+            // The code is written as its upper 10 bits, then the lower 10 if they are used
+            let (upper, lower) = ((word >> 16 & 0x3ff) as i32, (word >> 6 & 0x3ff) as i32);
+            if lower == 0 {
+                d(instruction, "break", Syntax::I(upper))
+            } else {
+                d(instruction, "break", Syntax::II(upper, lower))
+            }
+        }
+        0b101100 => {
+            let (lsb, msbd) = ((word >> 6 & 0x1f) as u8, rd);
+            let instruction = Instruction::r(word, 0b011111, rs, rt, msbd, lsb, 0b000000);
+            d(instruction, "ext", Syntax::Field(rt, rs, lsb, msbd + 1))
+        }
+        0b001100 => {
+            let (lsb, msb) = ((word >> 6 & 0x1f) as u8, rd);
+            let instruction = Instruction::r(word, 0b011111, rs, rt, msb, lsb, 0b000100);
+            let size = (msb + 1).saturating_sub(lsb);
+            d(instruction, "ins", Syntax::Field(rt, rs, lsb, size))
+        }
+        0b111100 => decode_pool32axf(word),
+        _ => Decoded::reserved(word, 4),
+    }
+}
+
+/**
+ * POOL32AXf: multiply and divide, HI/LO moves, register jumps, traps and bit operations
+ */
+fn decode_pool32axf(word: u32) -> Decoded {
+    let rt = (word >> 21 & 0x1f) as u8;
+    let rs = (word >> 16 & 0x1f) as u8;
+    let d = |instruction, mnemonic, syntax| Decoded::new(instruction, 4, mnemonic, syntax);
+    let pair = |opcode, funct, mnemonic| {
+        let instruction = Instruction::r(word, opcode, rs, rt, 0, 0, funct);
+        d(instruction, mnemonic, Syntax::RR(rs, rt))
+    };
+    let unary = |opcode, funct, shamt, mnemonic| {
+        let instruction = Instruction::r(word, opcode, rs, rs, rt, shamt, funct);
+        d(instruction, mnemonic, Syntax::RR(rt, rs))
+    };
+    match word >> 6 & 0x3ff {
+        0x000 => pair(0, 0b110100, "teq"),
+        0x008 => pair(0, 0b110000, "tge"),
+        0x010 => pair(0, 0b110001, "tgeu"),
+        0x020 => pair(0, 0b110010, "tlt"),
+        0x028 => pair(0, 0b110011, "tltu"),
+        0x030 => pair(0, 0b110110, "tne"),
+        0x22c => pair(0, 0b011000, "mult"),
+        0x26c => pair(0, 0b011001, "multu"),
+        0x2ac => pair(0, 0b011010, "div"),
+        0x2ec => pair(0, 0b011011, "divu"),
+        0x32c => pair(0b011100, 0b000000, "madd"),
+        0x36c => pair(0b011100, 0b000001, "maddu"),
+        0x3ac => pair(0b011100, 0b000100, "msub"),
+        0x3ec => pair(0b011100, 0b000101, "msubu"),
+        0x035 => d(
+            Instruction::r(word, 0, 0, 0, rs, 0, 0b010000),
+            "mfhi",
+            Syntax::R(rs),
+        ),
+        0x075 => d(
+            Instruction::r(word, 0, 0, 0, rs, 0, 0b010010),
+            "mflo",
+            Syntax::R(rs),
+        ),
+        0x0b5 => d(
+            Instruction::r(word, 0, rs, 0, 0, 0, 0b010001),
+            "mthi",
+            Syntax::R(rs),
+        ),
+        0x0f5 => d(
+            Instruction::r(word, 0, rs, 0, 0, 0, 0b010011),
+            "mtlo",
+            Syntax::R(rs),
+        ),
+        0x12c => unary(0b011100, 0b100001, 0, "clo"),
+        0x16c => unary(0b011100, 0b100000, 0, "clz"),
+        0x0ac => unary(0b011111, 0b100000, 0b10000, "seb"),
+        0x0ec => unary(0b011111, 0b100000, 0b11000, "seh"),
+        0x1ec => unary(0b011111, 0b100000, 0b00010, "wsbh"),
+        // jalr and jalrs, with and without hazard barrier
+        0x03c | 0x07c | 0x13c | 0x17c => {
+            let short = word >> 6 & 0x100 != 0;
+            let instruction = Instruction::r(word, 0, rs, 0, rt, 0, 0b001001);
+            let decoded = match (rt, short) {
+                (0, _) => d(instruction, "jr", Syntax::R(rs)),
+                (_, false) => d(instruction, "jalr", Syntax::RR(rt, rs)),
+                (_, true) => d(instruction, "jalrs", Syntax::RR(rt, rs)),
+            };
+            if short {
+                decoded.short_delay_slot()
+            } else {
+                decoded
+            }
+        }
+        0x22d => d(
+            Instruction::r(word, 0, 0, 0, 0, 0, 0b001100),
+            "syscall",
+            Syntax::None,
+        ),
+        0x1ad => d(
+            Instruction::r(word, 0, 0, 0, 0, 0, 0b001111),
+            "sync",
+            Syntax::None,
+        ),
+        _ => Decoded::reserved(word, 4),
+    }
+}
+
+/**
+ * POOL32I: branches against zero and lui
+ */
+fn decode_pool32i(word: u32) -> Decoded {
+    let rs = (word >> 16 & 0x1f) as u8;
+    let imm = word as u16 as i16 as i32;
+    let d = |instruction, mnemonic| Decoded::new(instruction, 4, mnemonic, Syntax::RB(rs, imm * 2));
+    match word >> 21 & 0x1f {
+        0b00000 => d(Instruction::i(word, 0b000001, rs, 0b00000, imm), "bltz"),
+        0b00001 => d(Instruction::i(word, 0b000001, rs, 0b10000, imm), "bltzal"),
+        0b00010 => d(Instruction::i(word, 0b000001, rs, 0b00001, imm), "bgez"),
+        0b00011 => d(Instruction::i(word, 0b000001, rs, 0b10001, imm), "bgezal"),
+        0b00100 => d(Instruction::i(word, 0b000110, rs, 0, imm), "blez"),
+        0b00110 => d(Instruction::i(word, 0b000111, rs, 0, imm), "bgtz"),
+        0b00101 => d(
+            Instruction::native(word, 0b010000, rs, 0b00101, imm),
+            "bnezc",
+        ),
+        0b00111 => d(
+            Instruction::native(word, 0b010000, rs, 0b00111, imm),
+            "beqzc",
+        ),
+        0b01101 => {
+            let instruction = Instruction::i(word, 0b001111, 0, rs, imm);
+            Decoded::new(instruction, 4, "lui", Syntax::RI(rs, word as u16 as i32))
+        }
+        _ => Decoded::reserved(word, 4),
+    }
+}
+
+/**
+ * Disassembles the microMIPS instruction at address.
+ * Returns its size in bytes and its assembly text.
+ */
+pub fn disassemble(memory: &Memory, address: u64) -> Result<(u32, String), MemoryError> {
+    let first = memory.read_halfword(address)?;
+    let second = if is_16bit(first) {
+        0
+    } else {
+        memory.read_halfword(address.wrapping_add(2))?
+    };
+    let decoded = decode(first, second);
+    Ok((decoded.size, decoded.text(address)))
+}
+
+impl CPU {
+    /**
+     * Fetches the microMIPS instruction at the PC, one or two halfwords
+     */
+    pub(super) fn fetch_micromips(&mut self, memory: &Memory) -> Option<Instruction> {
+        let first = self.fetch_halfword(memory, self.registers.pc)?;
+        let second = if is_16bit(first) {
+            0
+        } else {
+            // Translated on its own, the second halfword may be on the next page
+            let second = self.wrap_address(self.registers.pc.wrapping_add(2));
+            self.fetch_halfword(memory, second)?
+        };
+        let decoded = decode(first, second);
+        self.instruction_size = decoded.size;
+        self.short_delay_slot = decoded.short_delay_slot;
+        Some(decoded.instruction)
+    }
+
+    /**
+     * microMIPS instructions without a MIPS32 equivalent
+     */
+    pub(super) fn micromips_native(&mut self, instruction: &Instruction, memory: &mut Memory) {
+        match instruction.opcode {
+            // jrc and jraddiusp: compact jumps to a register
+            0b010001 => {
+                let target = self.read_address(instruction.rs);
+                if instruction.imm != 0 {
+                    let sp = self.registers.read_register(29);
+                    self.registers
+                        .write_register(29, sp.wrapping_add(instruction.imm as u32));
+                }
+                self.compact_branch(instruction, true, target);
+            }
+            // beqzc and bnezc
+            0b010000 => {
+                let zero = self.read_gpr(instruction.rs) == 0;
+                let taken = if instruction.rt == 0b00111 {
+                    zero
+                } else {
+                    !zero
+                };
+                let target = self.branch_offset_target(instruction);
+                self.compact_branch(instruction, taken, target);
+            }
+            // addiupc
+            0b011110 => {
+                let base = self.registers.pc & !3;
+                let address = base.wrapping_add(instruction.address as i32 as u64);
+                self.write_address(instruction.rt, self.wrap_address(address));
+            }
+            // lwm and swm: a lw or sw for each register, to consecutive words
+            0b001000 => {
+                let offset = instruction.imm as i16 as i32;
+                for (index, register) in saved_registers(instruction.rt).enumerate() {
+                    let single = Instruction::i(
+                        instruction.word,
+                        0b100011,
+                        instruction.rs,
+                        register,
+                        offset + 4 * index as i32,
+                    );
+                    let result = if instruction.funct == LWM {
+                        self.lw(&single, memory)
+                    } else {
+                        self.sw(&single, memory)
+                    };
+                    if let Err(error) = result {
+                        self.memory_fault(error);
+                    }
+                    if self.raised.is_some() {
+                        break;
+                    }
+                }
+            }
+            // movep: both sources are read before either destination is written
+            0b100001 => {
+                let first = self.registers.read_register(instruction.rs);
+                let second = self.registers.read_register(instruction.rt);
+                self.registers.write_register(instruction.rd, first);
+                self.registers.write_register(instruction.shamt, second);
+            }
+            _ => self.reserved_instruction(instruction),
+        }
+    }
+
+    /**
+     * Jump and link exchange, a jal that also switches between MIPS32 and microMIPS or MIPS16e
+     * opcode: 0b011101 (MIPS32), 0b111100 (microMIPS), 0b00011 (MIPS16e)
+     */
+    pub(super) fn jalx(&mut self, instruction: &Instruction) {
+        let region = self.wrap_address(self.registers.pc.wrapping_add(4)) & !0x0fff_ffff;
+        let target = region | (instruction.address << 2) as u64 | !self.compressed() as u64;
+        let link = self.link_address();
+        self.write_address(31, link);
+        self.branch(target);
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::field_reassign_with_default)]
+mod tests {
+    use super::*;
+    use crate::cpu::{Exception, StepResult};
+    use crate::memory::Permissions;
+
+    // opcode: 0b000001
+    #[test]
+    fn test_run_addu16() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.micromips_ase = true;
+        cpu.micromips = true;
+        cpu.registers.s1 = 40;
+        cpu.registers.v0 = 2;
+        memory.write_halfword(0x00400000, 0x0422).unwrap(); // addu16 $s0, $s1, $v0
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.s0, 42);
+        assert_eq!(cpu.registers.pc, 0x00400002);
+    }
+
+    // opcode: 0b111011
+    #[test]
+    fn test_run_li16() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.micromips_ase = true;
+        cpu.micromips = true;
+        memory.write_halfword(0x00400000, 0xee05).unwrap(); // li16 $a0, 5
+        memory.write_halfword(0x00400002, 0xee7f).unwrap(); // li16 $a0, -1
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.a0, 5);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.a0, 0xffffffff);
+    }
+
+    // opcode: 0b011010, 0b111010
+    #[test]
+    fn test_run_lw16_sw16() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.map(0x10010000, 0x1000, Permissions::READ_WRITE);
+        cpu.micromips_ase = true;
+        cpu.micromips = true;
+        cpu.registers.s0 = 0x10010000;
+        memory.write_word(0x10010004, 0x12345678).unwrap();
+        memory.write_halfword(0x00400000, 0x6901).unwrap(); // lw16 $v0, 4($s0)
+        memory.write_halfword(0x00400002, 0xe902).unwrap(); // sw16 $v0, 8($s0)
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.v0, 0x12345678);
+        assert_eq!(memory.read_word(0x10010008).unwrap(), 0x12345678);
+    }
+
+    // opcode: 0b001100
+    #[test]
+    fn test_run_addiu32() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.micromips_ase = true;
+        cpu.micromips = true;
+        cpu.registers.v1 = 10;
+        memory.write_word(0x00400000, 0x3043fffc).unwrap(); // addiu $v0, $v1, -4
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.v0, 6);
+        assert_eq!(cpu.registers.pc, 0x00400004);
+    }
+
+    // opcode: 0b110011
+    #[test]
+    fn test_run_b16() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.micromips_ase = true;
+        cpu.micromips = true;
+        memory.write_halfword(0x00400000, 0xcc04).unwrap(); // b16 0x0040000a
+        memory.write_halfword(0x00400002, 0xee05).unwrap(); // li16 $a0, 5
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.a0, 5);
+        assert_eq!(cpu.registers.pc, 0x0040000a);
+        assert!(cpu.micromips);
+    }
+
+    // opcode: 0b100011
+    #[test]
+    fn test_run_beqz16() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.micromips_ase = true;
+        cpu.micromips = true;
+        memory.write_halfword(0x00400000, 0x8e02).unwrap(); // beqz16 $a0, 0x00400006
+        memory.write_halfword(0x00400002, 0x0c00).unwrap(); // move $zero, $zero
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400006);
+    }
+
+    // opcode: 0b011101
+    #[test]
+    fn test_run_jalx_to_micromips() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.micromips_ase = true;
+        cpu.micromips = true;
+        cpu.micromips = false;
+        memory.write_word(0x00400000, 0x74100004).unwrap(); // jalx 0x00400010
+        memory.write_word(0x00400004, 0x00000000).unwrap(); // nop
+        memory.write_halfword(0x00400010, 0xee05).unwrap(); // li16 $a0, 5
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400010);
+        assert_eq!(cpu.registers.ra, 0x00400008);
+        assert!(cpu.micromips);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.a0, 5);
+        assert_eq!(cpu.registers.pc, 0x00400012);
+    }
+
+    // opcode: 0b011101
+    #[test]
+    fn test_run_jalx_without_micromips() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.write_word(0x00400000, 0x74100004).unwrap(); // jalx 0x00400010
+        let result = cpu.step(&mut memory);
+        assert!(matches!(
+            result,
+            StepResult::Exception {
+                exception: Exception::ReservedInstruction { .. },
+                ..
+            }
+        ));
+    }
+
+    // opcode: 0b010001
+    #[test]
+    fn test_run_jr16_to_mips32() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.micromips_ase = true;
+        cpu.micromips = true;
+        cpu.registers.ra = 0x00400008;
+        memory.write_halfword(0x00400000, 0x459f).unwrap(); // jr16 $ra
+        memory.write_halfword(0x00400002, 0xee05).unwrap(); // li16 $a0, 5
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.a0, 5);
+        assert_eq!(cpu.registers.pc, 0x00400008);
+        assert!(!cpu.micromips);
+    }
+
+    // opcode: 0b010001
+    #[test]
+    fn test_run_jrc() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.micromips_ase = true;
+        cpu.micromips = true;
+        cpu.registers.ra = 0x00400011;
+        memory.write_halfword(0x00400000, 0x45bf).unwrap(); // jrc $ra
+        memory.write_halfword(0x00400002, 0xee05).unwrap(); // li16 $a0, 5
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400010);
+        assert_eq!(cpu.registers.a0, 0);
+        assert!(cpu.micromips);
+    }
+
+    // opcode: 0b010001
+    #[test]
+    fn test_run_break16_epc() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.micromips_ase = true;
+        cpu.micromips = true;
+        memory.write_halfword(0x00400000, 0x0c00).unwrap(); // move $zero, $zero
+        memory.write_halfword(0x00400002, 0x4680).unwrap(); // break16 0
+        cpu.step(&mut memory);
+        let result = cpu.step(&mut memory);
+        assert!(matches!(result, StepResult::Breakpoint { pc: 0x00400002 }));
+        assert_eq!(cpu.control_registers.epc, 0x00400003);
+    }
+
+    // opcode: 0b010001
+    #[test]
+    fn test_run_swm16_lwm16() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.data_address = 0x10010000;
+        memory.map(0x10010000, 0x2000, Permissions::READ_WRITE);
+        cpu.micromips_ase = true;
+        cpu.micromips = true;
+        cpu.registers.sp = 0x10010ff0;
+        cpu.registers.s0 = 1;
+        cpu.registers.s1 = 2;
+        cpu.registers.ra = 0x00400101;
+        memory.write_halfword(0x00400000, 0x4552).unwrap(); // swm16 $s0-$s1, $ra, 8($sp)
+        memory.write_halfword(0x00400002, 0x4512).unwrap(); // lwm16 $s0-$s1, $ra, 8($sp)
+        cpu.step(&mut memory);
+        assert_eq!(memory.read_word(0x10010ff8).unwrap(), 1);
+        assert_eq!(memory.read_word(0x10010ffc).unwrap(), 2);
+        assert_eq!(memory.read_word(0x10011000).unwrap(), 0x00400101);
+        cpu.registers.s0 = 0;
+        cpu.registers.s1 = 0;
+        cpu.registers.ra = 0;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.s0, 1);
+        assert_eq!(cpu.registers.s1, 2);
+        assert_eq!(cpu.registers.ra, 0x00400101);
+        assert_eq!(cpu.registers.pc, 0x00400004);
+    }
+
+    // opcode: 0b001000
+    #[test]
+    fn test_run_swm32_lwm32() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.map(0x10010000, 0x1000, Permissions::READ_WRITE);
+        cpu.micromips_ase = true;
+        cpu.micromips = true;
+        cpu.registers.sp = 0x10010000;
+        cpu.registers.s7 = 7;
+        cpu.registers.fp = 8;
+        cpu.registers.ra = 9;
+        memory.write_word(0x00400000, 0x233dd000).unwrap(); // swm $s0-$s7, $fp, $ra, 0($sp)
+        memory.write_word(0x00400004, 0x233d5000).unwrap(); // lwm $s0-$s7, $fp, $ra, 0($sp)
+        cpu.step(&mut memory);
+        assert_eq!(memory.read_word(0x1001001c).unwrap(), 7);
+        assert_eq!(memory.read_word(0x10010020).unwrap(), 8);
+        assert_eq!(memory.read_word(0x10010024).unwrap(), 9);
+        cpu.registers.fp = 0;
+        cpu.registers.ra = 0;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.fp, 8);
+        assert_eq!(cpu.registers.ra, 9);
+    }
+
+    // opcode: 0b001000
+    #[test]
+    fn test_run_lwm32_unaligned() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.micromips_ase = true;
+        cpu.micromips = true;
+        cpu.registers.sp = 0x10010002;
+        memory.write_word(0x00400000, 0x205d5000).unwrap(); // lwm $s0-$s1, 0($sp)
+        let result = cpu.step(&mut memory);
+        assert!(matches!(
+            result,
+            StepResult::Exception {
+                exception: Exception::AddressErrorLoad(0x10010002),
+                ..
+            }
+        ));
+    }
+
+    // opcode: 0b100001
+    #[test]
+    fn test_run_movep() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.micromips_ase = true;
+        cpu.micromips = true;
+        cpu.registers.s1 = 1;
+        cpu.registers.v0 = 2;
+        cpu.registers.a0 = 3;
+        memory.write_halfword(0x00400000, 0x86a2).unwrap(); // movep $a0, $a1, $s1, $v0
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.a0, 1);
+        assert_eq!(cpu.registers.a1, 2);
+        assert_eq!(cpu.registers.pc, 0x00400002);
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.write_halfword(0x00400000, 0x0422).unwrap();
+        memory.write_halfword(0x00400002, 0x3043).unwrap();
+        memory.write_halfword(0x00400004, 0xfffc).unwrap();
+        memory.write_halfword(0x00400006, 0xcc04).unwrap();
+        memory.write_word(0x00400008, 0x00050007).unwrap();
+        memory.write_halfword(0x0040000c, 0x4552).unwrap();
+        memory.write_halfword(0x0040000e, 0x86a2).unwrap();
+        memory.write_halfword(0x00400010, 0x233d).unwrap();
+        memory.write_halfword(0x00400012, 0x5ff0).unwrap();
+        let listing = [
+            (2, "addu16 $s0, $s1, $v0"),
+            (4, "addiu $v0, $v1, -4"),
+            (2, "b16 0x00400010"),
+            (4, "break 5"),
+            (2, "swm16 $s0-$s1, $ra, 8($sp)"),
+            (2, "movep $a0, $a1, $s1, $v0"),
+            (4, "lwm $s0-$s7, $fp, $ra, -16($sp)"),
+        ];
+        let mut address = 0x00400000;
+        for (size, text) in listing {
+            assert_eq!(
+                disassemble(&memory, address).unwrap(),
+                (size, text.to_string())
+            );
+            address += size as u64;
+        }
+    }
+}
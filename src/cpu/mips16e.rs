@@ -0,0 +1,907 @@
+use super::micromips::{name, sign_extend, Decoded, Syntax, REGISTERS_16};
+use super::mmu::Access;
+use super::{Exception, Instruction, InstructionKind, CPU};
+use crate::memory::{Memory, MemoryError};
+
+/**
+ * Registers and frame size of save and restore
+ */
+struct Frame {
+    /// Bytes the stack pointer moves by
+    size: u32,
+    ra: bool,
+    s0: bool,
+    s1: bool,
+    /// Number of registers kept from $s2 up, $fp is the seventh
+    xsregs: u8,
+    /// Arguments save stores in the caller's frame, from $a0 up
+    args: u8,
+    /// Arguments kept in the frame like saved registers, from $a3 down
+    statics: u8,
+}
+
+impl Frame {
+    /**
+     * Fields of a save or restore, None for the reserved argument encoding
+     */
+    fn new(word: u32) -> Option<Self> {
+        let extend = word >> 16;
+        let (framesize, xsregs, aregs) = if extend == 0 {
+            // Unextended, a frame size of 0 means 128 bytes
+            let framesize = match word & 0xf {
+                0 => 16,
+                framesize => framesize,
+            };
+            (framesize, 0, 0)
+        } else {
+            let framesize = (extend >> 4 & 0xf) << 4 | word & 0xf;
+            (framesize, (extend >> 8 & 7) as u8, extend & 0xf)
+        };
+        let (args, statics) = match aregs {
+            0b1110 => (4, 0),
+            0b1011 => (0, 4),
+            0b1111 => return None,
+            aregs => ((aregs >> 2) as u8, (aregs & 3) as u8),
+        };
+        Some(Frame {
+            size: framesize * 8,
+            ra: word & 0x40 != 0,
+            s0: word & 0x20 != 0,
+            s1: word & 0x10 != 0,
+            xsregs,
+            args,
+            statics,
+        })
+    }
+
+    /**
+     * Registers kept in the frame, in the order they are stored down from its top
+     */
+    fn registers(&self) -> Vec<u8> {
+        let mut registers = Vec::new();
+        if self.ra {
+            registers.push(31);
+        }
+        registers.extend(
+            (0..self.xsregs)
+                .rev()
+                .map(|index| if index == 6 { 30 } else { 18 + index }),
+        );
+        if self.s1 {
+            registers.push(17);
+        }
+        if self.s0 {
+            registers.push(16);
+        }
+        registers.extend((0..self.statics).map(|index| 7 - index));
+        registers
+    }
+
+    /**
+     * Operands as they are written in assembly, such as $a0, 32, $ra, $s0-$s1
+     */
+    fn text(&self) -> String {
+        let args: Vec<u8> = (4..4 + self.args).collect();
+        let mut saved: Vec<u8> = self
+            .registers()
+            .into_iter()
+            .filter(|register| (16..=30).contains(register))
+            .collect();
+        saved.sort();
+        let statics: Vec<u8> = (8 - self.statics..8).collect();
+        let mut parts = ranges(&args);
+        parts.push(self.size.to_string());
+        if self.ra {
+            parts.push("$ra".to_string());
+        }
+        parts.extend(ranges(&saved));
+        parts.extend(ranges(&statics));
+        parts.join(", ")
+    }
+}
+
+/**
+ * Register names with consecutive registers collapsed into a range
+ */
+fn ranges(registers: &[u8]) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut index = 0;
+    while index < registers.len() {
+        let first = registers[index];
+        while index + 1 < registers.len() && registers[index + 1] == registers[index] + 1 {
+            index += 1;
+        }
+        let last = registers[index];
+        if first == last {
+            parts.push(format!("${}", name(first)));
+        } else {
+            parts.push(format!("${}-${}", name(first), name(last)));
+        }
+        index += 1;
+    }
+    parts
+}
+
+impl Instruction {
+    /**
+     * PC-relative instructions, compact jumps and branches, save and restore, which have no
+     * MIPS32 equivalent and are executed by mips16e_native.
+     * The offset is in bytes.
+     */
+    fn mips16e(word: u32, opcode: u8, rs: u8, rt: u8, offset: i32) -> Self {
+        Instruction {
+            address: offset as u32,
+            kind: InstructionKind::Mips16e,
+            ..Instruction::i(word, opcode, rs, rt, 0)
+        }
+    }
+}
+
+/**
+ * A 32-bit MIPS16e instruction is jal, jalx or an instruction with an EXTEND prefix
+ */
+fn is_32bit(first: u16) -> bool {
+    matches!(first >> 11, 0b00011 | 0b11110)
+}
+
+/**
+ * Decodes the instruction starting with the halfword first.
+ * second is the halfword after it, ignored for 16-bit instructions.
+ */
+pub(super) fn decode(first: u16, second: u16) -> Decoded {
+    let word = (first as u32) << 16 | second as u32;
+    match first >> 11 {
+        0b00011 => decode_jal(word),
+        // EXTEND can only prefix a 16-bit instruction
+        0b11110 if is_32bit(second) => Decoded::reserved(word, 4),
+        0b11110 => decode_instruction(second, Some(first)),
+        _ => decode_instruction(first, None),
+    }
+}
+
+/**
+ * jal and jalx, with a 16-bit delay slot
+ */
+fn decode_jal(word: u32) -> Decoded {
+    // The instruction index is split into index[20:16], index[25:21] and index[15:0]
+    let index = (word >> 16 & 0x1f) << 21 | (word >> 21 & 0x1f) << 16 | word & 0xffff;
+    let (opcode, mnemonic) = if word & 1 << 26 == 0 {
+        (0b000011, "jal")
+    } else {
+        (0b011101, "jalx")
+    };
+    let instruction = Instruction::j(word, opcode, index);
+    Decoded::new(instruction, 4, mnemonic, Syntax::J(index, 2)).short_delay_slot()
+}
+
+/**
+ * Decodes a 16-bit instruction, extended when it follows an EXTEND prefix
+ */
+fn decode_instruction(half: u16, extend: Option<u16>) -> Decoded {
+    let (word, size) = match extend {
+        Some(extend) => ((extend as u32) << 16 | half as u32, 4),
+        None => (half as u32, 2),
+    };
+    let field = |shift: u32, bits: u32| (half as u32 >> shift) & ((1 << bits) - 1);
+    // 3-bit register fields at bits 10..8, 7..5 and 4..2
+    let rx = REGISTERS_16[field(8, 3) as usize];
+    let ry = REGISTERS_16[field(5, 3) as usize];
+    let rz = REGISTERS_16[field(2, 3) as usize];
+    // EXTEND carries imm[10:5] and imm[15:11], the instruction imm[4:0]
+    let extended = extend.map(|extend| {
+        let extend = extend as u32;
+        sign_extend(
+            (extend & 0x1f) << 11 | (extend >> 5 & 0x3f) << 5 | field(0, 5),
+            16,
+        )
+    });
+    // Unextended immediates are scaled by the access size, extended ones are not
+    let imm = |bits: u32, scale: i32| extended.unwrap_or(field(0, bits) as i32 * scale);
+    let simm =
+        |bits: u32, scale: i32| extended.unwrap_or(sign_extend(field(0, bits), bits) * scale);
+    let d = |instruction, mnemonic, syntax| Decoded::new(instruction, size, mnemonic, syntax);
+    let memory = |mips32, base, rt, offset, mnemonic| {
+        let instruction = Instruction::i(word, mips32, base, rt, offset);
+        d(instruction, mnemonic, Syntax::Memory(rt, offset, base))
+    };
+    let opcode = (half >> 11) as u8;
+    match opcode {
+        0b00000 => {
+            let imm = imm(8, 4);
+            let instruction = Instruction::i(word, 0b001001, 29, rx, imm);
+            d(instruction, "addiu", Syntax::RRI(rx, 29, imm))
+        }
+        0b00001 => {
+            let imm = imm(8, 4);
+            let instruction = Instruction::mips16e(word, opcode, 0, rx, imm);
+            d(instruction, "addiu", Syntax::PcImmediate(rx, imm))
+        }
+        0b00010 => {
+            let offset = simm(11, 1) * 2;
+            let instruction = Instruction::mips16e(word, opcode, 0, 0, offset);
+            d(instruction, "b", Syntax::B(offset))
+        }
+        0b00100 | 0b00101 => {
+            let offset = simm(8, 1) * 2;
+            let mnemonic = if opcode == 0b00100 { "beqz" } else { "bnez" };
+            let instruction = Instruction::mips16e(word, opcode, rx, 0, offset);
+            d(instruction, mnemonic, Syntax::RB(rx, offset))
+        }
+        // SHIFT
+        0b00110 => {
+            let shamt = match extend {
+                Some(extend) => (extend >> 6 & 0x1f) as u8,
+                None if field(2, 3) == 0 => 8,
+                None => field(2, 3) as u8,
+            };
+            let (funct, mnemonic) = match field(0, 2) {
+                0b00 => (0b000000, "sll"),
+                0b10 => (0b000010, "srl"),
+                0b11 => (0b000011, "sra"),
+                _ => return Decoded::reserved(word, size),
+            };
+            let instruction = Instruction::r(word, 0, 0, ry, rx, shamt, funct);
+            d(instruction, mnemonic, Syntax::RRI(rx, ry, shamt as i32))
+        }
+        // RRI-A
+        0b01000 if half & 0x10 == 0 => {
+            // Extended, the immediate has 15 bits: imm[10:4] and imm[14:11], then imm[3:0]
+            let imm = match extend {
+                Some(extend) => {
+                    let extend = extend as u32;
+                    sign_extend(
+                        (extend & 0xf) << 11 | (extend >> 4 & 0x7f) << 4 | field(0, 4),
+                        15,
+                    )
+                }
+                None => sign_extend(field(0, 4), 4),
+            };
+            let instruction = Instruction::i(word, 0b001001, rx, ry, imm);
+            d(instruction, "addiu", Syntax::RRI(ry, rx, imm))
+        }
+        0b01001 => {
+            let imm = simm(8, 1);
+            let instruction = Instruction::i(word, 0b001001, rx, rx, imm);
+            d(instruction, "addiu", Syntax::RI(rx, imm))
+        }
+        // slti and sltiu set $t8
+        0b01010 | 0b01011 => {
+            let imm = imm(8, 1);
+            let (mips32, mnemonic) = if opcode == 0b01010 {
+                (0b001010, "slti")
+            } else {
+                (0b001011, "sltiu")
+            };
+            let instruction = Instruction::i(word, mips32, rx, 24, imm);
+            d(instruction, mnemonic, Syntax::RI(rx, imm))
+        }
+        0b01100 => decode_i8(half, extend),
+        // li and cmpi zero-extend the immediate, extended or not
+        0b01101 => {
+            let imm = extended.map_or(field(0, 8) as i32, |imm| imm as u16 as i32);
+            let instruction = Instruction::i(word, 0b001101, 0, rx, imm);
+            d(instruction, "li", Syntax::RI(rx, imm))
+        }
+        0b01110 => {
+            let imm = extended.map_or(field(0, 8) as i32, |imm| imm as u16 as i32);
+            let instruction = Instruction::i(word, 0b001110, rx, 24, imm);
+            d(instruction, "cmpi", Syntax::RI(rx, imm))
+        }
+        0b10000 => memory(0b100000, rx, ry, imm(5, 1), "lb"),
+        0b10001 => memory(0b100001, rx, ry, imm(5, 2), "lh"),
+        0b10010 => memory(0b100011, 29, rx, imm(8, 4), "lw"),
+        0b10011 => memory(0b100011, rx, ry, imm(5, 4), "lw"),
+        0b10100 => memory(0b100100, rx, ry, imm(5, 1), "lbu"),
+        0b10101 => memory(0b100101, rx, ry, imm(5, 2), "lhu"),
+        0b10110 => {
+            let offset = imm(8, 4);
+            let instruction = Instruction::mips16e(word, opcode, 0, rx, offset);
+            d(instruction, "lw", Syntax::PcMemory(rx, offset))
+        }
+        0b11000 => memory(0b101000, rx, ry, imm(5, 1), "sb"),
+        0b11001 => memory(0b101001, rx, ry, imm(5, 2), "sh"),
+        0b11010 => memory(0b101011, 29, rx, imm(8, 4), "sw"),
+        0b11011 => memory(0b101011, rx, ry, imm(5, 4), "sw"),
+        // The register to register formats cannot be extended
+        _ if extend.is_some() => Decoded::reserved(word, size),
+        // RRR
+        0b11100 => {
+            let (funct, mnemonic) = match field(0, 2) {
+                0b01 => (0b100001, "addu"),
+                0b11 => (0b100011, "subu"),
+                _ => return Decoded::reserved(word, size),
+            };
+            let instruction = Instruction::r(word, 0, rx, ry, rz, 0, funct);
+            d(instruction, mnemonic, Syntax::RRR(rz, rx, ry))
+        }
+        0b11101 => decode_rr(half),
+        _ => Decoded::reserved(word, size),
+    }
+}
+
+/**
+ * I8: branches on $t8, stack adjustment, save and restore and moves to and from all 32 GPRs
+ */
+fn decode_i8(half: u16, extend: Option<u16>) -> Decoded {
+    let (word, size) = match extend {
+        Some(extend) => ((extend as u32) << 16 | half as u32, 4),
+        None => (half as u32, 2),
+    };
+    let imm8 = word & 0xff;
+    let extended = extend.map(|extend| {
+        let extend = extend as u32;
+        sign_extend(
+            (extend & 0x1f) << 11 | (extend >> 5 & 0x3f) << 5 | word & 0x1f,
+            16,
+        )
+    });
+    let d = |instruction, mnemonic, syntax| Decoded::new(instruction, size, mnemonic, syntax);
+    match word >> 8 & 7 {
+        // bteqz and btnez run as beqz and bnez on $t8
+        0b000 | 0b001 => {
+            let offset = extended.unwrap_or(sign_extend(imm8, 8)) * 2;
+            let (opcode, mnemonic) = if word >> 8 & 7 == 0 {
+                (0b00100, "bteqz")
+            } else {
+                (0b00101, "btnez")
+            };
+            let instruction = Instruction::mips16e(word, opcode, 24, 0, offset);
+            d(instruction, mnemonic, Syntax::B(offset))
+        }
+        0b010 => {
+            let offset = extended.unwrap_or(imm8 as i32 * 4);
+            let instruction = Instruction::i(word, 0b101011, 29, 31, offset);
+            d(instruction, "sw", Syntax::Memory(31, offset, 29))
+        }
+        0b011 => {
+            let imm = extended.unwrap_or(sign_extend(imm8, 8) * 8);
+            let instruction = Instruction::i(word, 0b001001, 29, 29, imm);
+            d(instruction, "addiu", Syntax::RI(29, imm))
+        }
+        0b100 => {
+            let Some(frame) = Frame::new(word) else {
+                return Decoded::reserved(word, size);
+            };
+            let mnemonic = if word & 0x80 != 0 { "save" } else { "restore" };
+            let instruction = Instruction::mips16e(word, 0b01100, 0, 0, 0);
+            d(instruction, mnemonic, Syntax::Text(frame.text()))
+        }
+        _ if extend.is_some() => Decoded::reserved(word, size),
+        // The 5-bit register is split as r32[2:0], then r32[4:3]
+        0b101 => {
+            let rd = ((word & 0x18) | (word >> 5 & 7)) as u8;
+            let rs = REGISTERS_16[(word & 7) as usize];
+            let instruction = Instruction::r(word, 0, rs, 0, rd, 0, 0b100001);
+            d(instruction, "move", Syntax::RR(rd, rs))
+        }
+        0b111 => {
+            let rd = REGISTERS_16[(word >> 5 & 7) as usize];
+            let rs = (word & 0x1f) as u8;
+            let instruction = Instruction::r(word, 0, rs, 0, rd, 0, 0b100001);
+            d(instruction, "move", Syntax::RR(rd, rs))
+        }
+        _ => Decoded::reserved(word, size),
+    }
+}
+
+/**
+ * RR: two-operand arithmetic and logic, register jumps, HI/LO moves, multiply and divide
+ */
+fn decode_rr(half: u16) -> Decoded {
+    let word = half as u32;
+    let rx = REGISTERS_16[(word >> 8 & 7) as usize];
+    let ry = REGISTERS_16[(word >> 5 & 7) as usize];
+    let d = |instruction, mnemonic, syntax| Decoded::new(instruction, 2, mnemonic, syntax);
+    let pair = |rs, rt, rd, funct, mnemonic| {
+        let instruction = Instruction::r(word, 0, rs, rt, rd, 0, funct);
+        d(instruction, mnemonic, Syntax::RR(rx, ry))
+    };
+    match word & 0x1f {
+        // The ry field selects the jump: bit 7 makes it compact, bit 6 links and bit 5 uses $ra
+        0b00000 => {
+            let rs = if word & 0x20 != 0 { 31 } else { rx };
+            match word >> 5 & 7 {
+                0b000 | 0b001 => d(
+                    Instruction::r(word, 0, rs, 0, 0, 0, 0b001000),
+                    "jr",
+                    Syntax::R(rs),
+                )
+                .short_delay_slot(),
+                0b010 => d(
+                    Instruction::r(word, 0, rx, 0, 31, 0, 0b001001),
+                    "jalr",
+                    Syntax::RR(31, rx),
+                )
+                .short_delay_slot(),
+                0b100 | 0b101 => d(
+                    Instruction::mips16e(word, 0b11101, rs, 0, 0),
+                    "jrc",
+                    Syntax::R(rs),
+                ),
+                0b110 => d(
+                    Instruction::mips16e(word, 0b11101, rx, 31, 0),
+                    "jalrc",
+                    Syntax::RR(31, rx),
+                ),
+                _ => Decoded::reserved(word, 2),
+            }
+        }
+        // Comparisons set $t8
+        0b00010 => pair(rx, ry, 24, 0b101010, "slt"),
+        0b00011 => pair(rx, ry, 24, 0b101011, "sltu"),
+        0b01010 => pair(rx, ry, 24, 0b100110, "cmp"),
+        // Variable shifts shift ry by rx
+        0b00100 | 0b00110 | 0b00111 => {
+            let (funct, mnemonic) = match word & 0x1f {
+                0b00100 => (0b000100, "sllv"),
+                0b00110 => (0b000110, "srlv"),
+                _ => (0b000111, "srav"),
+            };
+            let instruction = Instruction::r(word, 0, rx, ry, ry, 0, funct);
+            d(instruction, mnemonic, Syntax::RR(ry, rx))
+        }
+        0b00101 => d(
+            Instruction::r(word, 0, 0, 0, 0, 0, 0b001101),
+            "break",
+            Syntax::I((word >> 5 & 0x3f) as i32),
+        ),
+        0b01011 => pair(0, ry, rx, 0b100011, "neg"),
+        0b01100 => pair(rx, ry, rx, 0b100100, "and"),
+        0b01101 => pair(rx, ry, rx, 0b100101, "or"),
+        0b01110 => pair(rx, ry, rx, 0b100110, "xor"),
+        0b01111 => pair(ry, 0, rx, 0b100111, "not"),
+        0b10000 => d(
+            Instruction::r(word, 0, 0, 0, rx, 0, 0b010000),
+            "mfhi",
+            Syntax::R(rx),
+        ),
+        0b10010 => d(
+            Instruction::r(word, 0, 0, 0, rx, 0, 0b010010),
+            "mflo",
+            Syntax::R(rx),
+        ),
+        // CNVT: zero and sign extension of the low byte or halfword
+        0b10001 => match word >> 5 & 7 {
+            0b000 => d(
+                Instruction::i(word, 0b001100, rx, rx, 0xff),
+                "zeb",
+                Syntax::R(rx),
+            ),
+            0b001 => d(
+                Instruction::i(word, 0b001100, rx, rx, 0xffff),
+                "zeh",
+                Syntax::R(rx),
+            ),
+            0b100 => d(
+                Instruction::r(word, 0b011111, 0, rx, rx, 0b10000, 0b100000),
+                "seb",
+                Syntax::R(rx),
+            ),
+            0b101 => d(
+                Instruction::r(word, 0b011111, 0, rx, rx, 0b11000, 0b100000),
+                "seh",
+                Syntax::R(rx),
+            ),
+            _ => Decoded::reserved(word, 2),
+        },
+        0b11000 => pair(rx, ry, 0, 0b011000, "mult"),
+        0b11001 => pair(rx, ry, 0, 0b011001, "multu"),
+        0b11010 => pair(rx, ry, 0, 0b011010, "div"),
+        0b11011 => pair(rx, ry, 0, 0b011011, "divu"),
+        _ => Decoded::reserved(word, 2),
+    }
+}
+
+/**
+ * Disassembles the MIPS16e instruction at address.
+ * Returns its size in bytes and its assembly text.
+ */
+pub fn disassemble(memory: &Memory, address: u64) -> Result<(u32, String), MemoryError> {
+    let first = memory.read_halfword(address)?;
+    let second = if is_32bit(first) {
+        memory.read_halfword(address.wrapping_add(2))?
+    } else {
+        0
+    };
+    let decoded = decode(first, second);
+    Ok((decoded.size, decoded.text(address)))
+}
+
+impl CPU {
+    /**
+     * Fetches the MIPS16e instruction at the PC, with its EXTEND prefix or second jal halfword
+     */
+    pub(super) fn fetch_mips16e(&mut self, memory: &Memory) -> Option<Instruction> {
+        let first = self.fetch_halfword(memory, self.registers.pc)?;
+        let second = if is_32bit(first) {
+            let second = self.wrap_address(self.registers.pc.wrapping_add(2));
+            self.fetch_halfword(memory, second)?
+        } else {
+            0
+        };
+        let decoded = decode(first, second);
+        self.instruction_size = decoded.size;
+        self.short_delay_slot = decoded.short_delay_slot;
+        Some(decoded.instruction)
+    }
+
+    /**
+     * MIPS16e instructions without a MIPS32 equivalent
+     */
+    pub(super) fn mips16e_native(&mut self, instruction: &Instruction, memory: &mut Memory) {
+        let offset = instruction.address;
+        match instruction.opcode {
+            // addiupc and lwpc
+            0b00001 | 0b10110 => {
+                // In the delay slot of a jump, the address of the jump is used
+                let pc = if self.in_delay_slot {
+                    self.branch_address
+                } else {
+                    self.registers.pc
+                };
+                let address = self.wrap_address((pc & !3).wrapping_add(offset as i32 as u64));
+                if instruction.opcode == 0b00001 {
+                    self.write_address(instruction.rt, address);
+                } else if let Err(error) = self.lwpc(instruction, address, memory) {
+                    self.memory_fault(error);
+                }
+            }
+            // b, and beqz and bnez, which also run bteqz and btnez
+            0b00010 | 0b00100 | 0b00101 => {
+                let zero = self.registers.read_register(instruction.rs) == 0;
+                let taken = match instruction.opcode {
+                    0b00010 => true,
+                    0b00100 => zero,
+                    _ => !zero,
+                };
+                let next = self.registers.pc.wrapping_add(self.instruction_size as u64);
+                let target = self.wrap_address(next.wrapping_add(offset as i32 as u64));
+                self.compact_branch(instruction, taken, target | 1);
+            }
+            0b01100 => self.save_restore(instruction, memory),
+            // jrc and jalrc
+            0b11101 => {
+                let target = self.read_address(instruction.rs);
+                let link = self.wrap_address(self.registers.pc.wrapping_add(2)) | 1;
+                self.write_address(instruction.rt, link);
+                self.compact_branch(instruction, true, target);
+            }
+            _ => self.reserved_instruction(instruction),
+        }
+    }
+
+    /**
+     * Load word relative to the PC
+     * opcode: 0b10110
+     */
+    fn lwpc(
+        &mut self,
+        instruction: &Instruction,
+        address: u64,
+        memory: &Memory,
+    ) -> Result<(), MemoryError> {
+        if address & 3 != 0 {
+            self.trigger_exception(Exception::AddressErrorLoad(address));
+            return Ok(());
+        }
+        let Some(address) = self.translate(address, Access::Load, memory) else {
+            return Ok(());
+        };
+        let value = memory.read_word(address)?;
+        self.registers.write_register(instruction.rt, value);
+        Ok(())
+    }
+
+    /**
+     * Save and restore: set up or tear down a stack frame
+     * opcode: 0b01100
+     * save stores the arguments into the caller's frame and the registers below the stack
+     * pointer, then moves it down. restore loads the registers from the top of the frame and
+     * moves the stack pointer back up.
+     */
+    fn save_restore(&mut self, instruction: &Instruction, memory: &mut Memory) {
+        let Some(frame) = Frame::new(instruction.word) else {
+            return self.reserved_instruction(instruction);
+        };
+        let save = instruction.word & 0x80 != 0;
+        let top = if save { 0 } else { frame.size as i32 };
+        let mut words = Vec::new();
+        if save {
+            words.extend((0..frame.args).map(|index| (4 + index, 4 * index as i32)));
+        }
+        for (index, register) in frame.registers().into_iter().enumerate() {
+            words.push((register, top - 4 * (index as i32 + 1)));
+        }
+        for (register, offset) in words {
+            let single = Instruction::i(instruction.word, 0b100011, 29, register, offset);
+            let result = if save {
+                self.sw(&single, memory)
+            } else {
+                self.lw(&single, memory)
+            };
+            if let Err(error) = result {
+                self.memory_fault(error);
+            }
+            if self.raised.is_some() {
+                return;
+            }
+        }
+        let sp = self.registers.read_register(29);
+        let sp = if save {
+            sp.wrapping_sub(frame.size)
+        } else {
+            sp.wrapping_add(frame.size)
+        };
+        self.registers.write_register(29, sp);
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::field_reassign_with_default)]
+mod tests {
+    use super::*;
+    use crate::cpu::StepResult;
+    use crate::memory::Permissions;
+
+    // opcode: 0b01101, 0b01001
+    #[test]
+    fn test_run_li_addiu8() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.mips16e_ase = true;
+        cpu.mips16e = true;
+        memory.write_halfword(0x00400000, 0x6a05).unwrap(); // li $v0, 5
+        memory.write_halfword(0x00400002, 0x4a03).unwrap(); // addiu $v0, 3
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.v0, 8);
+        assert_eq!(cpu.registers.pc, 0x00400004);
+    }
+
+    // opcode: 0b11110
+    #[test]
+    fn test_run_extend() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.mips16e_ase = true;
+        cpu.mips16e = true;
+        memory.write_halfword(0x00400000, 0xf222).unwrap(); // li $v0, 0x1234
+        memory.write_halfword(0x00400002, 0x6a14).unwrap();
+        memory.write_halfword(0x00400004, 0xf41f).unwrap(); // addiu $v0, -1000
+        memory.write_halfword(0x00400006, 0x4a18).unwrap();
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.v0, 0x1234);
+        assert_eq!(cpu.registers.pc, 0x00400004);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.v0, 0x1234 - 1000);
+        assert_eq!(cpu.registers.pc, 0x00400008);
+    }
+
+    // opcode: 0b11110
+    #[test]
+    fn test_run_extend_not_extendable() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.mips16e_ase = true;
+        cpu.mips16e = true;
+        memory.write_halfword(0x00400000, 0xf000).unwrap(); // extend
+        memory.write_halfword(0x00400002, 0xe820).unwrap(); // jr $ra
+        let result = cpu.step(&mut memory);
+        assert!(matches!(
+            result,
+            StepResult::Exception {
+                exception: Exception::ReservedInstruction { .. },
+                pc: 0x00400000
+            }
+        ));
+    }
+
+    // opcode: 0b10110, 0b00001
+    #[test]
+    fn test_run_lwpc_addiupc() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.mips16e_ase = true;
+        cpu.mips16e = true;
+        memory.write_halfword(0x00400000, 0x6500).unwrap(); // nop
+        memory.write_halfword(0x00400002, 0xb202).unwrap(); // lw $v0, 8($pc)
+        memory.write_halfword(0x00400004, 0x0b01).unwrap(); // addiu $v1, $pc, 4
+        memory.write_word(0x00400008, 0x12345678).unwrap();
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.v0, 0x12345678);
+        assert_eq!(cpu.registers.v1, 0x00400008);
+    }
+
+    // opcode: 0b00010, 0b00100
+    #[test]
+    fn test_run_b_beqz() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.mips16e_ase = true;
+        cpu.mips16e = true;
+        memory.write_halfword(0x00400000, 0x1003).unwrap(); // b 0x00400008
+        memory.write_halfword(0x00400008, 0x2402).unwrap(); // beqz $a0, 0x0040000e
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400008);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x0040000e);
+        assert!(cpu.mips16e);
+    }
+
+    // opcode: 0b01110, 0b01100
+    #[test]
+    fn test_run_cmpi_bteqz() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.mips16e_ase = true;
+        cpu.mips16e = true;
+        cpu.registers.a0 = 5;
+        memory.write_halfword(0x00400000, 0x7405).unwrap(); // cmpi $a0, 5
+        memory.write_halfword(0x00400002, 0x6001).unwrap(); // bteqz 0x00400006
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t8, 0);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400006);
+    }
+
+    // opcode: 0b00011, 0b11101
+    #[test]
+    fn test_run_jal_jr() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.mips16e_ase = true;
+        cpu.mips16e = true;
+        memory.write_halfword(0x00400000, 0x1a00).unwrap(); // jal 0x00400100
+        memory.write_halfword(0x00400002, 0x0040).unwrap();
+        memory.write_halfword(0x00400004, 0x6c01).unwrap(); // li $a0, 1
+        memory.write_halfword(0x00400100, 0xe820).unwrap(); // jr $ra
+        memory.write_halfword(0x00400102, 0x6d02).unwrap(); // li $a1, 2
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400100);
+        assert_eq!(cpu.registers.ra, 0x00400007);
+        assert_eq!(cpu.registers.a0, 1);
+        assert!(cpu.mips16e);
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400006);
+        assert_eq!(cpu.registers.a1, 2);
+        assert!(cpu.mips16e);
+    }
+
+    // opcode: 0b011101 (MIPS32), 0b00011
+    #[test]
+    fn test_run_jalx_mips16e() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.mips16e_ase = true;
+        memory.write_word(0x00400000, 0x74100004).unwrap(); // jalx 0x00400010
+        memory.write_word(0x00400004, 0x00000000).unwrap(); // nop
+        memory.write_halfword(0x00400010, 0x1e00).unwrap(); // jalx 0x00400020
+        memory.write_halfword(0x00400012, 0x0008).unwrap();
+        memory.write_halfword(0x00400014, 0x6500).unwrap(); // nop
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400010);
+        assert!(cpu.mips16e);
+        assert!(!cpu.micromips);
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400020);
+        assert_eq!(cpu.registers.ra, 0x00400017);
+        assert!(!cpu.mips16e);
+    }
+
+    // opcode: 0b01100
+    #[test]
+    fn test_run_save_restore() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.data_address = 0x10010000;
+        memory.map(0x10010000, 0x1000, Permissions::READ_WRITE);
+        cpu.mips16e_ase = true;
+        cpu.mips16e = true;
+        cpu.registers.sp = 0x10010200;
+        cpu.registers.ra = 0x00400101;
+        cpu.registers.s0 = 7;
+        memory.write_halfword(0x00400000, 0x64e4).unwrap(); // save 32, $ra, $s0
+        memory.write_halfword(0x00400002, 0x6464).unwrap(); // restore 32, $ra, $s0
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.sp, 0x100101e0);
+        assert_eq!(memory.read_word(0x100101fc).unwrap(), 0x00400101);
+        assert_eq!(memory.read_word(0x100101f8).unwrap(), 7);
+        cpu.registers.ra = 0;
+        cpu.registers.s0 = 0;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.sp, 0x10010200);
+        assert_eq!(cpu.registers.ra, 0x00400101);
+        assert_eq!(cpu.registers.s0, 7);
+    }
+
+    // opcode: 0b01100
+    #[test]
+    fn test_run_save_extended() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.data_address = 0x10010000;
+        memory.map(0x10010000, 0x1000, Permissions::READ_WRITE);
+        cpu.mips16e_ase = true;
+        cpu.mips16e = true;
+        cpu.registers.sp = 0x10010200;
+        cpu.registers.a0 = 1;
+        cpu.registers.ra = 2;
+        cpu.registers.s3 = 3;
+        cpu.registers.s2 = 4;
+        cpu.registers.s1 = 5;
+        cpu.registers.a3 = 6;
+        memory.write_halfword(0x00400000, 0xf215).unwrap(); // save $a0, 128, $ra, $s1-$s3, $a3
+        memory.write_halfword(0x00400002, 0x64d0).unwrap();
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.sp, 0x10010180);
+        let words = [
+            (0x10010200, 1),
+            (0x100101fc, 2),
+            (0x100101f8, 3),
+            (0x100101f4, 4),
+            (0x100101f0, 5),
+            (0x100101ec, 6),
+        ];
+        for (address, value) in words {
+            assert_eq!(memory.read_word(address).unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        let halfwords = [
+            0x6a05, 0xf222, 0x6a14, 0x1003, 0x1a00, 0x0040, 0xe820, 0xb202, 0xf215, 0x64d0,
+        ];
+        for (index, halfword) in halfwords.into_iter().enumerate() {
+            memory
+                .write_halfword(0x00400000 + 2 * index as u64, halfword)
+                .unwrap();
+        }
+        let listing = [
+            (2, "li $v0, 5"),
+            (4, "li $v0, 4660"),
+            (2, "b 0x0040000e"),
+            (4, "jal 0x00400100"),
+            (2, "jr $ra"),
+            (2, "lw $v0, 8($pc)"),
+            (4, "save $a0, 128, $ra, $s1-$s3, $a3"),
+        ];
+        let mut address = 0x00400000;
+        for (size, text) in listing {
+            assert_eq!(
+                disassemble(&memory, address).unwrap(),
+                (size, text.to_string())
+            );
+            address += size as u64;
+        }
+    }
+}
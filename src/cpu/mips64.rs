@@ -0,0 +1,534 @@
+use super::{Exception, Instruction, CPU};
+use crate::memory::{Memory, MemoryError};
+
+impl CPU {
+    /**
+     * 64-bit r-type, raises Reserved Instruction outside MIPS64 mode
+     * opcode: 0b000000
+     */
+    pub(super) fn special64(&mut self, instruction: &Instruction) {
+        let isa = self.isa;
+        match instruction.funct {
+            0b010100 => self.dsllv(instruction),
+            0b010110 => self.dsrlv(instruction),
+            0b010111 => self.dsrav(instruction),
+            0b011100 if isa.before_release6() => self.dmult(instruction),
+            0b011101 if isa.before_release6() => self.dmultu(instruction),
+            0b011110 if isa.before_release6() => self.ddiv(instruction),
+            0b011111 if isa.before_release6() => self.ddivu(instruction),
+            0b101100 => self.dadd(instruction),
+            0b101101 => self.daddu(instruction),
+            0b101110 => self.dsub(instruction),
+            0b101111 => self.dsubu(instruction),
+            0b111000 => self.dsll(instruction, 0),
+            0b111010 => self.dsrl(instruction, 0),
+            0b111011 => self.dsra(instruction, 0),
+            0b111100 => self.dsll(instruction, 32),
+            0b111110 => self.dsrl(instruction, 32),
+            0b111111 => self.dsra(instruction, 32),
+            _ => self.reserved_instruction(instruction),
+        }
+    }
+
+    /**
+     * Doubleword shift left logical variable
+     * opcode: 0b000000
+     * funct: 0b010100
+     */
+    fn dsllv(&mut self, instruction: &Instruction) {
+        let rt = self.registers.read_register64(instruction.rt);
+        let rs = self.registers.read_register(instruction.rs);
+        self.registers
+            .write_register64(instruction.rd, rt << (rs & 0x3f));
+    }
+
+    /**
+     * Doubleword shift right logical variable, drotrv when shamt is 1
+     * opcode: 0b000000
+     * funct: 0b010110
+     */
+    fn dsrlv(&mut self, instruction: &Instruction) {
+        if instruction.shamt != 0 && !self.isa.has_release2() {
+            return self.reserved_instruction(instruction);
+        }
+        let rt = self.registers.read_register64(instruction.rt);
+        let rs = self.registers.read_register(instruction.rs);
+        let shamt = rs & 0x3f;
+        let result = if instruction.shamt == 1 {
+            rt.rotate_right(shamt)
+        } else {
+            rt >> shamt
+        };
+        self.registers.write_register64(instruction.rd, result);
+    }
+
+    /**
+     * Doubleword shift right arithmetic variable
+     * opcode: 0b000000
+     * funct: 0b010111
+     */
+    fn dsrav(&mut self, instruction: &Instruction) {
+        let rt = self.registers.read_register64(instruction.rt) as i64;
+        let rs = self.registers.read_register(instruction.rs);
+        self.registers
+            .write_register64(instruction.rd, (rt >> (rs & 0x3f)) as u64);
+    }
+
+    /**
+     * Doubleword multiply
+     * opcode: 0b000000
+     * funct: 0b011100
+     */
+    fn dmult(&mut self, instruction: &Instruction) {
+        let rs = self.registers.read_register64(instruction.rs) as i64 as i128;
+        let rt = self.registers.read_register64(instruction.rt) as i64 as i128;
+        let result = rs * rt;
+        self.registers
+            .write_hi_lo64((result >> 64) as u64, result as u64);
+    }
+
+    /**
+     * Doubleword multiply unsigned
+     * opcode: 0b000000
+     * funct: 0b011101
+     */
+    fn dmultu(&mut self, instruction: &Instruction) {
+        let rs = self.registers.read_register64(instruction.rs) as u128;
+        let rt = self.registers.read_register64(instruction.rt) as u128;
+        let result = rs * rt;
+        self.registers
+            .write_hi_lo64((result >> 64) as u64, result as u64);
+    }
+
+    /**
+     * Doubleword divide
+     * opcode: 0b000000
+     * funct: 0b011110
+     */
+    fn ddiv(&mut self, instruction: &Instruction) {
+        let rs = self.registers.read_register64(instruction.rs) as i64;
+        let rt = self.registers.read_register64(instruction.rt) as i64;
+        if rt == 0 {
+            self.registers.write_hi_lo64(0, 0);
+        } else {
+            self.registers
+                .write_hi_lo64(rs.wrapping_rem(rt) as u64, rs.wrapping_div(rt) as u64);
+        }
+    }
+
+    /**
+     * Doubleword divide unsigned
+     * opcode: 0b000000
+     * funct: 0b011111
+     */
+    fn ddivu(&mut self, instruction: &Instruction) {
+        let rs = self.registers.read_register64(instruction.rs);
+        let rt = self.registers.read_register64(instruction.rt);
+        if rt == 0 {
+            self.registers.write_hi_lo64(0, 0);
+        } else {
+            self.registers.write_hi_lo64(rs % rt, rs / rt);
+        }
+    }
+
+    /**
+     * Doubleword add
+     * opcode: 0b000000
+     * funct: 0b101100
+     */
+    fn dadd(&mut self, instruction: &Instruction) {
+        let rs = self.registers.read_register64(instruction.rs) as i64;
+        let rt = self.registers.read_register64(instruction.rt) as i64;
+        match rs.checked_add(rt) {
+            Some(result) => self
+                .registers
+                .write_register64(instruction.rd, result as u64),
+            None => self.trigger_exception(Exception::IntegerOverflow),
+        }
+    }
+
+    /**
+     * Doubleword add unsigned
+     * opcode: 0b000000
+     * funct: 0b101101
+     */
+    fn daddu(&mut self, instruction: &Instruction) {
+        let rs = self.registers.read_register64(instruction.rs);
+        let rt = self.registers.read_register64(instruction.rt);
+        self.registers
+            .write_register64(instruction.rd, rs.wrapping_add(rt));
+    }
+
+    /**
+     * Doubleword subtract
+     * opcode: 0b000000
+     * funct: 0b101110
+     */
+    fn dsub(&mut self, instruction: &Instruction) {
+        let rs = self.registers.read_register64(instruction.rs) as i64;
+        let rt = self.registers.read_register64(instruction.rt) as i64;
+        match rs.checked_sub(rt) {
+            Some(result) => self
+                .registers
+                .write_register64(instruction.rd, result as u64),
+            None => self.trigger_exception(Exception::IntegerOverflow),
+        }
+    }
+
+    /**
+     * Doubleword subtract unsigned
+     * opcode: 0b000000
+     * funct: 0b101111
+     */
+    fn dsubu(&mut self, instruction: &Instruction) {
+        let rs = self.registers.read_register64(instruction.rs);
+        let rt = self.registers.read_register64(instruction.rt);
+        self.registers
+            .write_register64(instruction.rd, rs.wrapping_sub(rt));
+    }
+
+    /**
+     * Doubleword shift left logical, dsll32 adds 32 to the shift amount
+     * opcode: 0b000000
+     * funct: 0b111000, 0b111100
+     */
+    fn dsll(&mut self, instruction: &Instruction, plus: u32) {
+        let rt = self.registers.read_register64(instruction.rt);
+        let shamt = instruction.shamt as u32 + plus;
+        self.registers.write_register64(instruction.rd, rt << shamt);
+    }
+
+    /**
+     * Doubleword shift right logical, drotr when rs is 1.
+     * dsrl32 and drotr32 add 32 to the shift amount.
+     * opcode: 0b000000
+     * funct: 0b111010, 0b111110
+     */
+    fn dsrl(&mut self, instruction: &Instruction, plus: u32) {
+        if instruction.rs != 0 && !self.isa.has_release2() {
+            return self.reserved_instruction(instruction);
+        }
+        let rt = self.registers.read_register64(instruction.rt);
+        let shamt = instruction.shamt as u32 + plus;
+        let result = if instruction.rs == 0 {
+            rt >> shamt
+        } else {
+            rt.rotate_right(shamt)
+        };
+        self.registers.write_register64(instruction.rd, result);
+    }
+
+    /**
+     * Doubleword shift right arithmetic, dsra32 adds 32 to the shift amount
+     * opcode: 0b000000
+     * funct: 0b111011, 0b111111
+     */
+    fn dsra(&mut self, instruction: &Instruction, plus: u32) {
+        let rt = self.registers.read_register64(instruction.rt) as i64;
+        let shamt = instruction.shamt as u32 + plus;
+        self.registers
+            .write_register64(instruction.rd, (rt >> shamt) as u64);
+    }
+
+    /**
+     * Doubleword add immediate
+     * opcode: 0b011000
+     */
+    pub(super) fn daddi(&mut self, instruction: &Instruction) {
+        let rs = self.registers.read_register64(instruction.rs) as i64;
+        let imm = instruction.imm as i16 as i64;
+        match rs.checked_add(imm) {
+            Some(result) => self
+                .registers
+                .write_register64(instruction.rt, result as u64),
+            None => self.trigger_exception(Exception::IntegerOverflow),
+        }
+    }
+
+    /**
+     * Doubleword add immediate unsigned
+     * opcode: 0b011001
+     */
+    pub(super) fn daddiu(&mut self, instruction: &Instruction) {
+        let rs = self.registers.read_register64(instruction.rs);
+        let imm = instruction.imm as i16 as i64 as u64;
+        self.registers
+            .write_register64(instruction.rt, rs.wrapping_add(imm));
+    }
+
+    /**
+     * Load word unsigned, zero extended into the upper word
+     * opcode: 0b100111
+     */
+    pub(super) fn lwu(
+        &mut self,
+        instruction: &Instruction,
+        memory: &Memory,
+    ) -> Result<(), MemoryError> {
+        let Some(address) = self.load_address(instruction, 4, memory) else {
+            return Ok(());
+        };
+        let value = memory.read_word(address)?;
+        self.registers
+            .write_register64(instruction.rt, value as u64);
+        Ok(())
+    }
+
+    /**
+     * Load doubleword
+     * opcode: 0b110111
+     */
+    pub(super) fn ld(
+        &mut self,
+        instruction: &Instruction,
+        memory: &Memory,
+    ) -> Result<(), MemoryError> {
+        let Some(address) = self.load_address(instruction, 8, memory) else {
+            return Ok(());
+        };
+        let value = memory.read_doubleword(address)?;
+        self.registers.write_register64(instruction.rt, value);
+        Ok(())
+    }
+
+    /**
+     * Store doubleword
+     * opcode: 0b111111
+     */
+    pub(super) fn sd(
+        &mut self,
+        instruction: &Instruction,
+        memory: &mut Memory,
+    ) -> Result<(), MemoryError> {
+        let Some(address) = self.store_address(instruction, 8, memory) else {
+            return Ok(());
+        };
+        let value = self.registers.read_register64(instruction.rt);
+        memory.write_doubleword(address, value)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::field_reassign_with_default)]
+mod tests {
+    use super::*;
+    use crate::cpu::StepResult;
+    use crate::memory::Permissions;
+
+    // opcode: 0b000000
+    #[test]
+    fn test_run_daddu() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.mips64 = true;
+        cpu.registers.write_register64(9, 0x00000000_ffffffff);
+        cpu.registers.write_register64(10, 0x00000001_00000001);
+        memory.write_word(0x00400000, 0x012a402d).unwrap(); // daddu $t0, $t1, $t2
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.read_register64(8), 0x00000002_00000000);
+    }
+
+    // opcode: 0b000000
+    #[test]
+    fn test_run_dadd_overflow() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.mips64 = true;
+        cpu.registers.write_register64(9, i64::MAX as u64);
+        cpu.registers.write_register64(10, 1);
+        memory.write_word(0x00400000, 0x012a402c).unwrap(); // dadd $t0, $t1, $t2
+        let result = cpu.step(&mut memory);
+        assert!(matches!(
+            result,
+            StepResult::Exception {
+                exception: Exception::IntegerOverflow,
+                ..
+            }
+        ));
+        assert_eq!(cpu.registers.read_register64(8), 0);
+    }
+
+    // opcode: 0b000000
+    #[test]
+    fn test_run_dsll32() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.mips64 = true;
+        cpu.registers.t1 = 0x00000001;
+        memory.write_word(0x00400000, 0x0009403c).unwrap(); // dsll32 $t0, $t1, 0
+        memory.write_word(0x00400004, 0x0008483e).unwrap(); // dsrl32 $t1, $t0, 0
+        memory.write_word(0x00400008, 0x0008507f).unwrap(); // dsra32 $t2, $t0, 1
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.read_register64(8), 0x00000001_00000000);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.read_register64(9), 1);
+        cpu.registers.write_register64(8, 0x80000000_00000000);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.read_register64(10), 0xffffffff_c0000000);
+    }
+
+    // opcode: 0b011001
+    #[test]
+    fn test_run_daddiu() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.mips64 = true;
+        cpu.registers.write_register64(9, 0x7fffffff);
+        memory.write_word(0x00400000, 0x65280001).unwrap(); // daddiu $t0, $t1, 1
+        cpu.step(&mut memory);
+        // No sign extension of a doubleword result
+        assert_eq!(cpu.registers.read_register64(8), 0x00000000_80000000);
+    }
+
+    // opcode: 0b001001
+    #[test]
+    fn test_run_addiu_sign_extends() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.mips64 = true;
+        cpu.registers.write_register64(9, 0x7fffffff);
+        memory.write_word(0x00400000, 0x25280001).unwrap(); // addiu $t0, $t1, 1
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.read_register64(8), 0xffffffff_80000000);
+    }
+
+    // opcode: 0b000000
+    #[test]
+    fn test_run_dmult() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.mips64 = true;
+        cpu.registers.write_register64(9, -2i64 as u64);
+        cpu.registers.write_register64(10, 0x40000000_00000000);
+        memory.write_word(0x00400000, 0x012a001c).unwrap(); // dmult $t1, $t2
+        memory.write_word(0x00400004, 0x00004010).unwrap(); // mfhi $t0
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.read_register64(8), u64::MAX);
+        assert_eq!(cpu.registers.lo64(), 0x80000000_00000000);
+    }
+
+    // opcode: 0b110111
+    #[test]
+    fn test_run_ld_sd() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.mips64 = true;
+        cpu.registers.write_register64(9, 0x01234567_89abcdef);
+        cpu.registers.t2 = 0x10010000;
+        memory.data_address = 0x10010000;
+        memory.map(0x10010000, 0x1000, Permissions::READ_WRITE);
+        memory.write_word(0x00400000, 0xfd490008).unwrap(); // sd $t1, 8($t2)
+        memory.write_word(0x00400004, 0xdd480008).unwrap(); // ld $t0, 8($t2)
+        memory.write_word(0x00400008, 0x9d48000c).unwrap(); // lwu $t0, 12($t2)
+        cpu.step(&mut memory);
+        assert_eq!(
+            memory.read_doubleword(0x10010008).unwrap(),
+            0x01234567_89abcdef
+        );
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.read_register64(8), 0x01234567_89abcdef);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.read_register64(8), 0x00000000_89abcdef);
+    }
+
+    // opcode: 0b110111
+    #[test]
+    fn test_run_ld_outside_compatibility_segments() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.map(0x1_10010000, 0x1000, Permissions::ALL);
+        memory
+            .write_doubleword(0x1_10010008, 0x01234567_89abcdef)
+            .unwrap();
+        cpu.mips64 = true;
+        cpu.registers.write_register64(10, 0x00000001_10010000);
+        memory.write_word(0x00400000, 0xdd480008).unwrap(); // ld $t0, 8($t2)
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.read_register64(8), 0x01234567_89abcdef);
+
+        // There is no 64-bit TLB to map it through
+        let mut cpu = CPU::default();
+        cpu.mips64 = true;
+        cpu.mmu = true;
+        cpu.registers.pc = 0xffffffff_80400000; // kseg0 maps to the ld at 0x00400000
+        cpu.registers.write_register64(10, 0x00000001_10010000);
+        let result = cpu.step(&mut memory);
+        assert!(matches!(
+            result,
+            StepResult::Exception {
+                exception: Exception::AddressErrorLoad(0x1_10010008),
+                ..
+            }
+        ));
+    }
+
+    // opcode: 0b000100
+    #[test]
+    fn test_run_beq_compares_doublewords() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.mips64 = true;
+        cpu.registers.write_register64(9, 0x00000001_00000000);
+        memory.write_word(0x00400000, 0x11200003).unwrap(); // beq $t1, $zero, 0x00400010
+        memory.write_word(0x00400004, 0x00000000).unwrap(); // nop
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400008);
+    }
+
+    // funct: 0b001001
+    #[test]
+    fn test_run_jalr_outside_compatibility_segments() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.map(0x1_20000000, 0x1000, Permissions::ALL);
+        cpu.mips64 = true;
+        cpu.registers.pc = 0x1_20000000;
+        cpu.registers.write_register64(9, 0x00000001_20000100);
+        memory.write_word(0x1_20000000, 0x0120f809).unwrap(); // jalr $t1
+        memory.write_word(0x1_20000004, 0x00000000).unwrap(); // nop
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x1_20000100);
+        assert_eq!(cpu.registers.read_register64(31), 0x00000001_20000008);
+    }
+
+    // opcode: 0b000000
+    #[test]
+    fn test_run_daddu_without_mips64() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.mips64 = true;
+        cpu.mips64 = false;
+        memory.write_word(0x00400000, 0x012a402d).unwrap(); // daddu $t0, $t1, $t2
+        let result = cpu.step(&mut memory);
+        assert!(matches!(
+            result,
+            StepResult::Exception {
+                exception: Exception::ReservedInstruction { .. },
+                ..
+            }
+        ));
+    }
+}
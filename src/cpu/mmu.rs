@@ -0,0 +1,761 @@
+use super::cop0::STATUS_ERL;
+use super::{Exception, Instruction, CPU};
+use crate::memory::Memory;
+
+/// Number of JTLB entries, as on the 4Kc
+pub const TLB_ENTRIES: usize = 16;
+
+/// Index is the TLB entry read and written by tlbr and tlbwi, P is set when tlbp finds no match
+pub const INDEX_P: u32 = 1 << 31;
+/// Even/odd page pair number in EntryHi, also used to compare entries
+const ENTRY_HI_VPN2: u32 = 0xffff_e000;
+const ENTRY_HI_ASID: u32 = 0xff;
+pub const ENTRY_HI_WRITABLE: u32 = ENTRY_HI_VPN2 | ENTRY_HI_ASID;
+/// PFN, C, D, V and G
+pub const ENTRY_LO_WRITABLE: u32 = 0x3fff_ffff;
+const ENTRY_LO_G: u32 = 1 << 0;
+const ENTRY_LO_V: u32 = 1 << 1;
+const ENTRY_LO_D: u32 = 1 << 2;
+const ENTRY_LO_PFN_SHIFT: u32 = 6;
+/// Mask bits of PageMask, 4 KiB to 256 MiB pages
+pub const PAGE_MASK_WRITABLE: u32 = 0x1fff_e000;
+/// PTEBase of Context, the rest is BadVPN2
+pub const CONTEXT_WRITABLE: u32 = 0xff80_0000;
+const CONTEXT_BAD_VPN2_SHIFT: u32 = 9;
+
+/// kseg0 and kseg1 are windows onto the low 512 MiB of physical memory
+pub const KSEG_PHYSICAL: u32 = 0x1fff_ffff;
+
+/// Offset of the TLB refill vector from the exception base
+pub const TLB_REFILL_OFFSET: u32 = 0x000;
+
+/**
+ * One TLB entry, mapping an even/odd pair of pages
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TlbEntry {
+    pub page_mask: u32,
+    /// VPN2 and ASID
+    pub entry_hi: u32,
+    /// Even page
+    pub entry_lo0: u32,
+    /// Odd page
+    pub entry_lo1: u32,
+}
+
+impl TlbEntry {
+    /**
+     * The entry ignores the ASID when both pages were written global
+     */
+    fn global(&self) -> bool {
+        self.entry_lo0 & self.entry_lo1 & ENTRY_LO_G != 0
+    }
+
+    /**
+     * Whether the entry maps address in the address space asid
+     */
+    fn matches(&self, address: u32, asid: u32) -> bool {
+        let compared = ENTRY_HI_VPN2 & !self.page_mask;
+        (self.entry_hi ^ address) & compared == 0
+            && (self.global() || self.entry_hi & ENTRY_HI_ASID == asid)
+    }
+
+    /**
+     * Lowest address bit of the virtual page number, which selects the even or odd page
+     */
+    fn odd_page_bit(&self) -> u32 {
+        (self.page_mask | 0x1fff).wrapping_add(1) >> 1
+    }
+}
+
+/// Kind of memory access being translated
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum Access {
+    Fetch,
+    Load,
+    Store,
+}
+
+impl CPU {
+    /**
+     * Translate a virtual address to the physical address it accesses.
+     * Raises an address error for a segment the current mode may not use, a TLB exception
+     * when no valid entry maps the address, or a protection exception when the page does not
+     * allow the access, and returns None.
+     */
+    pub(super) fn translate(
+        &mut self,
+        address: u64,
+        access: Access,
+        memory: &Memory,
+    ) -> Option<u64> {
+        // MIPS64 reaches the 32-bit segments through sign-extended addresses
+        let compatible = !self.mips64 || address == address as i32 as u64;
+        let cop0 = &self.control_registers;
+        let allowed = if compatible {
+            cop0.address_allowed(address as u32)
+        } else {
+            // Only the compatibility segments are mapped, there is no 64-bit TLB
+            !self.mmu && cop0.address_allowed64(address)
+        };
+        if !allowed {
+            let exception = match access {
+                Access::Store => Exception::AddressErrorStore(address),
+                _ => Exception::AddressErrorLoad(address),
+            };
+            self.trigger_exception(exception);
+            return None;
+        }
+        let physical = if compatible {
+            self.map_address(address, access)?
+        } else {
+            address
+        };
+        let permissions = memory.permissions(physical);
+        let permitted = match access {
+            Access::Fetch => permissions.execute,
+            Access::Load => permissions.read,
+            Access::Store => permissions.write || self.self_modifying_code && permissions.execute,
+        };
+        if !permitted {
+            let exception = match access {
+                Access::Fetch => Exception::ExecuteInhibit(address),
+                Access::Load => Exception::ReadInhibit(address),
+                Access::Store => Exception::WriteProtected(address),
+            };
+            self.trigger_exception(exception);
+            return None;
+        }
+        Some(physical)
+    }
+
+    /**
+     * kseg0 and kseg1 are fixed windows on the first 512 MiB, kuseg, kseg2 and kseg3 go through the TLB.
+     * Without the MMU, addresses are physical, sign-extended ones at their 32-bit address.
+     */
+    fn map_address(&mut self, address: u64, access: Access) -> Option<u64> {
+        let segment_address = address as u32;
+        if !self.mmu {
+            return Some(segment_address as u64);
+        }
+        let cop0 = &self.control_registers;
+        match segment_address >> 29 {
+            // kseg0 and kseg1
+            0b100 | 0b101 => return Some((segment_address & KSEG_PHYSICAL) as u64),
+            // kuseg is unmapped while ERL is set, so reset code can run from it
+            0b000..=0b011 if cop0.status & STATUS_ERL != 0 => return Some(segment_address as u64),
+            _ => {}
+        }
+        let asid = cop0.entry_hi & ENTRY_HI_ASID;
+        let Some(entry) = cop0
+            .tlb
+            .iter()
+            .find(|entry| entry.matches(segment_address, asid))
+        else {
+            let exception = match access {
+                Access::Store => Exception::TlbRefillStore(address),
+                _ => Exception::TlbRefillLoad(address),
+            };
+            self.tlb_exception(segment_address, exception);
+            return None;
+        };
+        let odd_page_bit = entry.odd_page_bit();
+        let entry_lo = if segment_address & odd_page_bit != 0 {
+            entry.entry_lo1
+        } else {
+            entry.entry_lo0
+        };
+        if entry_lo & ENTRY_LO_V == 0 {
+            let exception = match access {
+                Access::Store => Exception::TlbInvalidStore(address),
+                _ => Exception::TlbInvalidLoad(address),
+            };
+            self.tlb_exception(segment_address, exception);
+            return None;
+        }
+        if access == Access::Store && entry_lo & ENTRY_LO_D == 0 {
+            self.tlb_exception(segment_address, Exception::TlbModified(address));
+            return None;
+        }
+        let offset_mask = odd_page_bit - 1;
+        let frame = (entry_lo >> ENTRY_LO_PFN_SHIFT) << 12;
+        Some((frame & !offset_mask | segment_address & offset_mask) as u64)
+    }
+
+    /**
+     * Record the faulting page in Context and EntryHi so the handler can refill it with tlbwr
+     */
+    fn tlb_exception(&mut self, address: u32, exception: Exception) {
+        let cop0 = &mut self.control_registers;
+        let bad_vpn2 = (address & ENTRY_HI_VPN2) >> CONTEXT_BAD_VPN2_SHIFT;
+        cop0.context = cop0.context & CONTEXT_WRITABLE | bad_vpn2;
+        cop0.entry_hi = cop0.entry_hi & ENTRY_HI_ASID | address & ENTRY_HI_VPN2;
+        self.trigger_exception(exception);
+    }
+
+    /**
+     * Read the instruction word at a virtual address.
+     * Raises the exception and returns None when it cannot be fetched.
+     */
+    pub(super) fn fetch_word(&mut self, memory: &Memory, address: u64) -> Option<u32> {
+        let physical = self.translate(address, Access::Fetch, memory)?;
+        match memory.read_word(physical) {
+            Ok(word) => Some(word),
+            Err(error) => {
+                self.trigger_exception(Exception::InstructionBusError(error.address()));
+                None
+            }
+        }
+    }
+
+    /**
+     * Read the instruction halfword at a virtual address, for microMIPS
+     */
+    pub(super) fn fetch_halfword(&mut self, memory: &Memory, address: u64) -> Option<u16> {
+        let physical = self.translate(address, Access::Fetch, memory)?;
+        match memory.read_halfword(physical) {
+            Ok(halfword) => Some(halfword),
+            Err(error) => {
+                self.trigger_exception(Exception::InstructionBusError(error.address()));
+                None
+            }
+        }
+    }
+
+    /**
+     * TLB instructions, which only exist when there is a TLB
+     * opcode: 0b010000
+     * rs: 0b10000 to 0b11111
+     */
+    pub(super) fn tlb(&mut self, instruction: &Instruction) {
+        match instruction.funct {
+            0b000001 => self.tlbr(),
+            0b000010 => self.tlbwi(),
+            0b000110 => self.tlbwr(),
+            0b001000 => self.tlbp(),
+            _ => self.reserved_instruction(instruction),
+        }
+    }
+
+    /**
+     * Read indexed TLB entry into PageMask, EntryHi, EntryLo0 and EntryLo1
+     * funct: 0b000001
+     */
+    fn tlbr(&mut self) {
+        let cop0 = &mut self.control_registers;
+        let entry = cop0.tlb[cop0.index as usize % TLB_ENTRIES];
+        cop0.page_mask = entry.page_mask;
+        cop0.entry_hi = entry.entry_hi;
+        cop0.entry_lo0 = entry.entry_lo0;
+        cop0.entry_lo1 = entry.entry_lo1;
+    }
+
+    /**
+     * Write indexed TLB entry
+     * funct: 0b000010
+     */
+    fn tlbwi(&mut self) {
+        let index = self.control_registers.index as usize % TLB_ENTRIES;
+        self.write_tlb_entry(index);
+    }
+
+    /**
+     * Write random TLB entry, one of those above Wired
+     * funct: 0b000110
+     */
+    fn tlbwr(&mut self) {
+        let index = self.control_registers.random as usize;
+        self.write_tlb_entry(index);
+    }
+
+    /**
+     * Probe the TLB for an entry matching EntryHi, setting Index to it or P when there is none
+     * funct: 0b001000
+     */
+    fn tlbp(&mut self) {
+        let cop0 = &mut self.control_registers;
+        let (address, asid) = (cop0.entry_hi, cop0.entry_hi & ENTRY_HI_ASID);
+        cop0.index = match cop0
+            .tlb
+            .iter()
+            .position(|entry| entry.matches(address, asid))
+        {
+            Some(index) => index as u32,
+            None => cop0.index | INDEX_P,
+        };
+    }
+
+    /**
+     * G is kept only when it is set in both EntryLo registers
+     */
+    fn write_tlb_entry(&mut self, index: usize) {
+        let cop0 = &mut self.control_registers;
+        let global = cop0.entry_lo0 & cop0.entry_lo1 & ENTRY_LO_G;
+        cop0.tlb[index] = TlbEntry {
+            page_mask: cop0.page_mask,
+            entry_hi: cop0.entry_hi & !cop0.page_mask,
+            entry_lo0: cop0.entry_lo0 & !ENTRY_LO_G | global,
+            entry_lo1: cop0.entry_lo1 & !ENTRY_LO_G | global,
+        };
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::field_reassign_with_default)]
+mod tests {
+    use super::*;
+    use crate::cpu::cop0::STATUS_EXL;
+    use crate::cpu::StepResult;
+    use crate::memory::Permissions;
+
+    /**
+     * Maps the page pair at 0x00400000 to physical 0x2000 and 0x3000, both valid and dirty
+     */
+    fn map_pages(cpu: &mut CPU, index: usize, asid: u32) {
+        cpu.control_registers.tlb[index] = TlbEntry {
+            page_mask: 0,
+            entry_hi: 0x00400000 | asid,
+            entry_lo0: 0x2 << 6 | ENTRY_LO_D | ENTRY_LO_V,
+            entry_lo1: 0x3 << 6 | ENTRY_LO_D | ENTRY_LO_V,
+        };
+    }
+
+    // opcode: 0b100011
+    #[test]
+    fn test_run_lw_kseg0_kseg1() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        cpu.mmu = true;
+        cpu.registers.pc = 0x80000000;
+        memory.map(0x00000000, 0x4000, Permissions::ALL);
+        memory.write_word(0x00001000, 0x12345678).unwrap();
+        cpu.registers.a0 = 0x80001000;
+        cpu.registers.a1 = 0xa0001000;
+        memory.write_word(0x00000000, 0x8c880000).unwrap(); // lw $t0, 0($a0)
+        memory.write_word(0x00000004, 0x8ca90000).unwrap(); // lw $t1, 0($a1)
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t0, 0x12345678);
+        assert_eq!(cpu.registers.t1, 0x12345678);
+    }
+
+    // opcode: 0b100011
+    #[test]
+    fn test_run_lw_mapped() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        cpu.mmu = true;
+        cpu.registers.pc = 0x80000000;
+        memory.map(0x00000000, 0x4000, Permissions::ALL);
+        map_pages(&mut cpu, 3, 0);
+        memory.write_word(0x00002010, 0x11111111).unwrap();
+        memory.write_word(0x00003010, 0x22222222).unwrap();
+        cpu.registers.a0 = 0x00400010;
+        cpu.registers.a1 = 0x00401010;
+        memory.write_word(0x00000000, 0x8c880000).unwrap(); // lw $t0, 0($a0)
+        memory.write_word(0x00000004, 0x8ca90000).unwrap(); // lw $t1, 0($a1)
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t0, 0x11111111);
+        assert_eq!(cpu.registers.t1, 0x22222222);
+    }
+
+    // opcode: 0b100011
+    #[test]
+    fn test_run_lw_large_page() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        cpu.mmu = true;
+        cpu.registers.pc = 0x80000000;
+        memory.map(0x00000000, 0x4000, Permissions::ALL);
+        // 16 KiB pages, the odd one at 0x00404000 is physical 0x8000
+        cpu.control_registers.tlb[0] = TlbEntry {
+            page_mask: 0x00006000,
+            entry_hi: 0x00400000,
+            entry_lo0: 0x4 << 6 | ENTRY_LO_V,
+            entry_lo1: 0x8 << 6 | ENTRY_LO_V,
+        };
+        memory.map(0x00008000, 0x4000, Permissions::READ_WRITE);
+        memory.write_word(0x00009ffc, 0x12345678).unwrap();
+        cpu.registers.a0 = 0x00405ffc;
+        memory.write_word(0x00000000, 0x8c880000).unwrap(); // lw $t0, 0($a0)
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t0, 0x12345678);
+    }
+
+    // opcode: 0b100011
+    #[test]
+    fn test_run_lw_tlb_refill() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        cpu.mmu = true;
+        cpu.registers.pc = 0x80000000;
+        memory.map(0x00000000, 0x4000, Permissions::ALL);
+        cpu.exception_handler = true;
+        cpu.control_registers.entry_hi = 0x05;
+        cpu.control_registers.context = 0x80000000;
+        cpu.registers.a0 = 0x00402468;
+        memory.write_word(0x00000100, 0x8c880000).unwrap(); // lw $t0, 0($a0)
+        cpu.registers.pc = 0x80000100;
+        let result = cpu.step(&mut memory);
+        assert_eq!(
+            result,
+            StepResult::Exception {
+                exception: Exception::TlbRefillLoad(0x00402468),
+                pc: 0x80000100
+            }
+        );
+        let cop0 = &cpu.control_registers;
+        assert_eq!(cop0.bad_vaddr, 0x00402468);
+        assert_eq!(cop0.entry_hi, 0x00402005);
+        assert_eq!(cop0.context, 0x80002010);
+        assert_eq!(cop0.epc, 0x80000100);
+        assert_eq!(cop0.cause >> 2 & 0x1f, 2);
+        assert_eq!(cpu.registers.pc, 0x80000000);
+    }
+
+    // opcode: 0b101011
+    #[test]
+    fn test_run_sw_tlb_refill_nested() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        cpu.mmu = true;
+        cpu.registers.pc = 0x80000000;
+        memory.map(0x00000000, 0x4000, Permissions::ALL);
+        cpu.exception_handler = true;
+        cpu.control_registers.status = STATUS_EXL;
+        cpu.registers.a0 = 0x00400000;
+        memory.write_word(0x00000000, 0xac880000).unwrap(); // sw $t0, 0($a0)
+        cpu.step(&mut memory);
+        assert_eq!(cpu.control_registers.cause >> 2 & 0x1f, 3);
+        // A miss inside a handler goes to the general vector
+        assert_eq!(cpu.registers.pc, 0x80000180);
+    }
+
+    // opcode: 0b100011
+    #[test]
+    fn test_run_lw_tlb_invalid() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        cpu.mmu = true;
+        cpu.registers.pc = 0x80000000;
+        memory.map(0x00000000, 0x4000, Permissions::ALL);
+        map_pages(&mut cpu, 0, 0);
+        cpu.control_registers.tlb[0].entry_lo1 &= !ENTRY_LO_V;
+        cpu.registers.a0 = 0x00401000;
+        memory.write_word(0x00000000, 0x8c880000).unwrap(); // lw $t0, 0($a0)
+        let result = cpu.step(&mut memory);
+        assert!(matches!(
+            result,
+            StepResult::Exception {
+                exception: Exception::TlbInvalidLoad(0x00401000),
+                ..
+            }
+        ));
+    }
+
+    // opcode: 0b101011
+    #[test]
+    fn test_run_sw_tlb_modified() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        cpu.mmu = true;
+        cpu.registers.pc = 0x80000000;
+        memory.map(0x00000000, 0x4000, Permissions::ALL);
+        map_pages(&mut cpu, 0, 0);
+        cpu.control_registers.tlb[0].entry_lo0 &= !ENTRY_LO_D;
+        cpu.registers.a0 = 0x00400000;
+        cpu.registers.t0 = 0xdeadbeef;
+        memory.write_word(0x00002000, 0x12345678).unwrap();
+        memory.write_word(0x00000000, 0xac880000).unwrap(); // sw $t0, 0($a0)
+        let result = cpu.step(&mut memory);
+        assert!(matches!(
+            result,
+            StepResult::Exception {
+                exception: Exception::TlbModified(0x00400000),
+                ..
+            }
+        ));
+        assert_eq!(memory.read_word(0x00002000).unwrap(), 0x12345678);
+        assert_eq!(cpu.control_registers.cause >> 2 & 0x1f, 1);
+    }
+
+    // opcode: 0b100011
+    #[test]
+    fn test_run_lw_asid() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        cpu.mmu = true;
+        cpu.registers.pc = 0x80000000;
+        memory.map(0x00000000, 0x4000, Permissions::ALL);
+        map_pages(&mut cpu, 0, 7);
+        cpu.control_registers.entry_hi = 8;
+        cpu.registers.a0 = 0x00400000;
+        memory.write_word(0x00002000, 0x12345678).unwrap();
+        memory.write_word(0x00000000, 0x8c880000).unwrap(); // lw $t0, 0($a0)
+        memory.write_word(0x00000004, 0x8c880000).unwrap(); // lw $t0, 0($a0)
+        let result = cpu.step(&mut memory);
+        assert!(matches!(
+            result,
+            StepResult::Exception {
+                exception: Exception::TlbRefillLoad(_),
+                ..
+            }
+        ));
+        // Global entries match every address space
+        cpu.control_registers.tlb[0].entry_lo0 |= ENTRY_LO_G;
+        cpu.control_registers.tlb[0].entry_lo1 |= ENTRY_LO_G;
+        cpu.halted = false;
+        cpu.control_registers.status = 0;
+        cpu.registers.pc = 0x80000004;
+        assert_eq!(cpu.step(&mut memory), StepResult::Retired);
+        assert_eq!(cpu.registers.t0, 0x12345678);
+    }
+
+    // opcode: 0b010000
+    // funct: 0b000010
+    #[test]
+    fn test_run_tlbwi() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        cpu.mmu = true;
+        cpu.registers.pc = 0x80000000;
+        memory.map(0x00000000, 0x4000, Permissions::ALL);
+        cpu.registers.t1 = 0x00400005;
+        cpu.registers.t2 = 0x2 << 6 | ENTRY_LO_V | ENTRY_LO_G;
+        cpu.registers.t3 = 0x3 << 6 | ENTRY_LO_V;
+        cpu.registers.t4 = 5;
+        memory.write_word(0x00000000, 0x40895000).unwrap(); // mtc0 $t1, $10
+        memory.write_word(0x00000004, 0x408a1000).unwrap(); // mtc0 $t2, $2
+        memory.write_word(0x00000008, 0x408b1800).unwrap(); // mtc0 $t3, $3
+        memory.write_word(0x0000000c, 0x408c0000).unwrap(); // mtc0 $t4, $0
+        memory.write_word(0x00000010, 0x42000002).unwrap(); // tlbwi
+        for _ in 0..5 {
+            cpu.step(&mut memory);
+        }
+        assert_eq!(
+            cpu.control_registers.tlb[5],
+            TlbEntry {
+                page_mask: 0,
+                entry_hi: 0x00400005,
+                // G is only kept when both pages are global
+                entry_lo0: 0x2 << 6 | ENTRY_LO_V,
+                entry_lo1: 0x3 << 6 | ENTRY_LO_V,
+            }
+        );
+    }
+
+    // opcode: 0b010000
+    // funct: 0b000110
+    #[test]
+    fn test_run_tlbwr() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        cpu.mmu = true;
+        cpu.registers.pc = 0x80000000;
+        memory.map(0x00000000, 0x4000, Permissions::ALL);
+        cpu.registers.t1 = 0x00400000;
+        cpu.registers.t4 = 12;
+        memory.write_word(0x00000000, 0x408c3000).unwrap(); // mtc0 $t4, $6
+        memory.write_word(0x00000004, 0x40895000).unwrap(); // mtc0 $t1, $10
+        memory.write_word(0x00000008, 0x42000006).unwrap(); // tlbwr
+        memory.write_word(0x0000000c, 0x40080800).unwrap(); // mfc0 $t0, $1
+        for _ in 0..4 {
+            cpu.step(&mut memory);
+        }
+        // Random counts down from 15 once per instruction and wraps at Wired
+        let written = cpu
+            .control_registers
+            .tlb
+            .iter()
+            .position(|entry| entry.entry_hi == 0x00400000);
+        assert_eq!(written, Some(13));
+        assert_eq!(cpu.registers.t0, 12);
+        cpu.registers.pc = 0x8000000c;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t0, 15);
+    }
+
+    // opcode: 0b010000
+    // funct: 0b001000
+    #[test]
+    fn test_run_tlbp() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        cpu.mmu = true;
+        cpu.registers.pc = 0x80000000;
+        memory.map(0x00000000, 0x4000, Permissions::ALL);
+        map_pages(&mut cpu, 9, 3);
+        cpu.control_registers.entry_hi = 0x00401003;
+        memory.write_word(0x00000000, 0x42000008).unwrap(); // tlbp
+        memory.write_word(0x00000004, 0x42000008).unwrap(); // tlbp
+        cpu.step(&mut memory);
+        assert_eq!(cpu.control_registers.index, 9);
+        cpu.control_registers.entry_hi = 0x00403003;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.control_registers.index & INDEX_P, INDEX_P);
+    }
+
+    // opcode: 0b010000
+    // funct: 0b000001
+    #[test]
+    fn test_run_tlbr() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        cpu.mmu = true;
+        cpu.registers.pc = 0x80000000;
+        memory.map(0x00000000, 0x4000, Permissions::ALL);
+        map_pages(&mut cpu, 2, 3);
+        cpu.control_registers.index = 2;
+        memory.write_word(0x00000000, 0x42000001).unwrap(); // tlbr
+        cpu.step(&mut memory);
+        let cop0 = &cpu.control_registers;
+        assert_eq!(cop0.entry_hi, 0x00400003);
+        assert_eq!(cop0.entry_lo0, 0x2 << 6 | ENTRY_LO_D | ENTRY_LO_V);
+        assert_eq!(cop0.entry_lo1, 0x3 << 6 | ENTRY_LO_D | ENTRY_LO_V);
+        assert_eq!(cop0.page_mask, 0);
+    }
+
+    // opcode: 0b010000
+    // funct: 0b000010
+    #[test]
+    fn test_run_tlbwi_without_mmu() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.write_word(0x00400000, 0x42000002).unwrap(); // tlbwi
+        let result = cpu.step(&mut memory);
+        assert!(matches!(
+            result,
+            StepResult::Exception {
+                exception: Exception::ReservedInstruction { .. },
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_fetch_mapped() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        cpu.mmu = true;
+        cpu.registers.pc = 0x80000000;
+        memory.map(0x00000000, 0x4000, Permissions::ALL);
+        map_pages(&mut cpu, 0, 0);
+        memory.write_word(0x00002000, 0x24080007).unwrap(); // addiu $t0, $zero, 7
+        cpu.registers.pc = 0x00400000;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t0, 7);
+        // Unmapped, the fetch misses the TLB
+        cpu.registers.pc = 0x00500000;
+        assert_eq!(
+            cpu.step(&mut memory),
+            StepResult::Exception {
+                exception: Exception::TlbRefillLoad(0x00500000),
+                pc: 0x00500000
+            }
+        );
+    }
+
+    const TEXT: Permissions = Permissions {
+        read: true,
+        write: false,
+        execute: true,
+    };
+    const DATA: Permissions = Permissions {
+        read: true,
+        write: true,
+        execute: false,
+    };
+
+    // opcode: 0b101011
+    #[test]
+    fn test_run_sw_write_protected() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.map(0x00400000, 0x1000, TEXT);
+        cpu.registers.pc = 0x00400000;
+        cpu.registers.a0 = 0x00400008;
+        cpu.registers.t0 = 0x12345678;
+        memory.write_word(0x00400000, 0xac880000).unwrap(); // sw $t0, 0($a0)
+        let result = cpu.step(&mut memory);
+        assert_eq!(
+            result,
+            StepResult::Exception {
+                exception: Exception::WriteProtected(0x00400008),
+                pc: 0x00400000
+            }
+        );
+        assert_eq!(cpu.control_registers.bad_vaddr, 0x00400008);
+        assert_eq!(cpu.control_registers.cause >> 2 & 0x1f, 1);
+        assert_eq!(memory.read_word(0x00400008).unwrap(), 0);
+    }
+
+    // opcode: 0b101011
+    #[test]
+    fn test_run_sw_self_modifying_code() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        cpu.self_modifying_code = true;
+        memory.map(0x00400000, 0x1000, TEXT);
+        cpu.registers.pc = 0x00400000;
+        cpu.registers.a0 = 0x00400008;
+        cpu.registers.t0 = 0x12345678;
+        memory.write_word(0x00400000, 0xac880000).unwrap(); // sw $t0, 0($a0)
+        cpu.step(&mut memory);
+        assert_eq!(memory.read_word(0x00400008).unwrap(), 0x12345678);
+    }
+
+    #[test]
+    fn test_run_execute_inhibit() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.map(0x10010000, 0x1000, DATA);
+        cpu.registers.pc = 0x10010000;
+        memory.write_word(0x10010000, 0x24080001).unwrap(); // addiu $t0, $zero, 1
+        let result = cpu.step(&mut memory);
+        assert_eq!(
+            result,
+            StepResult::Exception {
+                exception: Exception::ExecuteInhibit(0x10010000),
+                pc: 0x10010000
+            }
+        );
+        assert_eq!(cpu.control_registers.bad_vaddr, 0x10010000);
+        assert_eq!(cpu.control_registers.cause >> 2 & 0x1f, 20);
+        assert_eq!(cpu.registers.t0, 0);
+    }
+
+    // opcode: 0b100011
+    #[test]
+    fn test_run_lw_read_inhibit() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.map(
+            0x10010000,
+            0x1000,
+            Permissions {
+                read: false,
+                write: true,
+                execute: false,
+            },
+        );
+        memory.map(0x00000000, 0x1000, Permissions::ALL);
+        cpu.registers.pc = 0x00000000;
+        cpu.registers.a0 = 0x10010000;
+        memory.write_word(0x00000000, 0x8c880000).unwrap(); // lw $t0, 0($a0)
+        cpu.step(&mut memory);
+        assert_eq!(cpu.control_registers.cause >> 2 & 0x1f, 19);
+        assert_eq!(cpu.control_registers.bad_vaddr, 0x10010000);
+    }
+
+    #[test]
+    fn test_shared_page_permissions() {
+        let mut memory = Memory::default();
+        memory.map(0x00400f00, 0x100, TEXT);
+        memory.map(0x00400f80, 0x100, DATA);
+        assert_eq!(memory.permissions(0x00400000), Permissions::ALL);
+        assert_eq!(memory.permissions(0x00401000), DATA);
+    }
+}
@@ -0,0 +1,867 @@
+use super::mmu::Access;
+use super::{Instruction, InstructionKind, CPU};
+use crate::memory::{Memory, MemoryError};
+
+impl CPU {
+    /**
+     * Compact branches have no delay slot and take effect immediately
+     */
+    pub(super) fn compact_branch(&mut self, instruction: &Instruction, taken: bool, target: u64) {
+        if self.in_delay_slot {
+            return self.reserved_instruction(instruction);
+        }
+        if taken {
+            self.set_pc(target);
+            self.jump = true;
+        }
+    }
+
+    /**
+     * Compact branch and link: the return address is written whether or not the branch is taken
+     */
+    fn compact_branch_link(&mut self, instruction: &Instruction, taken: bool, target: u64) {
+        if self.in_delay_slot {
+            return self.reserved_instruction(instruction);
+        }
+        let link = self.wrap_address(self.registers.pc.wrapping_add(4));
+        self.write_address(31, link);
+        self.compact_branch(instruction, taken, target);
+    }
+
+    /**
+     * blezalc, bgezalc and bgeuc
+     * opcode: 0b000110
+     */
+    pub(super) fn pop06(&mut self, instruction: &Instruction) {
+        let rs = self.read_gpr(instruction.rs);
+        let rt = self.read_gpr(instruction.rt);
+        let target = self.branch_offset_target(instruction);
+        if instruction.rs == 0 {
+            self.compact_branch_link(instruction, rt <= 0, target);
+        } else if instruction.rs == instruction.rt {
+            self.compact_branch_link(instruction, rt >= 0, target);
+        } else {
+            self.compact_branch(instruction, rs as u64 >= rt as u64, target);
+        }
+    }
+
+    /**
+     * bgtzalc, bltzalc and bltuc
+     * opcode: 0b000111
+     */
+    pub(super) fn pop07(&mut self, instruction: &Instruction) {
+        let rs = self.read_gpr(instruction.rs);
+        let rt = self.read_gpr(instruction.rt);
+        let target = self.branch_offset_target(instruction);
+        if instruction.rs == 0 {
+            self.compact_branch_link(instruction, rt > 0, target);
+        } else if instruction.rs == instruction.rt {
+            self.compact_branch_link(instruction, rt < 0, target);
+        } else {
+            self.compact_branch(instruction, (rs as u64) < rt as u64, target);
+        }
+    }
+
+    /**
+     * bovc, beqzalc and beqc
+     * opcode: 0b001000
+     */
+    pub(super) fn pop10(&mut self, instruction: &Instruction) {
+        let rs = self.read_gpr(instruction.rs);
+        let rt = self.read_gpr(instruction.rt);
+        let target = self.branch_offset_target(instruction);
+        if instruction.rs >= instruction.rt {
+            let overflow = (rs as i32).checked_add(rt as i32).is_none();
+            self.compact_branch(instruction, overflow, target);
+        } else if instruction.rs == 0 {
+            self.compact_branch_link(instruction, rt == 0, target);
+        } else {
+            self.compact_branch(instruction, rs == rt, target);
+        }
+    }
+
+    /**
+     * blezc, bgezc and bgec
+     * opcode: 0b010110
+     */
+    pub(super) fn pop26(&mut self, instruction: &Instruction) {
+        if instruction.rt == 0 {
+            return self.reserved_instruction(instruction);
+        }
+        let rs = self.read_gpr(instruction.rs);
+        let rt = self.read_gpr(instruction.rt);
+        let target = self.branch_offset_target(instruction);
+        let taken = if instruction.rs == 0 {
+            rt <= 0
+        } else if instruction.rs == instruction.rt {
+            rt >= 0
+        } else {
+            rs >= rt
+        };
+        self.compact_branch(instruction, taken, target);
+    }
+
+    /**
+     * bgtzc, bltzc and bltc
+     * opcode: 0b010111
+     */
+    pub(super) fn pop27(&mut self, instruction: &Instruction) {
+        if instruction.rt == 0 {
+            return self.reserved_instruction(instruction);
+        }
+        let rs = self.read_gpr(instruction.rs);
+        let rt = self.read_gpr(instruction.rt);
+        let target = self.branch_offset_target(instruction);
+        let taken = if instruction.rs == 0 {
+            rt > 0
+        } else if instruction.rs == instruction.rt {
+            rt < 0
+        } else {
+            rs < rt
+        };
+        self.compact_branch(instruction, taken, target);
+    }
+
+    /**
+     * bnvc, bnezalc and bnec
+     * opcode: 0b011000
+     */
+    pub(super) fn pop30(&mut self, instruction: &Instruction) {
+        let rs = self.read_gpr(instruction.rs);
+        let rt = self.read_gpr(instruction.rt);
+        let target = self.branch_offset_target(instruction);
+        if instruction.rs >= instruction.rt {
+            let overflow = (rs as i32).checked_add(rt as i32).is_none();
+            self.compact_branch(instruction, !overflow, target);
+        } else if instruction.rs == 0 {
+            self.compact_branch_link(instruction, rt != 0, target);
+        } else {
+            self.compact_branch(instruction, rs != rt, target);
+        }
+    }
+
+    /**
+     * Target of bc and balc, a 26 bit offset relative to the next instruction
+     */
+    fn long_offset_target(&self, instruction: &Instruction) -> u64 {
+        let offset = ((instruction.address << 6) as i32) >> 4;
+        let target = self
+            .registers
+            .pc
+            .wrapping_add(4)
+            .wrapping_add(offset as u64);
+        self.wrap_address(target)
+    }
+
+    /**
+     * Branch compact
+     * opcode: 0b110010
+     */
+    pub(super) fn bc(&mut self, instruction: &Instruction) {
+        let target = self.long_offset_target(instruction);
+        self.compact_branch(instruction, true, target);
+    }
+
+    /**
+     * Branch and link compact
+     * opcode: 0b111010
+     */
+    pub(super) fn balc(&mut self, instruction: &Instruction) {
+        let target = self.long_offset_target(instruction);
+        self.compact_branch_link(instruction, true, target);
+    }
+
+    /**
+     * Target of beqzc and bnezc, a 21 bit offset relative to the next instruction
+     */
+    fn zero_offset_target(&self, instruction: &Instruction) -> u64 {
+        let offset = ((instruction.address << 11) as i32) >> 9;
+        let target = self
+            .registers
+            .pc
+            .wrapping_add(4)
+            .wrapping_add(offset as u64);
+        self.wrap_address(target)
+    }
+
+    /**
+     * Target of jic and jialc: rt plus an unshifted offset
+     */
+    fn indexed_target(&mut self, instruction: &Instruction) -> u64 {
+        let rt = self.read_address(instruction.rt);
+        self.wrap_address(rt.wrapping_add(instruction.imm as i16 as u64))
+    }
+
+    /**
+     * beqzc, or jic when rs is zero
+     * opcode: 0b110110
+     */
+    pub(super) fn pop66(&mut self, instruction: &Instruction) {
+        if instruction.rs == 0 {
+            let target = self.indexed_target(instruction);
+            self.compact_branch(instruction, true, target);
+        } else {
+            let rs = self.read_gpr(instruction.rs);
+            let target = self.zero_offset_target(instruction);
+            self.compact_branch(instruction, rs == 0, target);
+        }
+    }
+
+    /**
+     * bnezc, or jialc when rs is zero
+     * opcode: 0b111110
+     */
+    pub(super) fn pop76(&mut self, instruction: &Instruction) {
+        if instruction.rs == 0 {
+            let target = self.indexed_target(instruction);
+            self.compact_branch_link(instruction, true, target);
+        } else {
+            let rs = self.read_gpr(instruction.rs);
+            let target = self.zero_offset_target(instruction);
+            self.compact_branch(instruction, rs != 0, target);
+        }
+    }
+
+    /**
+     * Multiply, low word to rd (mul) or high word (muh)
+     * opcode: 0b000000
+     * funct: 0b011000
+     */
+    pub(super) fn mul_muh(&mut self, instruction: &Instruction) {
+        let rs = self.registers.read_register(instruction.rs) as i32 as i64;
+        let rt = self.registers.read_register(instruction.rt) as i32 as i64;
+        let product = rs * rt;
+        match instruction.shamt {
+            0b00010 => self
+                .registers
+                .write_register(instruction.rd, product as u32),
+            0b00011 => self
+                .registers
+                .write_register(instruction.rd, (product >> 32) as u32),
+            _ => self.reserved_instruction(instruction),
+        }
+    }
+
+    /**
+     * Multiply unsigned, low word to rd (mulu) or high word (muhu)
+     * opcode: 0b000000
+     * funct: 0b011001
+     */
+    pub(super) fn mulu_muhu(&mut self, instruction: &Instruction) {
+        let rs = self.registers.read_register(instruction.rs) as u64;
+        let rt = self.registers.read_register(instruction.rt) as u64;
+        let product = rs * rt;
+        match instruction.shamt {
+            0b00010 => self
+                .registers
+                .write_register(instruction.rd, product as u32),
+            0b00011 => self
+                .registers
+                .write_register(instruction.rd, (product >> 32) as u32),
+            _ => self.reserved_instruction(instruction),
+        }
+    }
+
+    /**
+     * Divide, quotient to rd (div) or remainder (mod)
+     * opcode: 0b000000
+     * funct: 0b011010
+     */
+    pub(super) fn div_mod(&mut self, instruction: &Instruction) {
+        let rs = self.registers.read_register(instruction.rs) as i32;
+        let rt = self.registers.read_register(instruction.rt) as i32;
+        // Division by zero is unpredictable, give zero like div does
+        let (quotient, remainder) = if rt == 0 {
+            (0, 0)
+        } else {
+            (rs.wrapping_div(rt), rs.wrapping_rem(rt))
+        };
+        match instruction.shamt {
+            0b00010 => self
+                .registers
+                .write_register(instruction.rd, quotient as u32),
+            0b00011 => self
+                .registers
+                .write_register(instruction.rd, remainder as u32),
+            _ => self.reserved_instruction(instruction),
+        }
+    }
+
+    /**
+     * Divide unsigned, quotient to rd (divu) or remainder (modu)
+     * opcode: 0b000000
+     * funct: 0b011011
+     */
+    pub(super) fn divu_modu(&mut self, instruction: &Instruction) {
+        let rs = self.registers.read_register(instruction.rs);
+        let rt = self.registers.read_register(instruction.rt);
+        let quotient = rs.checked_div(rt).unwrap_or(0);
+        let remainder = rs.checked_rem(rt).unwrap_or(0);
+        match instruction.shamt {
+            0b00010 => self.registers.write_register(instruction.rd, quotient),
+            0b00011 => self.registers.write_register(instruction.rd, remainder),
+            _ => self.reserved_instruction(instruction),
+        }
+    }
+
+    /**
+     * Load scaled address: rs shifted left by sa + 1, plus rt
+     * opcode: 0b000000
+     * funct: 0b000101
+     */
+    pub(super) fn lsa(&mut self, instruction: &Instruction) {
+        let rs = self.registers.read_register(instruction.rs);
+        let rt = self.registers.read_register(instruction.rt);
+        let shift = (instruction.shamt & 0b11) + 1;
+        self.registers
+            .write_register(instruction.rd, (rs << shift).wrapping_add(rt));
+    }
+
+    /**
+     * Select rs if rt is zero, otherwise zero
+     * opcode: 0b000000
+     * funct: 0b110101
+     */
+    pub(super) fn seleqz(&mut self, instruction: &Instruction) {
+        let rs = self.registers.read_register64(instruction.rs);
+        let rt = self.read_gpr(instruction.rt);
+        let value = if rt == 0 { rs } else { 0 };
+        self.registers.write_register64(instruction.rd, value);
+    }
+
+    /**
+     * Select rs if rt is not zero, otherwise zero
+     * opcode: 0b000000
+     * funct: 0b110111
+     */
+    pub(super) fn selnez(&mut self, instruction: &Instruction) {
+        let rs = self.registers.read_register64(instruction.rs);
+        let rt = self.read_gpr(instruction.rt);
+        let value = if rt != 0 { rs } else { 0 };
+        self.registers.write_register64(instruction.rd, value);
+    }
+
+    /**
+     * Add upper immediate, lui is aui with rs zero
+     * opcode: 0b001111
+     */
+    pub(super) fn aui(&mut self, instruction: &Instruction) {
+        let rs = self.registers.read_register(instruction.rs);
+        let upper = (instruction.imm as u32) << 16;
+        self.registers
+            .write_register(instruction.rt, rs.wrapping_add(upper));
+    }
+
+    /**
+     * addiupc, lwpc, auipc and aluipc, selected by rt and relative to this instruction
+     * opcode: 0b111011
+     */
+    pub(super) fn pcrel(
+        &mut self,
+        instruction: &Instruction,
+        memory: &Memory,
+    ) -> Result<(), MemoryError> {
+        let pc = self.registers.pc;
+        // 19 bit word offset
+        let offset = (instruction.word << 13) as i32 >> 11;
+        let upper = (instruction.imm as i16 as i64) << 16;
+        let address = match instruction.rt {
+            // addiupc
+            0b00000..=0b00111 => pc.wrapping_add(offset as u64),
+            // lwpc
+            0b01000..=0b01111 => {
+                let address = self.wrap_address(pc.wrapping_add(offset as u64));
+                let Some(address) = self.translate(address, Access::Load, memory) else {
+                    return Ok(());
+                };
+                let value = memory.read_word(address)?;
+                self.registers.write_register(instruction.rs, value);
+                return Ok(());
+            }
+            // auipc
+            0b11110 => pc.wrapping_add(upper as u64),
+            // aluipc
+            0b11111 => pc.wrapping_add(upper as u64) & !0xffff,
+            _ => {
+                self.reserved_instruction(instruction);
+                return Ok(());
+            }
+        };
+        self.write_address(instruction.rs, self.wrap_address(address));
+        Ok(())
+    }
+
+    /**
+     * Release 6 ll and sc, with a 9 bit offset
+     * opcode: 0b011111
+     * funct: 0b110110 or 0b100110
+     */
+    pub(super) fn ll_sc(
+        &mut self,
+        instruction: &Instruction,
+        memory: &mut Memory,
+    ) -> Result<(), MemoryError> {
+        let offset = (instruction.word << 16) as i32 >> 23;
+        let opcode = if instruction.funct == 0b110110 {
+            0b110000
+        } else {
+            0b111000
+        };
+        let instruction = Instruction {
+            opcode,
+            imm: offset as u16,
+            kind: InstructionKind::IType,
+            ..*instruction
+        };
+        if opcode == 0b110000 {
+            self.ll(&instruction, memory)
+        } else {
+            self.sc(&instruction, memory)
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::field_reassign_with_default)]
+mod tests {
+    use super::*;
+    use crate::cpu::{Exception, IsaLevel};
+    use crate::memory::{Memory, Permissions};
+
+    // opcode: 0b110010
+    #[test]
+    fn test_run_bc() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.isa = IsaLevel::Mips32r6;
+        memory.write_word(0x00400000, 0xc8000003).unwrap(); // bc 0x00400010
+        memory.write_word(0x00400010, 0xcbffffff).unwrap(); // bc 0x00400010
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400010);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400010);
+    }
+
+    // opcode: 0b111010
+    #[test]
+    fn test_run_balc() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.isa = IsaLevel::Mips32r6;
+        memory.write_word(0x00400000, 0xe8000003).unwrap(); // balc 0x00400010
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400010);
+        assert_eq!(cpu.registers.ra, 0x00400004);
+    }
+
+    // opcode: 0b110110
+    #[test]
+    fn test_run_beqzc_jic() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.isa = IsaLevel::Mips32r6;
+        cpu.registers.t1 = 0x00400020;
+        memory.write_word(0x00400000, 0xd9200002).unwrap(); // beqzc $t1, 0x0040000c
+        memory.write_word(0x00400004, 0xd8090008).unwrap(); // jic $t1, 8
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400004);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400028);
+    }
+
+    // opcode: 0b111110
+    #[test]
+    fn test_run_bnezc_jialc() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.isa = IsaLevel::Mips32r6;
+        cpu.registers.t1 = 0x00400020;
+        memory.write_word(0x00400000, 0xf9200002).unwrap(); // bnezc $t1, 0x0040000c
+        memory.write_word(0x0040000c, 0xf8090000).unwrap(); // jialc $t1, 0
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x0040000c);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400020);
+        assert_eq!(cpu.registers.ra, 0x00400010);
+    }
+
+    // opcode: 0b000110
+    #[test]
+    fn test_run_pop06() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.isa = IsaLevel::Mips32r6;
+        cpu.registers.t1 = 1;
+        cpu.registers.t2 = u32::MAX;
+        memory.write_word(0x00400000, 0x192a0002).unwrap(); // bgeuc $t1, $t2, 0x0040000c
+        memory.write_word(0x00400004, 0x180a0002).unwrap(); // blezalc $t2, 0x00400010
+        memory.write_word(0x00400010, 0x194a0002).unwrap(); // bgezalc $t2, 0x0040001c
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400004);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400010);
+        assert_eq!(cpu.registers.ra, 0x00400008);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400014);
+        assert_eq!(cpu.registers.ra, 0x00400014);
+    }
+
+    // opcode: 0b000111
+    #[test]
+    fn test_run_pop07() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.isa = IsaLevel::Mips32r6;
+        cpu.registers.t1 = 1;
+        cpu.registers.t2 = u32::MAX;
+        memory.write_word(0x00400000, 0x1d2a0002).unwrap(); // bltuc $t1, $t2, 0x0040000c
+        memory.write_word(0x0040000c, 0x1c0a0002).unwrap(); // bgtzalc $t2, 0x00400018
+        memory.write_word(0x00400010, 0x1d4a0002).unwrap(); // bltzalc $t2, 0x0040001c
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x0040000c);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400010);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x0040001c);
+        assert_eq!(cpu.registers.ra, 0x00400014);
+    }
+
+    // opcode: 0b001000
+    #[test]
+    fn test_run_pop10() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.isa = IsaLevel::Mips32r6;
+        cpu.registers.t1 = i32::MAX as u32;
+        cpu.registers.t2 = 1;
+        memory.write_word(0x00400000, 0x212a0002).unwrap(); // beqc $t1, $t2, 0x0040000c
+        memory.write_word(0x00400004, 0x21490002).unwrap(); // bovc $t2, $t1, 0x00400010
+        memory.write_word(0x00400010, 0x20000002).unwrap(); // bovc $zero, $zero, 0x0040001c
+        memory.write_word(0x00400014, 0x200a0002).unwrap(); // beqzalc $t2, 0x00400020
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400004);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400010);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400014);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400018);
+        assert_eq!(cpu.registers.ra, 0x00400018);
+    }
+
+    // opcode: 0b011000
+    #[test]
+    fn test_run_pop30() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.isa = IsaLevel::Mips32r6;
+        cpu.registers.t1 = i32::MAX as u32;
+        cpu.registers.t2 = 1;
+        memory.write_word(0x00400000, 0x612a0002).unwrap(); // bnec $t1, $t2, 0x0040000c
+        memory.write_word(0x0040000c, 0x61490002).unwrap(); // bnvc $t2, $t1, 0x00400018
+        memory.write_word(0x00400010, 0x600a0002).unwrap(); // bnezalc $t2, 0x0040001c
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x0040000c);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400010);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x0040001c);
+        assert_eq!(cpu.registers.ra, 0x00400014);
+    }
+
+    // opcode: 0b010110
+    #[test]
+    fn test_run_pop26() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.isa = IsaLevel::Mips32r6;
+        cpu.registers.t1 = 1;
+        cpu.registers.t2 = u32::MAX;
+        memory.write_word(0x00400000, 0x592a0002).unwrap(); // bgec $t1, $t2, 0x0040000c
+        memory.write_word(0x0040000c, 0x580a0002).unwrap(); // blezc $t2, 0x00400018
+        memory.write_word(0x00400018, 0x594a0002).unwrap(); // bgezc $t2, 0x00400024
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x0040000c);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400018);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x0040001c);
+    }
+
+    // opcode: 0b010111
+    #[test]
+    fn test_run_pop27() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.isa = IsaLevel::Mips32r6;
+        cpu.registers.t1 = 1;
+        cpu.registers.t2 = u32::MAX;
+        memory.write_word(0x00400000, 0x5d2a0002).unwrap(); // bltc $t1, $t2, 0x0040000c
+        memory.write_word(0x00400004, 0x5c0a0002).unwrap(); // bgtzc $t2, 0x00400010
+        memory.write_word(0x00400008, 0x5d4a0002).unwrap(); // bltzc $t2, 0x00400014
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400004);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400008);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x00400014);
+    }
+
+    #[test]
+    fn test_compact_branch_in_delay_slot() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.isa = IsaLevel::Mips32r6;
+        memory.write_word(0x00400000, 0x08100004).unwrap(); // j 0x00400010
+        memory.write_word(0x00400004, 0xc8000003).unwrap(); // bc 0x00400014
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(
+            cpu.exception,
+            Exception::ReservedInstruction {
+                word: 0xc8000003,
+                address: 0x00400004
+            }
+        );
+    }
+
+    // opcode: 0b000000
+    // funct: 0b011000
+    #[test]
+    fn test_run_mul_muh() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.isa = IsaLevel::Mips32r6;
+        cpu.registers.t2 = 0x10000;
+        cpu.registers.t3 = (-0x10000i32) as u32;
+        memory.write_word(0x00400000, 0x014b4898).unwrap(); // mul $t1, $t2, $t3
+        memory.write_word(0x00400004, 0x014b60d8).unwrap(); // muh $t4, $t2, $t3
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t1, 0);
+        assert_eq!(cpu.registers.t4, u32::MAX);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b011001
+    #[test]
+    fn test_run_mulu_muhu() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.isa = IsaLevel::Mips32r6;
+        cpu.registers.t2 = 0x10000;
+        cpu.registers.t3 = (-0x10000i32) as u32;
+        memory.write_word(0x00400000, 0x014b4899).unwrap(); // mulu $t1, $t2, $t3
+        memory.write_word(0x00400004, 0x014b60d9).unwrap(); // muhu $t4, $t2, $t3
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t1, 0);
+        assert_eq!(cpu.registers.t4, 0xffff);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b011010
+    #[test]
+    fn test_run_div_mod() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.isa = IsaLevel::Mips32r6;
+        cpu.registers.t2 = (-7i32) as u32;
+        cpu.registers.t3 = 2;
+        memory.write_word(0x00400000, 0x014b489a).unwrap(); // div $t1, $t2, $t3
+        memory.write_word(0x00400004, 0x014b60da).unwrap(); // mod $t4, $t2, $t3
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t1 as i32, -3);
+        assert_eq!(cpu.registers.t4 as i32, -1);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b011011
+    #[test]
+    fn test_run_divu_modu() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.isa = IsaLevel::Mips32r6;
+        cpu.registers.t2 = 7;
+        cpu.registers.t3 = 2;
+        memory.write_word(0x00400000, 0x014b489b).unwrap(); // divu $t1, $t2, $t3
+        memory.write_word(0x00400004, 0x014b60db).unwrap(); // modu $t4, $t2, $t3
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t1, 3);
+        assert_eq!(cpu.registers.t4, 1);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b010000
+    #[test]
+    fn test_run_clz_clo() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.isa = IsaLevel::Mips32r6;
+        cpu.registers.t2 = 0x00ff0000;
+        memory.write_word(0x00400000, 0x01404850).unwrap(); // clz $t1, $t2
+        memory.write_word(0x00400004, 0x01406051).unwrap(); // clo $t4, $t2
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t1, 8);
+        assert_eq!(cpu.registers.t4, 0);
+    }
+
+    #[test]
+    fn test_removed_encodings() {
+        for word in [
+            0x51200003, // beql $t1, $zero, 0x00400010
+            0x014b480a, // movz $t1, $t2, $t3
+            0x014b0018, // mult $t2, $t3
+            0x00004810, // mfhi $t1
+            0x714b4802, // mul $t1, $t2, $t3 (SPECIAL2)
+            0x892a0000, // lwl $t2, 0($t1)
+            0xc12a0000, // ll $t2, 0($t1)
+        ] {
+            let mut cpu = CPU::default();
+            let mut memory = Memory::default();
+            cpu.isa = IsaLevel::Mips32r6;
+            memory.text_address = 0x00400000;
+            memory.map(0x00400000, 0x1000, Permissions::ALL);
+            memory.write_word(0x00400000, word).unwrap();
+            cpu.step(&mut memory);
+            assert_eq!(
+                cpu.exception,
+                Exception::ReservedInstruction {
+                    word,
+                    address: 0x00400000
+                }
+            );
+        }
+    }
+
+    // opcode: 0b000000
+    // funct: 0b000101
+    #[test]
+    fn test_run_lsa() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.isa = IsaLevel::Mips32r6;
+        cpu.registers.t2 = 3;
+        cpu.registers.t3 = 5;
+        memory.write_word(0x00400000, 0x014b4845).unwrap(); // lsa $t1, $t2, $t3, 2
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t1, 17);
+    }
+
+    // opcode: 0b000000
+    // funct: 0b110101
+    #[test]
+    fn test_run_seleqz_selnez() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.isa = IsaLevel::Mips32r6;
+        cpu.registers.t2 = 0xDEADBEEF;
+        cpu.registers.t1 = 1;
+        cpu.registers.t4 = 1;
+        memory.write_word(0x00400000, 0x014b4835).unwrap(); // seleqz $t1, $t2, $t3
+        memory.write_word(0x00400004, 0x014b6037).unwrap(); // selnez $t4, $t2, $t3
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t1, 0xDEADBEEF);
+        assert_eq!(cpu.registers.t4, 0);
+    }
+
+    // opcode: 0b001111
+    #[test]
+    fn test_run_aui() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.isa = IsaLevel::Mips32r6;
+        cpu.registers.t2 = 0x5678;
+        memory.write_word(0x00400000, 0x3d491234).unwrap(); // aui $t1, $t2, 0x1234
+        memory.write_word(0x00400004, 0x3c0b1234).unwrap(); // lui $t3, 0x1234
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t1, 0x12345678);
+        assert_eq!(cpu.registers.t3, 0x12340000);
+    }
+
+    // opcode: 0b111011
+    #[test]
+    fn test_run_pcrel() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        cpu.isa = IsaLevel::Mips32r6;
+        memory.write_word(0x00400000, 0xed200002).unwrap(); // addiupc $t1, 8
+        memory.write_word(0x00400004, 0xed480002).unwrap(); // lwpc $t2, 8
+        memory.write_word(0x00400008, 0xed7e0001).unwrap(); // auipc $t3, 1
+        memory.write_word(0x0040000c, 0xed9f0001).unwrap(); // aluipc $t4, 1
+        for _ in 0..4 {
+            cpu.step(&mut memory);
+        }
+        assert_eq!(cpu.registers.t1, 0x00400008);
+        assert_eq!(cpu.registers.t2, 0xed9f0001);
+        assert_eq!(cpu.registers.t3, 0x00410008);
+        assert_eq!(cpu.registers.t4, 0x00410000);
+    }
+
+    // opcode: 0b011111
+    // funct: 0b110110
+    #[test]
+    fn test_run_ll_sc() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.text_address = 0x00400000;
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        memory.map(0x10010000, 0x1000, Permissions::READ_WRITE);
+        cpu.isa = IsaLevel::Mips32r6;
+        cpu.registers.t1 = 0x10010008;
+        cpu.registers.t3 = 9;
+        memory.write_word(0x10010004, 5).unwrap();
+        memory.write_word(0x00400000, 0x7d2afe36).unwrap(); // ll $t2, -4($t1)
+        memory.write_word(0x00400004, 0x7d2bfe26).unwrap(); // sc $t3, -4($t1)
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.t2, 5);
+        assert_eq!(cpu.registers.t3, 1);
+        assert_eq!(memory.read_word(0x10010004).unwrap(), 9);
+    }
+}
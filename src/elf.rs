@@ -1,3 +1,5 @@
+use crate::memory::Endianness;
+
 #[derive(Debug)]
 pub struct ELF {
     pub elf_header: ELFHeader,
@@ -17,9 +19,9 @@ pub struct ELFHeader {
     e_type: u16,
     instruction_set: u16,
     elf_version: u32,
-    pub entry: u32,
-    phoff: u32,
-    shoff: u32,
+    pub entry: u64,
+    phoff: u64,
+    shoff: u64,
     flags: u32,
     pub ehsize: u16,
     phentsize: u16,
@@ -32,87 +34,235 @@ pub struct ELFHeader {
 #[derive(Debug, Clone)]
 pub struct ProgramHeader {
     pub p_type: u32,
-    pub p_offset: u32,
-    pub _p_vaddr: u32,
-    pub p_paddr: u32,
-    pub p_filesz: u32,
-    pub p_memsz: u32,
-    pub _p_flags: u32,
-    pub _p_align: u32,
+    pub p_offset: u64,
+    pub p_vaddr: u64,
+    pub p_paddr: u64,
+    pub p_filesz: u64,
+    pub p_memsz: u64,
+    pub p_flags: u32,
+    pub _p_align: u64,
 }
 
 #[derive(Debug, Clone)]
 pub struct SectionHeader {
+    /// Read from the section name string table
+    pub name: String,
     pub sh_name: u32,
     _sh_type: u32,
-    _sh_flags: u32,
-    pub sh_addr: u32,
-    pub sh_offset: u32,
-    _sh_size: u32,
+    _shflags: u64,
+    pub sh_addr: u64,
+    pub sh_offset: u64,
+    pub sh_size: u64,
     _sh_link: u32,
     _sh_info: u32,
-    _sh_addralign: u32,
-    _sh_entsize: u32,
+    _sh_addralign: u64,
+    _sh_entsize: u64,
+}
+
+/// e_flags bit of objects that contain microMIPS code
+const EF_MIPS_ARCH_ASE_MICROMIPS: u32 = 0x02000000;
+const EF_MIPS_ARCH_ASE_M16: u32 = 0x04000000;
+/// e_shstrndx of a file without a section name string table
+const SHN_UNDEF: u16 = 0;
+
+/// Why a binary could not be loaded
+#[derive(Debug, Clone, PartialEq)]
+pub enum ElfError {
+    /// Not a MIPS executable
+    Invalid,
+}
+
+impl std::fmt::Display for ElfError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ElfError::Invalid => write!(f, "ELF file is not valid"),
+        }
+    }
+}
+
+/**
+ * The n bytes at offset, or Invalid when the file ends before them
+ */
+fn read_bytes<const N: usize>(elf: &[u8], offset: usize) -> Result<[u8; N], ElfError> {
+    let end = offset.checked_add(N).ok_or(ElfError::Invalid)?;
+    let mut bytes = [0; N];
+    bytes.copy_from_slice(elf.get(offset..end).ok_or(ElfError::Invalid)?);
+    Ok(bytes)
+}
+
+fn read_u16(elf: &[u8], offset: usize, endianness: Endianness) -> Result<u16, ElfError> {
+    let bytes = read_bytes(elf, offset)?;
+    Ok(match endianness {
+        Endianness::Big => u16::from_be_bytes(bytes),
+        Endianness::Little => u16::from_le_bytes(bytes),
+    })
+}
+
+fn read_u32(elf: &[u8], offset: usize, endianness: Endianness) -> Result<u32, ElfError> {
+    let bytes = read_bytes(elf, offset)?;
+    Ok(match endianness {
+        Endianness::Big => u32::from_be_bytes(bytes),
+        Endianness::Little => u32::from_le_bytes(bytes),
+    })
+}
+
+fn read_u64(elf: &[u8], offset: usize, endianness: Endianness) -> Result<u64, ElfError> {
+    let bytes = read_bytes(elf, offset)?;
+    Ok(match endianness {
+        Endianness::Big => u64::from_be_bytes(bytes),
+        Endianness::Little => u64::from_le_bytes(bytes),
+    })
+}
+
+/**
+ * The NUL-terminated name at offset in a string table
+ */
+fn read_name(names: &[u8], offset: usize) -> Result<String, ElfError> {
+    let name = names.get(offset..).ok_or(ElfError::Invalid)?;
+    let length = name
+        .iter()
+        .position(|&byte| byte == 0)
+        .ok_or(ElfError::Invalid)?;
+    let name = std::str::from_utf8(&name[..length]).map_err(|_| ElfError::Invalid)?;
+    Ok(name.to_string())
+}
+
+/**
+ * Reads an address, offset or size field, which is eight bytes wide in ELF64
+ */
+fn read_field(
+    elf: &[u8],
+    offset: usize,
+    endianness: Endianness,
+    elf64: bool,
+) -> Result<u64, ElfError> {
+    if elf64 {
+        read_u64(elf, offset, endianness)
+    } else {
+        read_u32(elf, offset, endianness).map(u64::from)
+    }
+}
+
+/**
+ * Offset in the file of entry index of a header table, or Invalid when the table starts
+ * past the end of the file
+ */
+fn table_entry(elf: &[u8], table: u64, index: u16, size: u16) -> Result<usize, ElfError> {
+    usize::try_from(table)
+        .ok()
+        .and_then(|table| table.checked_add(index as usize * size as usize))
+        .filter(|&offset| offset <= elf.len())
+        .ok_or(ElfError::Invalid)
+}
+
+/**
+ * The size bytes at offset in the file, or Invalid when they are not all in it
+ */
+fn file_range(elf: &[u8], offset: u64, size: u64) -> Result<std::ops::Range<usize>, ElfError> {
+    let end = offset.checked_add(size).ok_or(ElfError::Invalid)?;
+    if end > elf.len() as u64 {
+        return Err(ElfError::Invalid);
+    }
+    Ok(offset as usize..end as usize)
 }
 
 impl ELF {
-    pub fn parse_elf(elf: &[u8]) -> Self {
-        let elf_header = ELFHeader::parse_elf_header(elf);
+    pub fn parse_elf(elf: &[u8]) -> Result<Self, ElfError> {
+        let elf_header = ELFHeader::parse_elf_header(elf)?;
         if !elf_header.check() {
-            panic!("ELF file is not valid!");
+            return Err(ElfError::Invalid);
         }
 
         let mut program_headers = Vec::new();
         for i in 0..elf_header.phnum {
-            let offset = elf_header.phoff as usize + i as usize * elf_header.phentsize as usize;
-            let program_header = ProgramHeader::parse_program_header(elf, offset);
+            let offset = table_entry(elf, elf_header.phoff, i, elf_header.phentsize)?;
+            let program_header = ProgramHeader::parse_program_header(
+                elf,
+                offset,
+                elf_header.endianness(),
+                elf_header.is_elf64(),
+            )?;
+            // The file contents of a segment have to be in the file and fit in memory
+            file_range(elf, program_header.p_offset, program_header.p_filesz)?;
+            if program_header.p_filesz > program_header.p_memsz {
+                return Err(ElfError::Invalid);
+            }
             program_headers.push(program_header);
         }
 
         let mut section_headers = Vec::new();
         for i in 0..elf_header.shnum {
-            let offset = elf_header.shoff as usize + i as usize * elf_header.shentsize as usize;
-            let section_header = SectionHeader::parse_section_header(elf, offset);
+            let offset = table_entry(elf, elf_header.shoff, i, elf_header.shentsize)?;
+            let section_header = SectionHeader::parse_section_header(
+                elf,
+                offset,
+                elf_header.endianness(),
+                elf_header.is_elf64(),
+            )?;
             section_headers.push(section_header);
         }
+        if !section_headers.is_empty() && elf_header.shstrndx != SHN_UNDEF {
+            let names = section_headers
+                .get(elf_header.shstrndx as usize)
+                .ok_or(ElfError::Invalid)?;
+            let names = &elf[file_range(elf, names.sh_offset, names.sh_size)?];
+            for section_header in &mut section_headers {
+                section_header.name = read_name(names, section_header.sh_name as usize)?;
+            }
+        }
 
-        ELF {
+        Ok(ELF {
             elf: elf.to_vec(),
             elf_header,
             program_headers,
             section_headers,
-        }
+        })
+    }
+
+    /**
+     * The bytes of a segment that are in the file, parse_elf checked they are there
+     */
+    pub fn contents(&self, program_header: &ProgramHeader) -> &[u8] {
+        let start = program_header.p_offset as usize;
+        &self.elf[start..start + program_header.p_filesz as usize]
     }
 }
 
 impl ELFHeader {
-    fn parse_elf_header(elf: &[u8]) -> Self {
-        let ident = &elf[0..4];
-        let bit = elf[4];
-        let endianness = elf[5];
-        let elf_header_version = elf[6];
-        let os_abi = elf[7];
-        let os_abi_version = elf[8];
-        let _pad = &elf[9..15];
-
-        let e_type = u16::from_be_bytes([elf[16], elf[17]]);
-        let instruction_set = u16::from_be_bytes([elf[18], elf[19]]);
-        let elf_version = u32::from_be_bytes([elf[20], elf[21], elf[22], elf[23]]);
-        let entry = u32::from_be_bytes([elf[24], elf[25], elf[26], elf[27]]);
-        let phoff = u32::from_be_bytes([elf[28], elf[29], elf[30], elf[31]]);
-        let shoff = u32::from_be_bytes([elf[32], elf[33], elf[34], elf[35]]);
-        let flags = u32::from_be_bytes([elf[36], elf[37], elf[38], elf[39]]);
-        let ehsize = u16::from_be_bytes([elf[40], elf[41]]);
-        let phentsize = u16::from_be_bytes([elf[42], elf[43]]);
-        let phnum = u16::from_be_bytes([elf[44], elf[45]]);
-        let shentsize = u16::from_be_bytes([elf[46], elf[47]]);
-        let shnum = u16::from_be_bytes([elf[48], elf[49]]);
-        let shstrndx = u16::from_be_bytes([elf[50], elf[51]]);
-
-        ELFHeader {
-            ident: ident.try_into().unwrap(),
+    fn parse_elf_header(elf: &[u8]) -> Result<Self, ElfError> {
+        let e_ident: [u8; 16] = read_bytes(elf, 0)?;
+        let bit = e_ident[4];
+        let elf_header_version = e_ident[6];
+        let os_abi = e_ident[7];
+        let os_abi_version = e_ident[8];
+        let endianness = if e_ident[5] == 1 {
+            Endianness::Little
+        } else {
+            Endianness::Big
+        };
+
+        let elf64 = bit == 2;
+        // ELF64 widens entry, phoff and shoff to eight bytes, moving everything after them
+        let wide = if elf64 { 12 } else { 0 };
+
+        let e_type = read_u16(elf, 16, endianness)?;
+        let instruction_set = read_u16(elf, 18, endianness)?;
+        let elf_version = read_u32(elf, 20, endianness)?;
+        let entry = read_field(elf, 24, endianness, elf64)?;
+        let phoff = read_field(elf, if elf64 { 32 } else { 28 }, endianness, elf64)?;
+        let shoff = read_field(elf, if elf64 { 40 } else { 32 }, endianness, elf64)?;
+        let flags = read_u32(elf, 36 + wide, endianness)?;
+        let ehsize = read_u16(elf, 40 + wide, endianness)?;
+        let phentsize = read_u16(elf, 42 + wide, endianness)?;
+        let phnum = read_u16(elf, 44 + wide, endianness)?;
+        let shentsize = read_u16(elf, 46 + wide, endianness)?;
+        let shnum = read_u16(elf, 48 + wide, endianness)?;
+        let shstrndx = read_u16(elf, 50 + wide, endianness)?;
+
+        Ok(ELFHeader {
+            ident: [e_ident[0], e_ident[1], e_ident[2], e_ident[3]],
             bit,
-            endianness,
+            endianness: e_ident[5],
             elf_header_version,
             os_abi,
             os_abi_version,
@@ -129,164 +279,327 @@ impl ELFHeader {
             shentsize,
             shnum,
             shstrndx,
+        })
+    }
+
+    pub fn endianness(&self) -> Endianness {
+        if self.endianness == 1 {
+            Endianness::Little
+        } else {
+            Endianness::Big
         }
     }
 
+    /**
+     * The code uses the microMIPS ASE
+     */
+    pub fn is_micromips(&self) -> bool {
+        self.flags & EF_MIPS_ARCH_ASE_MICROMIPS != 0
+    }
+
+    /**
+     * The code uses the MIPS16e ASE
+     */
+    pub fn is_mips16e(&self) -> bool {
+        self.flags & EF_MIPS_ARCH_ASE_M16 != 0
+    }
+
+    /**
+     * The file is ELF64, built for a MIPS64 machine
+     */
+    pub fn is_elf64(&self) -> bool {
+        self.bit == 2
+    }
+
     fn check(&self) -> bool {
+        let layout = match self.bit {
+            1 => {
+                self.phoff == 0x00000034
+                    && self.ehsize == 52
+                    && self.phentsize == 0x0020
+                    && self.shentsize == 0x0028
+            }
+            2 => {
+                self.phoff == 0x00000040
+                    && self.ehsize == 64
+                    && self.phentsize == 0x0038
+                    && self.shentsize == 0x0040
+            }
+            _ => false,
+        };
         self.ident == [0x7f, 0x45, 0x4c, 0x46]
-            && self.bit == 1
-            && self.endianness == 2
+            && layout
+            && (self.endianness == 1 || self.endianness == 2)
             && self.elf_header_version == 0x01
             && self.os_abi == 0x00
             && self.os_abi_version == 0x00
             && self.e_type == 0x02
             && self.instruction_set == 0x0008
             && self.elf_version == 0x00000001
-            && self.phoff == 0x00000034
-            && self.ehsize == 52
-            && self.phentsize == 0x0020
-            && self.shentsize == 0x0028
     }
 }
 
 impl ProgramHeader {
-    fn parse_program_header(elf: &[u8], offset: usize) -> Self {
-        let p_type = u32::from_be_bytes([
-            elf[offset],
-            elf[offset + 1],
-            elf[offset + 2],
-            elf[offset + 3],
-        ]);
-        let p_offset = u32::from_be_bytes([
-            elf[offset + 4],
-            elf[offset + 5],
-            elf[offset + 6],
-            elf[offset + 7],
-        ]);
-        let _p_vaddr = u32::from_be_bytes([
-            elf[offset + 8],
-            elf[offset + 9],
-            elf[offset + 10],
-            elf[offset + 11],
-        ]);
-        let p_paddr = u32::from_be_bytes([
-            elf[offset + 12],
-            elf[offset + 13],
-            elf[offset + 14],
-            elf[offset + 15],
-        ]);
-        let p_filesz = u32::from_be_bytes([
-            elf[offset + 16],
-            elf[offset + 17],
-            elf[offset + 18],
-            elf[offset + 19],
-        ]);
-        let p_memsz = u32::from_be_bytes([
-            elf[offset + 20],
-            elf[offset + 21],
-            elf[offset + 22],
-            elf[offset + 23],
-        ]);
-        let _p_flags = u32::from_be_bytes([
-            elf[offset + 24],
-            elf[offset + 25],
-            elf[offset + 26],
-            elf[offset + 27],
-        ]);
-        let _p_align = u32::from_be_bytes([
-            elf[offset + 28],
-            elf[offset + 29],
-            elf[offset + 30],
-            elf[offset + 31],
-        ]);
-
-        ProgramHeader {
+    fn parse_program_header(
+        elf: &[u8],
+        offset: usize,
+        endianness: Endianness,
+        elf64: bool,
+    ) -> Result<Self, ElfError> {
+        let p_type = read_u32(elf, offset, endianness)?;
+        // ELF64 moves the flags up next to the type to keep the wide fields aligned
+        let (p_flags, fields) = if elf64 {
+            (read_u32(elf, offset + 4, endianness)?, offset + 8)
+        } else {
+            (read_u32(elf, offset + 24, endianness)?, offset + 4)
+        };
+        let width = if elf64 { 8 } else { 4 };
+        let field = |index: usize| read_field(elf, fields + index * width, endianness, elf64);
+        let p_offset = field(0)?;
+        let p_vaddr = field(1)?;
+        let p_paddr = field(2)?;
+        let p_filesz = field(3)?;
+        let p_memsz = field(4)?;
+        let _p_align = if elf64 { field(5)? } else { field(6)? };
+
+        Ok(ProgramHeader {
             p_type,
             p_offset,
-            _p_vaddr,
+            p_vaddr,
             p_paddr,
             p_filesz,
             p_memsz,
-            _p_flags,
+            p_flags,
             _p_align,
-        }
+        })
     }
 }
 
 impl SectionHeader {
-    fn parse_section_header(elf: &[u8], offset: usize) -> Self {
-        let sh_name = u32::from_be_bytes([
-            elf[offset],
-            elf[offset + 1],
-            elf[offset + 2],
-            elf[offset + 3],
-        ]);
-        let _sh_type = u32::from_be_bytes([
-            elf[offset + 4],
-            elf[offset + 5],
-            elf[offset + 6],
-            elf[offset + 7],
-        ]);
-        let _sh_flags = u32::from_be_bytes([
-            elf[offset + 8],
-            elf[offset + 9],
-            elf[offset + 10],
-            elf[offset + 11],
-        ]);
-        let sh_addr = u32::from_be_bytes([
-            elf[offset + 12],
-            elf[offset + 13],
-            elf[offset + 14],
-            elf[offset + 15],
-        ]);
-        let sh_offset = u32::from_be_bytes([
-            elf[offset + 16],
-            elf[offset + 17],
-            elf[offset + 18],
-            elf[offset + 19],
-        ]);
-        let _sh_size = u32::from_be_bytes([
-            elf[offset + 20],
-            elf[offset + 21],
-            elf[offset + 22],
-            elf[offset + 23],
-        ]);
-        let _sh_link = u32::from_be_bytes([
-            elf[offset + 24],
-            elf[offset + 25],
-            elf[offset + 26],
-            elf[offset + 27],
-        ]);
-        let _sh_info = u32::from_be_bytes([
-            elf[offset + 28],
-            elf[offset + 29],
-            elf[offset + 30],
-            elf[offset + 31],
-        ]);
-        let _sh_addralign = u32::from_be_bytes([
-            elf[offset + 32],
-            elf[offset + 33],
-            elf[offset + 34],
-            elf[offset + 35],
-        ]);
-        let _sh_entsize = u32::from_be_bytes([
-            elf[offset + 36],
-            elf[offset + 37],
-            elf[offset + 38],
-            elf[offset + 39],
-        ]);
-
-        SectionHeader {
+    fn parse_section_header(
+        elf: &[u8],
+        offset: usize,
+        endianness: Endianness,
+        elf64: bool,
+    ) -> Result<Self, ElfError> {
+        let width = if elf64 { 8 } else { 4 };
+        let sh_name = read_u32(elf, offset, endianness)?;
+        let _sh_type = read_u32(elf, offset + 4, endianness)?;
+        let _shflags = read_field(elf, offset + 8, endianness, elf64)?;
+        let sh_addr = read_field(elf, offset + 8 + width, endianness, elf64)?;
+        let sh_offset = read_field(elf, offset + 8 + 2 * width, endianness, elf64)?;
+        let sh_size = read_field(elf, offset + 8 + 3 * width, endianness, elf64)?;
+        let _sh_link = read_u32(elf, offset + 8 + 4 * width, endianness)?;
+        let _sh_info = read_u32(elf, offset + 12 + 4 * width, endianness)?;
+        let _sh_addralign = read_field(elf, offset + 16 + 4 * width, endianness, elf64)?;
+        let _sh_entsize = read_field(elf, offset + 16 + 5 * width, endianness, elf64)?;
+
+        Ok(SectionHeader {
+            name: String::new(),
             sh_name,
             _sh_type,
-            _sh_flags,
+            _shflags,
             sh_addr,
             sh_offset,
-            _sh_size,
+            sh_size,
             _sh_link,
             _sh_info,
             _sh_addralign,
             _sh_entsize,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::CPU;
+    use crate::memory::Memory;
+
+    /**
+     * Big-endian ELF64 MIPS executable with one read/execute PT_LOAD segment holding a nop
+     */
+    fn elf64(entry: u64, vaddr: u64, paddr: u64) -> Vec<u8> {
+        let mut elf = vec![0x7f, b'E', b'L', b'F', 2, 2, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        elf.extend(2u16.to_be_bytes()); // e_type: executable
+        elf.extend(8u16.to_be_bytes()); // e_machine: MIPS
+        elf.extend(1u32.to_be_bytes()); // e_version
+        elf.extend(entry.to_be_bytes());
+        elf.extend(64u64.to_be_bytes()); // e_phoff
+        elf.extend(0u64.to_be_bytes()); // e_shoff
+        elf.extend(0u32.to_be_bytes()); // e_flags
+        elf.extend(64u16.to_be_bytes()); // e_ehsize
+        elf.extend(56u16.to_be_bytes()); // e_phentsize
+        elf.extend(1u16.to_be_bytes()); // e_phnum
+        elf.extend(64u16.to_be_bytes()); // e_shentsize
+        elf.extend(0u16.to_be_bytes()); // e_shnum
+        elf.extend(0u16.to_be_bytes()); // e_shstrndx
+        elf.extend(1u32.to_be_bytes()); // p_type: PT_LOAD
+        elf.extend(5u32.to_be_bytes()); // p_flags: R X
+        elf.extend(120u64.to_be_bytes()); // p_offset
+        elf.extend(vaddr.to_be_bytes());
+        elf.extend(paddr.to_be_bytes());
+        elf.extend(4u64.to_be_bytes()); // p_filesz
+        elf.extend(4u64.to_be_bytes()); // p_memsz
+        elf.extend(0x1000u64.to_be_bytes()); // p_align
+        elf.extend(0x00000000u32.to_be_bytes()); // nop
+        elf
+    }
+
+    /**
+     * ELF64 section header
+     */
+    fn section_header(name: u32, address: u64, offset: u64, size: u64) -> Vec<u8> {
+        let mut header = Vec::new();
+        header.extend(name.to_be_bytes());
+        header.extend(1u32.to_be_bytes()); // sh_type: PROGBITS
+        header.extend(0u64.to_be_bytes()); // sh_flags
+        header.extend(address.to_be_bytes());
+        header.extend(offset.to_be_bytes());
+        header.extend(size.to_be_bytes());
+        header.extend([0; 24]); // sh_link, sh_info, sh_addralign and sh_entsize
+        header
+    }
+
+    /**
+     * elf64 with a .data section and the string table naming it
+     */
+    fn elf64_with_sections(data_name: u32, shstrndx: u16) -> Vec<u8> {
+        let mut elf = elf64(0x1000, 0x1000, 0x1000);
+        let names_offset = elf.len() as u64;
+        elf.extend(b"\0.data\0.shstrtab\0");
+        let shoff = elf.len() as u64;
+        elf.extend([0; 64]); // SHN_UNDEF
+        elf.extend(section_header(data_name, 0x10010000, 0, 0x10));
+        elf.extend(section_header(7, 0, names_offset, 17));
+        elf[40..48].copy_from_slice(&shoff.to_be_bytes()); // e_shoff
+        elf[60..62].copy_from_slice(&3u16.to_be_bytes()); // e_shnum
+        elf[62..64].copy_from_slice(&shstrndx.to_be_bytes()); // e_shstrndx
+        elf
+    }
+
+    #[test]
+    fn test_parse_section_names() {
+        let binary = elf64_with_sections(1, 2);
+        let elf = ELF::parse_elf(&binary).unwrap();
+        assert_eq!(elf.section_headers[1].name, ".data");
+        assert_eq!(elf.section_headers[2].name, ".shstrtab");
+        let mut memory = Memory::default();
+        memory.load_elf(&elf, false);
+        assert_eq!(memory.data_address, 0x10010000);
+        assert_eq!(memory.data_size, 0x10);
+    }
+
+    #[test]
+    fn test_parse_invalid_section_names() {
+        // String table index past the section headers
+        let binary = elf64_with_sections(1, 3);
+        assert_eq!(ELF::parse_elf(&binary).unwrap_err(), ElfError::Invalid);
+        // Name past the end of the string table
+        let binary = elf64_with_sections(17, 2);
+        assert_eq!(ELF::parse_elf(&binary).unwrap_err(), ElfError::Invalid);
+    }
+
+    #[test]
+    fn test_parse_segment_outside_file() {
+        let mut binary = elf64(0x1000, 0x1000, 0x1000);
+        binary[96..104].copy_from_slice(&0x100u64.to_be_bytes()); // p_filesz
+        binary[104..112].copy_from_slice(&0x100u64.to_be_bytes()); // p_memsz
+        assert_eq!(ELF::parse_elf(&binary).unwrap_err(), ElfError::Invalid);
+    }
+
+    #[test]
+    fn test_parse_segment_larger_in_file() {
+        let mut binary = elf64(0x1000, 0x1000, 0x1000);
+        binary[104..112].copy_from_slice(&2u64.to_be_bytes()); // p_memsz
+        assert_eq!(ELF::parse_elf(&binary).unwrap_err(), ElfError::Invalid);
+    }
+
+    #[test]
+    fn test_parse_elf64() {
+        let binary = elf64(0xffffffff_80001000, 0xffffffff_80001000, 0x1000);
+        let elf = ELF::parse_elf(&binary).unwrap();
+        assert!(elf.elf_header.is_elf64());
+        assert_eq!(elf.elf_header.entry, 0xffffffff_80001000);
+        assert_eq!(elf.program_headers[0].p_vaddr, 0xffffffff_80001000);
+        let mut memory = Memory::default();
+        assert_eq!(memory.load_elf(&elf, false), 0xffffffff_80001000);
+        assert_eq!(memory.text_address, 0x80001000);
+        assert!(memory.is_mapped(0x80001000));
+    }
+
+    #[test]
+    fn test_load_physical_address_differs() {
+        let mut binary = elf64(0x80001000, 0xffffffff_80001000, 0x2000);
+        binary[120..124].copy_from_slice(&0x24020011u32.to_be_bytes()); // addiu $v0, $zero, 17
+        let elf = ELF::parse_elf(&binary).unwrap();
+
+        // Without an MMU the CPU fetches from the virtual address
+        let mut memory = Memory::default();
+        memory.load_elf(&elf, false);
+        assert_eq!(memory.text_address, 0x80001000);
+        assert!(!memory.is_mapped(0x2000));
+        assert_eq!(memory.read_word(memory.text_address), Ok(0x24020011));
+
+        // kseg0 maps to physical memory by dropping the segment bits, whatever p_paddr says
+        let mut memory = Memory::default();
+        memory.load_elf(&elf, true);
+        assert_eq!(memory.text_address, 0x1000);
+        assert!(!memory.is_mapped(0x2000));
+        assert_eq!(memory.read_word(memory.text_address), Ok(0x24020011));
+
+        // Mapped segments go to their physical address
+        let mut binary = elf64(0x00400000, 0x00400000, 0x2000);
+        binary[120..124].copy_from_slice(&0x24020011u32.to_be_bytes()); // addiu $v0, $zero, 17
+        let elf = ELF::parse_elf(&binary).unwrap();
+        let mut memory = Memory::default();
+        memory.load_elf(&elf, true);
+        assert_eq!(memory.text_address, 0x2000);
+        assert!(!memory.is_mapped(0x00400000));
+        assert_eq!(memory.read_word(memory.text_address), Ok(0x24020011));
+    }
+
+    #[test]
+    fn test_load_elf64_outside_compatibility_segments() {
+        // n64 Linux executables are linked above 4 GiB
+        let binary = elf64(0x1_20000000, 0x1_20000000, 0x1_20000000);
+        let elf = ELF::parse_elf(&binary).unwrap();
+        assert_eq!(elf.elf_header.entry, 0x1_20000000);
+        let mut memory = Memory::default();
+        assert_eq!(memory.load_elf(&elf, false), 0x1_20000000);
+        assert!(memory.is_mapped(0x1_20000000));
+        assert!(!memory.is_mapped(0x20000000));
+
+        let mut cpu = CPU::default();
+        cpu.mips64 = true;
+        cpu.registers.pc = elf.elf_header.entry;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.registers.pc, 0x1_20000004);
+    }
+
+    #[test]
+    fn test_parse_truncated() {
+        let binary = elf64(0x1000, 0x1000, 0x1000);
+        for length in [0, 4, 16, 40, 63, 64, 100] {
+            assert_eq!(
+                ELF::parse_elf(&binary[..length]).unwrap_err(),
+                ElfError::Invalid
+            );
         }
     }
+
+    #[test]
+    fn test_parse_section_headers_outside_file() {
+        let mut binary = elf64(0x1000, 0x1000, 0x1000);
+        binary[40..48].copy_from_slice(&0xfff0u64.to_be_bytes()); // e_shoff
+        binary[60..62].copy_from_slice(&1u16.to_be_bytes()); // e_shnum
+        assert_eq!(ELF::parse_elf(&binary).unwrap_err(), ElfError::Invalid);
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        let mut binary = elf64(0x1000, 0x1000, 0x1000);
+        binary[18] = 0x3e; // e_machine: x86-64
+        assert_eq!(ELF::parse_elf(&binary).unwrap_err(), ElfError::Invalid);
+    }
 }
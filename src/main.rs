@@ -1,3 +1,5 @@
+#![allow(clippy::upper_case_acronyms)]
+
 use eframe::egui;
 
 use std::env::args;
@@ -6,6 +8,9 @@ mod cpu;
 mod elf;
 mod memory;
 
+/// Height of the heap listing, which scrolls on its own
+const HEAP_SEGMENT_HEIGHT: f32 = 400.0;
+
 fn main() -> eframe::Result {
     let binary = args().nth(1).expect("No binary provided");
     let options = eframe::NativeOptions {
@@ -19,10 +24,10 @@ fn main() -> eframe::Result {
         ..Default::default()
     };
 
-    let entry_point = app.memory.load_elf(&app.binary);
-    app.cpu.registers.pc = entry_point;
-    // app.cpu.run(&mut app.memory);
-    // std::process::exit(0);
+    if let Err(error) = app.load() {
+        eprintln!("Failed to load binary: {}", error);
+        std::process::exit(1);
+    }
 
     eframe::run_native("Hello World", options, Box::new(|_cc| Ok(Box::new(app))))
 }
@@ -41,8 +46,10 @@ impl eframe::App for MyApp {
     fn update(&mut self, _ctx: &egui::Context, _cc: &mut eframe::Frame) {
         _ctx.request_repaint_after(std::time::Duration::from_millis(0));
         if self.running {
-            for _ in 0..100 {
-                self.cpu.step(&mut self.memory);
+            self.cpu.run(&mut self.memory, 100);
+            if self.cpu.halted {
+                // Leave the faulting instruction on screen instead of retrying it
+                self.running = false;
             }
         }
         egui::CentralPanel::default().show(_ctx, |ui| {
@@ -54,21 +61,85 @@ impl eframe::App for MyApp {
                 if ui.button("step").clicked() {
                     self.cpu.step(&mut self.memory);
                 }
+
+                ui.checkbox(&mut self.cpu.delay_slots, "Delay slots");
+                ui.checkbox(&mut self.cpu.mips64, "MIPS64");
+                if ui.checkbox(&mut self.cpu.mmu, "MMU").changed() {
+                    // The program and the exception vector now live at different addresses,
+                    // so start again from a fresh load
+                    self.load().expect("The binary loaded before");
+                }
+                ui.checkbox(&mut self.cpu.self_modifying_code, "Self-modifying code");
+                ui.add(
+                    egui::DragValue::new(&mut self.memory.stack_size)
+                        .prefix("Max stack: ")
+                        .suffix(" bytes")
+                        .speed(1024),
+                );
+                // The heap can't be limited below what the program has already allocated
+                let heap_used = self.memory.program_break - self.memory.heap_address;
+                ui.add(
+                    egui::DragValue::new(&mut self.memory.heap_size)
+                        .range(heap_used..=u64::MAX)
+                        .prefix("Max heap: ")
+                        .suffix(" bytes")
+                        .speed(1024),
+                );
+                ui.label(self.memory.endianness.to_string());
+                ui.label(self.cpu.control_registers.mode().to_string());
+
+                egui::ComboBox::from_label("ISA")
+                    .selected_text(self.cpu.isa.to_string())
+                    .show_ui(ui, |ui| {
+                        for level in cpu::IsaLevel::ALL {
+                            ui.selectable_value(&mut self.cpu.isa, level, level.to_string());
+                        }
+                    });
             });
 
             ui.separator();
             ui.horizontal_top(|ui| {
                 self.draw_registers(ui);
+                self.draw_float_registers(ui);
                 self.draw_memory(ui);
                 ui.vertical(|ui| {
                     ui.heading("Status");
                     if self.cpu.exception != cpu::Exception::None {
                         ui.label(format!("{}", self.cpu.exception));
                     } else if self.cpu.halted {
-                        ui.label("Halted");
+                        ui.label(format!("Halted with exit code {}", self.cpu.exit_code));
                     } else {
                         ui.label("Running");
                     }
+
+                    ui.heading("COP0");
+                    let cop0 = &self.cpu.control_registers;
+                    egui::Grid::new("cop0").striped(true).show(ui, |ui| {
+                        for (name, register) in [
+                            ("Status", cop0.status.into()),
+                            ("Cause", cop0.cause.into()),
+                            ("EPC", cop0.epc),
+                            ("BadVAddr", cop0.bad_vaddr),
+                            ("Count", cop0.count.into()),
+                            ("Compare", cop0.compare.into()),
+                        ] {
+                            ui.label(name);
+                            ui.label(
+                                egui::RichText::new(format!("0x{:08x}", register)).monospace(),
+                            );
+                            ui.end_row();
+                        }
+                    });
+
+                    ui.heading("Interrupt lines");
+                    ui.horizontal(|ui| {
+                        for line in 0..cpu::INTERRUPT_LINES {
+                            let mut asserted = self.cpu.interrupt_line(line);
+                            if ui.checkbox(&mut asserted, line.to_string()).changed() {
+                                self.cpu.set_interrupt_line(line, asserted);
+                            }
+                        }
+                    });
                 });
             });
         });
@@ -76,6 +147,38 @@ impl eframe::App for MyApp {
 }
 
 impl MyApp {
+    /**
+     * Load the binary into fresh memory and point the CPU at its entry, keeping the settings
+     */
+    fn load(&mut self) -> Result<(), elf::ElfError> {
+        let elf = elf::ELF::parse_elf(&self.binary)?;
+        let mut memory = memory::Memory::default();
+        memory.stack_size = self.memory.stack_size;
+        memory.heap_size = self.memory.heap_size;
+        let entry_point = memory.load_elf(&elf, self.cpu.mmu);
+        self.memory = memory;
+
+        let mut cpu = cpu::CPU::default();
+        cpu.delay_slots = self.cpu.delay_slots;
+        cpu.mmu = self.cpu.mmu;
+        cpu.self_modifying_code = self.cpu.self_modifying_code;
+        cpu.isa = self.cpu.isa;
+        let elf_header = elf.elf_header;
+        cpu.mips64 = elf_header.is_elf64();
+        // microMIPS and MIPS16e entry points have the ISA mode bit set
+        let compressed = entry_point & 1 != 0;
+        cpu.mips16e_ase = elf_header.is_mips16e();
+        cpu.micromips_ase = elf_header.is_micromips() || compressed && !cpu.mips16e_ase;
+        cpu.micromips = compressed && cpu.micromips_ase;
+        cpu.mips16e = compressed && cpu.mips16e_ase;
+        cpu.registers.pc = entry_point & !1;
+        // A kernel linked with the program provides the general exception handler
+        cpu.exception_handler = cpu.handler_loaded(&self.memory);
+        self.cpu = cpu;
+        self.running = false;
+        Ok(())
+    }
+
     fn draw_registers(&mut self, ui: &mut egui::Ui) {
         ui.vertical(|ui| {
             ui.heading("Registers");
@@ -89,13 +192,54 @@ impl MyApp {
                     ui.label("Hex Value");
                     ui.allocate_space(egui::vec2(0.0, 0.0));
                     ui.end_row();
-                    for (name, register) in self.cpu.registers.clone().into_iter() {
+                    for (name, register) in self.cpu.registers.clone() {
+                        let hex = if self.cpu.mips64 {
+                            format!("0x{:016x}", register)
+                        } else {
+                            format!("0x{:08x}", register as u32)
+                        };
                         ui.label(name);
-                        ui.label(register.to_string());
+                        if self.cpu.mips64 {
+                            ui.label(register.to_string());
+                        } else {
+                            ui.label((register as u32).to_string());
+                        }
+                        ui.label(egui::RichText::new(hex).monospace());
+                        ui.allocate_space(egui::vec2(0.0, 0.0));
+                        ui.end_row();
+                    }
+                });
+        });
+    }
+
+    fn draw_float_registers(&mut self, ui: &mut egui::Ui) {
+        ui.vertical(|ui| {
+            ui.heading("FP Registers");
+            egui::Grid::new("float registers")
+                .striped(true)
+                .spacing(egui::vec2(3.0, 8.0))
+                .num_columns(3)
+                .show(ui, |ui| {
+                    ui.label("Name");
+                    ui.label("Single Value");
+                    ui.label("Hex Value");
+                    ui.allocate_space(egui::vec2(0.0, 0.0));
+                    ui.end_row();
+                    for (number, register) in self.cpu.float_registers.f.iter().enumerate() {
+                        ui.label(format!("f{}", number));
+                        ui.label(f32::from_bits(*register).to_string());
                         ui.label(egui::RichText::new(format!("0x{:08x}", register)).monospace());
                         ui.allocate_space(egui::vec2(0.0, 0.0));
                         ui.end_row();
                     }
+                    ui.label("fcsr");
+                    ui.label("");
+                    ui.label(
+                        egui::RichText::new(format!("0x{:08x}", self.cpu.float_registers.fcsr))
+                            .monospace(),
+                    );
+                    ui.allocate_space(egui::vec2(0.0, 0.0));
+                    ui.end_row();
                 });
         });
     }
@@ -105,10 +249,14 @@ impl MyApp {
             self.draw_text_segment(ui);
             self.draw_data_segment(ui);
             self.draw_heap_segment(ui);
+            self.draw_stack_segment(ui);
         });
     }
 
     fn draw_text_segment(&mut self, ui: &mut egui::Ui) {
+        if self.cpu.micromips_ase || self.cpu.mips16e_ase {
+            return self.draw_compressed_text_segment(ui);
+        }
         ui.vertical(|ui| {
             ui.heading("Text Segment");
             egui::Grid::new("Text segment")
@@ -121,9 +269,9 @@ impl MyApp {
                     ui.label("Hex Value");
                     ui.allocate_space(egui::vec2(0.0, 0.0));
                     ui.end_row();
-                    for i in 0..self.memory.text.len() / 4 {
-                        let address = self.memory.text_address + (i * 4) as u32;
-                        let value = self.memory.read_word(address);
+                    for i in 0..self.memory.text_size as usize / 4 {
+                        let address = self.memory.text_address + (i * 4) as u64;
+                        let value = self.memory.read_word(address).unwrap_or_default();
                         ui.label(egui::RichText::new(format!("0x{:08x}", address)).monospace());
                         ui.label(egui::RichText::new(format!("{}", value)));
                         ui.label(egui::RichText::new(format!("0x{:08x}", value)).monospace());
@@ -134,6 +282,55 @@ impl MyApp {
         });
     }
 
+    /**
+     * microMIPS and MIPS16e code mixes 16 and 32-bit instructions, so it is listed by instruction
+     */
+    fn draw_compressed_text_segment(&mut self, ui: &mut egui::Ui) {
+        let disassemble = if self.cpu.mips16e_ase {
+            cpu::disassemble_mips16e
+        } else {
+            cpu::disassemble
+        };
+        ui.vertical(|ui| {
+            ui.heading("Text Segment");
+            egui::Grid::new("Text segment")
+                .striped(true)
+                .spacing(egui::vec2(10.0, 8.0))
+                .num_columns(3)
+                .show(ui, |ui| {
+                    ui.label("Address");
+                    ui.label("Hex Value");
+                    ui.label("Instruction");
+                    ui.allocate_space(egui::vec2(0.0, 0.0));
+                    ui.end_row();
+                    let end = self.memory.text_address + self.memory.text_size;
+                    let mut address = self.memory.text_address;
+                    while address < end {
+                        let Ok((size, text)) = disassemble(&self.memory, address) else {
+                            break;
+                        };
+                        // A 32-bit instruction is two halfwords, whatever the byte order
+                        let halfword = |offset: u64| {
+                            self.memory
+                                .read_halfword(address + offset)
+                                .unwrap_or_default()
+                        };
+                        let hex = if size == 2 {
+                            format!("0x{:04x}", halfword(0))
+                        } else {
+                            format!("0x{:04x}{:04x}", halfword(0), halfword(2))
+                        };
+                        ui.label(egui::RichText::new(format!("0x{:08x}", address)).monospace());
+                        ui.label(egui::RichText::new(hex).monospace());
+                        ui.label(egui::RichText::new(text).monospace());
+                        ui.allocate_space(egui::vec2(0.0, 0.0));
+                        ui.end_row();
+                        address += size as u64;
+                    }
+                });
+        });
+    }
+
     fn draw_data_segment(&mut self, ui: &mut egui::Ui) {
         ui.vertical(|ui| {
             ui.heading("Data Segment");
@@ -147,9 +344,9 @@ impl MyApp {
                     ui.label("Hex Value");
                     ui.allocate_space(egui::vec2(0.0, 0.0));
                     ui.end_row();
-                    for i in 0..self.memory.data.len() / 4 {
-                        let address = self.memory.data_address + (i * 4) as u32;
-                        let value = self.memory.read_word(address);
+                    for i in 0..self.memory.data_size as usize / 4 {
+                        let address = self.memory.data_address + (i * 4) as u64;
+                        let value = self.memory.read_word(address).unwrap_or_default();
                         ui.label(egui::RichText::new(format!("0x{:08x}", address)).monospace());
                         ui.label(egui::RichText::new(format!("{}", value)));
                         ui.label(egui::RichText::new(format!("0x{:08x}", value)).monospace());
@@ -160,10 +357,55 @@ impl MyApp {
         });
     }
 
+    /**
+     * The heap can grow to megabytes, so only the rows scrolled into view are laid out
+     */
     fn draw_heap_segment(&mut self, ui: &mut egui::Ui) {
         ui.vertical(|ui| {
             ui.heading("Heap Segment");
-            egui::Grid::new("Heap segment")
+            let words = (self.memory.program_break - self.memory.heap_address) as usize / 4;
+            // show_rows adds the item spacing between rows, the grid spaces them by 8.0 instead
+            let row_height =
+                ui.text_style_height(&egui::TextStyle::Body) + 8.0 - ui.spacing().item_spacing.y;
+            egui::ScrollArea::vertical()
+                .id_salt("Heap segment scroll")
+                .max_height(HEAP_SEGMENT_HEIGHT)
+                .show_rows(ui, row_height, words + 1, |ui, rows| {
+                    egui::Grid::new("Heap segment")
+                        .striped(true)
+                        .spacing(egui::vec2(10.0, 8.0))
+                        .num_columns(2)
+                        .show(ui, |ui| {
+                            for row in rows {
+                                if row == 0 {
+                                    ui.label("Address");
+                                    ui.label("Decimal Value");
+                                    ui.label("Hex Value");
+                                    ui.allocate_space(egui::vec2(0.0, 0.0));
+                                    ui.end_row();
+                                    continue;
+                                }
+                                let address = self.memory.heap_address + (row as u64 - 1) * 4;
+                                let value = self.memory.read_word(address).unwrap_or_default();
+                                ui.label(
+                                    egui::RichText::new(format!("0x{:08x}", address)).monospace(),
+                                );
+                                ui.label(egui::RichText::new(format!("{}", value)));
+                                ui.label(
+                                    egui::RichText::new(format!("0x{:08x}", value)).monospace(),
+                                );
+                                ui.allocate_space(egui::vec2(0.0, 0.0));
+                                ui.end_row();
+                            }
+                        });
+                });
+        });
+    }
+
+    fn draw_stack_segment(&mut self, ui: &mut egui::Ui) {
+        ui.vertical(|ui| {
+            ui.heading("Stack Segment");
+            egui::Grid::new("Stack segment")
                 .striped(true)
                 .spacing(egui::vec2(10.0, 8.0))
                 .num_columns(2)
@@ -173,9 +415,10 @@ impl MyApp {
                     ui.label("Hex Value");
                     ui.allocate_space(egui::vec2(0.0, 0.0));
                     ui.end_row();
-                    for i in 0..self.memory.heap.len() / 4 {
-                        let address = 0x10400000 + i as u32 * 4;
-                        let value = self.memory.read_word(address);
+                    // From the top of the stack down to the deepest page written
+                    for i in 0..self.memory.stack_depth() as usize / 4 {
+                        let address = self.memory.stack_address - (i + 1) as u64 * 4;
+                        let value = self.memory.read_word(address).unwrap_or_default();
                         ui.label(egui::RichText::new(format!("0x{:08x}", address)).monospace());
                         ui.label(egui::RichText::new(format!("{}", value)));
                         ui.label(egui::RichText::new(format!("0x{:08x}", value)).monospace());
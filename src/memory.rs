@@ -1,166 +1,790 @@
-use crate::elf::ELF;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
 
-#[derive(Debug, Default)]
+use crate::cpu::KSEG_PHYSICAL;
+use crate::elf::{ProgramHeader, ELF};
+
+/// Memory is allocated in pages of this many bytes
+const PAGE_SIZE: usize = 4096;
+const PAGE_SHIFT: u32 = 12;
+
+/// The stack grows down from the top of kuseg
+const STACK_TOP: u64 = 0x80000000;
+const DEFAULT_STACK_SIZE: u64 = 8 * 1024 * 1024;
+/// Accesses this far below the stack limit are taken as the stack overflowing
+const STACK_GUARD_SIZE: u64 = 64 * 1024;
+
+/// Where the heap starts when no ELF has been loaded, as in MARS
+const HEAP_START: u64 = 0x10040000;
+const DEFAULT_HEAP_SIZE: u64 = 4 * 1024 * 1024;
+
+const PT_LOAD: u32 = 1;
+const PF_X: u32 = 1 << 0;
+const PF_W: u32 = 1 << 1;
+const PF_R: u32 = 1 << 2;
+
+/// What a page may be used for, from the p_flags of the segments loaded into it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Permissions {
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+}
+
+impl Permissions {
+    /// No restrictions, for memory set up by hand rather than loaded from segments
+    pub const ALL: Permissions = Permissions {
+        read: true,
+        write: true,
+        execute: true,
+    };
+    /// The stack and the heap hold data, never code
+    pub const READ_WRITE: Permissions = Permissions {
+        read: true,
+        write: true,
+        execute: false,
+    };
+
+    fn from_flags(flags: u32) -> Self {
+        Permissions {
+            read: flags & PF_R != 0,
+            write: flags & PF_W != 0,
+            execute: flags & PF_X != 0,
+        }
+    }
+
+    fn union(self, other: Permissions) -> Self {
+        Permissions {
+            read: self.read || other.read,
+            write: self.write || other.write,
+            execute: self.execute || other.execute,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Page {
+    bytes: [u8; PAGE_SIZE],
+    permissions: Permissions,
+}
+
+impl Page {
+    fn new(permissions: Permissions) -> Box<Self> {
+        Box::new(Page {
+            bytes: [0; PAGE_SIZE],
+            permissions,
+        })
+    }
+}
+
+/**
+ * A sparse 32-bit address space.
+ * Pages are allocated when a segment is loaded into them, or when a stack or heap page is
+ * first written. Accessing any other page is an unmapped access.
+ */
+#[derive(Debug)]
 pub struct Memory {
-    pub text: Vec<u8>,
-    pub data: Vec<u8>,
-    pub heap: Vec<u8>,
-    pub stack: Vec<u8>,
+    /// Allocated pages by page number
+    pages: HashMap<u64, Box<Page>>,
+
+    /// Start and size of the segment holding the entry point, shown as the text segment
+    pub text_address: u64,
+    pub text_size: u64,
+    /// Start and size of the .data section
+    pub data_address: u64,
+    pub data_size: u64,
+    /// Top of the stack, it grows down from here
+    pub stack_address: u64,
+    /// Largest the stack may grow to, in bytes
+    pub stack_size: u64,
+    /// Start of the heap, just past the loaded data and bss
+    pub heap_address: u64,
+    /// End of the heap, moved by sbrk
+    pub program_break: u64,
+    /// Largest the heap may grow to, in bytes
+    pub heap_size: u64,
+    pub kernel_address: u64,
 
-    pub text_address: u32,
-    pub data_address: u32,
-    pub heap_address: u32,
-    pub stack_address: u32,
+    /// Byte order of the machine, taken from the ELF header
+    pub endianness: Endianness,
+
+    /// Word last targeted by a load linked and the generation it was linked at, cleared by a
+    /// store to it. Kept here rather than in the CPU so stores from any CPU break the link.
+    link: Option<(u64, u32)>,
+    /// Generation of the most recent load linked
+    link_generation: u32,
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Self {
+            pages: HashMap::new(),
+            text_address: 0,
+            text_size: 0,
+            data_address: 0,
+            data_size: 0,
+            stack_address: STACK_TOP,
+            stack_size: DEFAULT_STACK_SIZE,
+            heap_address: HEAP_START,
+            program_break: HEAP_START,
+            heap_size: DEFAULT_HEAP_SIZE,
+            kernel_address: 0,
+            endianness: Endianness::default(),
+            link: None,
+            link_generation: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Endianness {
+    #[default]
+    Big,
+    Little,
+}
+
+impl std::fmt::Display for Endianness {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Endianness::Big => write!(f, "Big endian"),
+            Endianness::Little => write!(f, "Little endian"),
+        }
+    }
 }
 
-#[derive(Debug, Clone, Copy)]
-enum Section {
-    Text,
-    Data,
-    Heap,
-    Stack,
+/// A memory access that no page can satisfy
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MemoryError {
+    /// Nothing is mapped at the address
+    Unmapped(u64),
+    /// The address is just below the stack limit
+    StackOverflow(u64),
+}
+
+impl MemoryError {
+    pub fn address(&self) -> u64 {
+        match self {
+            MemoryError::Unmapped(address) | MemoryError::StackOverflow(address) => *address,
+        }
+    }
+}
+
+impl std::fmt::Display for MemoryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            MemoryError::Unmapped(address) => write!(f, "Unmapped address 0x{:08x}", address),
+            MemoryError::StackOverflow(address) => {
+                write!(f, "Stack overflow at 0x{:08x}", address)
+            }
+        }
+    }
+}
+
+fn page_offset(address: u64) -> usize {
+    address as usize & (PAGE_SIZE - 1)
 }
 
 impl Memory {
     fn set_sections(&mut self, elf: &ELF) {
-        self.text_address = 0x00400000;
+        self.kernel_address = 0x80000000;
 
         for section_header in &elf.section_headers {
-            let header_names = &elf.section_headers[elf.elf_header.shstrndx as usize];
-            let section_name = &elf.elf
-                [header_names.sh_offset as usize + section_header.sh_name as usize..]
-                .split(|&x| x == 0)
-                .next()
-                .unwrap();
+            if section_header.name == ".data" {
+                self.data_address = section_header.sh_addr;
+                self.data_size = section_header.sh_size;
+            }
+        }
+    }
 
-            let section_name = std::str::from_utf8(section_name).unwrap();
+    /**
+     * Lowest address the stack may grow down to
+     */
+    pub fn stack_limit(&self) -> u64 {
+        self.stack_address.saturating_sub(self.stack_size)
+    }
 
-            if section_name == ".data" {
-                self.data_address = section_header.sh_addr;
+    fn in_stack(&self, address: u64) -> bool {
+        (self.stack_limit()..self.stack_address).contains(&address)
+    }
+
+    fn in_stack_guard(&self, address: u64) -> bool {
+        let limit = self.stack_limit();
+        (limit.saturating_sub(STACK_GUARD_SIZE)..limit).contains(&address)
+    }
+
+    /**
+     * The stack and the heap up to the program break get their pages when first written
+     */
+    fn allocated_on_demand(&self, address: u64) -> bool {
+        self.in_stack(address) || (self.heap_address..self.program_break).contains(&address)
+    }
+
+    /**
+     * The page holding address.
+     * None for stack and heap pages that have not been written yet, which read as zero.
+     */
+    fn page(&self, address: u64) -> Result<Option<&[u8; PAGE_SIZE]>, MemoryError> {
+        if self.in_stack_guard(address) {
+            return Err(MemoryError::StackOverflow(address));
+        }
+        match self.pages.get(&(address >> PAGE_SHIFT)) {
+            Some(page) => Ok(Some(&page.bytes)),
+            None if self.allocated_on_demand(address) => Ok(None),
+            None => Err(MemoryError::Unmapped(address)),
+        }
+    }
+
+    /**
+     * The page holding address, allocated if this is the first write to a stack or heap page
+     */
+    fn page_mut(&mut self, address: u64) -> Result<&mut [u8; PAGE_SIZE], MemoryError> {
+        if self.in_stack_guard(address) {
+            return Err(MemoryError::StackOverflow(address));
+        }
+        let on_demand = self.allocated_on_demand(address);
+        match self.pages.entry(address >> PAGE_SHIFT) {
+            Entry::Occupied(entry) => Ok(&mut entry.into_mut().bytes),
+            Entry::Vacant(entry) if on_demand => {
+                Ok(&mut entry.insert(Page::new(Permissions::READ_WRITE)).bytes)
             }
+            Entry::Vacant(_) => Err(MemoryError::Unmapped(address)),
+        }
+    }
+
+    /**
+     * Permissions of the page holding address.
+     * Stack and heap pages that have not been allocated yet get Permissions::READ_WRITE when
+     * they are. Nothing restricts other unallocated pages, accessing them is an unmapped access.
+     */
+    pub fn permissions(&self, address: u64) -> Permissions {
+        match self.pages.get(&(address >> PAGE_SHIFT)) {
+            Some(page) => page.permissions,
+            None if self.allocated_on_demand(address) => Permissions::READ_WRITE,
+            None => Permissions::ALL,
         }
     }
 
-    fn get_section(&self, address: u32) -> Section {
-        let mut sections = [
-            (Section::Text, self.text_address),
-            (Section::Data, self.data_address),
-            (Section::Heap, self.heap_address),
-            (Section::Stack, self.stack_address),
+    /**
+     * Whether a page has been allocated for address
+     */
+    pub fn is_mapped(&self, address: u64) -> bool {
+        self.pages.contains_key(&(address >> PAGE_SHIFT))
+    }
+
+    /**
+     * Number of bytes from the top of the stack down to the deepest page written
+     */
+    pub fn stack_depth(&self) -> u64 {
+        let mut bottom = self.stack_address;
+        while bottom > self.stack_limit() && self.is_mapped(bottom - 1) {
+            bottom = (bottom - 1) & !(PAGE_SIZE as u64 - 1);
+        }
+        self.stack_address - bottom.max(self.stack_limit())
+    }
+
+    /**
+     * Move the program break by increment bytes. The heap below the break is allocated on
+     * demand; memory released by shrinking it is freed, so it reads as zero if the heap grows
+     * back. Returns the previous break, or None if the heap would shrink below its start
+     * or grow past heap_size.
+     */
+    pub fn sbrk(&mut self, increment: i64) -> Option<u64> {
+        let previous = self.program_break;
+        let program_break = previous.checked_add_signed(increment)?;
+        if program_break < self.heap_address || program_break - self.heap_address > self.heap_size {
+            return None;
+        }
+        if program_break < previous {
+            self.release(program_break, previous);
+        }
+        self.program_break = program_break;
+        Some(previous)
+    }
+
+    /**
+     * Free the heap between start and end, dropping the pages that lie wholly inside it and
+     * clearing the rest of the page holding start
+     */
+    fn release(&mut self, start: u64, end: u64) {
+        let first_whole = start.next_multiple_of(PAGE_SIZE as u64);
+        if let Some(page) = self.pages.get_mut(&(start >> PAGE_SHIFT)) {
+            let tail = first_whole.min(end) - start;
+            page.bytes[page_offset(start)..][..tail as usize].fill(0);
+        }
+        if first_whole < end {
+            for page in first_whole >> PAGE_SHIFT..=(end - 1) >> PAGE_SHIFT {
+                self.pages.remove(&page);
+            }
+        }
+    }
+
+    /**
+     * Allocate the pages covering size bytes from address, leaving their contents as they are.
+     * A page already allocated for another segment gets the permissions of both.
+     */
+    pub fn map(&mut self, address: u64, size: u64, permissions: Permissions) {
+        if size == 0 {
+            return;
+        }
+        let last = address.saturating_add(size - 1);
+        for page in address >> PAGE_SHIFT..=last >> PAGE_SHIFT {
+            self.pages
+                .entry(page)
+                .and_modify(|page| page.permissions = page.permissions.union(permissions))
+                .or_insert_with(|| Page::new(permissions));
+        }
+    }
+
+    pub fn read_byte(&self, address: u64) -> Result<u8, MemoryError> {
+        Ok(self
+            .page(address)?
+            .map_or(0, |page| page[page_offset(address)]))
+    }
+
+    /**
+     * Bit position in a word of the byte at offset from the start of the word
+     */
+    fn byte_lane(&self, offset: u32) -> u32 {
+        match self.endianness {
+            Endianness::Big => 24 - 8 * offset,
+            Endianness::Little => 8 * offset,
+        }
+    }
+
+    /**
+     * Number of bytes in its word that are more significant than the byte at address
+     */
+    pub fn more_significant_bytes(&self, address: u64) -> u32 {
+        let offset = address as u32 & 3;
+        match self.endianness {
+            Endianness::Big => offset,
+            Endianness::Little => 3 - offset,
+        }
+    }
+
+    pub fn read_halfword(&self, address: u64) -> Result<u16, MemoryError> {
+        let bytes = [
+            self.read_byte(address)?,
+            self.read_byte(address.wrapping_add(1))?,
         ];
-        sections.sort_by(|a, b| a.1.cmp(&b.1));
+        Ok(match self.endianness {
+            Endianness::Big => u16::from_be_bytes(bytes),
+            Endianness::Little => u16::from_le_bytes(bytes),
+        })
+    }
+
+    pub fn read_word(&self, address: u64) -> Result<u32, MemoryError> {
+        self.read_word_masked(address, u32::MAX)
+    }
 
-        if address >= sections[0].1 && address < sections[1].1 {
-            sections[0].0
-        } else if address >= sections[1].1 && address < sections[2].1 {
-            sections[1].0
-        } else if address >= sections[2].1 && address < sections[3].1 {
-            sections[2].0
-        } else if address >= sections[3].1 {
-            sections[3].0
-        } else {
-            panic!("Invalid address: 0x{:08x}", address);
-        }
-    }
-
-    fn get_location(&self, address: u32) -> usize {
-        match self.get_section(address) {
-            Section::Text => (address - self.text_address) as usize,
-            Section::Data => (address - self.data_address) as usize,
-            Section::Heap => (address - self.heap_address) as usize,
-            Section::Stack => todo!("Finish get_location for stack"),
-        }
-    }
-
-    fn read_byte(&self, address: u32) -> u8 {
-        let section = self.get_section(address);
-        let location = self.get_location(address);
-        match section {
-            Section::Text => self.text[location],
-            Section::Data => self.data[location],
-            Section::Heap => self.heap[location],
-            Section::Stack => self.stack[location],
-        }
-    }
-
-    pub fn read_halfword(&self, address: u32) -> u16 {
-        (self.read_byte(address) as u16) << 8 | self.read_byte(address + 1) as u16
-    }
-
-    pub fn read_word(&self, address: u32) -> u32 {
-        (self.read_byte(address) as u32) << 24
-            | (self.read_byte(address + 1) as u32) << 16
-            | (self.read_byte(address + 2) as u32) << 8
-            | self.read_byte(address + 3) as u32
-    }
-
-    fn write_to_memory(memory: &mut Vec<u8>, location: usize, value: u8) {
-        if location < memory.len() {
-            memory[location] = value;
-        } else {
-            memory.resize(location + 1, 0);
-            memory[location] = value;
-        }
-    }
-
-    pub fn write_byte(&mut self, address: u32, value: u8) {
-        let section = self.get_section(address);
-        let location = self.get_location(address);
-        match section {
-            Section::Text => Self::write_to_memory(&mut self.text, location, value),
-            Section::Data => Self::write_to_memory(&mut self.data, location, value),
-            Section::Heap => Self::write_to_memory(&mut self.heap, location, value),
-            Section::Stack => Self::write_to_memory(&mut self.stack, location, value),
-        }
-    }
-
-    pub fn write_halfword(&mut self, address: u32, value: u16) {
-        self.write_byte(address, (value >> 8) as u8);
-        self.write_byte(address + 1, value as u8);
-    }
-
-    pub fn write_word(&mut self, address: u32, value: u32) {
-        self.write_byte(address, (value >> 24) as u8);
-        self.write_byte(address + 1, (value >> 16) as u8);
-        self.write_byte(address + 2, (value >> 8) as u8);
-        self.write_byte(address + 3, value as u8);
-    }
-
-    pub fn load_elf(&mut self, binary: &[u8]) -> u32 {
-        let elf = ELF::parse_elf(binary);
-
-        self.set_sections(&elf);
-
-        for program_header in elf.program_headers {
-            if program_header.p_type == 1 {
-                let section = self.get_section(program_header.p_paddr);
-                let location = self.get_location(program_header.p_paddr);
-                match section {
-                    Section::Text => {
-                        self.text
-                            .resize(location + program_header.p_memsz as usize, 0);
-                        self.text[location..location + program_header.p_filesz as usize]
-                            .copy_from_slice(
-                                &binary[program_header.p_offset as usize
-                                    ..program_header.p_offset as usize
-                                        + program_header.p_filesz as usize],
-                            );
-                    }
-                    Section::Data => {
-                        self.data
-                            .resize(location + program_header.p_memsz as usize, 0);
-                        self.data[location..location + program_header.p_filesz as usize]
-                            .copy_from_slice(
-                                &binary[program_header.p_offset as usize
-                                    ..program_header.p_offset as usize
-                                        + program_header.p_filesz as usize],
-                            );
-                    }
-                    _ => panic!("Unsupported section"),
-                }
+    /**
+     * Read only the bytes of the word containing address that are selected by mask.
+     * The other bits of the result are zero.
+     */
+    pub fn read_word_masked(&self, address: u64, mask: u32) -> Result<u32, MemoryError> {
+        let aligned = address & !3;
+        // An aligned word never crosses a page
+        let Some(page) = self.page(aligned)? else {
+            return Ok(0);
+        };
+        let start = page_offset(aligned);
+        let mut value = 0;
+        for offset in 0..4 {
+            let lane = self.byte_lane(offset);
+            if mask >> lane & 0xff != 0 {
+                value |= (page[start + offset as usize] as u32) << lane;
+            }
+        }
+        Ok(value)
+    }
+
+    /**
+     * Two words, the more significant one first in big endian
+     */
+    pub fn read_doubleword(&self, address: u64) -> Result<u64, MemoryError> {
+        let first = self.read_word(address)? as u64;
+        let second = self.read_word(address.wrapping_add(4))? as u64;
+        Ok(match self.endianness {
+            Endianness::Big => first << 32 | second,
+            Endianness::Little => second << 32 | first,
+        })
+    }
+
+    /**
+     * A store breaks the link on its word
+     */
+    fn break_link(&mut self, address: u64) {
+        if matches!(self.link, Some((linked, _)) if linked == address & !3) {
+            self.link = None;
+        }
+    }
+
+    pub fn write_byte(&mut self, address: u64, value: u8) -> Result<(), MemoryError> {
+        self.page_mut(address)?[page_offset(address)] = value;
+        self.break_link(address);
+        Ok(())
+    }
+
+    pub fn write_halfword(&mut self, address: u64, value: u16) -> Result<(), MemoryError> {
+        let bytes = match self.endianness {
+            Endianness::Big => value.to_be_bytes(),
+            Endianness::Little => value.to_le_bytes(),
+        };
+        self.write_byte(address, bytes[0])?;
+        self.write_byte(address.wrapping_add(1), bytes[1])
+    }
+
+    pub fn write_word(&mut self, address: u64, value: u32) -> Result<(), MemoryError> {
+        self.write_word_masked(address, value, u32::MAX)
+    }
+
+    /**
+     * Write only the bytes of value selected by mask to the word containing address
+     */
+    pub fn write_word_masked(
+        &mut self,
+        address: u64,
+        value: u32,
+        mask: u32,
+    ) -> Result<(), MemoryError> {
+        let aligned = address & !3;
+        let lanes = [0, 1, 2, 3].map(|offset| self.byte_lane(offset));
+        let page = self.page_mut(aligned)?;
+        let start = page_offset(aligned);
+        for (offset, lane) in lanes.into_iter().enumerate() {
+            if mask >> lane & 0xff != 0 {
+                page[start + offset] = (value >> lane) as u8;
+            }
+        }
+        self.break_link(aligned);
+        Ok(())
+    }
+
+    pub fn write_doubleword(&mut self, address: u64, value: u64) -> Result<(), MemoryError> {
+        let (first, second) = match self.endianness {
+            Endianness::Big => ((value >> 32) as u32, value as u32),
+            Endianness::Little => (value as u32, (value >> 32) as u32),
+        };
+        self.write_word(address, first)?;
+        self.write_word(address.wrapping_add(4), second)
+    }
+
+    /**
+     * Start watching the word at address for stores, replacing any earlier link.
+     * Returns the generation to pass to link_intact.
+     */
+    pub fn link(&mut self, address: u64) -> u32 {
+        self.link_generation = self.link_generation.wrapping_add(1);
+        self.link = Some((address & !3, self.link_generation));
+        self.link_generation
+    }
+
+    /**
+     * Whether the word at address is still linked since link returned generation
+     */
+    pub fn link_intact(&self, address: u64, generation: u32) -> bool {
+        self.link == Some((address & !3, generation))
+    }
+
+    /**
+     * Copy bytes into memory at address, a page at a time
+     */
+    fn write_bytes(&mut self, address: u64, bytes: &[u8]) {
+        let mut address = address;
+        let mut bytes = bytes;
+        while !bytes.is_empty() {
+            let start = page_offset(address);
+            let length = bytes.len().min(PAGE_SIZE - start);
+            let page = self
+                .pages
+                .entry(address >> PAGE_SHIFT)
+                .or_insert_with(|| Page::new(Permissions::ALL));
+            page.bytes[start..start + length].copy_from_slice(&bytes[..length]);
+            bytes = &bytes[length..];
+            address = address.wrapping_add(length as u64);
+        }
+    }
+
+    /**
+     * Load every PT_LOAD segment where the CPU will find it and return the entry point.
+     * Without an MMU that is its virtual address. With one, kseg0 and kseg1 segments go to
+     * the physical address those windows map them to, and mapped segments to p_paddr.
+     * The pages of a segment may only be used as its p_flags allow.
+     * Segments can go anywhere; the one holding the entry point is shown as the text segment.
+     */
+    pub fn load_elf(&mut self, elf: &ELF, mmu: bool) -> u64 {
+        self.set_sections(elf);
+        self.endianness = elf.elf_header.endianness();
+        let entry = compatibility_address(elf.elf_header.entry & !1);
+        let data_address = compatibility_address(self.data_address);
+        let mut data_end = None;
+
+        for program_header in &elf.program_headers {
+            if program_header.p_type != PT_LOAD {
+                continue;
+            }
+            let address = load_address(program_header, mmu);
+            let contents = elf.contents(program_header);
+            // Pages past the file contents are the zeroed .bss
+            let permissions = Permissions::from_flags(program_header.p_flags);
+            self.map(address, program_header.p_memsz, permissions);
+            self.write_bytes(address, contents);
+
+            // The segment views read memory where the segments were loaded
+            let start = compatibility_address(program_header.p_vaddr);
+            let segment = start..start.wrapping_add(program_header.p_memsz);
+            if segment.contains(&entry) {
+                self.text_address = address;
+                self.text_size = program_header.p_memsz;
+            }
+            if segment.contains(&data_address) {
+                self.data_address = address + (data_address - start);
+            }
+            if permissions.write {
+                let end = address.saturating_add(program_header.p_memsz);
+                data_end = data_end.max(Some(end));
             }
         }
+        // The heap starts on the doubleword after the highest writable segment
+        self.heap_address = data_end.map_or(HEAP_START, |end| end.next_multiple_of(8));
+        self.program_break = self.heap_address;
 
         elf.elf_header.entry
     }
 }
+
+/**
+ * ELF64 addresses in the compatibility segments are sign-extended, they go where the 32-bit
+ * address would
+ */
+fn compatibility_address(address: u64) -> u64 {
+    if address == address as i32 as u64 {
+        address as u32 as u64
+    } else {
+        address
+    }
+}
+
+/**
+ * Address a segment is loaded at
+ */
+fn load_address(program_header: &ProgramHeader, mmu: bool) -> u64 {
+    let vaddr = compatibility_address(program_header.p_vaddr);
+    match vaddr >> 29 {
+        _ if !mmu => vaddr,
+        // kseg0 and kseg1
+        0b100 | 0b101 => vaddr & KSEG_PHYSICAL as u64,
+        _ => program_header.p_paddr,
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::field_reassign_with_default)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_boundary() {
+        let mut memory = Memory::default();
+        memory.map(0x00400000, 0x2000, Permissions::ALL);
+        memory.write_halfword(0x00400fff, 0x1234).unwrap();
+        assert_eq!(memory.read_byte(0x00400fff), Ok(0x12));
+        assert_eq!(memory.read_byte(0x00401000), Ok(0x34));
+        assert_eq!(memory.read_halfword(0x00400fff), Ok(0x1234));
+
+        memory
+            .write_doubleword(0x00400ffc, 0x0123456789abcdef)
+            .unwrap();
+        assert_eq!(memory.read_word(0x00400ffc), Ok(0x01234567));
+        assert_eq!(memory.read_word(0x00401000), Ok(0x89abcdef));
+        assert_eq!(memory.read_doubleword(0x00400ffc), Ok(0x0123456789abcdef));
+    }
+
+    #[test]
+    fn test_page_boundary_little_endian() {
+        let mut memory = Memory::default();
+        memory.endianness = Endianness::Little;
+        memory.map(0x00400000, 0x2000, Permissions::ALL);
+        memory.write_halfword(0x00400fff, 0x1234).unwrap();
+        assert_eq!(memory.read_byte(0x00400fff), Ok(0x34));
+        assert_eq!(memory.read_byte(0x00401000), Ok(0x12));
+        assert_eq!(memory.read_halfword(0x00400fff), Ok(0x1234));
+    }
+
+    #[test]
+    fn test_page_boundary_into_unmapped() {
+        let mut memory = Memory::default();
+        memory.map(0x00400000, 0x1000, Permissions::ALL);
+        assert_eq!(
+            memory.write_halfword(0x00400fff, 0x1234),
+            Err(MemoryError::Unmapped(0x00401000))
+        );
+        assert_eq!(
+            memory.read_halfword(0x00400fff),
+            Err(MemoryError::Unmapped(0x00401000))
+        );
+    }
+
+    #[test]
+    fn test_top_of_address_space() {
+        let mut memory = Memory::default();
+        memory.map(0xfffff000, 0x1000, Permissions::ALL);
+        memory.write_word(0xfffffffc, 0xdeadbeef).unwrap();
+        assert_eq!(memory.read_word(0xfffffffc), Ok(0xdeadbeef));
+        assert_eq!(memory.read_byte(0xffffffff), Ok(0xef));
+
+        // The second half is past 4 GiB
+        assert_eq!(
+            memory.read_halfword(0xffffffff),
+            Err(MemoryError::Unmapped(0x1_00000000))
+        );
+        assert_eq!(
+            memory.write_doubleword(0xfffffffc, 0),
+            Err(MemoryError::Unmapped(0x1_00000000))
+        );
+    }
+
+    #[test]
+    fn test_single_link() {
+        let mut memory = Memory::default();
+        memory.map(0x10010000, 0x1000, Permissions::READ_WRITE);
+        let first = memory.link(0x10010000);
+        let second = memory.link(0x10010006);
+        // A later load linked replaces the earlier link
+        assert!(!memory.link_intact(0x10010000, first));
+        assert!(memory.link_intact(0x10010004, second));
+        memory.write_byte(0x10010007, 1).unwrap();
+        assert!(!memory.link_intact(0x10010004, second));
+        // Relinking the same word starts a new generation
+        let third = memory.link(0x10010004);
+        assert_ne!(third, second);
+        assert!(!memory.link_intact(0x10010004, second));
+        assert!(memory.link_intact(0x10010004, third));
+    }
+
+    #[test]
+    fn test_unmapped() {
+        let mut memory = Memory::default();
+        assert_eq!(
+            memory.read_word(0x00400000),
+            Err(MemoryError::Unmapped(0x00400000))
+        );
+        assert_eq!(
+            memory.write_word(0x00400000, 1),
+            Err(MemoryError::Unmapped(0x00400000))
+        );
+        assert_eq!(
+            memory.write_byte(0x10010003, 1),
+            Err(MemoryError::Unmapped(0x10010003))
+        );
+        assert!(!memory.is_mapped(0x00400000));
+        assert!(!memory.is_mapped(0x10010000));
+    }
+
+    #[test]
+    fn test_stack_reads_as_zero() {
+        let memory = Memory::default();
+        assert_eq!(memory.read_word(0x7ffffffc), Ok(0));
+        assert_eq!(memory.read_doubleword(0x7f800000), Ok(0));
+        assert!(!memory.is_mapped(0x7ffffffc));
+    }
+
+    #[test]
+    fn test_stack_pages_read_write() {
+        let mut memory = Memory::default();
+        memory.write_word(0x7ffffffc, 0x12345678).unwrap();
+        assert_eq!(memory.read_word(0x7ffffffc), Ok(0x12345678));
+        assert!(memory.is_mapped(0x7ffffffc));
+        assert_eq!(memory.permissions(0x7ffffffc), Permissions::READ_WRITE);
+        assert_eq!(memory.permissions(0x7fffeffc), Permissions::READ_WRITE);
+    }
+
+    #[test]
+    fn test_heap_pages_read_write() {
+        let mut memory = Memory::default();
+        memory.program_break = memory.heap_address + 0x10;
+        assert_eq!(memory.read_word(memory.heap_address), Ok(0));
+        memory.write_word(memory.heap_address, 0x12345678).unwrap();
+        assert_eq!(
+            memory.permissions(memory.heap_address),
+            Permissions::READ_WRITE
+        );
+
+        let program_break = memory.program_break + 0x1000;
+        assert_eq!(
+            memory.write_word(program_break, 1),
+            Err(MemoryError::Unmapped(program_break))
+        );
+    }
+
+    #[test]
+    fn test_heap_shrink_then_regrow() {
+        let mut memory = Memory::default();
+        memory.heap_address = 0x10010100;
+        memory.program_break = 0x10010100;
+        memory.data_address = 0x10010000;
+        memory.map(0x10010000, 0x100, Permissions::READ_WRITE);
+        memory.write_word(0x10010000, 7).unwrap();
+        assert_eq!(memory.sbrk(0x2000), Some(0x10010100));
+        assert!(!memory.is_mapped(0x10011000));
+        memory.write_word(0x10010200, 1).unwrap();
+        memory.write_word(0x10011000, 2).unwrap();
+        memory.write_word(0x100120fc, 3).unwrap();
+
+        assert_eq!(memory.sbrk(-0x1f00), Some(0x10012100));
+        assert_eq!(memory.program_break, 0x10010200);
+        assert!(!memory.is_mapped(0x10011000));
+        assert!(!memory.is_mapped(0x10012000));
+        assert_eq!(
+            memory.read_word(0x10011000),
+            Err(MemoryError::Unmapped(0x10011000))
+        );
+        // The data sharing the first heap page is kept
+        assert_eq!(memory.read_word(0x10010000), Ok(7));
+
+        assert_eq!(memory.sbrk(0x1f00), Some(0x10010200));
+        assert_eq!(memory.read_word(0x10010200), Ok(0));
+        assert_eq!(memory.read_word(0x10011000), Ok(0));
+        assert_eq!(memory.read_word(0x100120fc), Ok(0));
+        assert!(!memory.is_mapped(0x10011000));
+    }
+
+    #[test]
+    fn test_stack_guard() {
+        let mut memory = Memory::default();
+        let guard = memory.stack_limit() - 4;
+        assert_eq!(
+            memory.read_word(guard),
+            Err(MemoryError::StackOverflow(guard))
+        );
+        assert_eq!(
+            memory.write_word(guard, 1),
+            Err(MemoryError::StackOverflow(guard))
+        );
+        assert_eq!(memory.read_word(memory.stack_limit()), Ok(0));
+
+        // Below the guard is no longer the stack
+        let below = memory.stack_limit() - STACK_GUARD_SIZE - 4;
+        assert_eq!(
+            memory.write_word(below, 1),
+            Err(MemoryError::Unmapped(below))
+        );
+        assert!(!memory.is_mapped(below));
+    }
+
+    #[test]
+    fn test_stack_depth() {
+        let mut memory = Memory::default();
+        assert_eq!(memory.stack_depth(), 0);
+        memory.write_word(0x7ffffffc, 1).unwrap();
+        assert_eq!(memory.stack_depth(), 0x1000);
+        memory.write_word(0x7fffeff0, 1).unwrap();
+        assert_eq!(memory.stack_depth(), 0x2000);
+
+        let limit = memory.stack_limit();
+        for address in (limit..memory.stack_address).step_by(PAGE_SIZE) {
+            memory.write_byte(address, 1).unwrap();
+        }
+        assert_eq!(memory.stack_depth(), memory.stack_size);
+    }
+}